@@ -4,6 +4,7 @@ use automerge::ReadDoc;
 
 use crate::byte_span::{to_str, AMbyteSpan};
 use crate::doc::{to_doc, to_doc_mut, AMdoc};
+use crate::item::to_link;
 use crate::items::AMitems;
 use crate::obj::{to_obj_id, to_obj_type, AMobjId, AMobjType};
 use crate::result::{to_result, AMresult};
@@ -383,6 +384,56 @@ pub unsafe extern "C" fn AMlistPutInt(
     })
 }
 
+/// \memberof AMdoc
+/// \brief Puts a link to another document into an item within a list object.
+///
+/// \param[in] doc A pointer to an `AMdoc` struct.
+/// \param[in] obj_id A pointer to an `AMobjId` struct or `AM_ROOT`.
+/// \param[in] pos The position of an item within the list object identified by
+///                \p obj_id or `SIZE_MAX` to indicate its last item if
+///                \p insert `== false` or one past its last item if
+///                \p insert `== true`.
+/// \param[in] insert A flag for inserting a new item for the link before
+///                   \p pos instead of putting the link into the item at
+///                   \p pos.
+/// \param[in] link_doc The identifier of the linked document as a UTF-8 string
+///                     view as an `AMbyteSpan` struct.
+/// \param[in] heads A pointer to an `AMitems` struct with `AM_VAL_TYPE_CHANGE_HASH`
+///                  items to select a historical version of the linked document
+///                  or `NULL` to follow its latest version.
+/// \return A pointer to an `AMresult` struct with an `AM_VAL_TYPE_VOID` item.
+/// \pre \p doc `!= NULL`
+/// \pre `0 <=` \p pos `<= AMobjSize(`\p obj_id `)` or \p pos `== SIZE_MAX`
+/// \pre \p link_doc.src `!= NULL`
+/// \warning The returned `AMresult` struct pointer must be passed to
+///          `AMresultFree()` in order to avoid a memory leak.
+/// \internal
+///
+/// # Safety
+/// doc must be a valid pointer to an AMdoc
+/// obj_id must be a valid pointer to an AMobjId or std::ptr::null()
+/// link_doc.src must be a byte array of length >= link_doc.count
+/// heads must be a valid pointer to an AMitems or std::ptr::null()
+#[no_mangle]
+pub unsafe extern "C" fn AMlistPutLink(
+    doc: *mut AMdoc,
+    obj_id: *const AMobjId,
+    pos: usize,
+    insert: bool,
+    link_doc: AMbyteSpan,
+    heads: *const AMitems,
+) -> *mut AMresult {
+    let doc = to_doc_mut!(doc);
+    let obj_id = to_obj_id!(obj_id);
+    let (pos, insert) = adjust!(pos, insert, doc.length(obj_id));
+    let link = to_link!(link_doc, heads);
+    to_result(if insert {
+        doc.insert(obj_id, pos, link)
+    } else {
+        doc.put(obj_id, pos, link)
+    })
+}
+
 /// \memberof AMdoc
 /// \brief Puts a null value into an item within a list object.
 ///
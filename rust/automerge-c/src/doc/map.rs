@@ -4,6 +4,7 @@ use automerge::ReadDoc;
 
 use crate::byte_span::{to_str, AMbyteSpan};
 use crate::doc::{to_doc, to_doc_mut, AMdoc};
+use crate::item::to_link;
 use crate::items::AMitems;
 use crate::obj::{to_obj_id, to_obj_type, AMobjId, AMobjType};
 use crate::result::{to_result, AMresult};
@@ -251,6 +252,46 @@ pub unsafe extern "C" fn AMmapPutCounter(
     ))
 }
 
+/// \memberof AMdoc
+/// \brief Puts a link to another document as the value of a key in a map
+///        object.
+///
+/// \param[in] doc A pointer to an `AMdoc` struct.
+/// \param[in] obj_id A pointer to an `AMobjId` struct or `AM_ROOT`.
+/// \param[in] key A UTF-8 string view key for the map object identified by
+///                \p obj_id as an `AMbyteSpan` struct.
+/// \param[in] link_doc The identifier of the linked document as a UTF-8 string
+///                     view as an `AMbyteSpan` struct.
+/// \param[in] heads A pointer to an `AMitems` struct with `AM_VAL_TYPE_CHANGE_HASH`
+///                  items to select a historical version of the linked document
+///                  or `NULL` to follow its latest version.
+/// \return A pointer to an `AMresult` struct with an `AM_VAL_TYPE_VOID` item.
+/// \pre \p doc `!= NULL`
+/// \pre \p key.src `!= NULL`
+/// \pre \p link_doc.src `!= NULL`
+/// \warning The returned `AMresult` struct pointer must be passed to
+///          `AMresultFree()` in order to avoid a memory leak.
+/// \internal
+///
+/// # Safety
+/// doc must be a valid pointer to an AMdoc
+/// obj_id must be a valid pointer to an AMobjId or std::ptr::null()
+/// key.src must be a byte array of length >= key.count
+/// link_doc.src must be a byte array of length >= link_doc.count
+/// heads must be a valid pointer to an AMitems or std::ptr::null()
+#[no_mangle]
+pub unsafe extern "C" fn AMmapPutLink(
+    doc: *mut AMdoc,
+    obj_id: *const AMobjId,
+    key: AMbyteSpan,
+    link_doc: AMbyteSpan,
+    heads: *const AMitems,
+) -> *mut AMresult {
+    let doc = to_doc_mut!(doc);
+    let link = to_link!(link_doc, heads);
+    to_result(doc.put(to_obj_id!(obj_id), to_str!(key), link))
+}
+
 /// \memberof AMdoc
 /// \brief Puts null as the value of a key in a map object.
 ///
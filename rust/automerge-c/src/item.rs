@@ -12,10 +12,26 @@ use crate::change::AMchange;
 use crate::doc::mark::AMmark;
 use crate::doc::AMdoc;
 use crate::index::{AMidxType, AMindex};
+use crate::items::AMitems;
 use crate::obj::AMobjId;
 use crate::result::{to_result, AMresult};
 use crate::sync::{AMsyncHave, AMsyncMessage, AMsyncState};
 
+macro_rules! to_link {
+    ($doc:expr, $heads:expr) => {{
+        let doc = to_str!($doc);
+        match $heads.as_ref() {
+            None => am::links::Link::new(doc),
+            Some(heads) => match <Vec<am::ChangeHash>>::try_from(heads) {
+                Ok(heads) => am::links::Link::at(doc, &heads),
+                Err(e) => return AMresult::error(&e.to_string()).into(),
+            },
+        }
+    }};
+}
+
+pub(crate) use to_link;
+
 /// \struct AMunknownValue
 /// \installed_headerfile
 /// \brief A value (typically for a `set` operation) whose type is unknown.
@@ -102,6 +118,22 @@ impl Value {
         })
     }
 
+    pub fn try_into_link(&self) -> Result<&am::links::Link, am::AutomergeError> {
+        use am::AutomergeError::InvalidValueType;
+        use am::ScalarValue::*;
+        use am::Value::*;
+
+        if let Self::Value(Scalar(scalar)) = &self {
+            if let Link(link) = scalar.as_ref() {
+                return Ok(link);
+            }
+        }
+        Err(InvalidValueType {
+            expected: type_name::<am::links::Link>().to_string(),
+            unexpected: type_name::<self::Value>().to_string(),
+        })
+    }
+
     pub fn try_into_str(&self) -> Result<AMbyteSpan, am::AutomergeError> {
         use am::AutomergeError::InvalidValueType;
         use am::ScalarValue::*;
@@ -541,6 +573,18 @@ impl Item {
         })
     }
 
+    pub fn try_into_link(&self) -> Result<&am::links::Link, am::AutomergeError> {
+        use am::AutomergeError::InvalidValueType;
+
+        if let Some(value) = &self.value {
+            return value.try_into_link();
+        }
+        Err(InvalidValueType {
+            expected: type_name::<am::links::Link>().to_string(),
+            unexpected: type_name::<Option<Value>>().to_string(),
+        })
+    }
+
     pub fn try_into_str(&self) -> Result<AMbyteSpan, am::AutomergeError> {
         use am::AutomergeError::InvalidValueType;
 
@@ -1242,6 +1286,8 @@ pub enum AMvalType {
     F64 = 1 << 8,
     /// A 64-bit signed integer value.
     Int = 1 << 9,
+    /// A link to another document.
+    Link = 1 << 20,
     /// A mark.
     Mark = 1 << 10,
     /// A null value.
@@ -1288,6 +1334,7 @@ impl From<&am::Value<'static>> for AMvalType {
                 Null => Self::Null,
                 Str(_) => Self::Str,
                 Timestamp(_) => Self::Timestamp,
+                Link(_) => Self::Link,
                 Uint(_) => Self::Uint,
                 Unknown { .. } => Self::Unknown,
             },
@@ -1432,6 +1479,30 @@ pub unsafe extern "C" fn AMitemFromInt(value: i64) -> *mut AMresult {
     AMresult::item(am::Value::int(value).into()).into()
 }
 
+/// \memberof AMitem
+/// \brief Allocates a new item and initializes it from a link to another
+///        document.
+///
+/// \param[in] doc The identifier of the linked document as a UTF-8 string view
+///                as an `AMbyteSpan` struct.
+/// \param[in] heads A pointer to an `AMitems` struct with `AM_VAL_TYPE_CHANGE_HASH`
+///                  items to select a historical version of the linked document
+///                  or `NULL` to follow its latest version.
+/// \return A pointer to an `AMresult` struct with an `AM_VAL_TYPE_LINK` item.
+/// \pre \p doc.src `!= NULL`
+/// \pre `0 <` \p doc.count `<= sizeof(`\p doc.src `)`
+/// \warning The returned `AMresult` struct pointer must be passed to
+///          `AMresultFree()` in order to avoid a memory leak.
+/// \internal
+///
+/// # Safety
+/// doc.src must be a byte array of length >= doc.count
+/// heads must be a valid pointer to an AMitems or std::ptr::null()
+#[no_mangle]
+pub unsafe extern "C" fn AMitemFromLink(doc: AMbyteSpan, heads: *const AMitems) -> *mut AMresult {
+    AMresult::item(am::Value::from(am::ScalarValue::from(to_link!(doc, heads))).into()).into()
+}
+
 /// \memberof AMitem
 /// \brief Allocates a new item and initializes it from a null value.
 ///
@@ -1553,6 +1624,34 @@ pub unsafe extern "C" fn AMitemKey(item: *const AMitem, value: *mut AMbyteSpan)
     false
 }
 
+/// \memberof AMitem
+/// \brief Gets the heads of the version of a document that a link value
+///        refers to.
+///
+/// \param[in] item A pointer to an `AMitem` struct.
+/// \return A pointer to an `AMresult` struct with `AM_VAL_TYPE_CHANGE_HASH`
+///         items, or with an `AM_VAL_TYPE_VOID` item if the link follows the
+///         latest version of the document.
+/// \pre \p item `!= NULL`
+/// \pre `AMitemValType(`\p item `) == AM_VAL_TYPE_LINK`
+/// \warning The returned `AMresult` struct pointer must be passed to
+///          `AMresultFree()` in order to avoid a memory leak.
+/// \internal
+///
+/// # Safety
+/// item must be a valid pointer to an AMitem
+#[no_mangle]
+pub unsafe extern "C" fn AMitemLinkHeads(item: *const AMitem) -> *mut AMresult {
+    match item.as_ref().map(|item| item.as_ref().try_into_link()) {
+        Some(Ok(link)) => match &link.heads {
+            Some(heads) => to_result(heads.as_slice()),
+            None => AMresult::item(Default::default()).into(),
+        },
+        Some(Err(e)) => AMresult::error(&e.to_string()).into(),
+        None => AMresult::error("invalid item").into(),
+    }
+}
+
 /// \memberof AMitem
 /// \brief Gets the unsigned integer position index of an item.
 ///
@@ -1876,6 +1975,32 @@ pub unsafe extern "C" fn AMitemToMark<'a>(
     false
 }
 
+/// \memberof AMitem
+/// \brief Gets the identifier of the linked document of a link value of an
+///        item.
+///
+/// \param[in] item A pointer to an `AMitem` struct.
+/// \param[out] value A pointer to a UTF-8 string view as an `AMbyteSpan` struct.
+/// \return `true` if `AMitemValType(`\p item `) == AM_VAL_TYPE_LINK` and
+///         \p *value has been reassigned, `false` otherwise.
+/// \pre \p item `!= NULL`
+/// \internal
+///
+/// # Safety
+/// item must be a valid pointer to an AMitem
+#[no_mangle]
+pub unsafe extern "C" fn AMitemToLink(item: *const AMitem, value: *mut AMbyteSpan) -> bool {
+    if let Some(item) = item.as_ref() {
+        if let Ok(link) = item.as_ref().try_into_link() {
+            if !value.is_null() {
+                *value = link.doc.as_bytes().into();
+                return true;
+            }
+        }
+    }
+    false
+}
+
 /// \memberof AMitem
 /// \brief Gets the UTF-8 string view value of an item.
 ///
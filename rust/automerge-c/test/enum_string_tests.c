@@ -93,6 +93,7 @@ static void test_AMvalTypeToString(void** state) {
     assert_to_string(AMvalTypeToString, AM_VAL_TYPE_DOC);
     assert_to_string(AMvalTypeToString, AM_VAL_TYPE_F64);
     assert_to_string(AMvalTypeToString, AM_VAL_TYPE_INT);
+    assert_to_string(AMvalTypeToString, AM_VAL_TYPE_LINK);
     assert_to_string(AMvalTypeToString, AM_VAL_TYPE_NULL);
     assert_to_string(AMvalTypeToString, AM_VAL_TYPE_OBJ_TYPE);
     assert_to_string(AMvalTypeToString, AM_VAL_TYPE_STR);
@@ -120,6 +121,7 @@ static void test_AMvalTypeFromString(void** state) {
     assert_from_string(AMvalTypeFromString, AMvalType, AM_VAL_TYPE_DOC);
     assert_from_string(AMvalTypeFromString, AMvalType, AM_VAL_TYPE_F64);
     assert_from_string(AMvalTypeFromString, AMvalType, AM_VAL_TYPE_INT);
+    assert_from_string(AMvalTypeFromString, AMvalType, AM_VAL_TYPE_LINK);
     assert_from_string(AMvalTypeFromString, AMvalType, AM_VAL_TYPE_NULL);
     assert_from_string(AMvalTypeFromString, AMvalType, AM_VAL_TYPE_OBJ_TYPE);
     assert_from_string(AMvalTypeFromString, AMvalType, AM_VAL_TYPE_STR);
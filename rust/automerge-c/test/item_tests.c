@@ -5,6 +5,7 @@
 #include <stdint.h>
 #include <stdio.h>
 #include <stdlib.h>
+#include <string.h>
 
 /* third-party */
 #include <cmocka.h>
@@ -194,6 +195,46 @@ static void test_AMitemToInt_null_out_arg(void** state) {
     assert_false(AMitemToInt(item, NULL));
 }
 
+static void test_AMitemToLink(void** state) {
+    DocState* doc_state = *state;
+    AMstack** stack_ptr = &doc_state->base_state->stack;
+
+    AMstackItem(NULL, AMmapPutStr(doc_state->doc, AM_ROOT, AMstr("str"), AMstr("hello")), cmocka_cb,
+                AMexpect(AM_VAL_TYPE_VOID));
+    AMstackItem(NULL, AMcommit(doc_state->doc, AMstr(""), NULL), cmocka_cb, AMexpect(AM_VAL_TYPE_CHANGE_HASH));
+    AMitems heads = AMstackItems(stack_ptr, AMgetHeads(doc_state->doc), cmocka_cb, AMexpect(AM_VAL_TYPE_CHANGE_HASH));
+    AMstackItem(NULL, AMmapPutLink(doc_state->doc, AM_ROOT, AMstr("latest"), AMstr("other"), NULL), cmocka_cb,
+                AMexpect(AM_VAL_TYPE_VOID));
+    AMstackItem(NULL, AMmapPutLink(doc_state->doc, AM_ROOT, AMstr("pinned"), AMstr("other"), &heads), cmocka_cb,
+                AMexpect(AM_VAL_TYPE_VOID));
+    /* A link without heads follows the latest version of the linked document. */
+    AMitem* item = AMstackItem(stack_ptr, AMmapGet(doc_state->doc, AM_ROOT, AMstr("latest"), NULL), cmocka_cb,
+                               AMexpect(AM_VAL_TYPE_LINK));
+    AMbyteSpan link_doc;
+    assert_true(AMitemToLink(item, &link_doc));
+    assert_int_equal(link_doc.count, strlen("other"));
+    assert_memory_equal(link_doc.src, "other", link_doc.count);
+    AMstackItem(stack_ptr, AMitemLinkHeads(item), cmocka_cb, AMexpect(AM_VAL_TYPE_VOID));
+    /* A link with heads refers to a historical version of the linked document. */
+    item = AMstackItem(stack_ptr, AMmapGet(doc_state->doc, AM_ROOT, AMstr("pinned"), NULL), cmocka_cb,
+                       AMexpect(AM_VAL_TYPE_LINK));
+    assert_true(AMitemToLink(item, &link_doc));
+    assert_memory_equal(link_doc.src, "other", link_doc.count);
+    AMitems link_heads =
+        AMstackItems(stack_ptr, AMitemLinkHeads(item), cmocka_cb, AMexpect(AM_VAL_TYPE_CHANGE_HASH));
+    assert_true(AMitemsEqual(&link_heads, &heads));
+}
+
+static void test_AMitemToLink_null_out_arg(void** state) {
+    DocState* doc_state = *state;
+    AMstack** stack_ptr = &doc_state->base_state->stack;
+
+    AMitem* const item =
+        AMstackItem(stack_ptr, AMitemFromLink(AMstr("other"), NULL), cmocka_cb, AMexpect(AM_VAL_TYPE_LINK));
+    assert_non_null(item);
+    assert_false(AMitemToLink(item, NULL));
+}
+
 static void test_AMitemToMark_null_out_arg(void** state) {
     DocState* doc_state = *state;
     AMstack** stack_ptr = &doc_state->base_state->stack;
@@ -300,6 +341,8 @@ int run_item_tests(void) {
         cmocka_unit_test(test_AMitemToDoc_null_out_arg),
         cmocka_unit_test(test_AMitemToF64_null_out_arg),
         cmocka_unit_test(test_AMitemToInt_null_out_arg),
+        cmocka_unit_test(test_AMitemToLink),
+        cmocka_unit_test(test_AMitemToLink_null_out_arg),
         cmocka_unit_test(test_AMitemToMark_null_out_arg),
         cmocka_unit_test(test_AMitemToStr_null_out_arg),
         cmocka_unit_test(test_AMitemToSyncHave_null_out_arg),
@@ -68,6 +68,7 @@ fn scalar_to_json(val: &am::ScalarValue) -> serde_json::Value {
         am::ScalarValue::Counter(c) => serde_json::Value::Number(i64::from(c).into()),
         am::ScalarValue::Timestamp(n) => serde_json::Value::Number((*n).into()),
         am::ScalarValue::Boolean(b) => serde_json::Value::Bool(*b),
+        am::ScalarValue::Link(l) => serde_json::to_value(l).unwrap_or(serde_json::Value::Null),
         am::ScalarValue::Null => serde_json::Value::Null,
     }
 }
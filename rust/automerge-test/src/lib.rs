@@ -238,8 +238,15 @@ pub enum OrdScalarValue {
     Counter(i64),
     Timestamp(i64),
     Boolean(bool),
+    Link {
+        doc: String,
+        heads: Option<Vec<automerge::ChangeHash>>,
+    },
     Null,
-    Unknown { type_code: u8, bytes: Vec<u8> },
+    Unknown {
+        type_code: u8,
+        bytes: Vec<u8>,
+    },
 }
 
 impl From<automerge::ScalarValue> for OrdScalarValue {
@@ -253,6 +260,10 @@ impl From<automerge::ScalarValue> for OrdScalarValue {
             automerge::ScalarValue::Counter(c) => OrdScalarValue::Counter(c.into()),
             automerge::ScalarValue::Timestamp(v) => OrdScalarValue::Timestamp(v),
             automerge::ScalarValue::Boolean(v) => OrdScalarValue::Boolean(v),
+            automerge::ScalarValue::Link(l) => OrdScalarValue::Link {
+                doc: l.doc,
+                heads: l.heads,
+            },
             automerge::ScalarValue::Null => OrdScalarValue::Null,
             automerge::ScalarValue::Unknown { type_code, bytes } => {
                 OrdScalarValue::Unknown { type_code, bytes }
@@ -272,6 +283,12 @@ impl From<&OrdScalarValue> for automerge::ScalarValue {
             OrdScalarValue::Counter(v) => automerge::ScalarValue::counter(*v),
             OrdScalarValue::Timestamp(v) => automerge::ScalarValue::Timestamp(*v),
             OrdScalarValue::Boolean(v) => automerge::ScalarValue::Boolean(*v),
+            OrdScalarValue::Link { doc, heads } => {
+                automerge::ScalarValue::from(automerge::links::Link {
+                    doc: doc.clone(),
+                    heads: heads.clone(),
+                })
+            }
             OrdScalarValue::Null => automerge::ScalarValue::Null,
             OrdScalarValue::Unknown { type_code, bytes } => automerge::ScalarValue::Unknown {
                 type_code: *type_code,
@@ -299,6 +316,9 @@ impl serde::Serialize for OrdScalarValue {
                 serializer.serialize_str(format!("Timestamp({})", v).as_str())
             }
             OrdScalarValue::Boolean(v) => serializer.serialize_bool(*v),
+            OrdScalarValue::Link { doc, .. } => {
+                serializer.serialize_str(format!("Link({})", doc).as_str())
+            }
             OrdScalarValue::Null => serializer.serialize_none(),
             OrdScalarValue::Unknown { type_code, .. } => serializer
                 .serialize_str(format!("An unknown type with code {}", type_code).as_str()),
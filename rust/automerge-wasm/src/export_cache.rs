@@ -1,4 +1,4 @@
-use crate::interop::error;
+use crate::interop::{self, error};
 use crate::value::Datatype;
 use crate::Automerge;
 use automerge as am;
@@ -260,6 +260,7 @@ impl<'a> ExportCache<'a> {
                 js_sys::Date::new(&(*v as f64).into()).into(),
             ),
             am::ScalarValue::Boolean(v) => (Datatype::Boolean, (*v).into()),
+            am::ScalarValue::Link(l) => (Datatype::Link, interop::export_link(l)),
             am::ScalarValue::Null => (Datatype::Null, JsValue::null()),
             am::ScalarValue::Unknown { bytes, type_code } => (
                 Datatype::Unknown(*type_code),
@@ -15,7 +15,7 @@ use std::ops::Deref;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
-use am::{marks::ExpandMark, ObjId, Patch, PatchAction, Value};
+use am::{blocks::Block, marks::ExpandMark, ObjId, Patch, PatchAction, Value};
 
 pub(crate) use crate::export_cache::ExportCache;
 
@@ -333,6 +333,7 @@ impl TryFrom<JS> for am::sync::State {
             in_flight,
             have_responded,
             their_capabilities,
+            ..Default::default()
         })
     }
 }
@@ -442,6 +443,9 @@ impl TryFrom<JS> for am::sync::Message {
             changes,
             supported_capabilities,
             version,
+            rejected: Vec::new(),
+            refs: None,
+            mark_policies: None,
         })
     }
 }
@@ -509,7 +513,7 @@ impl From<&[am::sync::Capability]> for AR {
             .filter_map(|c| match c {
                 am::sync::Capability::MessageV1 => Some(JsValue::from_str("message-v1")),
                 am::sync::Capability::MessageV2 => Some(JsValue::from_str("message-v2")),
-                am::sync::Capability::Unknown(_) => None,
+                am::sync::Capability::BloomParams(_) | am::sync::Capability::Unknown(_) => None,
             })
             .collect())
     }
@@ -957,6 +961,8 @@ impl Automerge {
             }
             PatchAction::Mark { .. } => Ok(()),
             PatchAction::Conflict { .. } => Ok(()),
+            PatchAction::SplitBlock { .. } => Ok(()),
+            PatchAction::UpdateBlock { .. } => Ok(()),
         }
     }
 
@@ -1007,6 +1013,8 @@ impl Automerge {
             PatchAction::SpliceText { .. } => Err(error::ApplyPatch::SpliceTextInMap),
             PatchAction::PutSeq { .. } => Err(error::ApplyPatch::PutIdxInMap),
             PatchAction::Mark { .. } => Err(error::ApplyPatch::MarkInMap),
+            PatchAction::SplitBlock { .. } => Err(error::ApplyPatch::InsertInMap),
+            PatchAction::UpdateBlock { .. } => Err(error::ApplyPatch::PutIdxInMap),
         }
     }
 
@@ -1074,6 +1082,14 @@ impl Automerge {
                 let result = before.concat(&String::from(value).into()).concat(&after);
                 Ok(result.into())
             }
+            PatchAction::SplitBlock { index, .. } => {
+                let index = *index as u32;
+                let length = string.length();
+                let before = string.slice(0, index);
+                let after = string.slice(index, length);
+                let result = before.concat(&"\u{fffc}".into()).concat(&after);
+                Ok(result.into())
+            }
             _ => Ok(string.into()),
         }
     }
@@ -1200,6 +1216,7 @@ impl Automerge {
                     None
                 }
             }
+            Some("link") => import_link(value).map(am::ScalarValue::from),
             Some("null") => Some(am::ScalarValue::Null),
             Some(_) => None,
             None => {
@@ -1251,6 +1268,31 @@ impl Automerge {
     }
 }
 
+/// Links are exported as `{doc, heads}`, with `heads` missing for a link to the latest version
+pub(crate) fn export_link(link: &am::links::Link) -> JsValue {
+    let result = Object::new();
+    Reflect::set(&result, &"doc".into(), &link.doc.as_str().into()).unwrap();
+    if let Some(heads) = &link.heads {
+        Reflect::set(&result, &"heads".into(), &JS::from(heads.clone()).0).unwrap();
+    }
+    result.into()
+}
+
+/// Imports a link from either the id of a document or an object as returned by `export_link`
+fn import_link(value: &JsValue) -> Option<am::links::Link> {
+    if let Some(doc) = value.as_string() {
+        return Some(am::links::Link::new(doc));
+    }
+    let doc = Reflect::get(value, &"doc".into()).ok()?.as_string()?;
+    let heads = Reflect::get(value, &"heads".into()).ok()?;
+    if heads.is_undefined() || heads.is_null() {
+        Some(am::links::Link::new(doc))
+    } else {
+        let heads = Vec::<ChangeHash>::try_from(JS(heads)).ok()?;
+        Some(am::links::Link::at(doc, &heads))
+    }
+}
+
 pub(crate) fn alloc(value: &Value<'_>, text_rep: TextRepresentation) -> (Datatype, JsValue) {
     match value {
         am::Value::Object(o) => match o {
@@ -1274,6 +1316,7 @@ pub(crate) fn alloc(value: &Value<'_>, text_rep: TextRepresentation) -> (Datatyp
                 js_sys::Date::new(&(*v as f64).into()).into(),
             ),
             am::ScalarValue::Boolean(v) => (Datatype::Boolean, (*v).into()),
+            am::ScalarValue::Link(l) => (Datatype::Link, export_link(l)),
             am::ScalarValue::Null => (Datatype::Null, JsValue::null()),
             am::ScalarValue::Unknown { bytes, type_code } => (
                 Datatype::Unknown(*type_code),
@@ -1451,6 +1494,18 @@ impl TryFrom<JsPatch> for JsValue {
                 js_set(&result, "path", export_path(path, &prop))?;
                 Ok(result.into())
             }
+            PatchAction::SplitBlock { index, block } => {
+                js_set(&result, "action", "splitBlock")?;
+                js_set(&result, "path", export_path(path, &Prop::Seq(index)))?;
+                js_set(&result, "value", export_block(&block)?)?;
+                Ok(result.into())
+            }
+            PatchAction::UpdateBlock { index, block } => {
+                js_set(&result, "action", "updateBlock")?;
+                js_set(&result, "path", export_path(path, &Prop::Seq(index)))?;
+                js_set(&result, "value", export_block(&block)?)?;
+                Ok(result.into())
+            }
         }
     }
 }
@@ -1467,6 +1522,23 @@ impl TryFrom<JsPatches> for Array {
     }
 }
 
+fn export_block(block: &Block) -> Result<Object, error::Export> {
+    let result = Object::new();
+    js_set(&result, "type", &block.block_type)?;
+    let parents = block.parents.iter().map(JsValue::from).collect::<Array>();
+    js_set(&result, "parents", parents)?;
+    let attrs = Object::new();
+    for (name, value) in block.attrs.iter() {
+        js_set(
+            &attrs,
+            name,
+            alloc(&value.into(), TextRepresentation::String).1,
+        )?;
+    }
+    js_set(&result, "attrs", attrs)?;
+    Ok(result)
+}
+
 fn shallow_copy(obj: &Object) -> Object {
     if Array::is_array(obj) {
         Array::from(obj).into()
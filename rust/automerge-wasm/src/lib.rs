@@ -933,7 +933,7 @@ impl Automerge {
 
         let expand = js_get(&range, "expand").ok();
         let expand = expand.map(|s| s.try_into()).transpose()?;
-        let expand = expand.unwrap_or_default();
+        let expand: am::marks::ExpandMark = expand.unwrap_or_default();
 
         let name = name.as_string().ok_or(error::Mark::InvalidName)?;
 
@@ -15,6 +15,7 @@ pub(crate) enum Datatype {
     Counter,
     Timestamp,
     Boolean,
+    Link,
     Null,
     Unknown(u8),
 }
@@ -63,6 +64,7 @@ impl From<&ScalarValue> for Datatype {
             ScalarValue::Counter(_) => Self::Counter,
             ScalarValue::Timestamp(_) => Self::Timestamp,
             ScalarValue::Boolean(_) => Self::Boolean,
+            ScalarValue::Link(_) => Self::Link,
             ScalarValue::Null => Self::Null,
             ScalarValue::Unknown { type_code, .. } => Self::Unknown(*type_code),
         }
@@ -105,6 +107,7 @@ impl From<Datatype> for String {
             Datatype::Counter => "counter".into(),
             Datatype::Timestamp => "timestamp".into(),
             Datatype::Boolean => "boolean".into(),
+            Datatype::Link => "link".into(),
             Datatype::Null => "null".into(),
             Datatype::Unknown(type_code) => format!("unknown{}", type_code),
         }
@@ -129,6 +132,7 @@ impl TryFrom<JsValue> for Datatype {
             "counter" => Ok(Datatype::Counter),
             "timestamp" => Ok(Datatype::Timestamp),
             "boolean" => Ok(Datatype::Boolean),
+            "link" => Ok(Datatype::Link),
             "null" => Ok(Datatype::Null),
             d => {
                 if d.starts_with("unknown") {
@@ -91,6 +91,14 @@ fn get_changes(_doc: &Automerge, patches: Vec<Patch>) {
                     prop, obj, path,
                 )
             }
+            PatchAction::SplitBlock { index, block } => println!(
+                "split block {:?} at {:?} in obj {:?}, object path {:?}",
+                block, index, obj, path,
+            ),
+            PatchAction::UpdateBlock { index, block } => println!(
+                "update block at {:?} to {:?} in obj {:?}, object path {:?}",
+                index, block, obj, path,
+            ),
         }
     }
 }
@@ -0,0 +1,308 @@
+//! Annotations, such as comment threads, anchored to ranges of a text object
+//!
+//! Marks are for formatting: their value is a scalar and overlapping marks with the same name are
+//! merged. An annotation instead attaches an object, for example a map holding a comment thread,
+//! to a range of text, and overlapping annotations stay separate.
+//!
+//! The annotations on a text object are kept in a list, the "store", which the application puts
+//! wherever suits its schema. Each element of the store is a map holding the range of the
+//! annotation, as a [`crate::Selection`] whose start is right sticky and whose end is left sticky,
+//! and the attached object. The range is resolved every time it is read, so it follows the text
+//! as it is edited and merged, shrinking as the annotated text is deleted.
+//!
+//! ```
+//! # use automerge::{AutoCommit, ObjType, ReadDoc, ROOT, transaction::Transactable};
+//! # use automerge::annotations;
+//! let mut doc = AutoCommit::new();
+//! let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+//! let comments = doc.put_object(ROOT, "comments", ObjType::List).unwrap();
+//! doc.splice_text(&text, 0, 0, "hello world").unwrap();
+//!
+//! let comment = annotations::add_annotation(&mut doc, &comments, &text, 6..11, ObjType::Map).unwrap();
+//! doc.put(&comment.data, "body", "which world?").unwrap();
+//!
+//! doc.splice_text(&text, 0, 0, "oh, ").unwrap();
+//! let found = annotations::annotations_in(&doc, &comments, &text, 0..12).unwrap();
+//! assert_eq!(found[0].range, 10..15);
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::exid::ExId;
+use crate::transaction::Transactable;
+use crate::{AutomergeError, ChangeHash, ObjType, Patch, ReadDoc, Selection, Value};
+
+const RANGE: &str = "range";
+const DATA: &str = "data";
+
+/// An annotation on a range of a text object
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// The map in the store which holds the annotation
+    pub id: ExId,
+    /// The object attached to the range
+    pub data: ExId,
+    /// Where the annotation currently is in the text
+    pub range: Range<usize>,
+}
+
+/// A change to an annotation between two versions of a document, see [`diff_annotations()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotationChange {
+    /// The map in the store which holds the annotation
+    pub id: ExId,
+    /// The range of the annotation before, `None` if it didn't exist
+    pub before: Option<Range<usize>>,
+    /// The range of the annotation after, `None` if it was removed
+    pub after: Option<Range<usize>>,
+}
+
+/// Annotate `range` of the text object `text`, adding the annotation to the list `store`
+///
+/// A new object of type `obj_type` is created and attached to the range, fill it in using the
+/// `data` field of the returned [`Annotation`].
+pub fn add_annotation<T: Transactable>(
+    doc: &mut T,
+    store: &ExId,
+    text: &ExId,
+    range: Range<usize>,
+    obj_type: ObjType,
+) -> Result<Annotation, AutomergeError> {
+    let selection = doc.get_selection(text, range.start, range.end, None)?;
+    let index = doc.length(store);
+    let id = doc.insert_object(store, index, ObjType::Map)?;
+    doc.put(&id, RANGE, selection.to_string())?;
+    let data = doc.put_object(&id, DATA, obj_type)?;
+    Ok(Annotation { id, data, range })
+}
+
+/// Move `annotation` to `range` of the text object `text`
+pub fn move_annotation<T: Transactable>(
+    doc: &mut T,
+    text: &ExId,
+    annotation: &ExId,
+    range: Range<usize>,
+) -> Result<(), AutomergeError> {
+    let selection = doc.get_selection(text, range.start, range.end, None)?;
+    doc.put(annotation, RANGE, selection.to_string())
+}
+
+/// Remove `annotation` from the list `store`
+pub fn remove_annotation<T: Transactable>(
+    doc: &mut T,
+    store: &ExId,
+    annotation: &ExId,
+) -> Result<(), AutomergeError> {
+    let index = doc
+        .list_range(store, ..)
+        .position(|item| &item.id == annotation);
+    match index {
+        Some(index) => doc.delete(store, index),
+        None => Ok(()),
+    }
+}
+
+/// All the annotations in the list `store` on the text object `text`, as at `at` if given
+///
+/// Elements of `store` which aren't annotations are skipped.
+pub fn annotations<R: ReadDoc>(
+    doc: &R,
+    store: &ExId,
+    text: &ExId,
+    at: Option<&[ChangeHash]>,
+) -> Result<Vec<Annotation>, AutomergeError> {
+    let mut result = Vec::new();
+    for id in candidates(doc, store, at)? {
+        if let Some(annotation) = resolve(doc, id, text, at)? {
+            result.push(annotation);
+        }
+    }
+    Ok(result)
+}
+
+/// The annotations in the list `store` which overlap `range` of the text object `text`
+///
+/// An empty annotation overlaps a range which contains it, including at either end, and an empty
+/// `range` finds the annotations containing that position.
+pub fn annotations_in<R: ReadDoc>(
+    doc: &R,
+    store: &ExId,
+    text: &ExId,
+    range: Range<usize>,
+) -> Result<Vec<Annotation>, AutomergeError> {
+    let mut found = annotations(doc, store, text, None)?;
+    found.retain(|a| overlaps(&a.range, &range));
+    Ok(found)
+}
+
+/// How the annotations in the list `store` changed between `before` and `after`
+///
+/// This reports annotations which were added or removed and those whose resolved range moved,
+/// either because the annotation was moved or because the text was edited.
+pub fn diff_annotations<R: ReadDoc>(
+    doc: &R,
+    store: &ExId,
+    text: &ExId,
+    before: &[ChangeHash],
+    after: &[ChangeHash],
+) -> Result<Vec<AnnotationChange>, AutomergeError> {
+    let old = annotations(doc, store, text, Some(before))?;
+    let new = annotations(doc, store, text, Some(after))?;
+    Ok(pair(old, new))
+}
+
+/// How the annotations in the list `store` changed between `before` and the current state of the
+/// document, given the `patches` between the two
+///
+/// This is meant to follow [`crate::AutoCommit::diff_incremental()`]: pass the diff cursor from
+/// before the call as `before` and the patches it returned. Unlike [`diff_annotations()`], which
+/// resolves the range of every annotation at both sets of heads, this uses the patches to decide
+/// which annotations could have changed. If neither the text nor the store changed nothing is
+/// resolved at all, and if only the store changed only the annotations which were added, removed
+/// or modified are.
+pub fn annotation_changes<R: ReadDoc>(
+    doc: &R,
+    store: &ExId,
+    text: &ExId,
+    before: &[ChangeHash],
+    patches: &[Patch],
+) -> Result<Vec<AnnotationChange>, AutomergeError> {
+    let mut store_changed = false;
+    let mut touched = HashSet::new();
+    for patch in patches {
+        if &patch.obj == text {
+            // every range may have moved
+            let old = annotations(doc, store, text, Some(before))?;
+            let new = annotations(doc, store, text, None)?;
+            return Ok(pair(old, new));
+        } else if &patch.obj == store {
+            store_changed = true;
+        } else if matches!(patch.path.last(), Some((parent, _)) if parent == store) {
+            touched.insert(&patch.obj);
+        }
+    }
+    if !store_changed && touched.is_empty() {
+        return Ok(Vec::new());
+    }
+    let old_ids = candidates(doc, store, Some(before))?;
+    let new_ids = candidates(doc, store, None)?;
+    let old_set = old_ids.iter().collect::<HashSet<_>>();
+    let new_set = new_ids.iter().collect::<HashSet<_>>();
+    let mut old = Vec::new();
+    for id in &old_ids {
+        if !new_set.contains(id) || touched.contains(id) {
+            old.extend(resolve(doc, id.clone(), text, Some(before))?);
+        }
+    }
+    let mut new = Vec::new();
+    for id in &new_ids {
+        if !old_set.contains(id) || touched.contains(id) {
+            new.extend(resolve(doc, id.clone(), text, None)?);
+        }
+    }
+    Ok(pair(old, new))
+}
+
+/// Pair up the annotations in `old` and `new` by id, keeping those which changed
+fn pair(old: Vec<Annotation>, new: Vec<Annotation>) -> Vec<AnnotationChange> {
+    let mut old = old
+        .into_iter()
+        .map(|a| (a.id, a.range))
+        .collect::<HashMap<_, _>>();
+    let mut changes = Vec::new();
+    for a in new {
+        let before = old.remove(&a.id);
+        if before.as_ref() != Some(&a.range) {
+            changes.push(AnnotationChange {
+                id: a.id,
+                before,
+                after: Some(a.range),
+            });
+        }
+    }
+    for (id, range) in old {
+        changes.push(AnnotationChange {
+            id,
+            before: Some(range),
+            after: None,
+        });
+    }
+    changes
+}
+
+/// The maps in `store`, each of which may be an annotation
+fn candidates<R: ReadDoc>(
+    doc: &R,
+    store: &ExId,
+    at: Option<&[ChangeHash]>,
+) -> Result<Vec<ExId>, AutomergeError> {
+    let length = match at {
+        Some(heads) => doc.length_at(store, heads),
+        None => doc.length(store),
+    };
+    let mut ids = Vec::with_capacity(length);
+    for index in 0..length {
+        if let Some((Value::Object(ObjType::Map), id)) = get(doc, store, index, at)? {
+            ids.push(id);
+        }
+    }
+    Ok(ids)
+}
+
+/// The annotation held by the map `id`, if it is one and its range is in `text`
+fn resolve<R: ReadDoc>(
+    doc: &R,
+    id: ExId,
+    text: &ExId,
+    at: Option<&[ChangeHash]>,
+) -> Result<Option<Annotation>, AutomergeError> {
+    let selection = match get(doc, &id, RANGE, at)? {
+        Some((value, _)) => value.to_str().and_then(|s| Selection::try_from(s).ok()),
+        None => None,
+    };
+    let data = match get(doc, &id, DATA, at)? {
+        Some((Value::Object(_), data)) => data,
+        _ => return Ok(None),
+    };
+    let range = match selection.map(|s| doc.get_selection_position(text, &s, at)) {
+        Some(Ok((start, end))) => start..end,
+        // the cursors aren't in this text object
+        Some(Err(AutomergeError::InvalidCursor(_))) | None => return Ok(None),
+        Some(Err(e)) => return Err(e),
+    };
+    Ok(Some(Annotation { id, data, range }))
+}
+
+fn get<'a, R: ReadDoc, P: Into<crate::Prop>>(
+    doc: &'a R,
+    obj: &ExId,
+    prop: P,
+    at: Option<&[ChangeHash]>,
+) -> Result<Option<(Value<'a>, ExId)>, AutomergeError> {
+    match at {
+        Some(heads) => doc.get_at(obj, prop, heads),
+        None => doc.get(obj, prop),
+    }
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    if a.is_empty() || b.is_empty() {
+        a.start <= b.end && b.start <= a.end
+    } else {
+        a.start < b.end && b.start < a.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_ranges() {
+        assert!(overlaps(&(0..5), &(4..6)));
+        assert!(!overlaps(&(0..5), &(5..6)));
+        assert!(overlaps(&(5..5), &(0..5)));
+        assert!(overlaps(&(2..2), &(2..2)));
+        assert!(!overlaps(&(6..6), &(0..5)));
+    }
+}
@@ -2,20 +2,27 @@ use std::ops::RangeBounds;
 
 use crate::automerge::SaveOptions;
 use crate::automerge::{current_state, diff};
+use crate::blocks::Block;
+use crate::conflicts::{Conflict, Conflicts};
 use crate::exid::ExId;
-use crate::iter::{Keys, ListRange, MapRange, Values};
-use crate::marks::{ExpandMark, Mark, MarkSet};
+use crate::history::{Log, ObjRevision, PropRevision};
+use crate::iter::{Keys, ListRange, MapRange, Spans, Values};
+use crate::marks::{ExpandMark, Mark, MarkPolicies, MarkPolicy, MarkSet};
 use crate::patches::{PatchLog, TextRepresentation};
+use crate::refs::{Ref, Refs};
+use crate::revert::Revert;
 use crate::sync::SyncDoc;
-use crate::transaction::{CommitOptions, Transactable};
+use crate::text_value::TextEncoding;
+use crate::transaction::{CommitOptions, Transactable, UpdateTextOptions};
+use crate::tree_diff::TreeDiff;
 use crate::types::Clock;
 use crate::{hydrate, OnPartialLoad};
 use crate::{sync, ObjType, Parents, Patch, ReadDoc, ScalarValue};
 use crate::{
     transaction::TransactionInner, ActorId, Automerge, AutomergeError, Change, ChangeHash, Cursor,
-    Prop, Value,
+    CursorResolution, Prop, StickyCursor, Value,
 };
-use crate::{LoadOptions, VerificationMode};
+use crate::{LoadOptions, MergePreview, VerificationMode, View};
 
 /// An automerge document that automatically manages transactions.
 ///
@@ -60,6 +67,10 @@ pub struct AutoCommit {
     diff_cursor: Vec<ChangeHash>,
     diff_cache: Option<(OpRange, Vec<Patch>)>,
     save_cursor: Vec<ChangeHash>,
+    /// The refs and mark policies as of the last save, so that [`Self::save_incremental()`] only
+    /// writes them when they have changed
+    saved_refs: Refs,
+    saved_mark_policies: MarkPolicies,
     isolation: Option<Vec<ChangeHash>>,
 }
 
@@ -75,6 +86,8 @@ impl Default for AutoCommit {
             diff_cursor: Vec::new(),
             diff_cache: None,
             save_cursor: Vec::new(),
+            saved_refs: Refs::default(),
+            saved_mark_policies: MarkPolicies::default(),
             isolation: None,
         }
     }
@@ -94,6 +107,8 @@ impl AutoCommit {
             diff_cursor: Vec::new(),
             diff_cache: None,
             save_cursor: Vec::new(),
+            saved_refs: Refs::default(),
+            saved_mark_policies: MarkPolicies::default(),
             isolation: None,
         })
     }
@@ -107,6 +122,8 @@ impl AutoCommit {
             diff_cursor: Vec::new(),
             diff_cache: None,
             save_cursor: Vec::new(),
+            saved_refs: Refs::default(),
+            saved_mark_policies: MarkPolicies::default(),
             isolation: None,
         })
     }
@@ -137,6 +154,8 @@ impl AutoCommit {
             diff_cursor: Vec::new(),
             diff_cache: None,
             save_cursor: Vec::new(),
+            saved_refs: Refs::default(),
+            saved_mark_policies: MarkPolicies::default(),
             isolation: None,
         })
     }
@@ -225,7 +244,8 @@ impl AutoCommit {
         {
             self.patch_log.make_patches(&self.doc)
         } else if range.before().is_empty() && range.after() == heads {
-            let mut patch_log = PatchLog::active(self.patch_log.text_rep());
+            let mut patch_log = PatchLog::active(self.patch_log.text_rep())
+                .with_text_encoding(self.patch_log.text_encoding());
             // This if statement is only active if the current heads are the same as `after`
             // so we don't need to tell the patch log to target a specific heads and consequently
             // it wll be able to generate patches very fast as it doesn't need to make any clocks
@@ -235,7 +255,8 @@ impl AutoCommit {
         } else {
             let before_clock = self.doc.clock_at(range.before());
             let after_clock = self.doc.clock_at(range.after());
-            let mut patch_log = PatchLog::active(self.patch_log.text_rep());
+            let mut patch_log = PatchLog::active(self.patch_log.text_rep())
+                .with_text_encoding(self.patch_log.text_encoding());
             patch_log.heads = Some(range.after().to_vec());
             diff::log_diff(&self.doc, &before_clock, &after_clock, &mut patch_log);
             patch_log.make_patches(&self.doc)
@@ -268,10 +289,13 @@ impl AutoCommit {
         Self {
             doc: self.doc.fork(),
             transaction: self.transaction.clone(),
-            patch_log: PatchLog::inactive(self.patch_log.text_rep()),
+            patch_log: PatchLog::inactive(self.patch_log.text_rep())
+                .with_text_encoding(self.patch_log.text_encoding()),
             diff_cursor: vec![],
             diff_cache: None,
             save_cursor: vec![],
+            saved_refs: Refs::default(),
+            saved_mark_policies: MarkPolicies::default(),
             isolation: None,
         }
     }
@@ -281,14 +305,105 @@ impl AutoCommit {
         Ok(Self {
             doc: self.doc.fork_at(heads)?,
             transaction: self.transaction.clone(),
-            patch_log: PatchLog::inactive(self.patch_log.text_rep()),
+            patch_log: PatchLog::inactive(self.patch_log.text_rep())
+                .with_text_encoding(self.patch_log.text_encoding()),
             diff_cursor: vec![],
             diff_cache: None,
             save_cursor: vec![],
+            saved_refs: Refs::default(),
+            saved_mark_policies: MarkPolicies::default(),
             isolation: None,
         })
     }
 
+    /// Save a document holding only the state of this one at `heads`, as a single change by
+    /// `actor`, see [`Automerge::snapshot_at()`]
+    pub fn save_snapshot_at(
+        &self,
+        heads: &[ChangeHash],
+        actor: ActorId,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        self.doc.save_snapshot_at(heads, actor)
+    }
+
+    /// Create a branch called `name` at the current heads, see [`crate::refs`]
+    pub fn create_branch(&mut self, name: &str) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.create_branch(name)
+    }
+
+    /// Create a branch called `name` at `heads`
+    pub fn create_branch_at(
+        &mut self,
+        name: &str,
+        heads: &[ChangeHash],
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.create_branch_at(name, heads)
+    }
+
+    /// Create a tag called `name` at the current heads
+    pub fn create_tag(&mut self, name: &str) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.create_tag(name)
+    }
+
+    /// Create a tag called `name` at `heads`
+    pub fn create_tag_at(
+        &mut self,
+        name: &str,
+        heads: &[ChangeHash],
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.create_tag_at(name, heads)
+    }
+
+    /// Delete the branch or tag called `name`
+    pub fn delete_ref(&mut self, name: &str) -> Result<(), AutomergeError> {
+        self.doc.delete_ref(name)
+    }
+
+    /// The branch or tag called `name`, if there is one
+    pub fn get_ref(&self, name: &str) -> Option<Ref> {
+        self.doc.get_ref(name)
+    }
+
+    /// All the branches and tags in this document
+    pub fn refs(&self) -> &Refs {
+        self.doc.refs()
+    }
+
+    /// Fork this document at the heads of the ref called `name`, see [`Automerge::checkout()`]
+    pub fn checkout(&mut self, name: &str) -> Result<Self, AutomergeError> {
+        self.ensure_transaction_closed();
+        Ok(Self {
+            doc: self.doc.checkout(name)?,
+            transaction: None,
+            patch_log: PatchLog::inactive(self.patch_log.text_rep())
+                .with_text_encoding(self.patch_log.text_encoding()),
+            diff_cursor: vec![],
+            diff_cache: None,
+            save_cursor: vec![],
+            saved_refs: Refs::default(),
+            saved_mark_policies: MarkPolicies::default(),
+            isolation: None,
+        })
+    }
+
+    /// The branch this document was checked out from, if any
+    pub fn checked_out_branch(&self) -> Option<&str> {
+        self.doc.checked_out_branch()
+    }
+
+    /// Merge the branch `from` into the branch `into`, see [`Automerge::merge_branch()`]
+    pub fn merge_branch(
+        &mut self,
+        from: &str,
+        into: &str,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        self.doc.merge_branch(from, into)
+    }
+
     /// Get the inner document.
     #[doc(hidden)]
     pub fn document(&mut self) -> &Automerge {
@@ -387,13 +502,44 @@ impl AutoCommit {
         }
     }
 
-    /// Save the entirety of this document in a compact form.
+    /// The patches and conflicts which [`Self::merge()`] would produce, without modifying this
+    /// document, see [`Automerge::preview_merge()`]
+    pub fn preview_merge(
+        &mut self,
+        other: &mut AutoCommit,
+    ) -> Result<MergePreview, AutomergeError> {
+        self.ensure_transaction_closed();
+        other.ensure_transaction_closed();
+        let patch_log = PatchLog::active(self.patch_log.text_rep())
+            .with_text_encoding(self.patch_log.text_encoding());
+        self.doc.preview_merge_log_patches(&other.doc, patch_log)
+    }
+
+    /// The patches and conflicts which [`Self::apply_changes()`] would produce, without modifying
+    /// this document
+    pub fn preview_changes(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+    ) -> Result<MergePreview, AutomergeError> {
+        self.ensure_transaction_closed();
+        let patch_log = PatchLog::active(self.patch_log.text_rep())
+            .with_text_encoding(self.patch_log.text_encoding());
+        self.doc.preview_changes_log_patches(changes, patch_log)
+    }
+
+    /// Save the entirety of this document in a compact form, see [`Automerge::save()`]
     pub fn save(&mut self) -> Vec<u8> {
         self.save_with_options(SaveOptions::default())
     }
 
     pub fn save_with_options(&mut self, options: SaveOptions) -> Vec<u8> {
         self.ensure_transaction_closed();
+        if options.refs {
+            self.saved_refs = self.doc.refs().clone();
+        }
+        if options.mark_policies {
+            self.saved_mark_policies = self.doc.mark_policies().clone();
+        }
         let bytes = self.doc.save_with_options(options);
         if !bytes.is_empty() {
             self.save_cursor = self.doc.get_heads()
@@ -422,12 +568,23 @@ impl AutoCommit {
     /// changes. This is useful if you know you have only made a small change since the last [`Self::save()`]
     /// and you want to immediately send it somewhere (e.g. you've inserted a single character in a
     /// text object).
+    ///
+    /// If the refs or mark policies have changed since the last save they follow the changes, so
+    /// that concatenating the output of every save loses nothing.
     pub fn save_incremental(&mut self) -> Vec<u8> {
         self.ensure_transaction_closed();
-        let bytes = self.doc.save_after(&self.save_cursor);
+        let mut bytes = self.doc.save_after(&self.save_cursor);
         if !bytes.is_empty() {
             self.save_cursor = self.doc.get_heads()
         }
+        if self.doc.refs() != &self.saved_refs {
+            bytes.extend(self.doc.refs().encode_chunk());
+            self.saved_refs = self.doc.refs().clone();
+        }
+        if self.doc.mark_policies() != &self.saved_mark_policies {
+            bytes.extend(self.doc.mark_policies().encode_chunk());
+            self.saved_mark_policies = self.doc.mark_policies().clone();
+        }
         bytes
     }
 
@@ -458,6 +615,25 @@ impl AutoCommit {
         self.doc.get_change_by_hash(hash)
     }
 
+    /// The differences between the document at `before` and at `after`, see
+    /// [`Automerge::tree_diff()`]
+    pub fn tree_diff(&self, before: &[ChangeHash], after: &[ChangeHash]) -> TreeDiff {
+        self.doc.tree_diff(before, after)
+    }
+
+    /// A read only view of this document as at `heads`, see [`Automerge::view_at()`]
+    ///
+    /// The view doesn't include the changes in the current transaction.
+    pub fn view_at(&self, heads: &[ChangeHash]) -> View<'_> {
+        self.doc.view_at(heads)
+    }
+
+    /// The metadata of the changes in this document, see [`Automerge::log()`]
+    pub fn log(&mut self) -> Log<'_> {
+        self.ensure_transaction_closed();
+        self.doc.log()
+    }
+
     /// Get changes in `other` that are not in `self`
     pub fn get_changes_added<'a>(&mut self, other: &'a mut Self) -> Vec<&'a Change> {
         self.ensure_transaction_closed();
@@ -517,6 +693,35 @@ impl AutoCommit {
         self
     }
 
+    /// Set the unit in which positions in text objects are measured in patches, see
+    /// [`TextEncoding`]
+    pub fn set_text_encoding(&mut self, encoding: TextEncoding) {
+        self.patch_log.set_text_encoding(encoding)
+    }
+
+    pub fn get_text_encoding(&self) -> TextEncoding {
+        self.patch_log.text_encoding()
+    }
+
+    pub fn with_text_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.patch_log.set_text_encoding(encoding);
+        self
+    }
+
+    /// Set the [`MarkPolicy`] for marks named `name`, see [`Automerge::set_mark_policy()`]
+    pub fn set_mark_policy<S: AsRef<str>>(&mut self, name: S, policy: MarkPolicy) {
+        self.doc.set_mark_policy(name, policy)
+    }
+
+    pub fn mark_policies(&self) -> &MarkPolicies {
+        self.doc.mark_policies()
+    }
+
+    /// Remove the [`MarkPolicy`] for marks named `name`, see [`Automerge::remove_mark_policy()`]
+    pub fn remove_mark_policy(&mut self, name: &str) {
+        self.doc.remove_mark_policy(name)
+    }
+
     /// Commit any uncommitted changes
     ///
     /// Returns [`None`] if there were no operations to commit
@@ -741,6 +946,31 @@ impl ReadDoc for AutoCommit {
         self.doc.text_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn convert_text_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        from: TextEncoding,
+        to: TextEncoding,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .convert_text_index_for(obj.as_ref(), index, from, to, self.get_scope(at))
+    }
+
+    fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
+        self.doc.spans_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn spans_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Spans<'_>, AutomergeError> {
+        self.doc
+            .spans_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
     fn get_cursor<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -756,9 +986,34 @@ impl ReadDoc for AutoCommit {
         obj: O,
         address: &Cursor,
         at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc.get_cursor_position_for(
+            obj.as_ref(),
+            address,
+            CursorResolution::Following,
+            self.get_scope(at),
+        )
+    }
+
+    fn get_cursor_position_with_resolution<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        resolution: CursorResolution,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .get_cursor_position_for(obj.as_ref(), cursor, resolution, self.get_scope(at))
+    }
+
+    fn get_sticky_cursor_position<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &StickyCursor,
+        at: Option<&[ChangeHash]>,
     ) -> Result<usize, AutomergeError> {
         self.doc
-            .get_cursor_position_for(obj.as_ref(), address, self.get_scope(at))
+            .get_sticky_cursor_position_for(obj.as_ref(), cursor, self.get_scope(at))
     }
 
     fn get<O: AsRef<ExId>, P: Into<Prop>>(
@@ -799,6 +1054,36 @@ impl ReadDoc for AutoCommit {
             .get_all_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
     }
 
+    fn conflicts<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Conflict<'_>>, AutomergeError> {
+        self.doc.conflicts_for(obj.as_ref(), self.get_scope(at))
+    }
+
+    fn all_conflicts(&self, at: Option<&[ChangeHash]>) -> Conflicts<'_> {
+        Conflicts::new(&self.doc, self.get_scope(at))
+    }
+
+    fn history_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<ObjRevision<'_>>, AutomergeError> {
+        self.doc.history_of_for(obj.as_ref(), self.get_scope(at))
+    }
+
+    fn history_of_prop<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<PropRevision<'_>>, AutomergeError> {
+        self.doc
+            .history_of_prop_for(obj.as_ref(), prop.into(), self.get_scope(at))
+    }
+
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
         self.doc.get_missing_deps(heads)
     }
@@ -881,6 +1166,33 @@ impl Transactable for AutoCommit {
         tx.delete(&mut self.doc, patch_log, obj.as_ref(), prop)
     }
 
+    fn resolve_conflict<O: AsRef<ExId>, P: Into<Prop>>(
+        &mut self,
+        obj: O,
+        prop: P,
+        winner: &ExId,
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.resolve_conflict(&mut self.doc, patch_log, obj.as_ref(), prop, winner)
+    }
+
+    fn revert(&mut self, change: ChangeHash) -> Result<Revert, AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        crate::revert::revert(tx, &mut self.doc, patch_log, change, None)
+    }
+
+    fn revert_range(
+        &mut self,
+        change: ChangeHash,
+        heads: &[ChangeHash],
+    ) -> Result<Revert, AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        crate::revert::revert(tx, &mut self.doc, patch_log, change, Some(heads))
+    }
+
     /// Splice new elements into the given sequence. Returns a vector of the OpIds used to insert
     /// the new elements
     fn splice<O: AsRef<ExId>, V: IntoIterator<Item = ScalarValue>>(
@@ -908,24 +1220,52 @@ impl Transactable for AutoCommit {
         Ok(())
     }
 
-    fn mark<O: AsRef<ExId>>(
+    fn mark<O: AsRef<ExId>, E: Into<Option<ExpandMark>>>(
         &mut self,
         obj: O,
         mark: Mark<'_>,
-        expand: ExpandMark,
+        expand: E,
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.mark(&mut self.doc, patch_log, obj.as_ref(), mark, expand.into())
+    }
+
+    fn split_block<O: AsRef<ExId>>(
+        &mut self,
+        text: O,
+        index: usize,
+        block: Block,
+    ) -> Result<ExId, AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.split_block(&mut self.doc, patch_log, text.as_ref(), index, &block)
+    }
+
+    fn join_block<O: AsRef<ExId>>(&mut self, text: O, index: usize) -> Result<(), AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.join_block(&mut self.doc, patch_log, text.as_ref(), index)
+    }
+
+    fn update_block<O: AsRef<ExId>>(
+        &mut self,
+        text: O,
+        index: usize,
+        block: Block,
     ) -> Result<(), AutomergeError> {
         self.ensure_transaction_open();
         let (patch_log, tx) = self.transaction.as_mut().unwrap();
-        tx.mark(&mut self.doc, patch_log, obj.as_ref(), mark, expand)
+        tx.update_block(&mut self.doc, patch_log, text.as_ref(), index, &block)
     }
 
-    fn unmark<O: AsRef<ExId>>(
+    fn unmark<O: AsRef<ExId>, E: Into<Option<ExpandMark>>>(
         &mut self,
         obj: O,
         key: &str,
         start: usize,
         end: usize,
-        expand: ExpandMark,
+        expand: E,
     ) -> Result<(), AutomergeError> {
         self.ensure_transaction_open();
         let (patch_log, tx) = self.transaction.as_mut().unwrap();
@@ -936,7 +1276,7 @@ impl Transactable for AutoCommit {
             key,
             start,
             end,
-            expand,
+            expand.into(),
         )
     }
 
@@ -948,14 +1288,15 @@ impl Transactable for AutoCommit {
         }
     }
 
-    fn update_text<S: AsRef<str>>(
+    fn update_text_with_options<S: AsRef<str>>(
         &mut self,
         obj: &ExId,
         new_text: S,
+        options: UpdateTextOptions,
     ) -> Result<(), AutomergeError> {
         self.ensure_transaction_open();
         let (patch_log, tx) = self.transaction.as_mut().unwrap();
-        crate::text_diff::myers_diff(&mut self.doc, tx, patch_log, obj, new_text)
+        crate::text_diff::update_text(&mut self.doc, tx, patch_log, obj, new_text, options)
     }
 }
 
@@ -1003,6 +1344,40 @@ impl<'a> SyncDoc for SyncWrapper<'a> {
             .doc
             .receive_sync_message_log_patches(sync_state, message, patch_log)
     }
+
+    fn generate_sync_message_with_policy(
+        &self,
+        sync_state: &mut sync::State,
+        policy: &dyn sync::SyncPolicy,
+    ) -> Option<sync::Message> {
+        self.inner
+            .doc
+            .generate_sync_message_with_policy(sync_state, policy)
+    }
+
+    fn receive_sync_message_with_policy(
+        &mut self,
+        sync_state: &mut sync::State,
+        message: sync::Message,
+        policy: &dyn sync::SyncPolicy,
+    ) -> Result<Vec<sync::RejectedChange>, AutomergeError> {
+        self.inner.ensure_transaction_closed();
+        if self.inner.isolation.is_some() {
+            self.inner.doc.receive_sync_message_inner(
+                sync_state,
+                message,
+                &mut PatchLog::null(),
+                Some(policy),
+            )
+        } else {
+            self.inner.doc.receive_sync_message_inner(
+                sync_state,
+                message,
+                &mut self.inner.patch_log,
+                Some(policy),
+            )
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -7,27 +7,39 @@ use std::ops::RangeBounds;
 use itertools::Itertools;
 
 use crate::change_graph::ChangeGraph;
+use crate::clock::ClockData;
 use crate::columnar::Key as EncodedKey;
+use crate::conflicts::{Conflict, Conflicts, Contender};
 use crate::exid::ExId;
-use crate::iter::{Keys, ListRange, MapRange, Values};
-use crate::marks::{Mark, MarkAccumulator, MarkSet, MarkStateMachine};
-use crate::op_set::{OpSet, OpSetData};
+use crate::history::{Log, ObjRevision, PropRevision, Revision};
+use crate::iter::{Keys, ListRange, MapRange, Spans, Values};
+use crate::marks::{Mark, MarkAccumulator, MarkPolicies, MarkPolicy, MarkSet, MarkStateMachine};
+use crate::op_set::{OpIdx, OpSet, OpSetData};
 use crate::parents::Parents;
-use crate::patches::{Patch, PatchLog, TextRepresentation};
-use crate::query;
+use crate::patches::{Patch, PatchAction, PatchLog, TextRepresentation};
+use crate::query::{self, OpIdSearch};
+use crate::refs::{Ref, RefKind, Refs};
 use crate::storage::{self, load, CompressConfig, VerificationMode};
 use crate::transaction::{
     self, CommitOptions, Failure, Success, Transactable, Transaction, TransactionArgs,
 };
+use crate::tree_diff::TreeDiff;
 use crate::types::{
-    ActorId, ChangeHash, Clock, ElemId, Export, Exportable, Key, MarkData, ObjId, ObjMeta,
-    OpBuilder, OpId, OpIds, OpType, Value,
+    ActorId, ChangeHash, Clock, ElemId, Export, Exportable, Key, ListEncoding, MarkData, ObjId,
+    ObjMeta, Op, OpBuilder, OpId, OpIds, OpType, Value,
+};
+use crate::{hydrate, ScalarValue, TextEncoding};
+use crate::{
+    AutomergeError, Change, Cursor, CursorResolution, ObjType, Prop, ReadDoc, Stickiness,
+    StickyCursor,
 };
-use crate::{hydrate, ScalarValue};
-use crate::{AutomergeError, Change, Cursor, ObjType, Prop, ReadDoc};
 
 pub(crate) mod current_state;
 pub(crate) mod diff;
+mod snapshot;
+mod view;
+
+pub use view::View;
 
 #[cfg(test)]
 mod tests;
@@ -183,6 +195,12 @@ pub struct Automerge {
     actor: Actor,
     /// The maximum operation counter this document has seen.
     max_op: u64,
+    /// How marks are applied when no expansion is given
+    mark_policies: MarkPolicies,
+    /// Named branches and tags
+    refs: Refs,
+    /// The branch which local changes are added to, set by [`Self::checkout()`]
+    checked_out: Option<String>,
 }
 
 impl Automerge {
@@ -198,6 +216,9 @@ impl Automerge {
             deps: Default::default(),
             actor: Actor::Unused(ActorId::random()),
             max_op: 0,
+            mark_policies: MarkPolicies::default(),
+            refs: Refs::default(),
+            checked_out: None,
         }
     }
 
@@ -492,10 +513,275 @@ impl Automerge {
         }
         let mut f = Self::new();
         f.set_actor(ActorId::random());
+        f.mark_policies = self.mark_policies.clone();
         f.apply_changes(changes.into_iter().rev().cloned())?;
         Ok(f)
     }
 
+    /// A new document holding only the state of this one at `heads`
+    ///
+    /// Unlike [`Self::fork_at()`], which keeps every change up to `heads`, the snapshot has a
+    /// single change by `actor` which recreates the visible state at `heads` from scratch. Deleted
+    /// values, overwritten and conflicting values and the actors and timestamps of the original
+    /// changes are all left behind. Branches and tags aren't copied either. The snapshot shares no
+    /// history with this document, so the two can't usefully be merged.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::InvalidHash`] if any of `heads` is not in the document
+    pub fn snapshot_at(
+        &self,
+        heads: &[ChangeHash],
+        actor: ActorId,
+    ) -> Result<Self, AutomergeError> {
+        if let Some(hash) = heads.iter().find(|h| !self.history_index.contains_key(h)) {
+            return Err(AutomergeError::InvalidHash(*hash));
+        }
+        let view = self.view_at(heads);
+        let mut f = Self::new().with_actor(actor);
+        f.mark_policies = self.mark_policies.clone();
+        let mut tx = f.transaction();
+        snapshot::copy_object(&view, &ExId::Root, &mut tx, &ExId::Root, ObjType::Map)?;
+        tx.commit();
+        Ok(f)
+    }
+
+    /// Save a snapshot of this document at `heads`, see [`Self::snapshot_at()`]
+    ///
+    /// This is intended for publishing a read only copy of a document without including any of
+    /// its edit history.
+    ///
+    /// ```
+    /// # use automerge::{ActorId, Automerge, AutoCommit, ReadDoc, ROOT, transaction::Transactable};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "draft", "not for publishing").unwrap();
+    /// doc.commit();
+    /// doc.delete(ROOT, "draft").unwrap();
+    /// doc.put(ROOT, "title", "Published").unwrap();
+    /// let heads = doc.get_heads();
+    ///
+    /// let bytes = doc.save_snapshot_at(&heads, ActorId::from(b"publisher")).unwrap();
+    /// let published = Automerge::load(&bytes).unwrap();
+    /// assert_eq!(published.get_changes(&[]).len(), 1);
+    /// assert_eq!(published.keys(ROOT).collect::<Vec<_>>(), vec!["title"]);
+    /// ```
+    pub fn save_snapshot_at(
+        &self,
+        heads: &[ChangeHash],
+        actor: ActorId,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        Ok(self.snapshot_at(heads, actor)?.save())
+    }
+
+    /// Create a branch called `name` at the current heads, see [`crate::refs`]
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::RefExists`] if there is already a ref called `name`
+    pub fn create_branch(&mut self, name: &str) -> Result<(), AutomergeError> {
+        self.create_branch_at(name, &self.get_heads())
+    }
+
+    /// Create a branch called `name` at `heads`
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::RefExists`] if there is already a ref called `name` and
+    /// [`AutomergeError::InvalidHash`] if any of `heads` is not in the document
+    pub fn create_branch_at(
+        &mut self,
+        name: &str,
+        heads: &[ChangeHash],
+    ) -> Result<(), AutomergeError> {
+        self.create_ref(name, RefKind::Branch, heads)
+    }
+
+    /// Create a tag called `name` at the current heads
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::RefExists`] if there is already a ref called `name`
+    pub fn create_tag(&mut self, name: &str) -> Result<(), AutomergeError> {
+        self.create_tag_at(name, &self.get_heads())
+    }
+
+    /// Create a tag called `name` at `heads`
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::RefExists`] if there is already a ref called `name` and
+    /// [`AutomergeError::InvalidHash`] if any of `heads` is not in the document
+    pub fn create_tag_at(
+        &mut self,
+        name: &str,
+        heads: &[ChangeHash],
+    ) -> Result<(), AutomergeError> {
+        self.create_ref(name, RefKind::Tag, heads)
+    }
+
+    fn create_ref(
+        &mut self,
+        name: &str,
+        kind: RefKind,
+        heads: &[ChangeHash],
+    ) -> Result<(), AutomergeError> {
+        if self.refs.get(name).is_some() {
+            return Err(AutomergeError::RefExists(name.to_string()));
+        }
+        if let Some(missing) = heads.iter().find(|h| !self.history_index.contains_key(h)) {
+            return Err(AutomergeError::InvalidHash(*missing));
+        }
+        let mut heads = heads.to_vec();
+        heads.sort();
+        heads.dedup();
+        let actor = self.get_actor().clone();
+        self.refs.set(name, kind, Some(heads), &actor);
+        Ok(())
+    }
+
+    /// Delete the branch or tag called `name`
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingRef`] if there is no ref called `name`
+    pub fn delete_ref(&mut self, name: &str) -> Result<(), AutomergeError> {
+        let existing = self
+            .refs
+            .get(name)
+            .ok_or_else(|| AutomergeError::MissingRef(name.to_string()))?;
+        let actor = self.get_actor().clone();
+        self.refs.set(name, existing.kind, None, &actor);
+        Ok(())
+    }
+
+    /// The branch or tag called `name`, if there is one
+    pub fn get_ref(&self, name: &str) -> Option<Ref> {
+        self.refs.get(name)
+    }
+
+    /// All the branches and tags in this document
+    pub fn refs(&self) -> &Refs {
+        &self.refs
+    }
+
+    /// Fork this document at the heads of the ref called `name`
+    ///
+    /// If `name` is a branch then committing changes to the fork moves the branch in the fork to
+    /// the new change, and merging the fork back into this document moves it here too.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingRef`] if there is no ref called `name`
+    pub fn checkout(&self, name: &str) -> Result<Self, AutomergeError> {
+        let r = self
+            .refs
+            .get(name)
+            .ok_or_else(|| AutomergeError::MissingRef(name.to_string()))?;
+        let mut f = self.fork_at(&r.heads)?;
+        f.refs = self.refs.clone();
+        if r.kind == RefKind::Branch {
+            f.checked_out = Some(name.to_string());
+        }
+        Ok(f)
+    }
+
+    /// The branch this document was checked out from, if any
+    pub fn checked_out_branch(&self) -> Option<&str> {
+        self.checked_out.as_deref()
+    }
+
+    /// Merge the branch `from` into the branch `into`
+    ///
+    /// Both branches are already in this document, so this only moves `into` to the heads of
+    /// both branches together. Returns the new heads of `into`.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingRef`] if either ref doesn't exist and
+    /// [`AutomergeError::RefIsTag`] if `into` is a tag
+    pub fn merge_branch(
+        &mut self,
+        from: &str,
+        into: &str,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        let theirs = self
+            .refs
+            .get(from)
+            .ok_or_else(|| AutomergeError::MissingRef(from.to_string()))?;
+        let ours = self
+            .refs
+            .get(into)
+            .ok_or_else(|| AutomergeError::MissingRef(into.to_string()))?;
+        if ours.kind == RefKind::Tag {
+            return Err(AutomergeError::RefIsTag(into.to_string()));
+        }
+        let mut candidates = ours.heads;
+        candidates.extend(theirs.heads);
+        candidates.sort();
+        candidates.dedup();
+        // drop any head which is an ancestor of another
+        let heads = candidates
+            .iter()
+            .filter(|head| {
+                candidates.iter().all(|other| {
+                    other == *head || !self.change_graph.ancestors(&[*other]).contains(head)
+                })
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        let actor = self.get_actor().clone();
+        self.refs
+            .set(into, RefKind::Branch, Some(heads.clone()), &actor);
+        Ok(heads)
+    }
+
+    /// Move the checked out branch, if there is one, to a change which was just committed
+    pub(crate) fn advance_checked_out_branch(&mut self, hash: ChangeHash) {
+        let branch = self
+            .checked_out
+            .as_ref()
+            .and_then(|name| self.refs.get(name))
+            .filter(|r| r.kind == RefKind::Branch);
+        if let Some(branch) = branch {
+            let actor = self.get_actor().clone();
+            self.refs
+                .set(&branch.name, RefKind::Branch, Some(vec![hash]), &actor);
+        }
+    }
+
+    /// Merge refs received from another peer into this document
+    pub(crate) fn merge_refs(&mut self, other: &Refs) -> bool {
+        self.refs.merge(other)
+    }
+
+    /// Set the [`MarkPolicy`] for marks named `name`
+    ///
+    /// [`crate::transaction::Transactable::mark()`] and
+    /// [`crate::transaction::Transactable::unmark()`] follow the policy for the mark they are given
+    /// and use its [`crate::marks::ExpandMark`] when they aren't given one. The policy is saved
+    /// and synced with the document, see [`MarkPolicies`]. Setting the policy a name already has
+    /// does nothing.
+    pub fn set_mark_policy<S: AsRef<str>>(&mut self, name: S, policy: MarkPolicy) {
+        let actor = self.get_actor().clone();
+        self.mark_policies.set(name.as_ref(), Some(policy), &actor);
+    }
+
+    /// Remove the [`MarkPolicy`] for marks named `name`, so they use [`MarkPolicy::default()`]
+    pub fn remove_mark_policy(&mut self, name: &str) {
+        let actor = self.get_actor().clone();
+        self.mark_policies.set(name, None, &actor);
+    }
+
+    /// The [`MarkPolicy`] for each mark name registered with this document
+    pub fn mark_policies(&self) -> &MarkPolicies {
+        &self.mark_policies
+    }
+
+    /// Merge mark policies received from another peer into this document
+    pub(crate) fn merge_mark_policies(&mut self, other: &MarkPolicies) -> bool {
+        self.mark_policies.merge(other)
+    }
+
     pub(crate) fn exid_to_opid(&self, id: &ExId) -> Result<OpId, AutomergeError> {
         match id {
             ExId::Root => Ok(OpId::new(0, 0)),
@@ -647,9 +933,25 @@ impl Automerge {
                 );
                 Self::new()
             }
+            storage::Chunk::Refs(refs, _) => {
+                tracing::trace!("first chunk is refs chunk");
+                let mut am = Self::new();
+                am.refs = refs;
+                am
+            }
+            storage::Chunk::MarkPolicies(policies, _) => {
+                tracing::trace!("first chunk is mark policies chunk");
+                let mut am = Self::new();
+                am.mark_policies = policies;
+                am
+            }
         };
         tracing::trace!("loading change chunks");
-        match load::load_changes(remaining.reset()) {
+        match load::load_changes_and_metadata(
+            remaining.reset(),
+            &mut am.refs,
+            &mut am.mark_policies,
+        ) {
             load::LoadedChanges::Complete(c) => {
                 am.apply_changes(change.into_iter().chain(c))?;
                 // Only allow missing deps if the first chunk was a document chunk
@@ -725,13 +1027,20 @@ impl Automerge {
                     .verification_mode(VerificationMode::Check),
             )?;
             doc = doc.with_actor(self.actor_id());
+            doc.refs.merge(&self.refs);
+            doc.mark_policies.merge(&self.mark_policies);
+            doc.checked_out = self.checked_out.take();
             if patch_log.is_active() {
                 current_state::log_current_state_patches(&doc, patch_log);
             }
             *self = doc;
             return Ok(self.ops.len());
         }
-        let changes = match load::load_changes(storage::parse::Input::new(data)) {
+        let changes = match load::load_changes_and_metadata(
+            storage::parse::Input::new(data),
+            &mut self.refs,
+            &mut self.mark_policies,
+        ) {
             load::LoadedChanges::Complete(c) => c,
             load::LoadedChanges::Partial { error, loaded, .. } => {
                 tracing::warn!(successful_chunks=loaded.len(), err=?error, "partial load");
@@ -911,9 +1220,147 @@ impl Automerge {
             .collect::<Vec<_>>();
         tracing::trace!(changes=?changes.iter().map(|c| c.hash()).collect::<Vec<_>>(), "merging new changes");
         self.apply_changes_log_patches(changes, patch_log)?;
+        self.refs.merge(&other.refs);
+        self.mark_policies.merge(&other.mark_policies);
         Ok(self.get_heads())
     }
 
+    /// The patches and conflicts which [`Self::merge()`] would produce, without modifying this
+    /// document
+    ///
+    /// If `other` already has every change in this document the patches are computed from the
+    /// history of `other`. If the documents have diverged the changes are applied to this
+    /// document and rolled back as in [`Self::preview_changes()`].
+    pub fn preview_merge(
+        &mut self,
+        other: &Self,
+        text_rep: TextRepresentation,
+    ) -> Result<MergePreview, AutomergeError> {
+        self.preview_merge_log_patches(other, PatchLog::active(text_rep))
+    }
+
+    /// Like [`Self::preview_merge()`] but with the text representation and encoding of
+    /// `patch_log`
+    pub(crate) fn preview_merge_log_patches(
+        &mut self,
+        other: &Self,
+        mut patch_log: PatchLog,
+    ) -> Result<MergePreview, AutomergeError> {
+        let changes = self.get_changes_added(other);
+        if changes.is_empty() {
+            return Ok(MergePreview {
+                patches: Vec::new(),
+                heads: self.get_heads(),
+                changes: Vec::new(),
+                missing_deps: self.get_missing_deps(&[]),
+            });
+        }
+        let contains_self = self
+            .deps
+            .iter()
+            .all(|head| other.history_index.contains_key(head));
+        if !contains_self || !self.queue.is_empty() {
+            return self.preview_changes_log_patches(changes.into_iter().cloned(), patch_log);
+        }
+        let heads = other.get_heads();
+        let before = other.clock_at(&self.get_heads());
+        let after = other.clock_at(&heads);
+        diff::log_diff(other, &before, &after, &mut patch_log);
+        patch_log.heads = Some(heads.clone());
+        Ok(MergePreview {
+            patches: patch_log.make_patches(other),
+            heads,
+            changes: changes.iter().map(|c| c.hash()).collect(),
+            missing_deps: Vec::new(),
+        })
+    }
+
+    /// The patches and conflicts which [`Self::apply_changes()`] would produce, without modifying
+    /// this document
+    ///
+    /// The changes are applied to this document to compute the patches and then rolled back, so
+    /// this costs about twice as much as applying them. The document is left as it was even if
+    /// applying the changes fails.
+    pub fn preview_changes(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+        text_rep: TextRepresentation,
+    ) -> Result<MergePreview, AutomergeError> {
+        self.preview_changes_log_patches(changes, PatchLog::active(text_rep))
+    }
+
+    /// Like [`Self::preview_changes()`] but with the text representation and encoding of
+    /// `patch_log`
+    pub(crate) fn preview_changes_log_patches(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+        mut patch_log: PatchLog,
+    ) -> Result<MergePreview, AutomergeError> {
+        let checkpoint = Checkpoint::new(self);
+        let preview = self
+            .apply_changes_log_patches(changes, &mut patch_log)
+            .map(|()| MergePreview {
+                patches: self.make_patches(&mut patch_log),
+                heads: self.get_heads(),
+                changes: self.history[checkpoint.history..]
+                    .iter()
+                    .map(|c| c.hash())
+                    .collect(),
+                missing_deps: self.get_missing_deps(&[]),
+            });
+        self.rollback_to(checkpoint);
+        preview
+    }
+
+    /// Undo everything applied since `checkpoint` was taken
+    fn rollback_to(&mut self, checkpoint: Checkpoint) {
+        // remove in reverse order so that each op is removed before its predecessors
+        let encoding = ListEncoding::List;
+        for i in (checkpoint.ops.0..self.ops.osd.len().0).rev() {
+            let op = OpIdx::new(i).as_op(&self.ops.osd);
+            let (obj, id) = (*op.obj(), *op.id());
+            let pred = op.pred().map(|op| *op.id()).collect::<Vec<_>>();
+            for pred_id in pred {
+                if let Some(p) = self
+                    .ops
+                    .search(&obj, OpIdSearch::opid(pred_id, encoding, None))
+                    .found()
+                {
+                    self.ops.remove_succ(&obj, p, OpIdx::new(i));
+                }
+            }
+            if let Some(pos) = self
+                .ops
+                .search(&obj, OpIdSearch::opid(id, encoding, None))
+                .found()
+            {
+                self.ops.remove(&obj, pos);
+            }
+        }
+        self.ops.osd.truncate(checkpoint.ops);
+        for change in self.history.drain(checkpoint.history..) {
+            self.history_index.remove(&change.hash());
+            if let Some(actor) = self.ops.osd.actors.lookup(change.actor_id()) {
+                if let Some(states) = self.states.get_mut(&actor) {
+                    states.pop();
+                    if states.is_empty() {
+                        self.states.remove(&actor);
+                    }
+                }
+            }
+        }
+        self.change_graph.truncate(checkpoint.history);
+        while self.ops.osd.actors.len() > checkpoint.actors {
+            self.ops.osd.actors.remove_last();
+        }
+        while self.ops.osd.props.len() > checkpoint.props {
+            self.ops.osd.props.remove_last();
+        }
+        self.deps = checkpoint.deps;
+        self.queue = checkpoint.queue;
+        self.max_op = checkpoint.max_op;
+    }
+
     /// Save the entirety of this document in a compact form.
     pub fn save_with_options(&self, options: SaveOptions) -> Vec<u8> {
         let heads = self.get_heads();
@@ -936,10 +1383,19 @@ impl Automerge {
                 bytes.extend(orphaned.raw_bytes());
             }
         }
+        if options.refs && !self.refs.is_empty() {
+            bytes.extend(self.refs.encode_chunk());
+        }
+        if options.mark_policies && !self.mark_policies.is_empty() {
+            bytes.extend(self.mark_policies.encode_chunk());
+        }
         bytes
     }
 
     /// Save the entirety of this document in a compact form.
+    ///
+    /// If the document has refs they are saved in a chunk after the document which versions of
+    /// automerge from before refs were added can't load, see [`SaveOptions::refs`].
     pub fn save(&self) -> Vec<u8> {
         self.save_with_options(SaveOptions::default())
     }
@@ -1319,30 +1775,188 @@ impl Automerge {
             ExId::Root => None,
             ExId::Id(..) => {
                 let opid = self.exid_to_opid(exid).ok()?;
-                let actor_indices = self.states.get(&opid.actor())?;
-                let change_index_index = actor_indices
-                    .binary_search_by(|change_index| {
-                        let change = self
-                            .history
-                            .get(*change_index)
-                            .expect("State index should refer to a valid change");
-                        let start = change.start_op().get();
-                        let len = change.len() as u64;
-                        if opid.counter() < start {
-                            Ordering::Greater
-                        } else if start + len <= opid.counter() {
-                            Ordering::Less
-                        } else {
-                            Ordering::Equal
-                        }
-                    })
-                    .ok()?;
-                let change_index = actor_indices.get(change_index_index).unwrap();
-                Some(self.history.get(*change_index).unwrap().hash())
+                let change_index = self.change_index_for_opid(&opid)?;
+                Some(self.history[change_index].hash())
             }
         }
     }
 
+    /// The object and key each op of `change` modified
+    ///
+    /// The key of an insert is the element it created. Ops whose actor or key this document
+    /// doesn't know, which it only can if `change` isn't in the document, are left out.
+    pub(crate) fn change_op_keys(&self, change: &Change) -> Vec<(ObjId, Key)> {
+        let osd = &self.ops.osd;
+        let actors = std::iter::once(change.actor_id())
+            .chain(change.other_actor_ids())
+            .map(|actor| osd.actors.lookup(actor))
+            .collect::<Option<Vec<_>>>();
+        let actors = match actors {
+            Some(actors) => actors,
+            None => return Vec::new(),
+        };
+        let opid = |id: &OpId| OpId::new(id.counter(), actors[id.actor()]);
+        change
+            .iter_ops()
+            .enumerate()
+            .filter_map(|(i, op)| {
+                let obj = if op.obj.is_root() {
+                    ObjId::root()
+                } else {
+                    ObjId(opid(op.obj.opid()))
+                };
+                let key = match &op.key {
+                    _ if op.insert => Key::Seq(ElemId(OpId::new(
+                        change.start_op().get() + i as u64,
+                        actors[0],
+                    ))),
+                    EncodedKey::Prop(name) => Key::Map(osd.props.lookup(&name.to_string())?),
+                    EncodedKey::Elem(ElemId(id)) => Key::Seq(ElemId(opid(id))),
+                };
+                Some((obj, key))
+            })
+            .collect()
+    }
+
+    fn change_index_for_opid(&self, opid: &OpId) -> Option<usize> {
+        let actor_indices = self.states.get(&opid.actor())?;
+        let change_index_index = actor_indices
+            .binary_search_by(|change_index| {
+                let change = self
+                    .history
+                    .get(*change_index)
+                    .expect("State index should refer to a valid change");
+                let start = change.start_op().get();
+                let len = change.len() as u64;
+                if opid.counter() < start {
+                    Ordering::Greater
+                } else if start + len <= opid.counter() {
+                    Ordering::Less
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .ok()?;
+        actor_indices.get(change_index_index).copied()
+    }
+
+    /// The changes containing `opids` which are covered by `clock`, in the order they were applied
+    fn changes_for_opids<I: IntoIterator<Item = OpId>>(
+        &self,
+        opids: I,
+        clock: Option<&Clock>,
+    ) -> Vec<&Change> {
+        opids
+            .into_iter()
+            .filter(|opid| clock.map_or(true, |clock| clock.covers(opid)))
+            .filter_map(|opid| self.change_index_for_opid(&opid))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|index| &self.history[index])
+            .collect()
+    }
+
+    /// The changes which created `obj`, or contain ops in it or the ops which deleted or
+    /// overwrote them
+    pub(crate) fn changes_touching(&self, obj: &ObjId, clock: Option<&Clock>) -> Vec<&Change> {
+        let mut opids = vec![obj.0];
+        if let Some(ops) = self.ops.op_iter(obj) {
+            for op in ops {
+                opids.push(*op.id());
+                opids.extend(op.succ().map(|succ| *succ.id()));
+            }
+        }
+        self.changes_for_opids(opids, clock)
+    }
+
+    /// The keys of `obj` which hold more than one value
+    pub(crate) fn conflicts_in(&self, obj: &ObjMeta, clock: Option<&Clock>) -> Vec<Conflict<'_>> {
+        let ops_by_key = self.ops.iter_ops(&obj.id).group_by(|o| o.elemid_or_key());
+        let mut conflicts = Vec::new();
+        let mut index = 0;
+        for (_key, key_ops) in ops_by_key.into_iter() {
+            let visible = key_ops.filter(|o| o.visible_at(clock)).collect::<Vec<_>>();
+            let prop = match visible.last() {
+                Some(op) => match op.map_prop() {
+                    Some(prop) => prop,
+                    None => {
+                        let prop = Prop::Seq(index);
+                        index += op.width(obj.encoding);
+                        prop
+                    }
+                },
+                None => continue,
+            };
+            if visible.len() > 1 {
+                conflicts.push(Conflict {
+                    obj: self.id_to_exid(obj.id.0),
+                    prop,
+                    values: visible
+                        .iter()
+                        .map(|op| {
+                            let (value, id) = op.tagged_value(clock);
+                            Contender {
+                                value,
+                                actor: self.ops.osd.actors[op.id().actor()].clone(),
+                                hash: self.hash_for_opid(&id),
+                                id,
+                            }
+                        })
+                        .collect(),
+                });
+            }
+        }
+        conflicts
+    }
+
+    /// The objects which are values in `obj`, including those which lost a conflict
+    pub(crate) fn child_objects(&self, obj: &ObjMeta, clock: Option<&Clock>) -> Vec<ObjMeta> {
+        self.ops
+            .iter_ops(&obj.id)
+            .filter(|op| matches!(op.action(), OpType::Make(_)) && op.visible_at(clock))
+            .filter_map(|op| self.get_obj_meta(ObjId(*op.id())).ok())
+            .collect()
+    }
+
+    pub(crate) fn conflicts_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<Vec<Conflict<'_>>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        Ok(self.conflicts_in(&obj, clock.as_ref()))
+    }
+
+    /// Every change in this document, in the order they were applied
+    pub(crate) fn history(&self) -> &[Change] {
+        &self.history
+    }
+
+    pub(crate) fn change_graph(&self) -> &ChangeGraph {
+        &self.change_graph
+    }
+
+    /// The metadata of the changes in this document, filtered by the options set on the returned
+    /// [`Log`]
+    pub fn log(&self) -> Log<'_> {
+        Log::new(self)
+    }
+
+    /// The differences between the document at `before_heads` and at `after_heads`, grouped by
+    /// object for reading rather than applying, see [`crate::tree_diff`]
+    ///
+    /// Only the objects which the changes between the heads modified, and the objects on the way
+    /// to them from the root, are read.
+    pub fn tree_diff(&self, before_heads: &[ChangeHash], after_heads: &[ChangeHash]) -> TreeDiff {
+        TreeDiff::new(self, before_heads, after_heads)
+    }
+
+    /// A read only view of this document as at `heads`, which is cheaper than the `*_at()`
+    /// methods of [`ReadDoc`] when reading a lot at the same heads, see [`View`]
+    pub fn view_at(&self, heads: &[ChangeHash]) -> View<'_> {
+        View::new(self, heads)
+    }
+
     fn calculate_marks(
         &self,
         obj: &ExId,
@@ -1459,6 +2073,45 @@ impl Automerge {
         Ok(self.ops.text(&obj.id, clock))
     }
 
+    pub(crate) fn convert_text_index_for(
+        &self,
+        obj: &ExId,
+        index: usize,
+        from: TextEncoding,
+        to: TextEncoding,
+        clock: Option<Clock>,
+    ) -> Result<usize, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if obj.typ != ObjType::Text || from == to {
+            return Ok(index);
+        }
+        let converted = if from.is_additive() && to.is_additive() {
+            self.ops
+                .search(&obj.id, query::TextIndex::new(index, from, to, clock))
+                .index()
+        } else {
+            // Grapheme clusters depend on their neighbours so we need the text
+            from.convert(&self.ops.text(&obj.id, clock), index, to)
+        };
+        converted.ok_or(AutomergeError::InvalidIndex(index))
+    }
+
+    pub(crate) fn spans_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<Spans<'_>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if obj.typ != ObjType::Text {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        Ok(Spans::new(
+            self,
+            self.ops.top_ops(&obj.id, clock.clone()),
+            clock,
+        ))
+    }
+
     pub(crate) fn get_cursor_for(
         &self,
         obj: &ExId,
@@ -1484,15 +2137,80 @@ impl Automerge {
         &self,
         obj: &ExId,
         cursor: &Cursor,
+        resolution: CursorResolution,
         clock: Option<Clock>,
     ) -> Result<usize, AutomergeError> {
         let obj = self.exid_to_obj(obj)?;
-        let opid = self.cursor_to_opid(cursor, clock.as_ref())?;
+        match self.seek_cursor(&obj, cursor, clock.as_ref())? {
+            (index, None) if resolution == CursorResolution::Preceding && index > 0 => {
+                // `index` is the end of the nearest visible element before the deleted one, so
+                // the element at `index - 1` is that element
+                let found = self.ops.seek_ops_by_prop(
+                    &obj.id,
+                    (index - 1).into(),
+                    obj.encoding,
+                    clock.as_ref(),
+                );
+                let width = found
+                    .ops
+                    .last()
+                    .map(|op| op.width(obj.encoding))
+                    .unwrap_or(1);
+                Ok(index - width)
+            }
+            (index, _) => Ok(index),
+        }
+    }
+
+    pub(crate) fn get_sticky_cursor_position_for(
+        &self,
+        obj: &ExId,
+        cursor: &StickyCursor,
+        clock: Option<Clock>,
+    ) -> Result<usize, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if !obj.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        match (cursor.cursor(), cursor.stickiness()) {
+            (None, Stickiness::Left) => Ok(0),
+            (None, Stickiness::Right) => Ok(self.ops.length(&obj.id, obj.encoding, clock)),
+            (Some(c), Stickiness::Left) => match self.seek_cursor(&obj, c, clock.as_ref())? {
+                (index, Some(width)) => Ok(index + width),
+                (index, None) => Ok(index),
+            },
+            (Some(c), Stickiness::Right) => Ok(self.seek_cursor(&obj, c, clock.as_ref())?.0),
+        }
+    }
+
+    /// Find the element `cursor` points at, returning its index and, if it is visible, its width.
+    /// The index of a deleted element is the index of the first visible element after it.
+    fn seek_cursor(
+        &self,
+        obj: &ObjMeta,
+        cursor: &Cursor,
+        clock: Option<&Clock>,
+    ) -> Result<(usize, Option<usize>), AutomergeError> {
+        let opid = self.cursor_to_opid(cursor, clock)?;
         let found = self
             .ops
-            .seek_list_opid(&obj.id, opid, clock.as_ref())
+            .seek_list_opid(&obj.id, opid, clock)
             .ok_or_else(|| AutomergeError::InvalidCursor(cursor.clone()))?;
-        Ok(found.index)
+        if found.visible {
+            return Ok((found.index, Some(found.op.width(obj.encoding))));
+        }
+        // the insert may have been overwritten rather than deleted, in which case the element is
+        // still there
+        let elem = found.op.elemid_or_key();
+        let at_index = self
+            .ops
+            .seek_ops_by_prop(&obj.id, found.index.into(), obj.encoding, clock);
+        match at_index.ops.last() {
+            Some(op) if op.elemid_or_key() == elem => {
+                Ok((found.index, Some(op.width(obj.encoding))))
+            }
+            _ => Ok((found.index, None)),
+        }
     }
 
     pub(crate) fn marks_for(
@@ -1519,6 +2237,153 @@ impl Automerge {
             .map(|op| op.tagged_value(clock.as_ref())))
     }
 
+    pub(crate) fn history_of_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<Vec<ObjRevision<'_>>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        let changes = self.changes_touching(&obj.id, clock.as_ref());
+        let clocks = self.clocks_at_changes(&changes);
+        let keys = self
+            .ops
+            .iter_ops(&obj.id)
+            .group_by(|op| op.elemid_or_key())
+            .into_iter()
+            .map(|(_, ops)| ops.collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        // The keys each change created or deleted an op at, by the index of the change in history
+        let mut touched = HashMap::<usize, BTreeSet<usize>>::new();
+        for (position, ops) in keys.iter().enumerate() {
+            for op in ops {
+                let ids = std::iter::once(*op.id()).chain(op.succ().map(|succ| *succ.id()));
+                for change in ids.filter_map(|id| self.change_index_for_opid(&id)) {
+                    touched.entry(change).or_default().insert(position);
+                }
+            }
+        }
+        fn winner<'a>(ops: &[Op<'a>], clock: &Clock) -> Option<Op<'a>> {
+            ops.iter()
+                .filter(|op| op.visible_at(Some(clock)))
+                .max_by(|a, b| a.lamport_cmp(*b.id()))
+                .copied()
+        }
+        Ok(changes
+            .into_iter()
+            .zip(clocks)
+            .map(|(change, clock)| {
+                let positions = touched
+                    .get(&self.history_index[&change.hash()])
+                    .into_iter()
+                    .flatten();
+                // the index of the key at `counted` in a sequence
+                let (mut index, mut counted) = (0, 0);
+                let mut value = Vec::new();
+                for &position in positions {
+                    let ops = &keys[position];
+                    let prop = match ops[0].map_prop() {
+                        Some(prop) => prop,
+                        None => {
+                            for ops in &keys[counted..position] {
+                                index += winner(ops, &clock).map_or(0, |op| op.width(obj.encoding));
+                            }
+                            counted = position;
+                            Prop::Seq(index)
+                        }
+                    };
+                    let current = winner(ops, &clock).map(|op| op.tagged_value(Some(&clock)));
+                    value.push((prop, current));
+                }
+                Revision {
+                    hash: change.hash(),
+                    value,
+                }
+            })
+            .collect())
+    }
+
+    /// The clock as at each of `changes`, built in one pass over the history up to the last of
+    /// them rather than by walking the ancestors of each
+    fn clocks_at_changes(&self, changes: &[&Change]) -> Vec<Clock> {
+        let indices = changes
+            .iter()
+            .map(|change| self.history_index[&change.hash()])
+            .collect::<Vec<_>>();
+        let last = match indices.iter().max() {
+            Some(last) => *last,
+            None => return Vec::new(),
+        };
+        let mut clocks: Vec<Clock> = Vec::with_capacity(last + 1);
+        for change in &self.history[..=last] {
+            let mut clock = change
+                .deps()
+                .iter()
+                .filter_map(|dep| self.history_index.get(dep))
+                .fold(Clock::new(), |clock, dep| {
+                    Clock::merge(&clock, &clocks[*dep])
+                });
+            if let Some(actor) = self.ops.osd.actors.lookup(change.actor_id()) {
+                clock.include(
+                    actor,
+                    ClockData {
+                        max_op: change.max_op(),
+                        seq: change.seq(),
+                    },
+                );
+            }
+            clocks.push(clock);
+        }
+        indices.into_iter().map(|i| clocks[i].clone()).collect()
+    }
+
+    pub(crate) fn history_of_prop_for(
+        &self,
+        obj: &ExId,
+        prop: Prop,
+        clock: Option<Clock>,
+    ) -> Result<Vec<PropRevision<'_>>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        let key = match prop {
+            Prop::Map(name) => self.ops.osd.props.lookup(&name).map(Key::Map),
+            Prop::Seq(_) => self
+                .ops
+                .seek_ops_by_prop(&obj.id, prop, obj.encoding, clock.as_ref())
+                .ops
+                .first()
+                .map(|op| op.elemid_or_key()),
+        };
+        let ops = match key {
+            Some(key) => self
+                .ops
+                .op_iter(&obj.id)
+                .into_iter()
+                .flatten()
+                .filter(|op| op.elemid_or_key() == key)
+                .collect::<Vec<_>>(),
+            None => return Ok(Vec::new()),
+        };
+        let opids = ops
+            .iter()
+            .flat_map(|op| std::iter::once(*op.id()).chain(op.succ().map(|succ| *succ.id())));
+        let changes = self.changes_for_opids(opids, clock.as_ref());
+        let clocks = self.clocks_at_changes(&changes);
+        Ok(changes
+            .into_iter()
+            .zip(clocks)
+            .map(|(change, clock)| {
+                let value = ops
+                    .iter()
+                    .filter(|op| op.visible_at(Some(&clock)))
+                    .max_by(|a, b| a.lamport_cmp(*b.id()))
+                    .map(|op| op.tagged_value(Some(&clock)));
+                Revision {
+                    hash: change.hash(),
+                    value,
+                }
+            })
+            .collect())
+    }
+
     pub(crate) fn get_all_for<O: AsRef<ExId>, P: Into<Prop>>(
         &self,
         obj: O,
@@ -1690,6 +2555,19 @@ impl ReadDoc for Automerge {
         self.text_for(obj.as_ref(), None)
     }
 
+    fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
+        self.spans_for(obj.as_ref(), None)
+    }
+
+    fn spans_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Spans<'_>, AutomergeError> {
+        let clock = self.clock_at(heads);
+        self.spans_for(obj.as_ref(), Some(clock))
+    }
+
     fn get_cursor<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -1707,7 +2585,28 @@ impl ReadDoc for Automerge {
         at: Option<&[ChangeHash]>,
     ) -> Result<usize, AutomergeError> {
         let clock = at.map(|heads| self.clock_at(heads));
-        self.get_cursor_position_for(obj.as_ref(), cursor, clock)
+        self.get_cursor_position_for(obj.as_ref(), cursor, CursorResolution::Following, clock)
+    }
+
+    fn get_cursor_position_with_resolution<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        resolution: CursorResolution,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.get_cursor_position_for(obj.as_ref(), cursor, resolution, clock)
+    }
+
+    fn get_sticky_cursor_position<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &StickyCursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.get_sticky_cursor_position_for(obj.as_ref(), cursor, clock)
     }
 
     fn text_at<O: AsRef<ExId>>(
@@ -1719,6 +2618,18 @@ impl ReadDoc for Automerge {
         self.text_for(obj.as_ref(), Some(clock))
     }
 
+    fn convert_text_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        from: TextEncoding,
+        to: TextEncoding,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.convert_text_index_for(obj.as_ref(), index, from, to, clock)
+    }
+
     fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError> {
         self.marks_for(obj.as_ref(), None)
     }
@@ -1782,6 +2693,38 @@ impl ReadDoc for Automerge {
         self.exid_to_obj(obj.as_ref()).map(|obj| obj.typ)
     }
 
+    fn conflicts<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Conflict<'_>>, AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.conflicts_for(obj.as_ref(), clock)
+    }
+
+    fn all_conflicts(&self, at: Option<&[ChangeHash]>) -> Conflicts<'_> {
+        Conflicts::new(self, at.map(|heads| self.clock_at(heads)))
+    }
+
+    fn history_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<ObjRevision<'_>>, AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.history_of_for(obj.as_ref(), clock)
+    }
+
+    fn history_of_prop<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<PropRevision<'_>>, AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.history_of_prop_for(obj.as_ref(), prop.into(), clock)
+    }
+
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
         let in_queue: HashSet<_> = self.queue.iter().map(|change| change.hash()).collect();
         let mut missing = HashSet::new();
@@ -1820,6 +2763,43 @@ impl Default for Automerge {
     }
 }
 
+/// What merging some changes would do, returned by [`Automerge::preview_merge()`] and
+/// [`Automerge::preview_changes()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergePreview {
+    /// The patches which would be applied to the current state of the document
+    pub patches: Vec<Patch>,
+    /// The heads of the document after merging
+    pub heads: Vec<ChangeHash>,
+    /// The hashes of the changes which would be applied, in the order they would be applied
+    pub changes: Vec<ChangeHash>,
+    /// Dependencies of the incoming changes which neither side has. Changes which depend on these
+    /// would not be applied until they arrive.
+    pub missing_deps: Vec<ChangeHash>,
+}
+
+impl MergePreview {
+    /// The patches which introduce a conflict
+    ///
+    /// These are [`PatchAction::Conflict`] patches, when the incoming value loses, and puts with
+    /// `conflict` set, when it wins.
+    pub fn conflicts(&self) -> impl Iterator<Item = &Patch> {
+        self.patches.iter().filter(|p| {
+            matches!(
+                p.action,
+                PatchAction::Conflict { .. }
+                    | PatchAction::PutMap { conflict: true, .. }
+                    | PatchAction::PutSeq { conflict: true, .. }
+            )
+        })
+    }
+
+    /// Whether merging would change nothing
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
 /// Options to pass to [`Automerge::save_with_options()`] and [`crate::AutoCommit::save_with_options()`]
 #[derive(Debug)]
 pub struct SaveOptions {
@@ -1827,6 +2807,17 @@ pub struct SaveOptions {
     pub deflate: bool,
     /// Whether to save changes which we do not have the dependencies for
     pub retain_orphans: bool,
+    /// Whether to save the refs of the document, see [`crate::refs`]
+    ///
+    /// Refs are saved in a chunk which versions of automerge from before refs were added fail to
+    /// load. The chunk is only written if the document has refs, turn this off to save a
+    /// document with refs for older versions, which loses the refs.
+    pub refs: bool,
+    /// Whether to save the mark policies of the document, see [`MarkPolicies`]
+    ///
+    /// Like refs they are saved in a chunk which older versions of automerge fail to load, which
+    /// is only written if a policy has been set.
+    pub mark_policies: bool,
 }
 
 impl std::default::Default for SaveOptions {
@@ -1834,6 +2825,34 @@ impl std::default::Default for SaveOptions {
         Self {
             deflate: true,
             retain_orphans: true,
+            refs: true,
+            mark_policies: true,
+        }
+    }
+}
+
+/// What applying changes modifies, so that [`Automerge::rollback_to()`] can undo it
+#[derive(Debug)]
+struct Checkpoint {
+    history: usize,
+    ops: (usize, usize),
+    actors: usize,
+    props: usize,
+    deps: HashSet<ChangeHash>,
+    queue: Vec<Change>,
+    max_op: u64,
+}
+
+impl Checkpoint {
+    fn new(doc: &Automerge) -> Self {
+        Self {
+            history: doc.history.len(),
+            ops: doc.ops.osd.len(),
+            actors: doc.ops.osd.actors.len(),
+            props: doc.ops.osd.props.len(),
+            deps: doc.deps.clone(),
+            queue: doc.queue.clone(),
+            max_op: doc.max_op,
         }
     }
 }
@@ -1879,5 +2898,8 @@ pub(crate) fn reconstruct_document<'a>(
         deps: heads.into_iter().collect(),
         actor: Actor::Unused(ActorId::random()),
         max_op,
+        mark_policies: MarkPolicies::default(),
+        refs: Refs::default(),
+        checked_out: None,
     })
 }
@@ -15,6 +15,8 @@ struct TextSpan {
     text: String,
     start: usize,
     marks: Option<Arc<MarkSet>>,
+    // The block marker this span consists of, if it's a block marker rather than text
+    block: Option<OpId>,
 }
 
 #[derive(Debug, Default)]
@@ -26,25 +28,35 @@ struct TextState<'a> {
 
 impl<'a> TextState<'a> {
     fn push_str(&mut self, text: &str, len: usize) {
-        if let Some(last_span) = self.spans.last_mut() {
-            last_span.text.push_str(text);
-        } else {
-            self.spans.push(TextSpan {
+        match self.spans.last_mut() {
+            Some(last_span) if last_span.block.is_none() => last_span.text.push_str(text),
+            _ => self.spans.push(TextSpan {
                 text: text.to_owned(),
-                start: 0,
-                marks: None,
-            });
+                start: self.len,
+                marks: self.marks.current().cloned(),
+                block: None,
+            }),
         }
         self.len += len;
     }
 
+    fn push_block(&mut self, id: OpId, len: usize) {
+        self.spans.push(TextSpan {
+            text: "".to_owned(),
+            start: self.len,
+            marks: self.marks.current().cloned(),
+            block: Some(id),
+        });
+        self.len += len;
+    }
+
     fn push_mark(&mut self) {
         let marks = self.marks.current();
         if let Some(last) = self.spans.last_mut() {
             if last.marks.as_ref() == marks {
                 return;
             }
-            if last.text.is_empty() {
+            if last.text.is_empty() && last.block.is_none() {
                 last.marks = marks.cloned();
                 return;
             }
@@ -53,6 +65,7 @@ impl<'a> TextState<'a> {
             text: "".to_owned(),
             start: self.len,
             marks: marks.cloned(),
+            block: None,
         })
     }
 }
@@ -105,6 +118,7 @@ fn log_text_patches<'a, I: Iterator<Item = Op<'a>>>(
         .fold(state, |mut state, (_key, key_ops)| {
             if let Some(o) = key_ops.filter(|o| o.visible_or_mark(None)).last() {
                 match o.action() {
+                    OpType::Make(ObjType::Map) => state.push_block(*o.id(), o.width(encoding)),
                     OpType::Make(_) | OpType::Put(_) => {
                         state.push_str(o.as_str(), o.width(encoding))
                     }
@@ -124,7 +138,9 @@ fn log_text_patches<'a, I: Iterator<Item = Op<'a>>>(
             state
         });
     for span in state.spans {
-        if !span.text.is_empty() {
+        if let Some(id) = span.block {
+            patch_log.split_block(*obj, span.start, id);
+        } else if !span.text.is_empty() {
             patch_log.splice(*obj, span.start, span.text.as_str(), span.marks);
         }
     }
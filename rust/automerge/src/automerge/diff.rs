@@ -4,13 +4,16 @@ use std::sync::Arc;
 
 use crate::patches::TextRepresentation;
 use crate::{
+    conflicts::{Conflict, Conflicts},
     exid::ExId,
-    iter::{Keys, ListRange, MapRange, Values},
+    history::{ObjRevision, PropRevision},
+    iter::{Keys, ListRange, MapRange, Spans, Values},
     marks::{Mark, MarkSet, MarkStateMachine},
     patches::PatchLog,
     types::{Clock, ListEncoding, ObjId, Op, Prop},
     value::Value,
-    Automerge, AutomergeError, ChangeHash, Cursor, ObjType, OpType, ReadDoc,
+    Automerge, AutomergeError, ChangeHash, Cursor, CursorResolution, ObjType, OpType, ReadDoc,
+    StickyCursor, TextEncoding,
 };
 
 #[derive(Clone, Debug)]
@@ -192,7 +195,11 @@ fn log_text_diff<'a, I: Iterator<Item = Patch<'a>>>(
     let encoding = ListEncoding::Text;
     patches.fold(0, |index, patch| match &patch {
         Patch::New(winner, marks) => {
-            patch_log.splice(*obj, index, winner.op.as_str(), marks.clone());
+            if let OpType::Make(ObjType::Map) = winner.op.action() {
+                patch_log.split_block(*obj, index, *winner.op.id());
+            } else {
+                patch_log.splice(*obj, index, winner.op.as_str(), marks.clone());
+            }
             index + winner.op.width(encoding)
         }
         Patch::Update {
@@ -200,7 +207,7 @@ fn log_text_diff<'a, I: Iterator<Item = Patch<'a>>>(
             after,
             marks,
         } => {
-            patch_log.delete_seq(*obj, index, before.op.width(encoding));
+            patch_log.delete_text(*obj, index, before.op.as_str());
             patch_log.splice(*obj, index, after.op.as_str(), marks.clone());
             index + after.op.width(encoding)
         }
@@ -212,7 +219,7 @@ fn log_text_diff<'a, I: Iterator<Item = Patch<'a>>>(
             index + len
         }
         Patch::Delete(before) => {
-            patch_log.delete_seq(*obj, index, before.op.width(encoding));
+            patch_log.delete_text(*obj, index, before.op.as_str());
             index
         }
     });
@@ -402,6 +409,31 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.text_at(obj, heads)
     }
 
+    fn convert_text_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        from: TextEncoding,
+        to: TextEncoding,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .convert_text_index(obj, index, from, to, Some(at.unwrap_or(self.heads)))
+    }
+
+    fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
+        self.doc
+            .spans_for(obj.as_ref(), Some(self.doc.clock_at(self.heads)))
+    }
+
+    fn spans_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Spans<'_>, AutomergeError> {
+        self.doc.spans_at(obj, heads)
+    }
+
     fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError> {
         self.doc.marks_at(obj, self.heads)
     }
@@ -442,6 +474,31 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.get_cursor_position(obj, cursor, at)
     }
 
+    fn get_cursor_position_with_resolution<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        resolution: CursorResolution,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc.get_cursor_position_with_resolution(
+            obj,
+            cursor,
+            resolution,
+            Some(at.unwrap_or(self.heads)),
+        )
+    }
+
+    fn get_sticky_cursor_position<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &StickyCursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .get_sticky_cursor_position(obj, cursor, Some(at.unwrap_or(self.heads)))
+    }
+
     fn get<O: AsRef<ExId>, P: Into<Prop>>(
         &self,
         obj: O,
@@ -488,6 +545,36 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.parents_at(obj, heads)
     }
 
+    fn conflicts<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Conflict<'_>>, AutomergeError> {
+        self.doc.conflicts(obj, Some(at.unwrap_or(self.heads)))
+    }
+
+    fn all_conflicts(&self, at: Option<&[ChangeHash]>) -> Conflicts<'_> {
+        self.doc.all_conflicts(Some(at.unwrap_or(self.heads)))
+    }
+
+    fn history_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<ObjRevision<'_>>, AutomergeError> {
+        self.doc.history_of(obj, Some(at.unwrap_or(self.heads)))
+    }
+
+    fn history_of_prop<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<PropRevision<'_>>, AutomergeError> {
+        self.doc
+            .history_of_prop(obj, prop, Some(at.unwrap_or(self.heads)))
+    }
+
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
         self.doc.get_missing_deps(heads)
     }
@@ -501,8 +588,8 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
 mod tests {
 
     use crate::{
-        marks::Mark, transaction::Transactable, types::MarkData, AutoCommit, ObjType, Patch,
-        PatchAction, Prop, ScalarValue, Value, ROOT,
+        blocks::Block, marks::Mark, transaction::Transactable, types::MarkData, AutoCommit,
+        ObjType, Patch, PatchAction, Prop, ScalarValue, Value, ROOT,
     };
     use itertools::Itertools;
 
@@ -531,6 +618,8 @@ mod tests {
         SpliceText(String),
         Mark(Vec<ObservedMark>),
         Conflict(Prop),
+        SplitBlock(Block),
+        UpdateBlock(Block),
     }
 
     #[derive(Debug, Clone, PartialEq)]
@@ -616,6 +705,14 @@ mod tests {
                     action: ObservedAction::Conflict(prop),
                     path: format!("/{}", path.clone().join("/")),
                 },
+                PatchAction::SplitBlock { index, block } => ObservedPatch {
+                    action: ObservedAction::SplitBlock(block),
+                    path: ex_path_and(path, index),
+                },
+                PatchAction::UpdateBlock { index, block } => ObservedPatch {
+                    action: ObservedAction::UpdateBlock(block),
+                    path: ex_path_and(path, index),
+                },
             }
         }
     }
@@ -0,0 +1,102 @@
+use crate::exid::ExId;
+use crate::marks::{ExpandMark, Mark};
+use crate::transaction::Transactable;
+use crate::{AutomergeError, ObjType, Prop, ReadDoc, Value};
+
+/// Copy the visible state of the object `from` in `source` into the empty object `to`
+///
+/// Only the winning value of a conflicted property is copied and counters are copied at their
+/// current value, so the copy contains nothing which isn't visible in `source`.
+pub(crate) fn copy_object<R: ReadDoc, T: Transactable>(
+    source: &R,
+    from: &ExId,
+    tx: &mut T,
+    to: &ExId,
+    obj_type: ObjType,
+) -> Result<(), AutomergeError> {
+    match obj_type {
+        ObjType::Map | ObjType::Table => {
+            for item in source.map_range(from, ..) {
+                copy_value(source, item.value, item.id, tx, to, item.key.into(), false)?;
+            }
+        }
+        ObjType::List => {
+            for item in source.list_range(from, ..) {
+                let prop = Prop::from(item.index);
+                copy_value(source, item.value, item.id, tx, to, prop, true)?;
+            }
+        }
+        ObjType::Text => copy_text(source, from, tx, to)?,
+    }
+    Ok(())
+}
+
+fn copy_value<R: ReadDoc, T: Transactable>(
+    source: &R,
+    value: Value<'_>,
+    id: ExId,
+    tx: &mut T,
+    obj: &ExId,
+    prop: Prop,
+    insert: bool,
+) -> Result<(), AutomergeError> {
+    match (value, prop) {
+        (Value::Object(obj_type), Prop::Seq(index)) if insert => {
+            let copy = tx.insert_object(obj, index, obj_type)?;
+            copy_object(source, &id, tx, &copy, obj_type)
+        }
+        (Value::Object(obj_type), prop) => {
+            let copy = tx.put_object(obj, prop, obj_type)?;
+            copy_object(source, &id, tx, &copy, obj_type)
+        }
+        (Value::Scalar(value), Prop::Seq(index)) if insert => {
+            tx.insert(obj, index, value.into_owned())
+        }
+        (Value::Scalar(value), prop) => tx.put(obj, prop, value.into_owned()),
+    }
+}
+
+/// Text is copied a run of characters at a time, block markers are copied like any other object
+/// and then the marks are added over the copied text
+fn copy_text<R: ReadDoc, T: Transactable>(
+    source: &R,
+    from: &ExId,
+    tx: &mut T,
+    to: &ExId,
+) -> Result<(), AutomergeError> {
+    let mut run = String::new();
+    for (index, item) in source.list_range(from, ..).enumerate() {
+        match item.value {
+            Value::Scalar(s) => {
+                if let Some(s) = s.to_str() {
+                    run.push_str(s);
+                }
+            }
+            Value::Object(obj_type) => {
+                flush(tx, to, &mut run)?;
+                let copy = tx.insert_object(to, index, obj_type)?;
+                copy_object(source, &item.id, tx, &copy, obj_type)?;
+            }
+        }
+    }
+    flush(tx, to, &mut run)?;
+    for mark in source.marks(from)? {
+        let copy = Mark::new(
+            mark.name().to_string(),
+            mark.value().clone(),
+            mark.start,
+            mark.end,
+        );
+        tx.mark(to, copy, ExpandMark::default())?;
+    }
+    Ok(())
+}
+
+fn flush<T: Transactable>(tx: &mut T, text: &ExId, run: &mut String) -> Result<(), AutomergeError> {
+    if !run.is_empty() {
+        let end = tx.length(text);
+        tx.splice_text(text, end, 0, run)?;
+        run.clear();
+    }
+    Ok(())
+}
@@ -1785,3 +1785,194 @@ fn hash_for_opid() {
     assert_eq!(doc.hash_for_opid(&id1), hash1);
     assert_eq!(doc.hash_for_opid(&id2), hash2);
 }
+
+#[test]
+fn preview_merge_matches_merge_without_applying_it() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "title", "draft").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    doc1.put(ROOT, "title", "ours").unwrap();
+    doc2.put(ROOT, "title", "theirs").unwrap();
+    doc2.put(ROOT, "new", 1).unwrap();
+
+    let heads = doc1.get_heads();
+    let preview = doc1.preview_merge(&mut doc2).unwrap();
+    assert_eq!(doc1.get_heads(), heads);
+    assert_eq!(preview.changes.len(), 1);
+    assert_eq!(preview.conflicts().count(), 1);
+    assert!(preview.missing_deps.is_empty());
+
+    doc1.update_diff_cursor();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(preview.heads, doc1.get_heads());
+    assert_eq!(preview.patches, doc1.diff_incremental());
+
+    // when the other document already has everything the patches come from its history
+    let mut doc3 = doc1.fork();
+    doc3.put(ROOT, "title", "final").unwrap();
+    let preview = doc1.preview_merge(&mut doc3).unwrap();
+    doc1.update_diff_cursor();
+    doc1.merge(&mut doc3).unwrap();
+    assert_eq!(preview.patches, doc1.diff_incremental());
+    assert_eq!(preview.conflicts().count(), 0);
+    assert!(doc1.preview_merge(&mut doc3).unwrap().is_empty());
+}
+
+#[test]
+fn preview_merge_of_diverged_documents() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.insert(&list, 0, "a").unwrap();
+    doc1.splice_text(&text, 0, 0, "hello").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+    doc1.insert(&list, 0, "ours").unwrap();
+    doc1.splice_text(&text, 5, 0, " world").unwrap();
+    doc2.insert(&list, 1, "theirs").unwrap();
+    doc2.splice_text(&text, 0, 1, "J").unwrap();
+    doc2.put(ROOT, "other", true).unwrap();
+    let other_heads = doc2.get_heads();
+
+    let preview = doc1.preview_merge(&mut doc2).unwrap();
+    assert_eq!(preview.changes, other_heads);
+    assert_eq!(doc2.get_heads(), other_heads);
+
+    doc1.update_diff_cursor();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(preview.heads, doc1.get_heads());
+    assert_eq!(preview.patches, doc1.diff_incremental());
+
+    // nothing to merge from a document which is behind
+    let behind = doc1.document().fork_at(&other_heads).unwrap();
+    let mut doc1 = doc1.document().clone();
+    let preview = doc1
+        .preview_merge(&behind, TextRepresentation::String)
+        .unwrap();
+    assert!(preview.is_empty());
+    assert_eq!(preview.heads, doc1.get_heads());
+}
+
+#[test]
+fn documents_saved_without_refs_load_in_loaders_from_before_refs() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "key", "value").unwrap();
+    doc.create_branch("main").unwrap();
+    doc.create_tag("v1").unwrap();
+
+    // loaders from before refs were added fail on the unknown refs chunk type
+    let chunk_types = |bytes: &[u8]| {
+        let mut input = storage::parse::Input::new(bytes);
+        let mut types = Vec::new();
+        while !input.is_empty() {
+            let (remaining, chunk) = storage::Chunk::parse(input).unwrap();
+            types.push(match chunk {
+                storage::Chunk::Document(_) => storage::ChunkType::Document,
+                storage::Chunk::Change(_) => storage::ChunkType::Change,
+                storage::Chunk::CompressedChange(..) => storage::ChunkType::Compressed,
+                storage::Chunk::Refs(..) => storage::ChunkType::Refs,
+                storage::Chunk::MarkPolicies(..) => storage::ChunkType::MarkPolicies,
+            });
+            input = remaining.reset();
+        }
+        types
+    };
+    let saved = doc.save_with_options(SaveOptions {
+        refs: false,
+        ..Default::default()
+    });
+    assert_eq!(chunk_types(&saved), vec![storage::ChunkType::Document]);
+    let changes = match load::load_changes(storage::parse::Input::new(&saved)) {
+        load::LoadedChanges::Complete(changes) => changes,
+        load::LoadedChanges::Partial { error, .. } => panic!("partial load: {}", error),
+    };
+    let mut old = Automerge::new();
+    old.apply_changes(changes).unwrap();
+    assert_eq!(old.hydrate(None), doc.hydrate(None));
+
+    let with_refs = doc.save();
+    assert_eq!(
+        chunk_types(&with_refs),
+        vec![storage::ChunkType::Document, storage::ChunkType::Refs]
+    );
+    assert_eq!(Automerge::load(&with_refs).unwrap().refs(), doc.refs());
+
+    let relay = crate::sync::Relay::load(&with_refs).unwrap();
+    let relay_saved = relay.save();
+    assert_eq!(
+        chunk_types(&relay_saved).last(),
+        Some(&storage::ChunkType::Refs)
+    );
+    assert_eq!(Automerge::load(&relay_saved).unwrap().refs(), doc.refs());
+}
+
+#[test]
+fn preview_merge_rolls_back_the_merged_changes() {
+    let mut doc1 = AutoCommit::new()
+        .with_actor(ActorId::from(b"aaaa"))
+        .with_text_encoding(TextEncoding::Utf8CodeUnit);
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "héllo").unwrap();
+    doc1.put(ROOT, "counter", ScalarValue::counter(1)).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+    doc1.put(ROOT, "title", "ours").unwrap();
+    doc1.commit();
+    doc2.splice_text(&text, 3, 0, "!").unwrap();
+    doc2.increment(ROOT, "counter", 2).unwrap();
+    let map = doc2.put_object(ROOT, "map", ObjType::Map).unwrap();
+    doc2.put(&map, "key", "value").unwrap();
+    doc2.delete(ROOT, "title").unwrap();
+    doc2.commit();
+
+    let saved = doc1.save();
+    let preview = doc1.preview_merge(&mut doc2).unwrap();
+    assert_eq!(doc1.save(), saved);
+    assert_eq!(
+        doc1.get(ROOT, "counter").unwrap().unwrap().0,
+        Value::counter(1)
+    );
+    assert_eq!(doc1.get(ROOT, "map").unwrap(), None);
+    assert_eq!(doc1.preview_merge(&mut doc2).unwrap(), preview);
+
+    // the patches use the text encoding of the document
+    doc1.update_diff_cursor();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(preview.heads, doc1.get_heads());
+    assert_eq!(preview.patches, doc1.diff_incremental());
+    assert_eq!(doc1.text(&text).unwrap(), "hél!lo");
+    assert_eq!(
+        doc1.get(ROOT, "counter").unwrap().unwrap().0,
+        Value::counter(3)
+    );
+}
+
+#[test]
+fn preview_changes_reports_missing_deps() {
+    let mut doc1 = Automerge::new();
+    let mut doc2 = doc1.fork();
+    doc2.transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "a", 1))
+        .unwrap();
+    doc2.transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "b", 2))
+        .unwrap();
+    let changes = doc2
+        .get_changes(&[])
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let preview = doc1
+        .preview_changes(vec![changes[1].clone()], TextRepresentation::String)
+        .unwrap();
+    assert!(preview.is_empty());
+    assert!(preview.patches.is_empty());
+    assert_eq!(preview.missing_deps, vec![changes[0].hash()]);
+
+    let preview = doc1
+        .preview_changes(changes, TextRepresentation::String)
+        .unwrap();
+    assert_eq!(preview.patches.len(), 2);
+    assert_eq!(preview.heads, doc2.get_heads());
+    assert!(doc1.get_heads().is_empty());
+}
@@ -0,0 +1,519 @@
+use std::ops::RangeBounds;
+
+use crate::clock::{Clock, ClockData};
+use crate::conflicts::{Conflict, Conflicts};
+use crate::cursor::{Cursor, CursorResolution, StickyCursor};
+use crate::exid::ExId;
+use crate::history::{ObjRevision, PropRevision};
+use crate::iter::{Keys, ListRange, MapRange, Spans, Values};
+use crate::marks::{Mark, MarkSet};
+use crate::{
+    Automerge, AutomergeError, Change, ChangeHash, ObjType, Parents, Prop, ReadDoc, TextEncoding,
+    Value,
+};
+
+/// A read only view of a document as at some heads, created by [`Automerge::view_at()`]
+///
+/// Every `*_at()` method of [`ReadDoc`] works out which changes the heads include before reading
+/// anything, which is expensive in a document with a long history. A view does that once when it
+/// is created and then reads as cheaply as the current state. Reads which pass their own heads
+/// to a view work the same as on the document.
+///
+/// A view can also be moved through history one change at a time without starting again:
+/// [`Self::step_forward()`] and [`Self::step_back()`] follow the order the changes were applied
+/// to the document, and [`Self::add_change()`] and [`Self::remove_change()`] move it by a
+/// particular change.
+///
+/// ```
+/// # use automerge::{AutoCommit, ReadDoc, ROOT, transaction::Transactable};
+/// let mut doc = AutoCommit::new();
+/// for i in 0..3 {
+///     doc.put(ROOT, "count", i).unwrap();
+///     doc.commit();
+/// }
+/// let heads = doc.get_heads();
+/// let mut view = doc.view_at(&[]);
+/// let mut values = Vec::new();
+/// while view.step_forward().is_some() {
+///     values.push(view.get(ROOT, "count").unwrap().unwrap().0.to_i64());
+/// }
+/// assert_eq!(values, vec![Some(0), Some(1), Some(2)]);
+/// assert_eq!(view.heads(), heads);
+/// ```
+#[derive(Debug, Clone)]
+pub struct View<'a> {
+    doc: &'a Automerge,
+    heads: Vec<ChangeHash>,
+    clock: Clock,
+    /// The index in the history of the document of the first change the view doesn't include
+    next: usize,
+    /// One past the index in the history of the last change the view includes
+    end: usize,
+}
+
+impl<'a> View<'a> {
+    pub(crate) fn new(doc: &'a Automerge, heads: &[ChangeHash]) -> Self {
+        let mut heads = heads.to_vec();
+        heads.sort_unstable();
+        heads.dedup();
+        let mut view = View {
+            doc,
+            clock: doc.clock_at(&heads),
+            heads,
+            next: 0,
+            end: doc.history.len(),
+        };
+        view.advance();
+        view.retreat();
+        view
+    }
+
+    /// The heads this view is at
+    pub fn heads(&self) -> &[ChangeHash] {
+        &self.heads
+    }
+
+    pub(crate) fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Move forward by the first change, in the order the changes were applied to the document,
+    /// which the view doesn't include yet. Returns the hash of that change, or `None` if the view
+    /// already includes every change.
+    pub fn step_forward(&mut self) -> Option<ChangeHash> {
+        // every change before the first one which isn't included is, so its dependencies are too
+        let doc = self.doc;
+        let change = doc.history.get(self.next)?;
+        self.add(change);
+        Some(change.hash())
+    }
+
+    /// Move back by the last change, in the order the changes were applied to the document,
+    /// which the view includes. Returns the hash of that change, or `None` if the view is empty.
+    pub fn step_back(&mut self) -> Option<ChangeHash> {
+        // nothing the view includes can depend on the last change it includes
+        let doc = self.doc;
+        let change = doc.history.get(self.end.checked_sub(1)?)?;
+        self.remove(change);
+        Some(change.hash())
+    }
+
+    /// Move forward to include `change`
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::InvalidViewStep`] if the view already includes `change` or doesn't
+    /// include all of its dependencies, [`AutomergeError::MissingHash`] if `change` is not in the
+    /// document
+    pub fn add_change(&mut self, change: ChangeHash) -> Result<(), AutomergeError> {
+        let change = self.change(change)?;
+        let addable = !self.includes(change)
+            && change.deps().iter().all(|dep| {
+                self.doc
+                    .get_change_by_hash(dep)
+                    .map_or(false, |dep| self.includes(dep))
+            });
+        if !addable {
+            return Err(AutomergeError::InvalidViewStep(change.hash()));
+        }
+        self.add(change);
+        Ok(())
+    }
+
+    /// Move back to exclude `change`
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::InvalidViewStep`] if `change` is not one of the heads of the view,
+    /// [`AutomergeError::MissingHash`] if `change` is not in the document
+    pub fn remove_change(&mut self, change: ChangeHash) -> Result<(), AutomergeError> {
+        let change = self.change(change)?;
+        if !self.heads.contains(&change.hash()) {
+            return Err(AutomergeError::InvalidViewStep(change.hash()));
+        }
+        self.remove(change);
+        Ok(())
+    }
+
+    fn change(&self, hash: ChangeHash) -> Result<&'a Change, AutomergeError> {
+        self.doc
+            .get_change_by_hash(&hash)
+            .ok_or(AutomergeError::MissingHash(hash))
+    }
+
+    /// The changes which one of `self` and `other` includes and the other doesn't
+    pub(crate) fn symmetric_difference(&self, other: &View<'a>) -> Vec<&'a Change> {
+        // both views include every change before the first either of them doesn't, and neither
+        // includes any after the last either of them does
+        let doc = self.doc;
+        let start = self.next.min(other.next);
+        let end = self.end.max(other.end).max(start);
+        doc.history[start..end]
+            .iter()
+            .filter(|change| self.includes(change) != other.includes(change))
+            .collect()
+    }
+
+    fn includes(&self, change: &Change) -> bool {
+        self.doc
+            .ops
+            .osd
+            .actors
+            .lookup(change.actor_id())
+            .and_then(|actor| self.clock.get_for_actor(&actor))
+            .map_or(false, |data| data.seq >= change.seq())
+    }
+
+    fn add(&mut self, change: &Change) {
+        if let Some(actor) = self.doc.ops.osd.actors.lookup(change.actor_id()) {
+            self.clock.set_for_actor(
+                actor,
+                Some(ClockData {
+                    max_op: change.max_op(),
+                    seq: change.seq(),
+                }),
+            );
+        }
+        self.heads.retain(|head| !change.deps().contains(head));
+        self.heads.push(change.hash());
+        self.heads.sort_unstable();
+        let index = self.doc.history_index[&change.hash()];
+        self.end = self.end.max(index + 1);
+        if index == self.next {
+            self.advance();
+        }
+    }
+
+    fn remove(&mut self, change: &Change) {
+        if let Some(actor) = self.doc.ops.osd.actors.lookup(change.actor_id()) {
+            // the previous change by the same actor is always a dependency of this one
+            let previous = self
+                .doc
+                .states
+                .get(&actor)
+                .and_then(|changes| changes.get((change.seq() as usize).checked_sub(2)?))
+                .map(|index| {
+                    let previous = &self.doc.history[*index];
+                    ClockData {
+                        max_op: previous.max_op(),
+                        seq: previous.seq(),
+                    }
+                });
+            self.clock.set_for_actor(actor, previous);
+        }
+        self.heads.retain(|head| *head != change.hash());
+        for dep in change.deps() {
+            if self.heads.contains(dep) {
+                continue;
+            }
+            // a dependency is only a head if no other change the view includes depends on it,
+            // and those all come after it in the history
+            let index = self.doc.history_index[dep];
+            let depended_on = self.doc.history[index + 1..self.end]
+                .iter()
+                .any(|other| other.deps().contains(dep) && self.includes(other));
+            if !depended_on {
+                self.heads.push(*dep);
+            }
+        }
+        self.heads.sort_unstable();
+        let index = self.doc.history_index[&change.hash()];
+        self.next = self.next.min(index);
+        if index + 1 == self.end {
+            self.retreat();
+        }
+    }
+
+    /// Move `next` past the changes the view includes
+    fn advance(&mut self) {
+        let history = &self.doc.history;
+        while self.next < history.len() && self.includes(&history[self.next]) {
+            self.next += 1;
+        }
+    }
+
+    /// Move `end` back past the changes the view doesn't include
+    fn retreat(&mut self) {
+        let history = &self.doc.history;
+        while self.end > 0 && !self.includes(&history[self.end - 1]) {
+            self.end -= 1;
+        }
+    }
+
+    fn get_scope(&self, heads: Option<&[ChangeHash]>) -> Option<Clock> {
+        match heads {
+            Some(heads) => Some(self.doc.clock_at(heads)),
+            None => Some(self.clock.clone()),
+        }
+    }
+}
+
+impl<'a> AsRef<Automerge> for View<'a> {
+    fn as_ref(&self) -> &Automerge {
+        self.doc
+    }
+}
+
+impl<'a> ReadDoc for View<'a> {
+    fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<Parents<'_>, AutomergeError> {
+        self.doc.parents_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn parents_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Parents<'_>, AutomergeError> {
+        self.doc
+            .parents_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn keys<O: AsRef<ExId>>(&self, obj: O) -> Keys<'_> {
+        self.doc.keys_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn keys_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Keys<'_> {
+        self.doc.keys_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn map_range<'c, O: AsRef<ExId>, R: RangeBounds<String> + 'c>(
+        &'c self,
+        obj: O,
+        range: R,
+    ) -> MapRange<'c, R> {
+        self.doc
+            .map_range_for(obj.as_ref(), range, self.get_scope(None))
+    }
+
+    fn map_range_at<'c, O: AsRef<ExId>, R: RangeBounds<String> + 'c>(
+        &'c self,
+        obj: O,
+        range: R,
+        heads: &[ChangeHash],
+    ) -> MapRange<'c, R> {
+        self.doc
+            .map_range_for(obj.as_ref(), range, self.get_scope(Some(heads)))
+    }
+
+    fn list_range<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> ListRange<'_, R> {
+        self.doc
+            .list_range_for(obj.as_ref(), range, self.get_scope(None))
+    }
+
+    fn list_range_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+        heads: &[ChangeHash],
+    ) -> ListRange<'_, R> {
+        self.doc
+            .list_range_for(obj.as_ref(), range, self.get_scope(Some(heads)))
+    }
+
+    fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
+        self.doc.values_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn values_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Values<'_> {
+        self.doc
+            .values_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc.length_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
+        self.doc
+            .length_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn object_type<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjType, AutomergeError> {
+        self.doc.object_type(obj)
+    }
+
+    fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        self.doc.marks_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn marks_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        self.doc
+            .marks_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn get_marks<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<MarkSet, AutomergeError> {
+        self.doc
+            .get_marks_for(obj.as_ref(), index, self.get_scope(heads))
+    }
+
+    fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError> {
+        self.doc.text_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn text_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<String, AutomergeError> {
+        self.doc.text_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn convert_text_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        from: TextEncoding,
+        to: TextEncoding,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .convert_text_index_for(obj.as_ref(), index, from, to, self.get_scope(at))
+    }
+
+    fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
+        self.doc.spans_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn spans_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Spans<'_>, AutomergeError> {
+        self.doc
+            .spans_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn get_cursor<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        position: usize,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Cursor, AutomergeError> {
+        self.doc
+            .get_cursor_for(obj.as_ref(), position, self.get_scope(at))
+    }
+
+    fn get_cursor_position<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        address: &Cursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc.get_cursor_position_for(
+            obj.as_ref(),
+            address,
+            CursorResolution::Following,
+            self.get_scope(at),
+        )
+    }
+
+    fn get_cursor_position_with_resolution<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        resolution: CursorResolution,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .get_cursor_position_for(obj.as_ref(), cursor, resolution, self.get_scope(at))
+    }
+
+    fn get_sticky_cursor_position<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &StickyCursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .get_sticky_cursor_position_for(obj.as_ref(), cursor, self.get_scope(at))
+    }
+
+    fn get<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc
+            .get_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn get_at<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        heads: &[ChangeHash],
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc
+            .get_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
+    }
+
+    fn get_all<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc
+            .get_all_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn get_all_at<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        heads: &[ChangeHash],
+    ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc
+            .get_all_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
+    }
+
+    fn conflicts<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Conflict<'_>>, AutomergeError> {
+        self.doc.conflicts_for(obj.as_ref(), self.get_scope(at))
+    }
+
+    fn all_conflicts(&self, at: Option<&[ChangeHash]>) -> Conflicts<'_> {
+        Conflicts::new(self.doc, self.get_scope(at))
+    }
+
+    fn history_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<ObjRevision<'_>>, AutomergeError> {
+        self.doc.history_of_for(obj.as_ref(), self.get_scope(at))
+    }
+
+    fn history_of_prop<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<PropRevision<'_>>, AutomergeError> {
+        self.doc
+            .history_of_prop_for(obj.as_ref(), prop.into(), self.get_scope(at))
+    }
+
+    fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
+        self.doc.get_missing_deps(heads)
+    }
+
+    fn get_change_by_hash(&self, hash: &ChangeHash) -> Option<&Change> {
+        self.doc.get_change_by_hash(hash)
+    }
+}
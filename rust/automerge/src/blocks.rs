@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::ops::Deref;
+
+use crate::hydrate;
+use crate::value::ScalarValue;
+
+/// The character a block marker occupies in the string returned by [`crate::ReadDoc::text()`]
+pub(crate) const BLOCK_PLACEHOLDER: &str = "\u{fffc}";
+
+/// A block marker in a text object
+///
+/// Marks describe inline formatting, blocks describe the structure of the text: paragraphs,
+/// headings, list items and so on. A block marker sits at the position in the text where its block
+/// begins, the block extends until the next marker. [`crate::transaction::Transactable::split_block()`]
+/// inserts a marker, [`crate::transaction::Transactable::join_block()`] removes one (merging its
+/// block into the previous one) and [`crate::transaction::Transactable::update_block()`] changes
+/// one. [`crate::ReadDoc::spans()`] reads the text and blocks together.
+///
+/// A marker occupies one position in the text (it appears as `\u{fffc}` in
+/// [`crate::ReadDoc::text()`]) and is stored as a map in the text object with the keys `type`,
+/// `parents` and `attrs`. Because markers are ordinary sequence elements concurrent splits and
+/// joins merge like any other concurrent edit to the text, and concurrent updates to the same
+/// block merge field by field.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Block {
+    /// The kind of block, e.g. "paragraph", "heading" or "list-item"
+    pub block_type: String,
+    /// The types of the blocks this block is nested in, outermost first. For example a list item
+    /// in a list nested inside another list would have parents `["list", "list"]`.
+    pub parents: Vec<String>,
+    /// Any other attributes of the block, e.g. the level of a heading
+    pub attrs: BTreeMap<String, ScalarValue>,
+}
+
+impl Block {
+    pub fn new<S: Into<String>>(block_type: S) -> Self {
+        Block {
+            block_type: block_type.into(),
+            parents: Vec::new(),
+            attrs: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_parents<I: IntoIterator<Item = S>, S: Into<String>>(mut self, parents: I) -> Self {
+        self.parents = parents.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_attr<S: Into<String>, V: Into<ScalarValue>>(mut self, name: S, value: V) -> Self {
+        self.attrs.insert(name.into(), value.into());
+        self
+    }
+
+    /// Read a block from the hydrated map which stores it, ignoring anything malformed
+    pub(crate) fn from_hydrated(value: &hydrate::Value) -> Self {
+        let mut block = Block::default();
+        let map = match value {
+            hydrate::Value::Map(map) => map,
+            _ => return block,
+        };
+        let get = |key: &str| map.deref().get(key).map(|v| v.value());
+        if let Some(hydrate::Value::Scalar(ScalarValue::Str(s))) = get("type") {
+            block.block_type = s.to_string();
+        }
+        if let Some(hydrate::Value::List(parents)) = get("parents") {
+            block.parents = parents
+                .iter()
+                .filter_map(|p| match p.value() {
+                    hydrate::Value::Scalar(ScalarValue::Str(s)) => Some(s.to_string()),
+                    _ => None,
+                })
+                .collect();
+        }
+        if let Some(hydrate::Value::Map(attrs)) = get("attrs") {
+            block.attrs = attrs
+                .iter()
+                .filter_map(|(k, v)| match v.value() {
+                    hydrate::Value::Scalar(s) => Some((k.clone(), s.clone())),
+                    _ => None,
+                })
+                .collect();
+        }
+        block
+    }
+}
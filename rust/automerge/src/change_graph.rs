@@ -85,6 +85,29 @@ impl ChangeGraph {
         Ok(())
     }
 
+    /// Remove every change after the first `len`
+    ///
+    /// The changes are removed in the reverse of the order they were added, so nothing which is
+    /// kept depends on them.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len >= self.nodes.len() {
+            return;
+        }
+        // Edges are added along with their child, so those of the removed nodes come last
+        let edges = self.nodes[len..]
+            .iter()
+            .filter_map(|node| node.parents)
+            .map(|edge| edge.0 as usize)
+            .min()
+            .unwrap_or(self.edges.len());
+        for hash in self.hashes.drain(len..) {
+            self.nodes_by_hash.remove(&hash);
+        }
+        self.nodes.truncate(len);
+        self.edges.truncate(edges);
+        self.clock_cache.truncate(len / CACHE_STEP as usize);
+    }
+
     fn add_node(&mut self, actor_index: usize, change: &Change) -> NodeIdx {
         let idx = NodeIdx(self.nodes.len() as u32);
         let hash_idx = self.add_hash(change.hash());
@@ -197,6 +220,39 @@ impl ChangeGraph {
         });
     }
 
+    /// The hashes of `heads` and all their ancestors
+    pub(crate) fn ancestors(&self, heads: &[ChangeHash]) -> BTreeSet<ChangeHash> {
+        let mut result = BTreeSet::new();
+        self.traverse_ancestors(self.heads_to_nodes(heads), |node, _idx| {
+            result.insert(self.hashes[node.hash_idx.0 as usize]);
+            true
+        });
+        result
+    }
+
+    /// The hashes of `heads` and all their descendants
+    pub(crate) fn descendants(&self, heads: &[ChangeHash]) -> BTreeSet<ChangeHash> {
+        // nodes are added after their parents, so a single pass in order sees every parent of a
+        // node before the node itself
+        let mut included = vec![false; self.nodes.len()];
+        for idx in self.heads_to_nodes(heads) {
+            included[idx.0 as usize] = true;
+        }
+        for i in 0..self.nodes.len() {
+            if !included[i] {
+                included[i] = self
+                    .parents(NodeIdx(i as u32))
+                    .any(|parent| included[parent.0 as usize]);
+            }
+        }
+        self.nodes
+            .iter()
+            .zip(included)
+            .filter(|(_, included)| *included)
+            .map(|(node, _)| self.hashes[node.hash_idx.0 as usize])
+            .collect()
+    }
+
     /// Call `f` for each (node, hash) in the graph, starting from the given heads
     ///
     /// No guarantees are made about the order of traversal but each node will only be visited
@@ -287,6 +343,34 @@ mod tests {
         assert_eq!(changes, expected_changes);
     }
 
+    #[test]
+    fn ancestors_and_descendants() {
+        let mut builder = TestGraphBuilder::new();
+        let actor1 = builder.actor();
+        let actor2 = builder.actor();
+        let actor3 = builder.actor();
+        let change1 = builder.change(&actor1, 10, &[]);
+        let change2 = builder.change(&actor2, 20, &[change1]);
+        let change3 = builder.change(&actor3, 30, &[change1]);
+        let change4 = builder.change(&actor1, 10, &[change2]);
+        let graph = builder.build();
+
+        assert_eq!(
+            graph.ancestors(&[change4]),
+            vec![change1, change2, change4].into_iter().collect()
+        );
+        assert_eq!(
+            graph.descendants(&[change2]),
+            vec![change2, change4].into_iter().collect()
+        );
+        assert_eq!(
+            graph.descendants(&[change1]),
+            vec![change1, change2, change3, change4]
+                .into_iter()
+                .collect()
+        );
+    }
+
     struct TestGraphBuilder {
         actors: Vec<ActorId>,
         changes: Vec<Change>,
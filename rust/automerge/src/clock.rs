@@ -78,6 +78,18 @@ impl Clock {
             .or_insert(data);
     }
 
+    /// Set the entry for the actor, even if that moves it backwards. `None` removes the actor.
+    pub(crate) fn set_for_actor(&mut self, actor_index: usize, data: Option<ClockData>) {
+        match data {
+            Some(data) => {
+                self.0.insert(actor_index, data);
+            }
+            None => {
+                self.0.remove(&actor_index);
+            }
+        }
+    }
+
     pub(crate) fn isolate(&mut self, actor_index: usize) {
         self.include(
             actor_index,
@@ -9,6 +9,7 @@ use crate::{
         },
         SpliceError,
     },
+    links::Link,
     storage::parse::{
         leb128::{leb128_i64, leb128_u64},
         Input, ParseResult,
@@ -251,6 +252,15 @@ impl<'a> Iterator for ValueIter<'a> {
                     ValueType::Timestamp => self.parse_input(val_meta, |input| {
                         leb128_i64(input).map(|(i, n)| (i, ScalarValue::Timestamp(n)))
                     }),
+                    // a link which doesn't parse is kept as it is, like a value of an unknown type
+                    ValueType::Link => self.parse_raw(val_meta, |bytes| {
+                        Ok(Link::from_bytes(bytes)
+                            .map(ScalarValue::from)
+                            .unwrap_or_else(|| ScalarValue::Unknown {
+                                type_code: 10,
+                                bytes: bytes.to_vec(),
+                            }))
+                    }),
                     ValueType::Unknown(code) => self.parse_raw(val_meta, |bytes| {
                         Ok(ScalarValue::Unknown {
                             type_code: code,
@@ -364,10 +374,29 @@ fn encode_val<S: Sink>(out: &mut RawEncoder<S>, val: &ScalarValue) -> usize {
         ScalarValue::Counter(i) => out.append(i.start),
         ScalarValue::Str(s) => out.append(RawBytes::from(s.as_bytes())),
         ScalarValue::Bytes(b) => out.append(RawBytes::from(&b[..])),
+        ScalarValue::Link(l) => out.append(RawBytes::from(&l.to_bytes()[..])),
         ScalarValue::Unknown { bytes, .. } => out.append(RawBytes::from(&bytes[..])),
     }
 }
 
+/// The type of a value, from the low four bits of its entry in the value metadata column
+///
+/// | Code | Type      | Value bytes                                                |
+/// |------|-----------|------------------------------------------------------------|
+/// | 0    | Null      | none                                                       |
+/// | 1    | False     | none                                                       |
+/// | 2    | True      | none                                                       |
+/// | 3    | Uleb      | uLEB128 encoded integer                                    |
+/// | 4    | Leb       | LEB128 encoded integer                                     |
+/// | 5    | Float     | little endian IEEE 754 64 bit float                        |
+/// | 6    | String    | UTF-8 bytes                                                |
+/// | 7    | Bytes     | raw bytes                                                  |
+/// | 8    | Counter   | LEB128 encoded starting value                              |
+/// | 9    | Timestamp | LEB128 encoded milliseconds since the unix epoch           |
+/// | 10   | Link      | a document id and optional heads, see [`crate::links`]     |
+///
+/// Any other code is loaded as [`ScalarValue::Unknown`] and written back unchanged, so documents
+/// using a type from a newer version of automerge survive a round trip through an older one.
 #[derive(Debug)]
 enum ValueType {
     Null,
@@ -380,6 +409,7 @@ enum ValueType {
     Bytes,
     Counter,
     Timestamp,
+    Link,
     Unknown(u8),
 }
 
@@ -400,6 +430,7 @@ impl ValueMeta {
             7 => ValueType::Bytes,
             8 => ValueType::Counter,
             9 => ValueType::Timestamp,
+            10 => ValueType::Link,
             other => ValueType::Unknown(other),
         }
     }
@@ -424,6 +455,7 @@ impl From<&ScalarValue> for ValueMeta {
             ScalarValue::Counter(i) => Self((lebsize(i.start) << 4) | 8),
             ScalarValue::Str(s) => Self(((s.as_bytes().len() as u64) << 4) | 6),
             ScalarValue::Bytes(b) => Self(((b.len() as u64) << 4) | 7),
+            ScalarValue::Link(l) => Self(((l.to_bytes().len() as u64) << 4) | 10),
             ScalarValue::Unknown { type_code, bytes } => {
                 Self(((bytes.len() as u64) << 4) | (*type_code as u64))
             }
@@ -458,6 +490,7 @@ impl From<&ScalarValue> for ValueType {
             ScalarValue::Counter(_) => ValueType::Counter,
             ScalarValue::Str(_) => ValueType::String,
             ScalarValue::Bytes(_) => ValueType::Bytes,
+            ScalarValue::Link(_) => ValueType::Link,
             ScalarValue::Unknown { type_code, .. } => ValueType::Unknown(*type_code),
         }
     }
@@ -476,6 +509,7 @@ impl From<ValueType> for u64 {
             ValueType::Bytes => 7,
             ValueType::Counter => 8,
             ValueType::Timestamp => 9,
+            ValueType::Link => 10,
             ValueType::Unknown(other) => other as u64,
         }
     }
@@ -7,7 +7,8 @@ use smol_str::SmolStr;
 
 use crate::{
     columnar::Key,
-    types::{ElemId, OpId, ScalarValue},
+    links::Link,
+    types::{ChangeHash, ElemId, OpId, ScalarValue},
 };
 
 #[derive(Clone, Debug)]
@@ -169,7 +170,12 @@ pub(crate) fn scalar_value() -> impl Strategy<Value = ScalarValue> + Clone {
         any::<Vec<u8>>().prop_map(ScalarValue::Bytes),
         encodable_int().prop_map(|i| ScalarValue::Counter(i.into())),
         encodable_int().prop_map(ScalarValue::Timestamp),
-        (10..15_u8, any::<Vec<u8>>()).prop_map(|(c, b)| ScalarValue::Unknown { type_code: c, bytes: b }),
+        (any::<String>(), proptest::option::of(proptest::collection::vec(any::<[u8; 32]>(), 0..3)))
+            .prop_map(|(doc, heads)| ScalarValue::from(Link {
+                doc,
+                heads: heads.map(|h| h.into_iter().map(ChangeHash).collect()),
+            })),
+        (11..16_u8, any::<Vec<u8>>()).prop_map(|(c, b)| ScalarValue::Unknown { type_code: c, bytes: b }),
     }
 }
 
@@ -0,0 +1,99 @@
+//! Finding and resolving conflicts
+//!
+//! When peers concurrently put different values in the same key of a map or the same index of a
+//! list every value is kept, and reads such as [`crate::ReadDoc::get()`] pick one of them
+//! deterministically. [`crate::ReadDoc::conflicts()`] lists the keys of an object which hold more
+//! than one value and [`crate::ReadDoc::all_conflicts()`] lists them for the whole document.
+//! [`crate::transaction::Transactable::resolve_conflict()`] then chooses a winner.
+//!
+//! ```
+//! # use automerge::{AutoCommit, ReadDoc, ROOT, transaction::Transactable};
+//! let mut doc1 = AutoCommit::new();
+//! let mut doc2 = doc1.fork();
+//! doc1.put(ROOT, "title", "ours").unwrap();
+//! doc2.put(ROOT, "title", "theirs").unwrap();
+//! doc1.merge(&mut doc2).unwrap();
+//!
+//! let conflicts = doc1.all_conflicts(None).collect::<Vec<_>>();
+//! assert_eq!(conflicts.len(), 1);
+//! assert_eq!(conflicts[0].values.len(), 2);
+//!
+//! let theirs = conflicts[0]
+//!     .values
+//!     .iter()
+//!     .find(|c| c.value.to_str() == Some("theirs"))
+//!     .unwrap()
+//!     .id
+//!     .clone();
+//! doc1.resolve_conflict(ROOT, "title", &theirs).unwrap();
+//! assert_eq!(doc1.all_conflicts(None).count(), 0);
+//! assert_eq!(doc1.get(ROOT, "title").unwrap().unwrap().0.to_str(), Some("theirs"));
+//! ```
+use std::collections::VecDeque;
+
+use crate::exid::ExId;
+use crate::types::{Clock, ObjMeta};
+use crate::{ActorId, Automerge, ChangeHash, Prop, Value};
+
+/// A key or index which holds more than one value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict<'a> {
+    /// The object containing the conflict
+    pub obj: ExId,
+    pub prop: Prop,
+    /// Every value, in the order of [`crate::ReadDoc::get_all()`]. The last one is the value
+    /// [`crate::ReadDoc::get()`] returns.
+    pub values: Vec<Contender<'a>>,
+}
+
+/// One of the values of a [`Conflict`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contender<'a> {
+    pub value: Value<'a>,
+    /// The id of the op which set the value
+    pub id: ExId,
+    /// The actor which set the value
+    pub actor: ActorId,
+    /// The change which set the value, `None` if it is in a transaction which hasn't been
+    /// committed yet
+    pub hash: Option<ChangeHash>,
+}
+
+/// Iterator over the conflicts in a document, created by [`crate::ReadDoc::all_conflicts()`]
+///
+/// Objects are visited depth first from the root, including objects which are only reachable
+/// through a value which lost a conflict.
+#[derive(Debug)]
+pub struct Conflicts<'a> {
+    doc: &'a Automerge,
+    clock: Option<Clock>,
+    objs: Vec<ObjMeta>,
+    found: VecDeque<Conflict<'a>>,
+}
+
+impl<'a> Conflicts<'a> {
+    pub(crate) fn new(doc: &'a Automerge, clock: Option<Clock>) -> Self {
+        Conflicts {
+            doc,
+            clock,
+            objs: vec![ObjMeta::root()],
+            found: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a> Iterator for Conflicts<'a> {
+    type Item = Conflict<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.found.is_empty() {
+            let obj = self.objs.pop()?;
+            let mut children = self.doc.child_objects(&obj, self.clock.as_ref());
+            children.reverse();
+            self.objs.extend(children);
+            self.found
+                .extend(self.doc.conflicts_in(&obj, self.clock.as_ref()));
+        }
+        self.found.pop_front()
+    }
+}
@@ -119,3 +119,270 @@ impl TryFrom<Vec<u8>> for Cursor {
         Self::try_from(value.as_slice())
     }
 }
+
+/// Where a [`Cursor`] whose element has been deleted resolves to
+///
+/// See [`ReadDoc::get_cursor_position_with_resolution()`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CursorResolution {
+    /// The position of the nearest visible element before the deleted one, or `0` if there
+    /// isn't one
+    Preceding,
+    /// The position of the nearest visible element after the deleted one, or the end of the
+    /// sequence if there isn't one. This is what [`ReadDoc::get_cursor_position()`] does.
+    Following,
+}
+
+/// Which side of a position in a sequence a [`StickyCursor`] attaches to
+///
+/// A position in a sequence lies between two elements. When another user inserts text at that
+/// position concurrently the position has to end up either before or after the inserted text,
+/// the stickiness of the cursor decides which.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stickiness {
+    /// Attach to the element before the position, text inserted at the position ends up after
+    /// the cursor
+    Left,
+    /// Attach to the element after the position, text inserted at the position ends up before
+    /// the cursor
+    Right,
+}
+
+/// A position between two elements of a sequence which attaches to one of them
+///
+/// Unlike a [`Cursor`], which identifies an element, a sticky cursor identifies the gap before or
+/// after an element according to its [`Stickiness`]. A left sticky cursor at the start of the
+/// sequence, or a right sticky cursor at the end, has no element to attach to and stays at the
+/// start or end.
+///
+/// If the element the cursor is attached to is deleted the cursor resolves to the position the
+/// element used to be at.
+///
+/// A sticky cursor is obtained from [`ReadDoc::get_sticky_cursor()`] and dereferenced with
+/// [`ReadDoc::get_sticky_cursor_position()`].
+#[derive(Clone, PartialEq, Debug)]
+pub struct StickyCursor {
+    cursor: Option<Cursor>,
+    stickiness: Stickiness,
+}
+
+impl StickyCursor {
+    /// A cursor attached to the element `cursor` points at
+    pub fn new(cursor: Cursor, stickiness: Stickiness) -> Self {
+        StickyCursor {
+            cursor: Some(cursor),
+            stickiness,
+        }
+    }
+
+    /// A cursor which always stays at the start of the sequence
+    pub fn start() -> Self {
+        StickyCursor {
+            cursor: None,
+            stickiness: Stickiness::Left,
+        }
+    }
+
+    /// A cursor which always stays at the end of the sequence
+    pub fn end() -> Self {
+        StickyCursor {
+            cursor: None,
+            stickiness: Stickiness::Right,
+        }
+    }
+
+    /// The element this cursor is attached to, `None` if it is at the start or end
+    pub fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
+
+    pub fn stickiness(&self) -> Stickiness {
+        self.stickiness
+    }
+
+    fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        let mut flags = 0;
+        if self.stickiness == Stickiness::Right {
+            flags |= STICKY_RIGHT;
+        }
+        match &self.cursor {
+            Some(cursor) => {
+                bytes.push(flags | HAS_CURSOR);
+                let cursor_bytes = cursor.to_bytes();
+                leb128::write::unsigned(bytes, cursor_bytes.len() as u64).unwrap();
+                bytes.extend_from_slice(&cursor_bytes);
+            }
+            None => bytes.push(flags),
+        }
+    }
+
+    fn parse(i: parse::Input<'_>) -> Result<(parse::Input<'_>, Self), AutomergeError> {
+        let (i, flags) = parse::take1::<()>(i).map_err(|_| AutomergeError::InvalidCursorFormat)?;
+        if flags & !(STICKY_RIGHT | HAS_CURSOR) != 0 {
+            return Err(AutomergeError::InvalidCursorFormat);
+        }
+        let stickiness = if flags & STICKY_RIGHT == 0 {
+            Stickiness::Left
+        } else {
+            Stickiness::Right
+        };
+        if flags & HAS_CURSOR == 0 {
+            return Ok((
+                i,
+                StickyCursor {
+                    cursor: None,
+                    stickiness,
+                },
+            ));
+        }
+        let (i, len) = parse::leb128_u64::<parse::leb128::Error>(i)
+            .map_err(|_| AutomergeError::InvalidCursorFormat)?;
+        let (i, cursor) = parse::take_n::<()>(len as usize, i)
+            .map_err(|_| AutomergeError::InvalidCursorFormat)?;
+        Ok((i, StickyCursor::new(cursor.try_into()?, stickiness)))
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "start" => Some(Self::start()),
+            "end" => Some(Self::end()),
+            _ => {
+                if let Some(cursor) = s.strip_prefix('<') {
+                    Some(Self::new(Cursor::from_str(cursor)?, Stickiness::Left))
+                } else {
+                    let cursor = s.strip_prefix('>')?;
+                    Some(Self::new(Cursor::from_str(cursor)?, Stickiness::Right))
+                }
+            }
+        }
+    }
+}
+
+const STICKY_RIGHT: u8 = 1;
+const HAS_CURSOR: u8 = 2;
+
+impl fmt::Display for StickyCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.cursor, self.stickiness) {
+            (None, Stickiness::Left) => write!(f, "start"),
+            (None, Stickiness::Right) => write!(f, "end"),
+            (Some(cursor), Stickiness::Left) => write!(f, "<{}", cursor),
+            (Some(cursor), Stickiness::Right) => write!(f, ">{}", cursor),
+        }
+    }
+}
+
+/// A selection in a sequence which survives concurrent changes
+///
+/// A selection has an anchor, the end where the selection started, and a head, the end which
+/// moves as the selection is extended. The head can be before the anchor. Each end is a
+/// [`StickyCursor`]. [`ReadDoc::get_selection()`] attaches the start of the selection to the
+/// first selected element and the end to the last selected element, so text inserted
+/// concurrently just outside the selection is not selected, and if all the selected text is
+/// deleted both ends resolve to the same position and the selection collapses.
+///
+/// A selection is dereferenced with [`ReadDoc::get_selection_position()`] and can be persisted
+/// using [`Self::to_bytes()`] and [`TryFrom<&[u8]>`][TryFrom] or as a string.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Selection {
+    anchor: StickyCursor,
+    head: StickyCursor,
+}
+
+impl Selection {
+    pub fn new(anchor: StickyCursor, head: StickyCursor) -> Self {
+        Selection { anchor, head }
+    }
+
+    pub fn anchor(&self) -> &StickyCursor {
+        &self.anchor
+    }
+
+    pub fn head(&self) -> &StickyCursor {
+        &self.head
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // The serialized format is
+        //
+        // .-----------------------------------------.
+        // | version | anchor        | head          |
+        // +-----------------------------------------+
+        // | 1 byte  | sticky cursor | sticky cursor |
+        // '-----------------------------------------'
+        //
+        // where a sticky cursor is
+        //
+        // .-----------------------------------------.
+        // | flags  | cursor len      | cursor bytes |
+        // +-----------------------------------------+
+        // | 1 byte | unsigned leb128 | variable     |
+        // '-----------------------------------------'
+        //
+        // Bit 0 of the flags is set if the cursor is right sticky and bit 1 if it is attached to
+        // an element, otherwise the cursor length and bytes are omitted. The cursor bytes are
+        // in the format of `Cursor::to_bytes`. Version is currently always `0`
+        //
+        let mut bytes = vec![SERIALIZATION_VERSION_TAG];
+        self.anchor.write_bytes(&mut bytes);
+        self.head.write_bytes(&mut bytes);
+        bytes
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        let (anchor, head) = s.split_once(',')?;
+        Some(Selection {
+            anchor: StickyCursor::from_str(anchor)?,
+            head: StickyCursor::from_str(head)?,
+        })
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.anchor, self.head)
+    }
+}
+
+impl TryFrom<&str> for Selection {
+    type Error = AutomergeError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Selection::from_str(s).ok_or(AutomergeError::InvalidCursorFormat)
+    }
+}
+
+impl TryFrom<String> for Selection {
+    type Error = AutomergeError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.as_str().try_into()
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Selection {
+    type Error = AutomergeError;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let i = parse::Input::new(value);
+        let (i, version) =
+            parse::take1::<()>(i).map_err(|_| AutomergeError::InvalidCursorFormat)?;
+        if version != SERIALIZATION_VERSION_TAG {
+            return Err(AutomergeError::InvalidCursorFormat);
+        }
+        let (i, anchor) = StickyCursor::parse(i)?;
+        let (i, head) = StickyCursor::parse(i)?;
+        if !i.is_empty() {
+            return Err(AutomergeError::InvalidCursorFormat);
+        }
+        Ok(Selection { anchor, head })
+    }
+}
+
+impl TryFrom<Vec<u8>> for Selection {
+    type Error = AutomergeError;
+
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::try_from(value.as_slice())
+    }
+}
@@ -22,6 +22,8 @@ pub enum AutomergeError {
     InvalidChangeHashBytes(#[from] InvalidChangeHashSlice),
     #[error("invalid UTF-8 character at {0}")]
     InvalidCharacter(usize),
+    #[error("`{0}` is not one of the values of the property")]
+    InvalidContender(String),
     #[error("invalid hash {0}")]
     InvalidHash(ChangeHash),
     #[error("index {0} is out of bounds")]
@@ -32,12 +34,16 @@ pub enum AutomergeError {
     InvalidObjIdFormat(String),
     #[error("invalid op for object of type `{0}`")]
     InvalidOp(ObjType),
+    #[error("there is no block marker at index {0}")]
+    NotABlock(usize),
     #[error("seq {0} is out of bounds")]
     InvalidSeq(u64),
     #[error("cursor {0} is invalid")]
     InvalidCursor(Cursor),
     #[error("cursor format is invalid")]
     InvalidCursorFormat,
+    #[error("change {0} can't be added to or removed from the view")]
+    InvalidViewStep(ChangeHash),
     #[error("invalid type of value, expected `{expected}` but received `{unexpected}`")]
     InvalidValueType {
         expected: String,
@@ -53,10 +59,18 @@ pub enum AutomergeError {
     MissingHash(ChangeHash),
     #[error("change's deps should already be in the document")]
     MissingDeps,
+    #[error("there is no branch or tag called `{0}`")]
+    MissingRef(String),
     #[error("compressed chunk was not a change")]
     NonChangeCompressed,
     #[error("id was not an object id")]
     NotAnObject,
+    #[error("there is already a branch or tag called `{0}`")]
+    RefExists(String),
+    #[error("`{0}` is a tag, which can't be moved")]
+    RefIsTag(String),
+    #[error("linked document {0} could not be resolved")]
+    UnresolvedLink(String),
     #[error(transparent)]
     HydrateError(#[from] HydrateError),
 }
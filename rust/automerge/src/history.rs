@@ -0,0 +1,192 @@
+//! Querying the history of a document
+//!
+//! [`crate::Automerge::log()`] lists the changes in a document, like `git log`. Each change is
+//! described by a [`ChangeMeta`] which borrows its metadata from the document rather than
+//! returning the whole [`crate::Change`], and the changes can be filtered by actor, time, their
+//! place in the change graph and the objects they modified.
+//!
+//! [`crate::ReadDoc::history_of()`] and [`crate::ReadDoc::history_of_prop()`] list the changes to
+//! a single object or key along with what each change did to it, as a [`Revision`].
+//!
+//! ```
+//! # use automerge::{AutoCommit, ReadDoc, ROOT, transaction::Transactable};
+//! let mut doc = AutoCommit::new();
+//! doc.put(ROOT, "title", "draft").unwrap();
+//! doc.commit_with(automerge::transaction::CommitOptions::default().with_message("start"));
+//! let list = doc.put_object(ROOT, "todo", automerge::ObjType::List).unwrap();
+//! doc.insert(&list, 0, "write docs").unwrap();
+//!
+//! let log = doc.log();
+//! let messages = log
+//!     .changes()
+//!     .unwrap()
+//!     .iter()
+//!     .map(|c| c.message)
+//!     .collect::<Vec<_>>();
+//! assert_eq!(messages, vec![Some("start"), None]);
+//! assert_eq!(doc.log().touching(&list).changes().unwrap().len(), 1);
+//! ```
+use std::collections::HashSet;
+use std::ops::{Bound, RangeBounds};
+
+use crate::exid::ExId;
+use crate::{ActorId, Automerge, AutomergeError, Change, ChangeHash, Prop, Value};
+
+/// The metadata of a change, returned by [`Log::changes()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeMeta<'a> {
+    pub hash: ChangeHash,
+    pub actor: &'a ActorId,
+    pub seq: u64,
+    /// The time the change was made, in milliseconds since the unix epoch
+    pub timestamp: i64,
+    pub message: Option<&'a str>,
+    pub deps: &'a [ChangeHash],
+    /// The number of ops in the change
+    pub num_ops: usize,
+}
+
+impl<'a> From<&'a Change> for ChangeMeta<'a> {
+    fn from(change: &'a Change) -> Self {
+        ChangeMeta {
+            hash: change.hash(),
+            actor: change.actor_id(),
+            seq: change.seq(),
+            timestamp: change.timestamp(),
+            message: change.message().map(String::as_str),
+            deps: change.deps(),
+            num_ops: change.len(),
+        }
+    }
+}
+
+/// What a change did to an object or key, returned by [`crate::ReadDoc::history_of()`] and
+/// [`crate::ReadDoc::history_of_prop()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revision<V> {
+    pub hash: ChangeHash,
+    /// The value as at the heads `[hash]`, i.e. including the change and everything it depends
+    /// on but none of the changes concurrent with it
+    pub value: V,
+}
+
+/// A [`Revision`] of a key, holding the value and the id of the op which set it, `None` if the
+/// key was deleted
+pub type PropRevision<'a> = Revision<Option<(Value<'a>, ExId)>>;
+
+/// A [`Revision`] of an object, holding the keys of the object which the change modified, each
+/// with its value after the change as in a [`PropRevision`]
+///
+/// For a list or text object the keys are indexes as at the change. An element which the change
+/// deleted has the index of the next element which is still there.
+pub type ObjRevision<'a> = Revision<Vec<(Prop, Option<(Value<'a>, ExId)>)>>;
+
+/// A query over the history of a document, created by [`crate::Automerge::log()`]
+///
+/// With no filters every change in the document is returned. Each filter narrows the result
+/// further, so setting several returns the changes which match all of them.
+#[derive(Debug, Clone)]
+pub struct Log<'a> {
+    doc: &'a Automerge,
+    actor: Option<ActorId>,
+    time: (Bound<i64>, Bound<i64>),
+    ancestors_of: Option<Vec<ChangeHash>>,
+    descendants_of: Option<Vec<ChangeHash>>,
+    touching: Option<ExId>,
+}
+
+impl<'a> Log<'a> {
+    pub(crate) fn new(doc: &'a Automerge) -> Self {
+        Log {
+            doc,
+            actor: None,
+            time: (Bound::Unbounded, Bound::Unbounded),
+            ancestors_of: None,
+            descendants_of: None,
+            touching: None,
+        }
+    }
+
+    /// Only changes made by `actor`
+    pub fn actor(mut self, actor: &ActorId) -> Self {
+        self.actor = Some(actor.clone());
+        self
+    }
+
+    /// Only changes whose timestamp is in `range`
+    ///
+    /// Timestamps are set by whoever made the change, so they may not be accurate and may not be
+    /// in the same order as the change graph.
+    pub fn time_range<R: RangeBounds<i64>>(mut self, range: R) -> Self {
+        self.time = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    /// Only `heads` and the changes they depend on, directly or indirectly
+    pub fn ancestors_of(mut self, heads: &[ChangeHash]) -> Self {
+        self.ancestors_of = Some(heads.to_vec());
+        self
+    }
+
+    /// Only `heads` and the changes which depend on them, directly or indirectly
+    pub fn descendants_of(mut self, heads: &[ChangeHash]) -> Self {
+        self.descendants_of = Some(heads.to_vec());
+        self
+    }
+
+    /// Only changes which modified the object `obj`
+    ///
+    /// That is the change which created it and any change which put, inserted, deleted, marked
+    /// or incremented something in it. Changes to objects nested inside `obj` are not included.
+    pub fn touching(mut self, obj: &ExId) -> Self {
+        self.touching = Some(obj.clone());
+        self
+    }
+
+    /// The changes which match the filters, in the order they were applied to the document
+    ///
+    /// This order always has a change after all of its dependencies. Use `.rev()` on the result
+    /// to get the most recent changes first.
+    ///
+    /// # Errors
+    ///
+    /// If the object passed to [`Self::touching()`] is not in the document
+    pub fn changes(&self) -> Result<Vec<ChangeMeta<'a>>, AutomergeError> {
+        let ancestors = self
+            .ancestors_of
+            .as_ref()
+            .map(|heads| self.doc.change_graph().ancestors(heads));
+        let descendants = self
+            .descendants_of
+            .as_ref()
+            .map(|heads| self.doc.change_graph().descendants(heads));
+        let touching = match &self.touching {
+            Some(obj) => Some(self.touching_changes(obj)?),
+            None => None,
+        };
+        Ok(self
+            .doc
+            .history()
+            .iter()
+            .filter(|change| {
+                let hash = change.hash();
+                self.actor.as_ref().map_or(true, |a| a == change.actor_id())
+                    && self.time.contains(&change.timestamp())
+                    && ancestors.as_ref().map_or(true, |a| a.contains(&hash))
+                    && descendants.as_ref().map_or(true, |d| d.contains(&hash))
+                    && touching.as_ref().map_or(true, |t| t.contains(&hash))
+            })
+            .map(ChangeMeta::from)
+            .collect())
+    }
+
+    fn touching_changes(&self, obj: &ExId) -> Result<HashSet<ChangeHash>, AutomergeError> {
+        let obj = self.doc.exid_to_obj(obj)?;
+        Ok(self
+            .doc
+            .changes_touching(&obj.id, None)
+            .into_iter()
+            .map(Change::hash)
+            .collect())
+    }
+}
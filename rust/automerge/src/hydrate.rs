@@ -154,13 +154,22 @@ impl Automerge {
         Value::Text(Text::new(text.into()))
     }
 
+    pub(crate) fn hydrate_obj(&self, obj: &ObjId, typ: ObjType, clock: Option<&Clock>) -> Value {
+        match typ {
+            ObjType::Map | ObjType::Table => self.hydrate_map(obj, clock),
+            ObjType::List => self.hydrate_list(obj, clock),
+            ObjType::Text => self.hydrate_text(obj, clock),
+        }
+    }
+
     pub(crate) fn hydrate_op(&self, op: Op<'_>, clock: Option<&Clock>) -> Value {
         match op.action() {
             OpType::Make(ObjType::Map) => self.hydrate_map(&op.id().into(), clock),
             OpType::Make(ObjType::Table) => self.hydrate_map(&op.id().into(), clock),
             OpType::Make(ObjType::List) => self.hydrate_list(&op.id().into(), clock),
             OpType::Make(ObjType::Text) => self.hydrate_text(&op.id().into(), clock),
-            OpType::Put(scalar) => Value::Scalar(scalar.clone()),
+            // counters include only the increments covered by `clock`
+            OpType::Put(_) => op.value_at(clock).into(),
             _ => panic!("invalid op to hydrate"),
         }
     }
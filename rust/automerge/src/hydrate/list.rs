@@ -69,6 +69,10 @@ impl List {
         self.0.get(index).map(|lv| &lv.value)
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = &ListValue> {
+        self.0.iter()
+    }
+
     pub(crate) fn push<V: Into<Value>>(&mut self, value: V, _id: ExId, conflict: bool) {
         self.0.push(ListValue::new(value.into(), conflict))
     }
@@ -79,6 +83,10 @@ impl List {
 }
 
 impl ListValue {
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
     pub(crate) fn increment(&mut self, n: i64) -> Result<(), HydrateError> {
         if let Value::Scalar(ScalarValue::Counter(c)) = &mut self.value {
             c.increment(n);
@@ -60,6 +60,10 @@ impl Map {
 }
 
 impl MapValue {
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
     pub(crate) fn new(value: Value, _id: ExId, conflict: bool) -> Self {
         Self { value, conflict }
     }
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::{text_value::TextValue, PatchAction, ScalarValue};
+use crate::{blocks::BLOCK_PLACEHOLDER, text_value::TextValue, PatchAction, ScalarValue};
 
 use super::{HydrateError, Value};
 
@@ -23,6 +23,11 @@ impl Text {
                 }
                 Ok(())
             }
+            PatchAction::SplitBlock { index, .. } => {
+                self.value.splice(index, BLOCK_PLACEHOLDER);
+                Ok(())
+            }
+            PatchAction::UpdateBlock { .. } => Ok(()),
             PatchAction::Mark { marks: _ } => {
                 todo!()
             }
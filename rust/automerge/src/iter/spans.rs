@@ -0,0 +1,83 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::sync::Arc;
+
+use crate::blocks::Block;
+use crate::marks::MarkSet;
+use crate::types::{Clock, ObjType, OpType};
+use crate::Automerge;
+
+use super::TopOps;
+
+/// A run of text or a block marker in a text object, see [`crate::ReadDoc::spans()`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Span {
+    /// A run of text containing no block markers, all of which has the same marks
+    Text(String, Option<Arc<MarkSet>>),
+    /// A block marker
+    Block(Block),
+}
+
+/// Iterator created by the [`crate::ReadDoc::spans()`] method
+#[derive(Default)]
+pub struct Spans<'a> {
+    iter: Option<SpansInner<'a>>,
+}
+
+struct SpansInner<'a> {
+    doc: &'a Automerge,
+    ops: Peekable<TopOps<'a>>,
+    clock: Option<Clock>,
+}
+
+impl<'a> Spans<'a> {
+    pub(crate) fn new(doc: &'a Automerge, ops: TopOps<'a>, clock: Option<Clock>) -> Self {
+        Self {
+            iter: Some(SpansInner {
+                doc,
+                ops: ops.peekable(),
+                clock,
+            }),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Spans<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spans").finish()
+    }
+}
+
+impl<'a> Iterator for Spans<'a> {
+    type Item = Span;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let inner = self.iter.as_mut()?;
+        let mut text = String::new();
+        let mut marks = None;
+        while let Some(top) = inner.ops.peek() {
+            if let OpType::Make(ObjType::Map) = top.op.action() {
+                if !text.is_empty() {
+                    break;
+                }
+                let block = inner
+                    .doc
+                    .hydrate_map(&top.op.id().into(), inner.clock.as_ref());
+                inner.ops.next();
+                return Some(Span::Block(Block::from_hydrated(&block)));
+            }
+            if text.is_empty() {
+                marks = top.marks.clone();
+            } else if top.marks != marks {
+                break;
+            }
+            text.push_str(top.op.as_str());
+            inner.ops.next();
+        }
+        if text.is_empty() {
+            None
+        } else {
+            Some(Span::Text(text, marks))
+        }
+    }
+}
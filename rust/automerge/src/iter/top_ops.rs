@@ -76,10 +76,10 @@ impl<'a> Iterator for TopOpsInner<'a> {
                 let key = op.elemid_or_key();
                 let visible = op.visible_at(self.clock.as_ref());
                 match &self.clock {
-                    Some(c) if c.covers(op.id()) => {
+                    Some(c) if !c.covers(op.id()) => {}
+                    _ => {
                         self.marks.process(*op.id(), op.action(), self.iter.osd);
                     }
-                    _ => {}
                 }
                 match &self.key {
                     Some(k) if k == &key => {
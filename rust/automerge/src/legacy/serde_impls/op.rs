@@ -217,6 +217,9 @@ impl<'de> Deserialize<'de> for Op {
                         Some(ScalarValue::Bytes(s)) => {
                             Err(Error::invalid_value(Unexpected::Bytes(&s), &"a number"))
                         }
+                        Some(ScalarValue::Link(_)) => {
+                            Err(Error::invalid_value(Unexpected::Other("link"), &"a number"))
+                        }
                         Some(ScalarValue::Unknown { bytes, .. }) => {
                             Err(Error::invalid_value(Unexpected::Bytes(&bytes), &"a number"))
                         }
@@ -256,21 +256,26 @@ macro_rules! __log {
      }
  }
 
+pub mod annotations;
 mod autocommit;
 mod automerge;
 mod autoserde;
+pub mod blocks;
 mod change;
 mod change_graph;
 mod clock;
 mod columnar;
+pub mod conflicts;
 mod convert;
 mod cursor;
 mod error;
 mod exid;
+pub mod history;
 pub mod hydrate;
 mod indexed_cache;
 pub mod iter;
 mod legacy;
+pub mod links;
 pub mod marks;
 mod op_set;
 pub mod op_tree;
@@ -278,22 +283,29 @@ mod parents;
 pub mod patches;
 mod query;
 mod read;
+pub mod refs;
+pub mod revert;
+pub mod rich_text;
 mod sequence_tree;
 mod storage;
 pub mod sync;
 mod text_diff;
+mod text_lines;
 mod text_value;
 pub mod transaction;
+pub mod tree_diff;
 mod types;
 mod value;
 #[cfg(feature = "optree-visualisation")]
 mod visualisation;
 
-pub use crate::automerge::{Automerge, LoadOptions, OnPartialLoad, SaveOptions, StringMigration};
+pub use crate::automerge::{
+    Automerge, LoadOptions, MergePreview, OnPartialLoad, SaveOptions, StringMigration, View,
+};
 pub use autocommit::AutoCommit;
 pub use autoserde::AutoSerde;
 pub use change::{Change, LoadError as LoadChangeError};
-pub use cursor::Cursor;
+pub use cursor::{Cursor, CursorResolution, Selection, Stickiness, StickyCursor};
 pub use error::AutomergeError;
 pub use error::InvalidActorId;
 pub use error::InvalidChangeHashSlice;
@@ -304,6 +316,8 @@ pub use patches::{Patch, PatchAction, PatchLog};
 pub use read::ReadDoc;
 pub use sequence_tree::SequenceTree;
 pub use storage::VerificationMode;
+pub use text_lines::TextLines;
+pub use text_value::TextEncoding;
 pub use types::{ActorId, ChangeHash, ObjType, OpType, ParseChangeHashError, Prop};
 pub use value::{ScalarValue, Value};
 
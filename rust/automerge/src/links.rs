@@ -0,0 +1,257 @@
+//! Typed links between documents
+//!
+//! A data model often spans many documents, with one document referring to another by its id.
+//! Storing the id as a string works, but nothing distinguishes it from any other string. A
+//! [`Link`] is a scalar value which holds the id of another document and, optionally, the heads
+//! of the version it refers to. A link without heads follows the latest version of the target.
+//!
+//! Automerge doesn't know how documents are identified or stored, so following a link goes
+//! through a [`LinkResolver`] which the application implements to look up documents by id.
+//!
+//! ```
+//! # use std::collections::HashMap;
+//! # use automerge::{AutoCommit, Automerge, ReadDoc, ROOT, transaction::Transactable};
+//! # use automerge::links::{self, Link};
+//! let mut author = AutoCommit::new();
+//! author.put(ROOT, "name", "Ada").unwrap();
+//!
+//! let mut post = AutoCommit::new();
+//! post.put(ROOT, "author", Link::new("ada")).unwrap();
+//!
+//! let mut docs = HashMap::new();
+//! docs.insert("ada".to_string(), author.document().clone());
+//!
+//! let found = links::links(&post);
+//! let view = links::follow(&found[0].link, &docs).unwrap();
+//! let (name, _) = view.get(ROOT, "name").unwrap().unwrap();
+//! assert_eq!(name.to_str(), Some("Ada"));
+//! ```
+//!
+//! ## Encoding
+//!
+//! Links are stored in the value columns with type code 10. The value bytes are the uLEB128
+//! length of the document id followed by its UTF-8 bytes and then, only if the link has heads,
+//! the uLEB128 number of heads followed by each 32 byte head. Versions of automerge from before
+//! links load such a value as a [`crate::ScalarValue::Unknown`] and preserve it.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::exid::ExId;
+use crate::types::HASH_SIZE;
+use crate::{Automerge, AutomergeError, ChangeHash, ObjType, Prop, ReadDoc, Value, View, ROOT};
+
+/// A link to another document, stored as [`crate::ScalarValue::Link`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Link {
+    /// The id of the linked document
+    pub doc: String,
+    /// The version of the document the link refers to, `None` to follow the latest version
+    pub heads: Option<Vec<ChangeHash>>,
+}
+
+impl Link {
+    /// A link to the latest version of `doc`
+    pub fn new<S: Into<String>>(doc: S) -> Self {
+        Self {
+            doc: doc.into(),
+            heads: None,
+        }
+    }
+
+    /// A link to the version of `doc` at `heads`
+    pub fn at<S: Into<String>>(doc: S, heads: &[ChangeHash]) -> Self {
+        Self {
+            doc: doc.into(),
+            heads: Some(heads.to_vec()),
+        }
+    }
+
+    /// The encoding of the link in a value column: the length prefixed id of the document
+    /// followed, if the link has heads, by the number of heads and the heads themselves
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        leb128::write::unsigned(&mut bytes, self.doc.len() as u64).unwrap();
+        bytes.extend(self.doc.as_bytes());
+        if let Some(heads) = &self.heads {
+            leb128::write::unsigned(&mut bytes, heads.len() as u64).unwrap();
+            for head in heads {
+                bytes.extend(head.as_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub(crate) fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let len = leb128::read::unsigned(&mut bytes).ok()? as usize;
+        if bytes.len() < len {
+            return None;
+        }
+        let doc = std::str::from_utf8(&bytes[..len]).ok()?.to_string();
+        bytes = &bytes[len..];
+        if bytes.is_empty() {
+            return Some(Self { doc, heads: None });
+        }
+        let count = leb128::read::unsigned(&mut bytes).ok()? as usize;
+        if bytes.len() != count.checked_mul(HASH_SIZE)? {
+            return None;
+        }
+        let heads = bytes
+            .chunks_exact(HASH_SIZE)
+            .map(|chunk| ChangeHash::try_from(chunk).ok())
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            doc,
+            heads: Some(heads),
+        })
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.doc)?;
+        if let Some(heads) = &self.heads {
+            let heads = heads.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+            write!(f, "@[{}]", heads.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// A link found in a document by [`links()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboundLink {
+    /// The object holding the link
+    pub obj: ExId,
+    /// Where the link is in `obj`
+    pub prop: Prop,
+    /// The link itself
+    pub link: Link,
+}
+
+/// Looks up the documents which links refer to
+pub trait LinkResolver {
+    /// The document with id `doc`, or `None` if it isn't available
+    fn resolve(&self, doc: &str) -> Option<&Automerge>;
+}
+
+impl LinkResolver for HashMap<String, Automerge> {
+    fn resolve(&self, doc: &str) -> Option<&Automerge> {
+        self.get(doc)
+    }
+}
+
+impl LinkResolver for BTreeMap<String, Automerge> {
+    fn resolve(&self, doc: &str) -> Option<&Automerge> {
+        self.get(doc)
+    }
+}
+
+/// Every link in the visible state of `doc`, in depth first order
+///
+/// Only the winning value of a conflicted property is considered. To find the links in a
+/// historical version of a document pass a [`View`] of it.
+pub fn links<R: ReadDoc>(doc: &R) -> Vec<OutboundLink> {
+    let mut found = Vec::new();
+    collect(doc, ROOT, ObjType::Map, &mut found);
+    found
+}
+
+fn collect<R: ReadDoc>(doc: &R, obj: ExId, obj_type: ObjType, found: &mut Vec<OutboundLink>) {
+    let values: Vec<(Prop, Value<'_>, ExId)> = match obj_type {
+        ObjType::Map | ObjType::Table => doc
+            .map_range(&obj, ..)
+            .map(|item| (Prop::from(item.key), item.value, item.id))
+            .collect(),
+        ObjType::List => doc
+            .list_range(&obj, ..)
+            .map(|item| (Prop::from(item.index), item.value, item.id))
+            .collect(),
+        ObjType::Text => return,
+    };
+    for (prop, value, id) in values {
+        match value {
+            Value::Object(obj_type) => collect(doc, id, obj_type, found),
+            Value::Scalar(s) => {
+                if let Some(link) = s.to_link() {
+                    found.push(OutboundLink {
+                        obj: obj.clone(),
+                        prop,
+                        link: link.clone(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The version of the document `link` refers to
+///
+/// # Errors
+///
+/// * [`AutomergeError::UnresolvedLink`] if the resolver doesn't have the document
+/// * [`AutomergeError::MissingHash`] if the document doesn't have the heads of the link
+pub fn follow<'a, L: LinkResolver + ?Sized>(
+    link: &Link,
+    resolver: &'a L,
+) -> Result<View<'a>, AutomergeError> {
+    let doc = resolver
+        .resolve(&link.doc)
+        .ok_or_else(|| AutomergeError::UnresolvedLink(link.doc.clone()))?;
+    match &link.heads {
+        Some(heads) => {
+            if let Some(missing) = heads.iter().find(|h| doc.get_change_by_hash(h).is_none()) {
+                return Err(AutomergeError::MissingHash(*missing));
+            }
+            Ok(doc.view_at(heads))
+        }
+        None => Ok(doc.view_at(&doc.get_heads())),
+    }
+}
+
+/// A document which has to be synced along with the one passed to [`sync_set()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncTarget {
+    /// The id of the document
+    pub doc: String,
+    /// Every head which links pin the document to, the peer needs at least these changes
+    pub heads: BTreeSet<ChangeHash>,
+    /// Whether any link follows the latest version of the document
+    pub latest: bool,
+    /// Whether the resolver has the document, the links in a document it doesn't have can't be
+    /// followed so the set may be missing documents reachable through it
+    pub resolved: bool,
+}
+
+/// Every document reachable by following links from `doc`, sorted by id
+///
+/// The links in a linked document are read at the version the link refers to, so a document
+/// pinned to old heads only pulls in what it linked to at the time. `doc` itself is only
+/// included if something links back to it.
+pub fn sync_set<R: ReadDoc, L: LinkResolver + ?Sized>(doc: &R, resolver: &L) -> Vec<SyncTarget> {
+    let mut targets = BTreeMap::<String, SyncTarget>::new();
+    let mut seen = HashSet::new();
+    let mut pending = links(doc).into_iter().map(|l| l.link).collect::<Vec<_>>();
+    while let Some(link) = pending.pop() {
+        if !seen.insert(link.clone()) {
+            continue;
+        }
+        let target = targets
+            .entry(link.doc.clone())
+            .or_insert_with(|| SyncTarget {
+                doc: link.doc.clone(),
+                heads: BTreeSet::new(),
+                latest: false,
+                resolved: resolver.resolve(&link.doc).is_some(),
+            });
+        match &link.heads {
+            Some(heads) => target.heads.extend(heads.iter().copied()),
+            None => target.latest = true,
+        }
+        if let Ok(view) = follow(&link, resolver) {
+            pending.extend(links(&view).into_iter().map(|l| l.link));
+        }
+    }
+    targets.into_values().collect()
+}
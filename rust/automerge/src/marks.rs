@@ -4,8 +4,10 @@ use std::fmt::Display;
 use std::sync::Arc;
 
 use crate::op_tree::OpSetData;
+use crate::storage::{parse, ChunkType, Header};
 use crate::types::{OpId, OpType};
 use crate::value::ScalarValue;
+use crate::ActorId;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 
@@ -67,6 +69,15 @@ impl MarkAccumulator {
         })
     }
 
+    /// Replace the `(start, end)` of every mark with `f(start, end)`
+    pub(crate) fn map_ranges<F: FnMut(usize, usize) -> (usize, usize)>(&mut self, mut f: F) {
+        for item in self.marks.values_mut().flatten() {
+            let (start, end) = f(item.index, item.index + item.len);
+            item.index = start;
+            item.len = end - start;
+        }
+    }
+
     pub(crate) fn add(&mut self, index: usize, len: usize, other: &MarkSet) {
         for (name, value) in other.marks.iter() {
             let entry = self.marks.entry(name.clone()).or_default();
@@ -301,7 +312,7 @@ impl Display for MarkData {
 /// mark should also inherit the mark.
 /// See <https://www.inkandswitch.com/peritext/> for details and
 /// suggestions of which value to use for which operations when building a rich text editor.
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
 pub enum ExpandMark {
     Before,
     After,
@@ -331,3 +342,211 @@ impl ExpandMark {
         matches!(self, Self::After | Self::Both)
     }
 }
+
+/// How marks with a particular name should be applied, see [`MarkPolicies`]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default)]
+pub struct MarkPolicy {
+    /// The [`ExpandMark`] to use when none is given to
+    /// [`crate::transaction::Transactable::mark()`] or
+    /// [`crate::transaction::Transactable::unmark()`]
+    pub expand: ExpandMark,
+    /// Whether at most one mark with this name should cover any run of text. Marking a range
+    /// removes every existing mark with this name which overlaps it, not just the overlapping
+    /// part, and unmarking part of a mark removes all of it. This suits links, where splitting a
+    /// link in two or leaving half of an old link next to a new one is never wanted.
+    pub exclusive: bool,
+    /// Whether a new mark should merge with existing marks with this name rather than replacing
+    /// them. If set, marking a range only marks the parts of it which don't already have a value
+    /// for this mark, so overlapping marks keep their existing values.
+    pub merge: bool,
+}
+
+impl MarkPolicy {
+    pub fn new(expand: ExpandMark) -> Self {
+        MarkPolicy {
+            expand,
+            exclusive: false,
+            merge: false,
+        }
+    }
+
+    pub fn with_exclusive(mut self, exclusive: bool) -> Self {
+        self.exclusive = exclusive;
+        self
+    }
+
+    pub fn with_merge(mut self, merge: bool) -> Self {
+        self.merge = merge;
+        self
+    }
+}
+
+/// A registry of [`MarkPolicy`]s by mark name
+///
+/// Every document has one, see [`crate::Automerge::set_mark_policy()`]. The policies are part of
+/// the document: they are saved and loaded with it, exchanged by the sync protocol and merged by
+/// [`crate::Automerge::merge()`], so every peer applies marks the same way. Registering a policy
+/// doesn't create a change. Marks with no registered policy use [`MarkPolicy::default()`], which
+/// expands after and is neither exclusive nor merging.
+///
+/// Each policy is a last writer wins register, like a ref (see [`crate::refs::Refs`]): when two
+/// peers set the policy for a name concurrently the update with the highest version, or the
+/// highest actor ID if the versions are equal, wins.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Default)]
+pub struct MarkPolicies {
+    entries: BTreeMap<SmolStr, PolicyEntry>,
+}
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+struct PolicyEntry {
+    // `None` if the policy was removed
+    policy: Option<MarkPolicy>,
+    version: u64,
+    actor: ActorId,
+}
+
+impl PolicyEntry {
+    fn supersedes(&self, other: &PolicyEntry) -> bool {
+        (self.version, &self.actor) > (other.version, &other.actor)
+    }
+}
+
+// flags in the encoding of each policy
+const REMOVED: u8 = 1;
+const EXPAND_BEFORE: u8 = 2;
+const EXPAND_AFTER: u8 = 4;
+const EXCLUSIVE: u8 = 8;
+const MERGE: u8 = 16;
+
+impl MarkPolicies {
+    /// The policy for marks named `name`
+    pub fn get(&self, name: &str) -> MarkPolicy {
+        self.entries
+            .get(name)
+            .and_then(|entry| entry.policy)
+            .unwrap_or_default()
+    }
+
+    /// The registered policies in order of their names
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MarkPolicy)> {
+        self.entries
+            .iter()
+            .filter_map(|(name, entry)| entry.policy.as_ref().map(|p| (name.as_str(), p)))
+    }
+
+    /// Whether there are no policies, including policies which have been removed
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Set or, if `policy` is `None`, remove the policy for `name`. Returns whether anything
+    /// changed.
+    pub(crate) fn set(&mut self, name: &str, policy: Option<MarkPolicy>, actor: &ActorId) -> bool {
+        let existing = self.entries.get(name);
+        if existing.and_then(|entry| entry.policy) == policy {
+            return false;
+        }
+        let version = existing.map_or(0, |entry| entry.version + 1);
+        self.entries.insert(
+            SmolStr::new(name),
+            PolicyEntry {
+                policy,
+                version,
+                actor: actor.clone(),
+            },
+        );
+        true
+    }
+
+    /// Merge `other` into `self`, returning whether anything changed
+    pub(crate) fn merge(&mut self, other: &MarkPolicies) -> bool {
+        let mut changed = false;
+        for (name, theirs) in &other.entries {
+            let replace = self
+                .entries
+                .get(name)
+                .map_or(true, |ours| theirs.supersedes(ours));
+            if replace {
+                self.entries.insert(name.clone(), theirs.clone());
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Encode these policies as a chunk which can follow a saved document
+    pub(crate) fn encode_chunk(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.encode(&mut data);
+        let header = Header::new(ChunkType::MarkPolicies, &data);
+        let mut out = Vec::with_capacity(header.len() + data.len());
+        header.write(&mut out);
+        out.extend(data);
+        out
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        leb128::write::unsigned(out, self.entries.len() as u64).unwrap();
+        for (name, entry) in &self.entries {
+            leb128::write::unsigned(out, name.len() as u64).unwrap();
+            out.extend(name.as_bytes());
+            let flags = match entry.policy {
+                None => REMOVED,
+                Some(policy) => {
+                    let mut flags = 0;
+                    if policy.expand.before() {
+                        flags |= EXPAND_BEFORE;
+                    }
+                    if policy.expand.after() {
+                        flags |= EXPAND_AFTER;
+                    }
+                    if policy.exclusive {
+                        flags |= EXCLUSIVE;
+                    }
+                    if policy.merge {
+                        flags |= MERGE;
+                    }
+                    flags
+                }
+            };
+            out.push(flags);
+            leb128::write::unsigned(out, entry.version).unwrap();
+            leb128::write::unsigned(out, entry.actor.to_bytes().len() as u64).unwrap();
+            out.extend(entry.actor.to_bytes());
+        }
+    }
+
+    pub(crate) fn parse<E>(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, E>
+    where
+        E: From<parse::leb128::Error> + From<parse::InvalidUtf8>,
+    {
+        let (mut i, count) = parse::leb128_u64(input)?;
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let (rest, len) = parse::leb128_u64(i)?;
+            let (rest, name) = parse::utf_8(len as usize, rest)?;
+            let (rest, flags) = parse::take1(rest)?;
+            let (rest, version) = parse::leb128_u64(rest)?;
+            let (rest, actor) = parse::actor_id(rest)?;
+            let policy = if flags & REMOVED == 0 {
+                Some(MarkPolicy {
+                    expand: ExpandMark::from(flags & EXPAND_BEFORE != 0, flags & EXPAND_AFTER != 0),
+                    exclusive: flags & EXCLUSIVE != 0,
+                    merge: flags & MERGE != 0,
+                })
+            } else {
+                None
+            };
+            entries.insert(
+                SmolStr::new(name),
+                PolicyEntry {
+                    policy,
+                    version,
+                    actor,
+                },
+            );
+            i = rest;
+        }
+        Ok((i, MarkPolicies { entries }))
+    }
+}
@@ -9,10 +9,11 @@ use crate::op_tree::{
     OpTreeInternal, OpsFound,
 };
 use crate::parents::Parents;
-use crate::query::{ChangeVisibility, TreeQuery};
+use crate::query::{self, ChangeVisibility, TreeQuery};
 use crate::text_value::TextValue;
 use crate::types::{
-    self, ActorId, Export, Exportable, Key, ListEncoding, ObjId, ObjMeta, OpId, OpIds, OpType, Prop,
+    self, ActorId, ElemId, Export, Exportable, Key, ListEncoding, ObjId, ObjMeta, OpId, OpIds,
+    OpType, Prop,
 };
 use crate::ObjType;
 use fxhash::FxBuildHasher;
@@ -166,6 +167,58 @@ impl OpSetInternal {
         Some(Parent { obj, prop, visible })
     }
 
+    /// Whether `obj` is nested anywhere inside a text object, e.g. as part of a block marker
+    ///
+    /// Unlike [`Self::parent_object()`] this only follows the ops which created each object, so it
+    /// doesn't need to find them in their parents and ignores whether they are visible.
+    pub(crate) fn in_text(&self, obj: &ObjId) -> bool {
+        let mut obj = *obj;
+        while let Some(idx) = self.trees.get(&obj).and_then(|tree| tree.parent) {
+            obj = *idx.as_op(&self.osd).obj();
+            if self.object_type(&obj) == Some(ObjType::Text) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Every op at `key` in `obj`, visible or not
+    ///
+    /// Also returns the position of the first of them in the object and, if `obj` is a sequence,
+    /// the index of the key at `clock`. Returns [`None`] if there is no such object or element.
+    pub(crate) fn ops_at_key(
+        &self,
+        obj: &ObjId,
+        key: Key,
+        clock: Option<&Clock>,
+    ) -> Option<(usize, usize, Vec<Op<'_>>)> {
+        let tree = self.trees.get(obj)?;
+        let (pos, index) = match key {
+            Key::Map(_) => (
+                tree.internal
+                    .binary_search_by(&self.osd, |op| op.key_cmp(&key)),
+                0,
+            ),
+            Key::Seq(ElemId(id)) => {
+                let (_typ, encoding) = self.type_and_encoding(obj)?;
+                let query = tree
+                    .internal
+                    .search(query::OpIdSearch::opid(id, encoding, clock), &self.osd);
+                let pos = query.found()?;
+                let op = tree.internal.get(pos)?.as_op(&self.osd);
+                (pos, query.index_for(op))
+            }
+        };
+        let ops = tree
+            .internal
+            .iter()
+            .skip(pos)
+            .map(|idx| idx.as_op(&self.osd))
+            .take_while(|op| op.elemid_or_key() == key)
+            .collect();
+        Some((pos, index, ops))
+    }
+
     pub(crate) fn seek_ops_by_prop<'a>(
         &'a self,
         obj: &ObjId,
@@ -632,6 +685,20 @@ impl<'a> DoubleEndedIterator for ChangeOpIter<'a> {
 }
 
 impl OpSetData {
+    /// The number of ops and of links between them, to pass to [`Self::truncate()`]
+    pub(crate) fn len(&self) -> (usize, usize) {
+        (self.ops.len(), self.op_deps.len())
+    }
+
+    /// Drop the ops and links added since [`Self::len()`] returned `len`
+    ///
+    /// The ops must already have been removed from the op trees and unlinked from their
+    /// predecessors, as when rolling back.
+    pub(crate) fn truncate(&mut self, len: (usize, usize)) {
+        self.ops.truncate(len.0);
+        self.op_deps.truncate(len.1);
+    }
+
     pub(crate) fn start_range(&self) -> OpIdxRange {
         let len = self.ops.len() as u32;
         OpIdxRange {
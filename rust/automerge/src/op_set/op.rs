@@ -172,7 +172,9 @@ impl<'a> Op<'a> {
             if self.is_inc() || self.is_mark() {
                 false
             } else {
-                clock.covers(&self.op().id) && !self.succ().any(|i| clock.covers(i.id()))
+                // increments are successors of the counter they increment but don't hide it
+                clock.covers(&self.op().id)
+                    && self.succ().all(|o| o.is_inc() || !clock.covers(o.id()))
             }
         } else {
             self.visible()
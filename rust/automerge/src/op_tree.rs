@@ -122,7 +122,11 @@ impl<'a> FoundOpWithPatchLog<'a> {
                     }
                 }
             } else if obj.typ == ObjType::Text {
-                patch_log.splice(obj.id, self.index, op.as_str(), self.marks.clone());
+                if let OpType::Make(ObjType::Map) = op.action() {
+                    patch_log.split_block(obj.id, self.index, *op.id());
+                } else {
+                    patch_log.splice(obj.id, self.index, op.as_str(), self.marks.clone());
+                }
             } else {
                 patch_log.insert(
                     obj.id,
@@ -145,6 +149,9 @@ impl<'a> FoundOpWithPatchLog<'a> {
             match (self.before, self.overwritten, self.after) {
                 (None, Some(over), None) => match key {
                     Prop::Map(k) => patch_log.delete_map(obj.id, &k),
+                    Prop::Seq(index) if obj.typ == ObjType::Text => {
+                        patch_log.delete_text(obj.id, index, over.as_str())
+                    }
                     Prop::Seq(index) => {
                         patch_log.delete_seq(obj.id, index, over.width(obj.encoding))
                     }
@@ -486,7 +493,7 @@ impl OpTreeInternal {
         })
     }
 
-    fn binary_search_by<F>(&self, osd: &OpSetData, f: F) -> usize
+    pub(crate) fn binary_search_by<F>(&self, osd: &OpSetData, f: F) -> usize
     where
         F: Fn(Op<'_>) -> Ordering,
     {
@@ -1,4 +1,5 @@
 use crate::{
+    blocks::Block,
     marks::{Mark, MarkSet},
     ObjId, Prop, Value,
 };
@@ -82,6 +83,14 @@ pub enum PatchAction {
     DeleteSeq { index: usize, length: usize },
     /// Some marks within a text object were added or removed
     Mark { marks: Vec<Mark<'static>> },
+    /// A block marker was inserted into a text object
+    ///
+    /// The marker occupies `index` in the text. Removing a marker (see
+    /// [`crate::transaction::Transactable::join_block`]) is reported as a [`Self::DeleteSeq`] of
+    /// length one.
+    SplitBlock { index: usize, block: Block },
+    /// The type, parents or attributes of the block marker at `index` in a text object changed
+    UpdateBlock { index: usize, block: Block },
 }
 
 impl fmt::Display for PatchAction {
@@ -1,7 +1,9 @@
 use core::fmt::Debug;
 use std::sync::Arc;
 
+use crate::blocks::Block;
 use crate::marks::MarkSet;
+use crate::text_value::{TextEncoding, TextValue};
 use crate::{ObjId, Prop, ReadDoc, Value};
 
 use super::{Patch, PatchAction};
@@ -11,9 +13,21 @@ use crate::{marks::Mark, sequence_tree::SequenceTree};
 pub(crate) struct PatchBuilder {
     patches: Vec<Patch>,
     last_mark_set: Option<Arc<MarkSet>>, // keep this around for a quick pointer equality test
+    encoding: TextEncoding,
 }
 
 impl PatchBuilder {
+    pub(crate) fn new(encoding: TextEncoding) -> Self {
+        Self {
+            encoding,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn encoding(&self) -> TextEncoding {
+        self.encoding
+    }
+
     pub(crate) fn get_path<R: ReadDoc>(
         &mut self,
         doc: &R,
@@ -86,11 +100,11 @@ impl PatchBuilder {
             ..
         }) = maybe_append(&mut self.patches, &obj)
         {
-            let range = *tail_index..=*tail_index + prev_value.len();
-            if marks == self.last_mark_set && range.contains(&index) {
-                let i = index - *tail_index;
-                prev_value.splice(i, value);
-                return;
+            if marks == self.last_mark_set && index >= *tail_index {
+                if let Some(i) = native_offset(self.encoding, prev_value, index - *tail_index) {
+                    prev_value.splice(i, value);
+                    return;
+                }
             }
         }
         if let Some(path) = self.get_path(doc, &obj) {
@@ -116,11 +130,13 @@ impl PatchBuilder {
                 index: tail_index,
                 value,
                 ..
-            }) => {
-                let range = *tail_index..*tail_index + value.len();
-                if range.contains(&index) && range.contains(&(index + length - 1)) {
-                    for _ in 0..length {
-                        value.remove(index - *tail_index);
+            }) if index >= *tail_index => {
+                let offset = index - *tail_index;
+                let start = native_offset(self.encoding, value, offset);
+                let end = native_offset(self.encoding, value, offset + length);
+                if let (Some(start), Some(end)) = (start, end) {
+                    for _ in start..end {
+                        value.remove(start);
                     }
                     return;
                 }
@@ -226,6 +242,32 @@ impl PatchBuilder {
         }
     }
 
+    pub(crate) fn split_block<R: ReadDoc>(
+        &mut self,
+        doc: &R,
+        obj: ObjId,
+        index: usize,
+        block: Block,
+    ) {
+        if let Some(path) = self.get_path(doc, &obj) {
+            let action = PatchAction::SplitBlock { index, block };
+            self.push(Patch { obj, path, action });
+        }
+    }
+
+    pub(crate) fn update_block<R: ReadDoc>(
+        &mut self,
+        doc: &R,
+        obj: ObjId,
+        index: usize,
+        block: Block,
+    ) {
+        if let Some(path) = self.get_path(doc, &obj) {
+            let action = PatchAction::UpdateBlock { index, block };
+            self.push(Patch { obj, path, action });
+        }
+    }
+
     pub(crate) fn flag_conflict<R: ReadDoc>(&mut self, doc: &R, obj: ObjId, prop: Prop) {
         let conflict = match maybe_append(&mut self.patches, &obj) {
             Some(PatchAction::PutMap { key, conflict, .. })
@@ -263,3 +305,16 @@ fn maybe_append<'a>(patches: &'a mut [Patch], obj: &ObjId) -> Option<&'a mut Pat
         _ => None,
     }
 }
+
+/// Convert `offset`, a position in `value` in `encoding`, to a native position in `value`. Returns
+/// [`None`] if the position is outside `value` or if `value` can't be edited in isolation, which
+/// is the case for grapheme clusters, which can merge with the text around them.
+fn native_offset(encoding: TextEncoding, value: &TextValue, offset: usize) -> Option<usize> {
+    if encoding.is_native() {
+        Some(offset).filter(|o| *o <= value.len())
+    } else if encoding.is_additive() {
+        encoding.convert(&value.make_string(), offset, TextEncoding::native())
+    } else {
+        None
+    }
+}
@@ -1,12 +1,15 @@
 use crate::automerge::diff::ReadDocAt;
+use crate::blocks::{Block, BLOCK_PLACEHOLDER};
 use crate::exid::ExId;
 use crate::hydrate::Value;
-use crate::iter::{ListRangeItem, MapRangeItem};
+use crate::iter::{ListRangeItem, MapRangeItem, Span};
 use crate::marks::{MarkAccumulator, MarkSet};
+use crate::text_value::TextEncoding;
+use crate::text_value::TextValue;
 use crate::types::{ObjId, ObjType, OpId, Prop};
-use crate::{Automerge, ChangeHash, Patch, ReadDoc};
+use crate::{Automerge, ChangeHash, Parent, Patch, ReadDoc};
 use std::collections::BTreeSet;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use super::{PatchBuilder, TextRepresentation};
@@ -45,9 +48,13 @@ pub struct PatchLog {
     expose: HashSet<OpId>,
     active: bool,
     text_rep: TextRepresentation,
+    text_encoding: TextEncoding,
     pub(crate) heads: Option<Vec<ChangeHash>>,
 }
 
+/// The text object, index and ID of a block marker
+type BlockLocation = (ExId, usize, ExId);
+
 #[derive(Clone, Debug)]
 pub(crate) enum Event {
     PutMap {
@@ -65,6 +72,10 @@ pub(crate) enum Event {
     DeleteSeq {
         index: usize,
         num: usize,
+        // The deleted text, if this is a deletion from a text object. It's needed to convert indices
+        // to a non-native text encoding and is recorded whatever the encoding of the log, as that
+        // can change before the patches are made.
+        deleted: Option<String>,
     },
     DeleteMap {
         key: String,
@@ -100,6 +111,10 @@ pub(crate) enum Event {
     Mark {
         marks: MarkAccumulator,
     },
+    SplitBlock {
+        index: usize,
+        id: OpId,
+    },
 }
 
 impl PatchLog {
@@ -122,6 +137,7 @@ impl PatchLog {
             events: vec![],
             heads: None,
             text_rep,
+            text_encoding: TextEncoding::native(),
         }
     }
 
@@ -159,7 +175,26 @@ impl PatchLog {
     }
 
     pub(crate) fn delete_seq(&mut self, obj: ObjId, index: usize, num: usize) {
-        self.events.push((obj, Event::DeleteSeq { index, num }))
+        self.events.push((
+            obj,
+            Event::DeleteSeq {
+                index,
+                num,
+                deleted: None,
+            },
+        ))
+    }
+
+    pub(crate) fn delete_text(&mut self, obj: ObjId, index: usize, deleted: &str) {
+        let num = TextValue::width(deleted);
+        self.events.push((
+            obj,
+            Event::DeleteSeq {
+                index,
+                num,
+                deleted: Some(deleted.to_string()),
+            },
+        ))
     }
 
     pub(crate) fn delete_map(&mut self, obj: ObjId, key: &str) {
@@ -284,6 +319,10 @@ impl PatchLog {
         ))
     }
 
+    pub(crate) fn split_block(&mut self, obj: ObjId, index: usize, id: OpId) {
+        self.events.push((obj, Event::SplitBlock { index, id }))
+    }
+
     pub(crate) fn mark(&mut self, obj: ObjId, index: usize, len: usize, marks: &Arc<MarkSet>) {
         if let Some((_, Event::Mark { marks: tail_marks })) = self.events.last_mut() {
             tail_marks.add(index, len, marks);
@@ -320,21 +359,40 @@ impl PatchLog {
         let expose = ExposeQueue(self.expose.iter().map(|id| doc.id_to_exid(*id)).collect());
         if let Some(heads) = self.heads.as_ref() {
             let read_doc = ReadDocAt { doc, heads };
-            Self::make_patches_inner(&self.events, expose, doc, &read_doc, self.text_rep)
+            self.make_patches_inner(expose, doc, &read_doc)
         } else {
-            Self::make_patches_inner(&self.events, expose, doc, doc, self.text_rep)
+            self.make_patches_inner(expose, doc, doc)
         }
     }
 
     fn make_patches_inner<R: ReadDoc>(
-        events: &[(ObjId, Event)],
+        &self,
         mut expose_queue: ExposeQueue,
         doc: &Automerge,
         read_doc: &R,
-        text_rep: TextRepresentation,
     ) -> Vec<Patch> {
-        let mut patch_builder = PatchBuilder::default();
-        for (obj, event) in events {
+        let text_rep = self.text_rep;
+        let mut texts = TextCache::default();
+        let converted;
+        // With the array representation text is indexed by element, like a list
+        let (events, unconverted) = if self.text_encoding.is_native() || text_rep.is_array() {
+            (&self.events, Vec::new())
+        } else {
+            let (events, unconverted) =
+                convert_text_events(&self.events, doc, read_doc, self.text_encoding, &mut texts);
+            converted = events;
+            (&converted, unconverted)
+        };
+        let mut patch_builder = PatchBuilder::new(self.text_encoding);
+        let clock = self.heads.as_ref().map(|heads| doc.clock_at(heads));
+        let read_block = |id: &ExId| {
+            Block::from_hydrated(&doc.hydrate_map(&id.to_internal_obj(), clock.as_ref()))
+        };
+        // Blocks which have already been reported in these patches, along with the object we last
+        // looked up the enclosing block of
+        let mut blocks_done = HashSet::new();
+        let mut last_block: Option<(ObjId, Option<BlockLocation>)> = None;
+        for (obj, event) in events.iter() {
             let exid = doc.id_to_exid(obj.0);
             // ignore events on objects in the expose queue
             // incremental updates are ignored and a observation
@@ -345,6 +403,27 @@ impl PatchLog {
             }
             // any objects exposed BEFORE exid get observed here
             expose_queue.pump_queue(&exid, &mut patch_builder, doc, read_doc, text_rep);
+            // changes to the objects which make up a block marker are reported as one
+            // `UpdateBlock` with the final state of the block
+            if !text_rep.is_array()
+                && doc.ops().object_type(obj) != Some(ObjType::Text)
+                && doc.ops().in_text(obj)
+            {
+                let block = match &last_block {
+                    Some((last, block)) if last == obj => block.clone(),
+                    _ => {
+                        let block = self.enclosing_block(read_doc, &exid, &mut texts);
+                        last_block = Some((*obj, block.clone()));
+                        block
+                    }
+                };
+                if let Some((text, index, block)) = block {
+                    if blocks_done.insert(block.clone()) {
+                        patch_builder.update_block(read_doc, text, index, read_block(&block));
+                    }
+                    continue;
+                }
+            }
             match event {
                 Event::PutMap {
                     key,
@@ -397,7 +476,7 @@ impl PatchLog {
                         marks.clone(),
                     );
                 }
-                Event::DeleteSeq { index, num } => {
+                Event::DeleteSeq { index, num, .. } => {
                     patch_builder.delete_seq(read_doc, exid, *index, *num);
                 }
                 Event::IncrementSeq { index, n, id } => {
@@ -413,14 +492,68 @@ impl PatchLog {
                 Event::Mark { marks } => {
                     patch_builder.mark(read_doc, exid, marks.clone().into_iter())
                 }
+                Event::SplitBlock { index, id } => {
+                    let block = doc.id_to_exid(*id);
+                    if text_rep.is_array() {
+                        let value = (crate::Value::Object(ObjType::Map), block.clone());
+                        patch_builder.insert(read_doc, exid, *index, value, false, None);
+                        expose_queue.insert(block);
+                    } else {
+                        patch_builder.split_block(read_doc, exid, *index, read_block(&block));
+                        blocks_done.insert(block);
+                    }
+                }
             }
         }
         // any objects exposed AFTER all other events get exposed here
         expose_queue.flush_queue(&mut patch_builder, doc, read_doc, text_rep);
 
+        // text objects whose events couldn't be converted are put again and filled in from their
+        // final state, as though they had just been created
+        for obj in unconverted {
+            let exid = doc.id_to_exid(obj.0);
+            if let Some(Parent {
+                obj: parent,
+                prop,
+                visible: true,
+            }) = read_doc.parents(&exid).ok().and_then(|mut p| p.next())
+            {
+                let value = (crate::Value::Object(ObjType::Text), exid.clone());
+                patch_builder.put(read_doc, parent, prop, value, false);
+                expose_queue.flush_obj(exid, &mut patch_builder, doc, read_doc, text_rep);
+            }
+        }
+
         patch_builder.take_patches()
     }
 
+    /// The block marker which `obj` is part of, if any, with the index in the encoding of this log
+    fn enclosing_block<R: ReadDoc>(
+        &self,
+        read_doc: &R,
+        obj: &ExId,
+        texts: &mut TextCache,
+    ) -> Option<BlockLocation> {
+        let mut child = obj.clone();
+        for Parent { obj, prop, visible } in read_doc.parents(obj).ok()? {
+            if !visible {
+                return None;
+            }
+            if read_doc.object_type(&obj).ok()? == ObjType::Text {
+                let index = prop.as_index()?;
+                let index = if self.text_encoding.is_native() {
+                    index
+                } else {
+                    let text = texts.get(read_doc, &obj)?;
+                    TextEncoding::native().convert(text, index, self.text_encoding)?
+                };
+                return Some((obj, index, child));
+            }
+            child = obj;
+        }
+        None
+    }
+
     pub(crate) fn truncate(&mut self) {
         self.active = true;
         self.events.truncate(0);
@@ -433,6 +566,7 @@ impl PatchLog {
             expose: HashSet::new(),
             events: Default::default(),
             text_rep: self.text_rep,
+            text_encoding: self.text_encoding,
             heads: None,
         }
     }
@@ -448,6 +582,218 @@ impl PatchLog {
     pub(crate) fn set_text_rep(&mut self, rep: TextRepresentation) {
         self.text_rep = rep;
     }
+
+    /// Measure positions in text objects in the patches generated from this log in `encoding`
+    ///
+    /// This only has an effect with [`TextRepresentation::String`] and must be set before any
+    /// changes are recorded. See [`TextEncoding`] for details.
+    pub fn with_text_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.text_encoding = encoding;
+        self
+    }
+
+    pub(crate) fn text_encoding(&self) -> TextEncoding {
+        self.text_encoding
+    }
+
+    pub(crate) fn set_text_encoding(&mut self, encoding: TextEncoding) {
+        self.text_encoding = encoding;
+    }
+}
+
+/// The current text of the text objects patches are being made for, so that each is only read
+/// once however many events there are on it
+#[derive(Default)]
+struct TextCache(HashMap<ExId, Option<String>>);
+
+impl TextCache {
+    fn get<R: ReadDoc>(&mut self, read_doc: &R, obj: &ExId) -> Option<&str> {
+        self.0
+            .entry(obj.clone())
+            .or_insert_with(|| read_doc.text(obj).ok())
+            .as_deref()
+    }
+}
+
+/// Convert the positions in events on text objects from the native encoding to `encoding`
+///
+/// The events are relative to the state of the text before the first of them, so we undo them,
+/// starting from the text in `read_doc`, to find that state and then replay them converting each
+/// position as we go. Edits which would split a segment the encoding can't address the inside of
+/// (i.e. a grapheme cluster) are widened into a replacement of the whole segment.
+///
+/// Some events can't be converted this way, e.g. a put of a character into a text object, or a
+/// deletion which didn't record the deleted text. The events on such objects are dropped and the
+/// objects are returned so that their patches can be regenerated from their final state.
+fn convert_text_events<R: ReadDoc>(
+    events: &[(ObjId, Event)],
+    doc: &Automerge,
+    read_doc: &R,
+    encoding: TextEncoding,
+    texts: &mut TextCache,
+) -> (Vec<(ObjId, Event)>, Vec<ObjId>) {
+    // The events on each text object are converted together, as the events on other objects may
+    // be interleaved with them
+    let mut by_obj = HashMap::<ObjId, Vec<usize>>::new();
+    for (i, (obj, _)) in events.iter().enumerate() {
+        if doc.ops().object_type(obj) == Some(ObjType::Text) {
+            by_obj.entry(*obj).or_default().push(i);
+        }
+    }
+    let mut converted = vec![None; events.len()];
+    let mut unconverted = Vec::new();
+    for (obj, positions) in by_obj {
+        let group = positions.iter().map(|i| &events[*i].1).collect::<Vec<_>>();
+        let result = texts
+            .get(read_doc, &doc.id_to_exid(obj.0))
+            .and_then(|text| convert_text_group(&group, text.to_string(), encoding));
+        match result {
+            Some(result) => {
+                for (i, events) in positions.into_iter().zip(result) {
+                    converted[i] = Some(events);
+                }
+            }
+            None => {
+                tracing::debug!(
+                    ?obj,
+                    ?encoding,
+                    "regenerating the patches for a text object"
+                );
+                for i in positions {
+                    converted[i] = Some(Vec::new());
+                }
+                unconverted.push(obj);
+            }
+        }
+    }
+    unconverted.sort_unstable();
+    let events = events
+        .iter()
+        .zip(converted)
+        .flat_map(|((obj, event), converted)| {
+            converted
+                .unwrap_or_else(|| vec![event.clone()])
+                .into_iter()
+                .map(move |e| (*obj, e))
+        })
+        .collect();
+    (events, unconverted)
+}
+
+/// The events which each of `group` converts to, or `None` if `group` doesn't match `text`
+fn convert_text_group(
+    group: &[&Event],
+    mut text: String,
+    encoding: TextEncoding,
+) -> Option<Vec<Vec<Event>>> {
+    let native = TextEncoding::native();
+    for event in group.iter().rev() {
+        match event {
+            Event::Splice {
+                index,
+                text: inserted,
+                ..
+            } => {
+                let start = native.byte_offset(&text, *index)?;
+                let end = start + inserted.len();
+                text.get(start..end)?;
+                text.replace_range(start..end, "");
+            }
+            Event::DeleteSeq {
+                index,
+                deleted: Some(deleted),
+                ..
+            } => {
+                let start = native.byte_offset(&text, *index)?;
+                text.insert_str(start, deleted);
+            }
+            Event::SplitBlock { index, .. } => {
+                let start = native.byte_offset(&text, *index)?;
+                let end = start + BLOCK_PLACEHOLDER.len();
+                text.get(start..end)?;
+                text.replace_range(start..end, "");
+            }
+            Event::Mark { .. } => {}
+            _ => return None,
+        }
+    }
+
+    let mut converted = Vec::with_capacity(group.len());
+    for event in group {
+        let mut out = Vec::new();
+        match event {
+            Event::Splice {
+                index,
+                text: inserted,
+                marks,
+            } => {
+                let at = native.byte_offset(&text, *index)?;
+                let (lo, hi) = encoding.expand(&text, at, at);
+                let index = encoding.index_of_byte(&text, lo, false);
+                if lo < hi {
+                    let num = encoding.index_of_byte(&text, hi, false) - index;
+                    out.push(Event::DeleteSeq {
+                        index,
+                        num,
+                        deleted: None,
+                    });
+                }
+                out.push(Event::Splice {
+                    index,
+                    text: format!("{}{}{}", &text[lo..at], inserted, &text[at..hi]),
+                    marks: marks.clone(),
+                });
+                text.insert_str(at, inserted);
+            }
+            Event::DeleteSeq {
+                index,
+                deleted: Some(deleted),
+                ..
+            } => {
+                let start = native.byte_offset(&text, *index)?;
+                let end = start + deleted.len();
+                let (lo, hi) = encoding.expand(&text, start, end);
+                let index = encoding.index_of_byte(&text, lo, false);
+                let num = encoding.index_of_byte(&text, hi, false) - index;
+                out.push(Event::DeleteSeq {
+                    index,
+                    num,
+                    deleted: None,
+                });
+                if lo < start || end < hi {
+                    out.push(Event::Splice {
+                        index,
+                        text: format!("{}{}", &text[lo..start], &text[end..hi]),
+                        marks: None,
+                    });
+                }
+                text.replace_range(start..end, "");
+            }
+            Event::Mark { marks } => {
+                let mut marks = marks.clone();
+                marks.map_ranges(|start, end| {
+                    let start = native.byte_offset(&text, start).unwrap_or(text.len());
+                    let end = native.byte_offset(&text, end).unwrap_or(text.len());
+                    (
+                        encoding.index_of_byte(&text, start, false),
+                        encoding.index_of_byte(&text, end, true),
+                    )
+                });
+                out.push(Event::Mark { marks });
+            }
+            Event::SplitBlock { index, id } => {
+                let at = native.byte_offset(&text, *index)?;
+                out.push(Event::SplitBlock {
+                    index: encoding.index_of_byte(&text, at, false),
+                    id: *id,
+                });
+                text.insert_str(at, BLOCK_PLACEHOLDER);
+            }
+            _ => return None,
+        }
+        converted.push(out);
+    }
+    Some(converted)
 }
 
 impl AsRef<OpId> for &(ObjId, Event) {
@@ -516,9 +862,19 @@ impl ExposeQueue {
         self.remove(&exid);
         match doc.ops().object_type(&id)? {
             ObjType::Text if matches!(text_rep, TextRepresentation::String) => {
-                let text = read_doc.text(&exid).ok()?;
-                // TODO - need read_doc, text_spans()
-                patch_builder.splice_text(read_doc, exid, 0, &text, None);
+                let mut index = 0;
+                for span in read_doc.spans(&exid).ok()? {
+                    match span {
+                        Span::Text(text, marks) => {
+                            patch_builder.splice_text(read_doc, exid.clone(), index, &text, marks);
+                            index += patch_builder.encoding().width(&text);
+                        }
+                        Span::Block(block) => {
+                            patch_builder.split_block(read_doc, exid.clone(), index, block);
+                            index += patch_builder.encoding().width(BLOCK_PLACEHOLDER);
+                        }
+                    }
+                }
             }
             ObjType::List | ObjType::Text => {
                 for ListRangeItem {
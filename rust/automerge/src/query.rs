@@ -1,6 +1,7 @@
 use crate::marks::MarkData;
 use crate::op_set::Op;
 use crate::op_tree::{OpSetData, OpTree, OpTreeNode};
+use crate::text_value::TextEncoding;
 use crate::types::{Key, ListEncoding, OpBuilder, OpId, OpType};
 use fxhash::FxBuildHasher;
 use std::collections::{HashMap, HashSet};
@@ -11,12 +12,14 @@ mod list_state;
 mod nth;
 mod opid;
 mod seek_mark;
+mod text_index;
 
 pub(crate) use insert::InsertNth;
 pub(crate) use list_state::{ListState, MarkMap};
 pub(crate) use nth::Nth;
 pub(crate) use opid::{OpIdSearch, SimpleOpIdSearch};
 pub(crate) use seek_mark::SeekMark;
+pub(crate) use text_index::TextIndex;
 
 // use a struct for the args for clarity as they are passed up the update chain in the optree
 #[derive(Debug, Clone)]
@@ -64,14 +67,41 @@ pub(crate) enum QueryResult {
     Finish,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// The width of the visible text in each of the encodings which can be summed over the nodes of
+/// the tree, so that positions in any of them can be found without visiting every element
+#[derive(Clone, Debug, PartialEq, Default)]
 struct TextWidth {
-    width: usize,
+    utf8: usize,
+    utf16: usize,
+    code_points: usize,
 }
 
 impl TextWidth {
+    fn of(op: Op<'_>) -> Self {
+        let s = op.as_str();
+        TextWidth {
+            utf8: s.len(),
+            utf16: s.encode_utf16().count(),
+            code_points: s.chars().count(),
+        }
+    }
+
+    /// The width in `encoding`, or [`None`] for [`TextEncoding::GraphemeCluster`], where the
+    /// width of a node isn't the sum of the widths of its children
+    fn get(&self, encoding: TextEncoding) -> Option<usize> {
+        match encoding {
+            TextEncoding::Utf8CodeUnit => Some(self.utf8),
+            TextEncoding::Utf16CodeUnit => Some(self.utf16),
+            TextEncoding::UnicodeCodePoint => Some(self.code_points),
+            TextEncoding::GraphemeCluster => None,
+        }
+    }
+
     fn add_op(&mut self, op: Op<'_>) {
-        self.width += op.width(ListEncoding::Text);
+        let width = Self::of(op);
+        self.utf8 += width.utf8;
+        self.utf16 += width.utf16;
+        self.code_points += width.code_points;
     }
 
     fn remove_op(&mut self, op: Op<'_>) {
@@ -102,11 +132,16 @@ impl TextWidth {
         //
         // Really this is a sign that we should be tracking the type of the Index (List or Text) at
         // the type level, but for now we just look the other way.
-        self.width = self.width.saturating_sub(op.width(ListEncoding::Text));
+        let width = Self::of(op);
+        self.utf8 = self.utf8.saturating_sub(width.utf8);
+        self.utf16 = self.utf16.saturating_sub(width.utf16);
+        self.code_points = self.code_points.saturating_sub(width.code_points);
     }
 
     fn merge(&mut self, other: &TextWidth) {
-        self.width += other.width;
+        self.utf8 += other.utf8;
+        self.utf16 += other.utf16;
+        self.code_points += other.code_points;
     }
 }
 
@@ -130,7 +165,7 @@ impl Index {
     pub(crate) fn new() -> Self {
         Index {
             visible: Default::default(),
-            visible_text: TextWidth { width: 0 },
+            visible_text: TextWidth::default(),
             ops: Default::default(),
             never_seen_puts: true,
             mark_begin: Default::default(),
@@ -142,10 +177,18 @@ impl Index {
     pub(crate) fn visible_len(&self, encoding: ListEncoding) -> usize {
         match encoding {
             ListEncoding::List => self.visible.len(),
-            ListEncoding::Text => self.visible_text.width,
+            ListEncoding::Text => self
+                .visible_text
+                .get(TextEncoding::native())
+                .unwrap_or_default(),
         }
     }
 
+    /// The width of the visible text in this node in `encoding`, see [`TextWidth::get()`]
+    pub(crate) fn text_width(&self, encoding: TextEncoding) -> Option<usize> {
+        self.visible_text.get(encoding)
+    }
+
     pub(crate) fn has_visible(&self, seen: &Key) -> bool {
         self.visible.contains_key(seen)
     }
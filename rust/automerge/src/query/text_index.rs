@@ -0,0 +1,103 @@
+use crate::op_set::Op;
+use crate::op_tree::OpSetData;
+use crate::query::{Index, OpTreeNode, QueryResult, TreeQuery};
+use crate::text_value::TextEncoding;
+use crate::types::{Clock, Key};
+use std::fmt::Debug;
+
+/// Converts a position in a text object from one encoding to another
+///
+/// Nodes which lie entirely before `target` are skipped using the widths in their index, so only
+/// the elements around the position are measured. Both encodings must be additive, see
+/// [`TextEncoding::is_additive()`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TextIndex {
+    from: TextEncoding,
+    to: TextEncoding,
+    target: usize,
+    clock: Option<Clock>,
+    /// The position reached so far in `from` and in `to`
+    from_index: usize,
+    to_index: usize,
+    last_seen: Option<Key>,
+    last_width: (usize, usize),
+}
+
+impl TextIndex {
+    pub(crate) fn new(
+        target: usize,
+        from: TextEncoding,
+        to: TextEncoding,
+        clock: Option<Clock>,
+    ) -> Self {
+        debug_assert!(from.is_additive() && to.is_additive());
+        TextIndex {
+            from,
+            to,
+            target,
+            clock,
+            from_index: 0,
+            to_index: 0,
+            last_seen: None,
+            last_width: (0, 0),
+        }
+    }
+
+    /// The position in `to`, or [`None`] if the target is past the end of the text or inside a
+    /// character
+    pub(crate) fn index(&self) -> Option<usize> {
+        if self.from_index == self.target {
+            Some(self.to_index)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> TreeQuery<'a> for TextIndex {
+    fn query_node(
+        &mut self,
+        _child: &'a OpTreeNode,
+        index: &'a Index,
+        _osd: &'a OpSetData,
+    ) -> QueryResult {
+        if self.from_index >= self.target {
+            return QueryResult::Finish;
+        }
+        // The widths in the index are only accurate for text which has only been spliced, see
+        // `ListState::process_node`
+        if self.clock.is_some() || !index.has_never_seen_puts() {
+            return QueryResult::Descend;
+        }
+        match (index.text_width(self.from), index.text_width(self.to)) {
+            (Some(from), Some(to)) if self.from_index + from <= self.target => {
+                self.from_index += from;
+                self.to_index += to;
+                self.last_seen = None;
+                QueryResult::Next
+            }
+            _ => QueryResult::Descend,
+        }
+    }
+
+    fn query_element(&mut self, op: Op<'a>) -> QueryResult {
+        if op.insert() && self.from_index >= self.target {
+            return QueryResult::Finish;
+        }
+        if op.visible_at(self.clock.as_ref()) {
+            let s = op.as_str();
+            let width = (self.from.width(s), self.to.width(s));
+            let key = op.elemid_or_key();
+            if self.last_seen == Some(key) {
+                // A conflicting value for the element we just counted, the last one wins
+                self.from_index -= self.last_width.0;
+                self.to_index -= self.last_width.1;
+            }
+            self.from_index += width.0;
+            self.to_index += width.1;
+            self.last_seen = Some(key);
+            self.last_width = width;
+        }
+        QueryResult::Next
+    }
+}
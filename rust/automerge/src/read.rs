@@ -1,10 +1,15 @@
 use crate::{
+    conflicts::{Conflict, Conflicts},
     error::AutomergeError,
     exid::ExId,
-    iter::{Keys, ListRange, MapRange, Values},
+    history::{ObjRevision, PropRevision},
+    iter::{Keys, ListRange, MapRange, Spans, Values},
     marks::{Mark, MarkSet},
     parents::Parents,
-    Change, ChangeHash, Cursor, ObjType, Prop, Value,
+    text_lines::TextLines,
+    text_value::{IndexConverter, TextEncoding},
+    Change, ChangeHash, Cursor, CursorResolution, ObjType, Prop, Selection, Stickiness,
+    StickyCursor, Value,
 };
 
 use std::ops::RangeBounds;
@@ -159,6 +164,37 @@ pub trait ReadDoc {
         heads: &[ChangeHash],
     ) -> Result<String, AutomergeError>;
 
+    /// The lines of the text object `obj`, see [`TextLines`] for how they are split and how to
+    /// convert between line numbers and indexes
+    fn lines<O: AsRef<ExId>>(&self, obj: O) -> Result<TextLines, AutomergeError> {
+        Ok(TextLines::new(&self.text(obj)?))
+    }
+
+    /// The lines of the text object `obj` as at `heads`, see [`Self::lines()`]
+    fn lines_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<TextLines, AutomergeError> {
+        Ok(TextLines::new(&self.text_at(obj, heads)?))
+    }
+
+    /// Iterate over the text, marks and block markers in the text object `obj`
+    ///
+    /// Consecutive characters with the same marks are combined into a single
+    /// [`crate::iter::Span::Text`] along with the [`crate::marks::MarkSet`] which applies to them,
+    /// each block marker is a [`crate::iter::Span::Block`]. See [`crate::blocks::Block`]. This
+    /// makes a single pass over the text, so it is much cheaper than calling [`Self::text()`] and
+    /// [`Self::marks()`] and combining the results.
+    fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError>;
+
+    /// Iterate over the text and block markers in the text object `obj` as at `heads`
+    fn spans_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Spans<'_>, AutomergeError>;
+
     /// Obtain the stable address (Cursor) for a [`usize`] position in a Sequence (either [`ObjType::List`] or [`ObjType::Text`]).
     ///
     /// Example use cases:
@@ -177,6 +213,10 @@ pub trait ReadDoc {
     ///
     /// Applicable only for Sequences (either [`ObjType::List`] or [`ObjType::Text`]).
     ///
+    /// If the element the cursor points at has been deleted this returns the position of the
+    /// next visible element (or the end of the sequence), see
+    /// [`Self::get_cursor_position_with_resolution()`] to choose the previous one instead.
+    ///
     /// To reverse the operation, see [`Self::get_cursor()`].
     fn get_cursor_position<O: AsRef<ExId>>(
         &self,
@@ -185,6 +225,204 @@ pub trait ReadDoc {
         at: Option<&[ChangeHash]>,
     ) -> Result<usize, AutomergeError>;
 
+    /// Like [`Self::get_cursor_position()`] but `resolution` decides where a cursor whose element
+    /// has been deleted ends up
+    fn get_cursor_position_with_resolution<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        resolution: CursorResolution,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError>;
+
+    /// Obtain a [`StickyCursor`] for the gap at `position` in a sequence
+    ///
+    /// A [`Stickiness::Left`] cursor attaches to the element before `position` and a
+    /// [`Stickiness::Right`] cursor to the element at `position`, see [`Stickiness`] for how this
+    /// affects concurrent inserts. A left sticky cursor at `0` stays at the start of the
+    /// sequence and a right sticky cursor at the end stays at the end.
+    ///
+    /// To reverse the operation, see [`Self::get_sticky_cursor_position()`].
+    fn get_sticky_cursor<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        position: usize,
+        stickiness: Stickiness,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<StickyCursor, AutomergeError> {
+        let obj = obj.as_ref();
+        match stickiness {
+            Stickiness::Left if position == 0 => Ok(StickyCursor::start()),
+            Stickiness::Left => Ok(StickyCursor::new(
+                self.get_cursor(obj, position - 1, at)?,
+                stickiness,
+            )),
+            Stickiness::Right => {
+                let length = match at {
+                    Some(heads) => self.length_at(obj, heads),
+                    None => self.length(obj),
+                };
+                if position == length {
+                    Ok(StickyCursor::end())
+                } else {
+                    Ok(StickyCursor::new(
+                        self.get_cursor(obj, position, at)?,
+                        stickiness,
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Translate a [`StickyCursor`] into a position in a sequence
+    ///
+    /// A left sticky cursor resolves to the position just after the element it is attached to,
+    /// a right sticky cursor to the position of its element. If the element has been deleted
+    /// either resolves to the position the element used to be at.
+    fn get_sticky_cursor_position<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &StickyCursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError>;
+
+    /// Obtain a [`Selection`] from `anchor` to `head` in a sequence
+    ///
+    /// `head` may be before `anchor`. The start of the selection is right sticky and the end is
+    /// left sticky so that text inserted concurrently at either edge is not selected. If `anchor`
+    /// and `head` are the same both ends are left sticky, so the selection stays collapsed.
+    fn get_selection<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        anchor: usize,
+        head: usize,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Selection, AutomergeError> {
+        let obj = obj.as_ref();
+        let (anchor_stickiness, head_stickiness) = match anchor.cmp(&head) {
+            std::cmp::Ordering::Less => (Stickiness::Right, Stickiness::Left),
+            std::cmp::Ordering::Equal => (Stickiness::Left, Stickiness::Left),
+            std::cmp::Ordering::Greater => (Stickiness::Left, Stickiness::Right),
+        };
+        Ok(Selection::new(
+            self.get_sticky_cursor(obj, anchor, anchor_stickiness, at)?,
+            self.get_sticky_cursor(obj, head, head_stickiness, at)?,
+        ))
+    }
+
+    /// Translate a [`Selection`] into the positions of its anchor and head
+    ///
+    /// If all the selected elements have been deleted both ends resolve to the same position,
+    /// and if the ends have crossed each other the selection collapses to the position of the
+    /// head.
+    fn get_selection_position<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        selection: &Selection,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<(usize, usize), AutomergeError> {
+        let obj = obj.as_ref();
+        let anchor = self.get_sticky_cursor_position(obj, selection.anchor(), at)?;
+        let head = self.get_sticky_cursor_position(obj, selection.head(), at)?;
+        let forwards = selection.anchor().stickiness() == Stickiness::Right
+            || selection.head().stickiness() == Stickiness::Left;
+        let crossed = if forwards {
+            anchor > head
+        } else {
+            head > anchor
+        };
+        if crossed {
+            Ok((head, head))
+        } else {
+            Ok((anchor, head))
+        }
+    }
+
+    /// Like [`Self::get_cursor()`] but `position` is measured in `encoding`
+    fn get_cursor_with_encoding<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        position: usize,
+        at: Option<&[ChangeHash]>,
+        encoding: TextEncoding,
+    ) -> Result<Cursor, AutomergeError> {
+        let converter = IndexConverter::new(self, obj.as_ref(), at, encoding)?;
+        self.get_cursor(obj.as_ref(), converter.to_native(position)?, at)
+    }
+
+    /// Like [`Self::get_cursor_position()`] but the position is measured in `encoding`
+    fn get_cursor_position_with_encoding<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        at: Option<&[ChangeHash]>,
+        encoding: TextEncoding,
+    ) -> Result<usize, AutomergeError> {
+        let converter = IndexConverter::new(self, obj.as_ref(), at, encoding)?;
+        let position = self.get_cursor_position(obj.as_ref(), cursor, at)?;
+        converter.to_encoding(position, false)
+    }
+
+    /// Convert `index`, a position in the text object `obj` measured in `from`, to the same
+    /// position measured in `to`, as at `at` if given
+    ///
+    /// Positions in objects other than text are returned unchanged. Positions inside a grapheme
+    /// cluster are rounded down to the start of the cluster. Conversions between encodings other
+    /// than [`TextEncoding::GraphemeCluster`] only look at the text around `index`, conversions
+    /// to or from grapheme clusters need the whole text.
+    ///
+    /// ### Errors
+    ///
+    /// Returns [`AutomergeError::InvalidIndex`] if `index` is past the end of the text or inside
+    /// a character.
+    fn convert_text_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        from: TextEncoding,
+        to: TextEncoding,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError>;
+
+    /// The length of the text object `obj` measured in `encoding`, as at `at` if given
+    fn text_length<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+        encoding: TextEncoding,
+    ) -> Result<usize, AutomergeError> {
+        let length = match at {
+            Some(heads) => self.length_at(obj.as_ref(), heads),
+            None => self.length(obj.as_ref()),
+        };
+        self.convert_text_index(obj, length, TextEncoding::native(), encoding, at)
+    }
+
+    /// Like [`Self::marks()`] but the start and end of each mark are measured in `encoding`
+    ///
+    /// A mark which starts or ends inside a grapheme cluster is widened to cover the whole
+    /// cluster.
+    fn marks_with_encoding<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+        encoding: TextEncoding,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        let converter = IndexConverter::new(self, obj.as_ref(), at, encoding)?;
+        let marks = match at {
+            Some(heads) => self.marks_at(obj.as_ref(), heads)?,
+            None => self.marks(obj.as_ref())?,
+        };
+        marks
+            .into_iter()
+            .map(|mut mark| {
+                mark.start = converter.to_encoding(mark.start, false)?;
+                mark.end = converter.to_encoding(mark.end, true)?;
+                Ok(mark)
+            })
+            .collect()
+    }
+
     /// Get a value out of the document.
     ///
     /// This returns a tuple of `(value, object ID)`. This is for two reasons:
@@ -235,6 +473,49 @@ pub trait ReadDoc {
         heads: &[ChangeHash],
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError>;
 
+    /// The keys of `obj` which hold more than one value, see [`crate::conflicts`]
+    ///
+    /// For a list or text object the prop of each conflict is its index. Each conflict lists the
+    /// values [`Self::get_all()`] returns along with who set them. If `at` is given this is as
+    /// at those heads.
+    fn conflicts<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Conflict<'_>>, AutomergeError>;
+
+    /// Every key in the document which holds more than one value
+    ///
+    /// This is [`Self::conflicts()`] for every object reachable from the root.
+    fn all_conflicts(&self, at: Option<&[ChangeHash]>) -> Conflicts<'_>;
+
+    /// The changes which modified the object `obj`, in the order they were applied, each with
+    /// the keys of `obj` it modified and their values after it
+    ///
+    /// These are the change which created `obj` and every change which put, inserted, deleted,
+    /// marked or incremented something in it, found from the ids of the ops in `obj`. Changes to
+    /// objects nested inside `obj` are not included. If `at` is given only changes up to `at` are
+    /// returned. Use [`Self::hydrate()`] with the hash of a revision to read the whole object as
+    /// at that change.
+    fn history_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<ObjRevision<'_>>, AutomergeError>;
+
+    /// The changes which modified the key `prop` of `obj`, in the order they were applied, each
+    /// with the value of the key after it, `None` if the change deleted it
+    ///
+    /// For a list or text object `prop` is the index of an element as at `at` and the history
+    /// is of that element, from the change which inserted it. As with [`Self::get()`] conflicting
+    /// values are resolved to a single value.
+    fn history_of_prop<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<PropRevision<'_>>, AutomergeError>;
+
     /// Get the hashes of the changes in this document that aren't transitive dependencies of the
     /// given `heads`.
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash>;
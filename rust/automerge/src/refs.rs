@@ -0,0 +1,288 @@
+//! Named branches and tags
+//!
+//! A ref is a name for a set of heads. Refs are metadata about the history of a document rather
+//! than part of its content: creating or moving one doesn't create a change. They are merged by
+//! [`crate::Automerge::merge()`] and exchanged by the sync protocol. [`crate::Automerge::save()`]
+//! saves them in a chunk after the document which versions of automerge from before refs can't
+//! load, set [`crate::SaveOptions::refs`] to `false` to leave them out.
+//!
+//! A branch can be moved, and [`crate::Automerge::checkout()`] returns a fork of the document at
+//! the branch which moves the branch along with it as changes are committed. Merging the fork
+//! back into the original document brings both the changes and the new position of the branch.
+//! [`crate::Automerge::merge_branch()`] then merges one branch into another, which only moves the
+//! target branch: the changes on both are already in the document.
+//!
+//! A tag is fixed once it is created, though it can be deleted.
+//!
+//! ```
+//! # use automerge::{Automerge, ReadDoc, ROOT, transaction::Transactable};
+//! # fn main() -> Result<(), automerge::AutomergeError> {
+//! let mut doc = Automerge::new();
+//! doc.transact::<_, _, automerge::AutomergeError>(|tx| tx.put(ROOT, "title", "draft"))
+//!     .unwrap();
+//! doc.create_branch("main")?;
+//! doc.create_branch("proposal")?;
+//!
+//! let mut proposal = doc.checkout("proposal")?;
+//! proposal
+//!     .transact::<_, _, automerge::AutomergeError>(|tx| tx.put(ROOT, "title", "final"))
+//!     .unwrap();
+//! doc.merge(&mut proposal)?;
+//!
+//! // the main branch hasn't moved
+//! let main = doc.checkout("main")?;
+//! assert_eq!(main.get(ROOT, "title")?.unwrap().0.to_str(), Some("draft"));
+//!
+//! doc.merge_branch("proposal", "main")?;
+//! let main = doc.checkout("main")?;
+//! assert_eq!(main.get(ROOT, "title")?.unwrap().0.to_str(), Some("final"));
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::BTreeMap;
+
+use crate::storage::{parse, ChunkType, Header};
+use crate::sync::RefInfo;
+use crate::{ActorId, ChangeHash};
+
+/// Whether a ref is a branch or a tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RefKind {
+    /// A ref which can be moved
+    Branch,
+    /// A ref which can't be moved once it is created
+    Tag,
+}
+
+/// A named set of heads, see the [module documentation](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ref {
+    pub name: String,
+    pub kind: RefKind,
+    pub heads: Vec<ChangeHash>,
+}
+
+/// The refs of a document
+///
+/// Each ref is a last writer wins register: every update carries a version which is one more
+/// than the version it replaced, and when two peers update a ref concurrently the update with
+/// the highest version, or the highest actor ID if the versions are equal, wins. Deleted refs
+/// are kept, without their heads, so that the deletion wins over older versions of the ref.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Refs {
+    entries: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Entry {
+    kind: RefKind,
+    // `None` if the ref was deleted
+    heads: Option<Vec<ChangeHash>>,
+    version: u64,
+    actor: ActorId,
+}
+
+impl Entry {
+    fn supersedes(&self, other: &Entry) -> bool {
+        (self.version, &self.actor) > (other.version, &other.actor)
+    }
+}
+
+// flags in the encoding of each entry
+const TAG: u8 = 1;
+const DELETED: u8 = 2;
+
+impl Refs {
+    /// The ref called `name`, if there is one
+    pub fn get(&self, name: &str) -> Option<Ref> {
+        self.entries
+            .get(name)
+            .and_then(|entry| Self::to_ref(name, entry))
+    }
+
+    /// Iterate over the refs in order of their names
+    pub fn iter(&self) -> impl Iterator<Item = Ref> + '_ {
+        self.entries
+            .iter()
+            .filter_map(|(name, entry)| Self::to_ref(name, entry))
+    }
+
+    /// Whether there are no refs, including refs which have been deleted
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Set the ref called `name`, creating it if it doesn't exist
+    pub(crate) fn set(
+        &mut self,
+        name: &str,
+        kind: RefKind,
+        heads: Option<Vec<ChangeHash>>,
+        actor: &ActorId,
+    ) {
+        let version = self.entries.get(name).map_or(0, |entry| entry.version + 1);
+        self.entries.insert(
+            name.to_string(),
+            Entry {
+                kind,
+                heads,
+                version,
+                actor: actor.clone(),
+            },
+        );
+    }
+
+    /// Merge `other` into `self`, returning whether anything changed
+    pub(crate) fn merge(&mut self, other: &Refs) -> bool {
+        let mut changed = false;
+        for (name, theirs) in &other.entries {
+            let replace = self
+                .entries
+                .get(name)
+                .map_or(true, |ours| theirs.supersedes(ours));
+            if replace {
+                self.entries.insert(name.clone(), theirs.clone());
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// The entries, including deletions, for which `keep` returns `true`
+    pub(crate) fn filter<F: Fn(&RefInfo<'_>) -> bool + ?Sized>(&self, keep: &F) -> Refs {
+        Refs {
+            entries: self
+                .entries
+                .iter()
+                .filter(|(name, entry)| {
+                    keep(&RefInfo {
+                        name,
+                        kind: entry.kind,
+                        heads: entry.heads.as_deref(),
+                    })
+                })
+                .map(|(name, entry)| (name.clone(), entry.clone()))
+                .collect(),
+        }
+    }
+
+    fn to_ref(name: &str, entry: &Entry) -> Option<Ref> {
+        entry.heads.as_ref().map(|heads| Ref {
+            name: name.to_string(),
+            kind: entry.kind,
+            heads: heads.clone(),
+        })
+    }
+
+    /// Encode these refs as a chunk which can follow a saved document
+    pub(crate) fn encode_chunk(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        self.encode(&mut data);
+        let header = Header::new(ChunkType::Refs, &data);
+        let mut out = Vec::with_capacity(header.len() + data.len());
+        header.write(&mut out);
+        out.extend(data);
+        out
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        leb128::write::unsigned(out, self.entries.len() as u64).unwrap();
+        for (name, entry) in &self.entries {
+            leb128::write::unsigned(out, name.len() as u64).unwrap();
+            out.extend(name.as_bytes());
+            let mut flags = 0;
+            if entry.kind == RefKind::Tag {
+                flags |= TAG;
+            }
+            if entry.heads.is_none() {
+                flags |= DELETED;
+            }
+            out.push(flags);
+            leb128::write::unsigned(out, entry.version).unwrap();
+            leb128::write::unsigned(out, entry.actor.to_bytes().len() as u64).unwrap();
+            out.extend(entry.actor.to_bytes());
+            let heads = entry.heads.as_deref().unwrap_or(&[]);
+            leb128::write::unsigned(out, heads.len() as u64).unwrap();
+            for head in heads {
+                out.extend(head.as_bytes());
+            }
+        }
+    }
+
+    pub(crate) fn parse<E>(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, E>
+    where
+        E: From<parse::leb128::Error> + From<parse::InvalidUtf8>,
+    {
+        let (mut i, count) = parse::leb128_u64(input)?;
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let (rest, len) = parse::leb128_u64(i)?;
+            let (rest, name) = parse::utf_8(len as usize, rest)?;
+            let (rest, flags) = parse::take1(rest)?;
+            let (rest, version) = parse::leb128_u64(rest)?;
+            let (rest, actor) = parse::actor_id(rest)?;
+            let (rest, heads) = parse::length_prefixed(parse::change_hash)(rest)?;
+            let kind = if flags & TAG == 0 {
+                RefKind::Branch
+            } else {
+                RefKind::Tag
+            };
+            let heads = if flags & DELETED == 0 {
+                Some(heads)
+            } else {
+                None
+            };
+            entries.insert(
+                name,
+                Entry {
+                    kind,
+                    heads,
+                    version,
+                    actor,
+                },
+            );
+            i = rest;
+        }
+        Ok((i, Refs { entries }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_updates_converge() {
+        let (alice, bob) = (ActorId::from(b"alice"), ActorId::from(b"bob"));
+        let mut base = Refs::default();
+        base.set("main", RefKind::Branch, Some(Vec::new()), &alice);
+
+        let mut ours = base.clone();
+        ours.set("main", RefKind::Branch, None, &alice);
+        let mut theirs = base.clone();
+        theirs.set(
+            "main",
+            RefKind::Branch,
+            Some(vec![ChangeHash([1; 32])]),
+            &bob,
+        );
+        theirs.set("v1", RefKind::Tag, Some(vec![ChangeHash([1; 32])]), &bob);
+
+        let mut merged1 = ours.clone();
+        assert!(merged1.merge(&theirs));
+        let mut merged2 = theirs.clone();
+        merged2.merge(&ours);
+        assert_eq!(merged1, merged2);
+        assert_eq!(
+            merged1.get("main").unwrap().heads,
+            vec![ChangeHash([1; 32])]
+        );
+        assert!(!merged1.merge(&base));
+
+        let mut encoded = Vec::new();
+        merged1.encode(&mut encoded);
+        let (_, decoded) =
+            Refs::parse::<crate::sync::ReadMessageError>(parse::Input::new(&encoded)).unwrap();
+        assert_eq!(decoded, merged1);
+    }
+}
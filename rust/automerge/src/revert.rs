@@ -0,0 +1,374 @@
+//! Reverting changes
+//!
+//! [`crate::transaction::Transactable::revert()`] makes new ops which undo the effects of a change
+//! anywhere in the history of a document, and [`crate::transaction::Transactable::revert_range()`]
+//! does the same for a change and the changes made on top of it. Every value the changes set is
+//! removed and every value they overwrote or deleted is put back, as it is now.
+//!
+//! Later edits, including concurrent ones, take priority: a value which another change has
+//! modified since is left alone and reported in [`Revert::skipped`]. Marks and blocks are
+//! reported there too, as they can't be reverted yet.
+//!
+//! ```
+//! # use automerge::{AutoCommit, ReadDoc, ROOT, transaction::Transactable};
+//! let mut doc = AutoCommit::new();
+//! doc.put(ROOT, "title", "draft").unwrap();
+//! doc.put(ROOT, "author", "alice").unwrap();
+//! doc.commit();
+//! doc.put(ROOT, "title", "vandalised").unwrap();
+//! doc.put(ROOT, "author", "mallory").unwrap();
+//! let bad = doc.commit().unwrap();
+//! doc.put(ROOT, "author", "bob").unwrap();
+//! doc.commit();
+//!
+//! let revert = doc.revert(bad).unwrap();
+//! assert_eq!(revert.changes, vec![bad]);
+//! assert_eq!(doc.get(ROOT, "title").unwrap().unwrap().0.to_str(), Some("draft"));
+//! // "author" was changed again after the bad change, so it is left alone
+//! assert_eq!(doc.get(ROOT, "author").unwrap().unwrap().0.to_str(), Some("bob"));
+//! assert_eq!(revert.skipped.len(), 1);
+//! ```
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+
+use crate::exid::ExId;
+use crate::op_set::Op;
+use crate::patches::PatchLog;
+use crate::transaction::TransactionInner;
+use crate::types::{Clock, Key, ObjId, ObjMeta, OpId, OpType};
+use crate::{Automerge, AutomergeError, ChangeHash, ObjType, Prop, ReadDoc, ScalarValue, Value};
+
+/// The result of reverting changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revert {
+    /// The changes which were reverted, in the order they were applied to the document
+    pub changes: Vec<ChangeHash>,
+    /// The parts of those changes which were left as they are
+    pub skipped: Vec<Skipped>,
+}
+
+impl Revert {
+    /// Whether every part of the changes was reverted
+    pub fn is_clean(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+/// A key or index which [`crate::transaction::Transactable::revert()`] didn't revert
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub obj: ExId,
+    /// The key, or the index in the object as it was before reverting
+    pub prop: Prop,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// A change which isn't being reverted has modified the value since, or put another value
+    /// concurrently which would conflict with the one being restored
+    Modified,
+    /// The change added a mark
+    Mark,
+    /// The change created a block or overwrote a character of a text object
+    Unsupported,
+}
+
+/// The ops of the changes being reverted
+struct Targets(Vec<(usize, Range<u64>)>);
+
+impl Targets {
+    fn contains(&self, op: &Op<'_>) -> bool {
+        let id = op.id();
+        self.0
+            .iter()
+            .any(|(actor, ops)| *actor == id.actor() && ops.contains(&id.counter()))
+    }
+}
+
+enum Restore {
+    Scalar(ScalarValue),
+    /// Copy the current contents of an object into a new one
+    Object(ObjType, ExId),
+}
+
+impl Restore {
+    fn new(value: Value<'_>, id: ExId) -> Self {
+        match value {
+            Value::Object(typ) => Restore::Object(typ, id),
+            Value::Scalar(s) => Restore::Scalar(s.into_owned()),
+        }
+    }
+}
+
+enum Action {
+    Delete(Prop),
+    /// Delete some of the values of a key which holds a conflict
+    DeleteValues(Prop, Vec<OpId>),
+    Put(Prop, Restore),
+    Insert(usize, Restore),
+    SpliceText(usize, isize, String),
+    Increment(Prop, i64),
+}
+
+pub(crate) fn revert(
+    tx: &mut TransactionInner,
+    doc: &mut Automerge,
+    patch_log: &mut PatchLog,
+    change: ChangeHash,
+    heads: Option<&[ChangeHash]>,
+) -> Result<Revert, AutomergeError> {
+    for hash in std::iter::once(&change).chain(heads.into_iter().flatten()) {
+        if doc.get_change_by_hash(hash).is_none() {
+            return Err(AutomergeError::MissingHash(*hash));
+        }
+    }
+    let mut reverting = doc.change_graph().descendants(&[change]);
+    match heads {
+        Some(heads) => {
+            let ancestors = doc.change_graph().ancestors(heads);
+            reverting.retain(|hash| ancestors.contains(hash));
+        }
+        None => reverting.retain(|hash| *hash == change),
+    }
+
+    let mut changes = Vec::new();
+    let mut targets = Vec::new();
+    let mut keys = HashMap::new();
+    for c in doc.history() {
+        if reverting.contains(&c.hash()) {
+            changes.push(c.hash());
+            if let Some(actor) = doc.osd().actors.lookup(c.actor_id()) {
+                let start = c.start_op().get();
+                targets.push((actor, start..start + c.len() as u64));
+            }
+            for (obj, key) in doc.change_op_keys(c) {
+                keys.entry(obj).or_insert_with(BTreeSet::new).insert(key);
+            }
+        }
+    }
+
+    let clock = tx.get_scope().clone();
+    let mut skipped = Vec::new();
+    let plans = plan(doc, clock.as_ref(), &Targets(targets), keys, &mut skipped);
+    for (obj, actions) in plans {
+        let ex_obj = doc.id_to_exid(obj.id.0);
+        // actions are in index order, so applying them backwards keeps the indexes valid
+        for action in actions.into_iter().rev() {
+            match action {
+                Action::Delete(prop) => tx.delete(doc, patch_log, &ex_obj, prop)?,
+                Action::DeleteValues(prop, ids) => {
+                    tx.delete_values(doc, patch_log, &obj, prop, &ids)?
+                }
+                Action::Put(prop, value) => put(tx, doc, patch_log, &ex_obj, prop, value, false)?,
+                Action::Insert(index, value) => {
+                    put(tx, doc, patch_log, &ex_obj, index.into(), value, true)?
+                }
+                Action::SpliceText(index, del, text) => {
+                    tx.splice_text(doc, patch_log, &ex_obj, index, del, &text)?
+                }
+                Action::Increment(prop, by) => tx.increment(doc, patch_log, &ex_obj, prop, by)?,
+            }
+        }
+    }
+    Ok(Revert { changes, skipped })
+}
+
+/// The actions which revert the targets at `keys`, by object
+///
+/// Only the objects and keys which the targets modified are looked at, in the order they appear
+/// in the document.
+fn plan(
+    doc: &Automerge,
+    clock: Option<&Clock>,
+    targets: &Targets,
+    keys: HashMap<ObjId, BTreeSet<Key>>,
+    skipped: &mut Vec<Skipped>,
+) -> Vec<(ObjMeta, Vec<Action>)> {
+    let mut keys = keys.into_iter().collect::<Vec<_>>();
+    keys.sort_by(|(a, _), (b, _)| doc.osd().lamport_cmp(a.0, b.0));
+    let mut plans = Vec::new();
+    for (obj, obj_keys) in keys {
+        let obj = match doc.get_obj_meta(obj) {
+            Ok(obj) => obj,
+            Err(_) => continue,
+        };
+        let mut found = obj_keys
+            .into_iter()
+            .filter_map(|key| doc.ops().ops_at_key(&obj.id, key, clock))
+            .collect::<Vec<_>>();
+        found.sort_by_key(|(pos, _, _)| *pos);
+        let mut actions = Vec::new();
+        for (_, index, ops) in found {
+            let ops = ops
+                .into_iter()
+                .filter(|op| !op.is_delete())
+                .collect::<Vec<_>>();
+            let prop = match ops.first().and_then(|op| op.map_prop()) {
+                Some(prop) => prop,
+                None => Prop::Seq(index),
+            };
+            match plan_key(doc, &obj, &ops, clock, targets, &prop, index) {
+                Ok(Some(action)) => actions.push(action),
+                Ok(None) => {}
+                Err(reason) => skipped.push(Skipped {
+                    obj: doc.id_to_exid(obj.id.0),
+                    prop,
+                    reason,
+                }),
+            }
+        }
+        if !actions.is_empty() {
+            plans.push((obj, actions));
+        }
+    }
+    plans
+}
+
+/// The action which reverts the target ops at one key or list element, if there is anything to
+/// revert
+fn plan_key(
+    doc: &Automerge,
+    obj: &ObjMeta,
+    ops: &[Op<'_>],
+    clock: Option<&Clock>,
+    targets: &Targets,
+    prop: &Prop,
+    index: usize,
+) -> Result<Option<Action>, SkipReason> {
+    let is_text = obj.typ == ObjType::Text;
+    if ops.iter().any(|op| op.is_mark() && targets.contains(op)) {
+        return Err(SkipReason::Mark);
+    }
+    let targeted = |op: &Op<'_>| !op.is_inc() && targets.contains(op);
+    let changed = ops.iter().filter(|op| targeted(op)).collect::<Vec<_>>();
+    // the newest value which the targets overwrote or deleted and nothing else has
+    let before = ops
+        .iter()
+        .filter(|op| !op.is_inc() && !targets.contains(op))
+        .rfind(|op| {
+            let mut succ = op.succ().filter(|s| !s.is_inc()).peekable();
+            succ.peek().is_some() && succ.all(|s| targeted(&s))
+        });
+    let visible = ops
+        .iter()
+        .filter(|op| op.visible_at(clock))
+        .collect::<Vec<_>>();
+
+    if changed.is_empty() && before.is_none() {
+        // only increments of counters which aren't being reverted
+        let mut by = 0;
+        for inc in ops.iter().filter(|op| op.is_inc() && targets.contains(op)) {
+            if !inc.pred().any(|counter| counter.visible_at(clock)) {
+                return Err(SkipReason::Modified);
+            }
+            by -= inc.get_increment_value().unwrap_or(0);
+        }
+        return Ok((by != 0).then(|| Action::Increment(prop.clone(), by)));
+    }
+    if changed
+        .iter()
+        .any(|op| op.succ().any(|s| !s.is_inc() && !targets.contains(&s)))
+    {
+        return Err(SkipReason::Modified);
+    }
+    if is_text
+        && (changed.iter().chain(before.iter())).any(|op| matches!(op.action(), OpType::Make(_)))
+    {
+        return Err(SkipReason::Unsupported);
+    }
+
+    let ours = visible
+        .iter()
+        .filter(|op| targeted(op))
+        .map(|op| *op.id())
+        .collect::<Vec<_>>();
+    if ours.len() < visible.len() {
+        return if !ours.is_empty() {
+            Ok(Some(Action::DeleteValues(prop.clone(), ours)))
+        } else if before.is_some() {
+            Err(SkipReason::Modified)
+        } else {
+            Ok(None)
+        };
+    }
+    match before {
+        Some(op) => {
+            let restore = Restore::new(op.value_at(clock), doc.id_to_exid(*op.id()));
+            match (restore, obj.typ.is_sequence(), ours.is_empty()) {
+                (Restore::Scalar(ScalarValue::Str(s)), true, true) if is_text => {
+                    Ok(Some(Action::SpliceText(index, 0, s.to_string())))
+                }
+                (_, _, false) if is_text => Err(SkipReason::Unsupported),
+                (restore, true, true) => Ok(Some(Action::Insert(index, restore))),
+                (restore, _, _) => Ok(Some(Action::Put(prop.clone(), restore))),
+            }
+        }
+        None if ours.is_empty() => Ok(None),
+        None if is_text => Ok(Some(Action::SpliceText(
+            index,
+            visible[0].width(obj.encoding) as isize,
+            String::new(),
+        ))),
+        None => Ok(Some(Action::Delete(prop.clone()))),
+    }
+}
+
+fn put(
+    tx: &mut TransactionInner,
+    doc: &mut Automerge,
+    patch_log: &mut PatchLog,
+    obj: &ExId,
+    prop: Prop,
+    value: Restore,
+    insert: bool,
+) -> Result<(), AutomergeError> {
+    match (value, prop) {
+        (Restore::Scalar(value), Prop::Seq(index)) if insert => {
+            tx.insert(doc, patch_log, obj, index, value)
+        }
+        (Restore::Scalar(value), prop) => tx.put(doc, patch_log, obj, prop, value),
+        (Restore::Object(typ, from), prop) => {
+            let to = match prop {
+                Prop::Seq(index) if insert => tx.insert_object(doc, patch_log, obj, index, typ)?,
+                prop => tx.put_object(doc, patch_log, obj, prop, typ)?,
+            };
+            copy_object(tx, doc, patch_log, &from, &to, typ)
+        }
+    }
+}
+
+fn copy_object(
+    tx: &mut TransactionInner,
+    doc: &mut Automerge,
+    patch_log: &mut PatchLog,
+    from: &ExId,
+    to: &ExId,
+    typ: ObjType,
+) -> Result<(), AutomergeError> {
+    match typ {
+        ObjType::Map | ObjType::Table => {
+            let values = doc
+                .map_range(from, ..)
+                .map(|item| (Prop::from(item.key), Restore::new(item.value, item.id)))
+                .collect::<Vec<_>>();
+            for (prop, value) in values {
+                put(tx, doc, patch_log, to, prop, value, false)?;
+            }
+        }
+        ObjType::List => {
+            let values = doc
+                .list_range(from, ..)
+                .map(|item| Restore::new(item.value, item.id))
+                .collect::<Vec<_>>();
+            for (index, value) in values.into_iter().enumerate() {
+                put(tx, doc, patch_log, to, index.into(), value, true)?;
+            }
+        }
+        ObjType::Text => {
+            let text = doc.text(from)?;
+            tx.splice_text(doc, patch_log, to, 0, 0, &text)?;
+        }
+    }
+    Ok(())
+}
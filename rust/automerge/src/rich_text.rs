@@ -0,0 +1,252 @@
+//! Convert text objects with marks to and from Markdown and HTML
+//!
+//! The conversions understand four marks:
+//!
+//! * [`BOLD`] and [`ITALIC`], with a value of `true`
+//! * [`CODE`], with a value of `true`
+//! * [`LINK`], whose value is the URL as a string
+//!
+//! Other marks are ignored when exporting and block markers are skipped. Importing splices the
+//! plain text into the text object and then adds a mark for each formatted range, expanding it
+//! according to the [`crate::marks::MarkPolicy`] registered for the mark. [`expand_mark()`] gives
+//! the expansion we suggest registering for each of these marks.
+//!
+//! ```
+//! # use automerge::{AutoCommit, ObjType, ReadDoc, ROOT, transaction::Transactable};
+//! # use automerge::rich_text;
+//! let mut doc = AutoCommit::new();
+//! let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+//! rich_text::splice_markdown(&mut doc, &text, 0, "some **bold** text").unwrap();
+//! assert_eq!(doc.text(&text).unwrap(), "some bold text");
+//! assert_eq!(
+//!     rich_text::to_html(&doc, &text).unwrap(),
+//!     "some <strong>bold</strong> text"
+//! );
+//! ```
+use crate::exid::ExId;
+use crate::iter::Span;
+use crate::marks::{ExpandMark, Mark, MarkSet};
+use crate::transaction::Transactable;
+use crate::{AutomergeError, ReadDoc, ScalarValue};
+
+mod html;
+mod markdown;
+
+pub use html::{parse_html, to_html};
+pub use markdown::{parse_markdown, to_markdown};
+
+/// The name of the mark for bold text
+pub const BOLD: &str = "bold";
+/// The name of the mark for italic text
+pub const ITALIC: &str = "italic";
+/// The name of the mark for inline code
+pub const CODE: &str = "code";
+/// The name of the mark for links, the value of the mark is the URL
+pub const LINK: &str = "link";
+
+/// The [`ExpandMark`] we suggest registering for the marks created when importing
+///
+/// Text typed at the end of bold or italic text usually continues the formatting, so these
+/// expand after. Text typed next to a link or a piece of code usually isn't part of it, so these
+/// don't expand at all.
+///
+/// ```
+/// # use automerge::{AutoCommit, marks::MarkPolicy, rich_text};
+/// let mut doc = AutoCommit::new();
+/// for name in [rich_text::BOLD, rich_text::ITALIC, rich_text::CODE, rich_text::LINK] {
+///     doc.set_mark_policy(name, MarkPolicy::new(rich_text::expand_mark(name)));
+/// }
+/// ```
+pub fn expand_mark(name: &str) -> ExpandMark {
+    match name {
+        BOLD | ITALIC => ExpandMark::After,
+        _ => ExpandMark::None,
+    }
+}
+
+/// Parse `markdown` and insert it at `index` in the text object `text`
+pub fn splice_markdown<T: Transactable, O: AsRef<ExId>>(
+    doc: &mut T,
+    text: O,
+    index: usize,
+    markdown: &str,
+) -> Result<(), AutomergeError> {
+    let (plain, marks) = parse_markdown(markdown);
+    splice_marked(doc, text.as_ref(), index, &plain, marks)
+}
+
+/// Parse `html` and insert it at `index` in the text object `text`
+///
+/// Unsupported elements are dropped (keeping their text, except for `script` and `style`) as are
+/// links whose URL isn't `http`, `https`, `mailto` or relative.
+pub fn splice_html<T: Transactable, O: AsRef<ExId>>(
+    doc: &mut T,
+    text: O,
+    index: usize,
+    html: &str,
+) -> Result<(), AutomergeError> {
+    let (plain, marks) = parse_html(html);
+    splice_marked(doc, text.as_ref(), index, &plain, marks)
+}
+
+fn splice_marked<T: Transactable>(
+    doc: &mut T,
+    text: &ExId,
+    index: usize,
+    plain: &str,
+    marks: Vec<Mark<'static>>,
+) -> Result<(), AutomergeError> {
+    doc.splice_text(text, index, 0, plain)?;
+    for mut mark in marks {
+        mark.start += index;
+        mark.end += index;
+        doc.mark(text, mark, None)?;
+    }
+    Ok(())
+}
+
+/// The formatting of a run of text, outermost first
+#[derive(Debug, Clone, PartialEq)]
+enum Format {
+    Link(String),
+    Bold,
+    Italic,
+    Code,
+}
+
+impl Format {
+    fn from_marks(marks: Option<&MarkSet>) -> Vec<Format> {
+        let mut link = None;
+        let (mut bold, mut italic, mut code) = (false, false, false);
+        for (name, value) in marks.into_iter().flat_map(|m| m.iter()) {
+            match (name, value) {
+                (LINK, ScalarValue::Str(url)) => link = Some(url.to_string()),
+                (BOLD, value) => bold = is_set(value),
+                (ITALIC, value) => italic = is_set(value),
+                (CODE, value) => code = is_set(value),
+                _ => {}
+            }
+        }
+        let mut formats = Vec::new();
+        formats.extend(link.map(Format::Link));
+        if bold {
+            formats.push(Format::Bold);
+        }
+        if italic {
+            formats.push(Format::Italic);
+        }
+        if code {
+            formats.push(Format::Code);
+        }
+        formats
+    }
+}
+
+fn is_set(value: &ScalarValue) -> bool {
+    !matches!(value, ScalarValue::Null | ScalarValue::Boolean(false))
+}
+
+/// Something which renders formatted text
+trait Writer {
+    fn open(&mut self, format: &Format);
+    fn close(&mut self, format: &Format);
+    fn text(&mut self, text: &str);
+}
+
+/// Render the spans of `text`, opening and closing formats so that they nest properly
+fn write_spans<R: ReadDoc, W: Writer>(
+    doc: &R,
+    text: &ExId,
+    writer: &mut W,
+) -> Result<(), AutomergeError> {
+    let mut open: Vec<Format> = Vec::new();
+    for span in doc.spans(text)? {
+        if let Span::Text(text, marks) = span {
+            let formats = Format::from_marks(marks.as_deref());
+            let common = open
+                .iter()
+                .zip(formats.iter())
+                .take_while(|(a, b)| a == b)
+                .count();
+            while open.len() > common {
+                if let Some(format) = open.pop() {
+                    writer.close(&format);
+                }
+            }
+            for format in &formats[common..] {
+                writer.open(format);
+                open.push(format.clone());
+            }
+            writer.text(&text);
+        }
+    }
+    while let Some(format) = open.pop() {
+        writer.close(&format);
+    }
+    Ok(())
+}
+
+/// Whether `url` is safe to put in a link: `http`, `https`, `mailto` or relative
+fn is_safe_url(url: &str) -> bool {
+    let scheme_end = url.find(|c| matches!(c, ':' | '/' | '?' | '#'));
+    match scheme_end {
+        Some(i) if url[i..].starts_with(':') => {
+            let scheme = url[..i].to_ascii_lowercase();
+            matches!(scheme.as_str(), "http" | "https" | "mailto")
+        }
+        _ => true,
+    }
+}
+
+/// A position in a [`MarkedText`], as a byte offset and as an index in the text object
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Position {
+    byte: usize,
+    index: usize,
+}
+
+/// Builds the plain text and marks of a parsed document, measuring positions as the text object
+/// does
+#[derive(Debug, Default)]
+struct MarkedText {
+    text: String,
+    len: usize,
+    marks: Vec<Mark<'static>>,
+}
+
+impl MarkedText {
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.len += crate::TextEncoding::native().width(c.encode_utf8(&mut [0; 4]));
+    }
+
+    fn push_str(&mut self, s: &str) {
+        s.chars().for_each(|c| self.push(c));
+    }
+
+    /// Insert `s` at `at`, moving any marks after it along
+    fn insert(&mut self, at: Position, s: &str) {
+        let width = crate::TextEncoding::native().width(s);
+        self.text.insert_str(at.byte, s);
+        self.len += width;
+        for mark in &mut self.marks {
+            if mark.start >= at.index {
+                mark.start += width;
+            }
+            if mark.end > at.index {
+                mark.end += width;
+            }
+        }
+    }
+
+    fn mark<V: Into<ScalarValue>>(&mut self, name: &str, value: V, start: usize) {
+        if start < self.len {
+            self.marks
+                .push(Mark::new(name.to_string(), value, start, self.len));
+        }
+    }
+
+    fn finish(self) -> (String, Vec<Mark<'static>>) {
+        (self.text, self.marks)
+    }
+}
@@ -0,0 +1,328 @@
+use crate::exid::ExId;
+use crate::marks::Mark;
+use crate::{AutomergeError, ReadDoc};
+
+use super::{is_safe_url, write_spans, Format, MarkedText, Writer, BOLD, CODE, ITALIC, LINK};
+
+/// Render the text object `text` as HTML
+///
+/// Bold is written as `<strong>`, italic as `<em>`, code as `<code>` and links as `<a href>`.
+/// Newlines become `<br>`, all text and URLs are escaped and links with a URL which isn't `http`,
+/// `https`, `mailto` or relative are left out, so the output is safe to embed in a page.
+pub fn to_html<R: ReadDoc, O: AsRef<ExId>>(doc: &R, text: O) -> Result<String, AutomergeError> {
+    let mut writer = HtmlWriter::default();
+    write_spans(doc, text.as_ref(), &mut writer)?;
+    Ok(writer.out)
+}
+
+/// Parse `html` into plain text and the marks on it
+///
+/// `<strong>` and `<b>` are bold, `<em>` and `<i>` are italic, `<code>` is code and `<a href>` is a
+/// link. `<br>` and the end of a paragraph, heading, list item or `<div>` become newlines. Other
+/// elements are dropped, keeping their text, except for `<script>` and `<style>` which are
+/// dropped entirely. As in a browser runs of whitespace are collapsed to a single space.
+pub fn parse_html(html: &str) -> (String, Vec<Mark<'static>>) {
+    let mut parser = HtmlParser::default();
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => match rest.find('>') {
+                Some(end) => {
+                    let tag = &rest[1..end];
+                    rest = &rest[end + 1..];
+                    if let Some(skipped) = parser.tag(tag, rest) {
+                        rest = skipped;
+                    }
+                }
+                None => {
+                    parser.text("<");
+                    rest = &rest[1..];
+                }
+            },
+            '&' => {
+                let (decoded, len) = decode_entity(rest);
+                parser.text(&decoded);
+                rest = &rest[len..];
+            }
+            c => {
+                let len = c.len_utf8();
+                parser.text(&rest[..len]);
+                rest = &rest[len..];
+            }
+        }
+    }
+    parser.out.finish()
+}
+
+#[derive(Default)]
+struct HtmlWriter {
+    out: String,
+}
+
+impl Writer for HtmlWriter {
+    fn open(&mut self, format: &Format) {
+        match format {
+            Format::Link(url) if is_safe_url(url) => {
+                self.out.push_str("<a href=\"");
+                escape_into(&mut self.out, url);
+                self.out.push_str("\">");
+            }
+            Format::Link(_) => {}
+            Format::Bold => self.out.push_str("<strong>"),
+            Format::Italic => self.out.push_str("<em>"),
+            Format::Code => self.out.push_str("<code>"),
+        }
+    }
+
+    fn close(&mut self, format: &Format) {
+        match format {
+            Format::Link(url) if is_safe_url(url) => self.out.push_str("</a>"),
+            Format::Link(_) => {}
+            Format::Bold => self.out.push_str("</strong>"),
+            Format::Italic => self.out.push_str("</em>"),
+            Format::Code => self.out.push_str("</code>"),
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let mut lines = text.split('\n');
+        if let Some(first) = lines.next() {
+            escape_into(&mut self.out, first);
+        }
+        for line in lines {
+            self.out.push_str("<br>");
+            escape_into(&mut self.out, line);
+        }
+    }
+}
+
+fn escape_into(out: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Decode the character reference at the start of `s`, returning the decoded text and how many
+/// bytes it took up. Anything which isn't a valid reference is a literal `&`.
+fn decode_entity(s: &str) -> (String, usize) {
+    let end = match s[1..].find(';') {
+        Some(i) if i <= 10 => i + 1,
+        _ => return ("&".to_string(), 1),
+    };
+    let name = &s[1..end];
+    let decoded = match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => name
+            .strip_prefix("#x")
+            .or_else(|| name.strip_prefix("#X"))
+            .map(|hex| u32::from_str_radix(hex, 16))
+            .or_else(|| name.strip_prefix('#').map(|dec| dec.parse()))
+            .and_then(Result::ok)
+            .and_then(char::from_u32),
+    };
+    match decoded {
+        Some(c) => (c.to_string(), end + 1),
+        None => ("&".to_string(), 1),
+    }
+}
+
+#[derive(Default)]
+struct HtmlParser {
+    out: MarkedText,
+    // how deeply nested we are in elements of each kind, and where the outermost one started
+    bold: (usize, usize),
+    italic: (usize, usize),
+    code: (usize, usize),
+    // the start and URL of each open `<a>`, `None` for links we're ignoring
+    links: Vec<Option<(usize, String)>>,
+    // whether the last thing we output was whitespace, so we can collapse runs of it
+    space: bool,
+}
+
+impl HtmlParser {
+    fn text(&mut self, s: &str) {
+        for c in s.chars() {
+            if c.is_ascii_whitespace() {
+                if !self.space {
+                    self.out.push(' ');
+                    self.space = true;
+                }
+            } else {
+                self.out.push(c);
+                self.space = false;
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.space = true;
+    }
+
+    /// Handle the tag `tag` (without the angle brackets). If the contents of the element should be
+    /// skipped returns the remainder of the input after the element.
+    fn tag<'a>(&mut self, tag: &str, rest: &'a str) -> Option<&'a str> {
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name_end = tag
+            .find(|c: char| c.is_ascii_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        let len = self.out.len;
+        match (name.as_str(), closing) {
+            ("script" | "style", false) => {
+                let close = format!("</{}", name);
+                let end = rest.to_ascii_lowercase().find(&close)?;
+                let after = &rest[end..];
+                return Some(after.find('>').map(|i| &after[i + 1..]).unwrap_or(""));
+            }
+            ("b" | "strong", _) => nest(&mut self.out, &mut self.bold, closing, BOLD, len),
+            ("i" | "em", _) => nest(&mut self.out, &mut self.italic, closing, ITALIC, len),
+            ("code", _) => nest(&mut self.out, &mut self.code, closing, CODE, len),
+            ("a", false) => {
+                let link = href(&tag[name_end..])
+                    .filter(|url| is_safe_url(url))
+                    .map(|url| (len, url));
+                self.links.push(link);
+            }
+            ("a", true) => {
+                if let Some(Some((start, url))) = self.links.pop() {
+                    self.out.mark(LINK, url, start);
+                }
+            }
+            ("br", _) => self.newline(),
+            ("p" | "div" | "li" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => self.newline(),
+            _ => {}
+        }
+        None
+    }
+}
+
+fn nest(out: &mut MarkedText, state: &mut (usize, usize), closing: bool, name: &str, len: usize) {
+    let (depth, start) = state;
+    if closing {
+        if *depth == 1 {
+            out.mark(name, true, *start);
+        }
+        *depth = depth.saturating_sub(1);
+    } else {
+        if *depth == 0 {
+            *start = len;
+        }
+        *depth += 1;
+    }
+}
+
+/// The value of the `href` attribute in `attrs`, decoded
+///
+/// Attributes are parsed as a browser does, so `href` only matches a whole attribute name and not
+/// e.g. `data-href` or text inside the value of another attribute.
+fn href(attrs: &str) -> Option<String> {
+    let is_space = |c: char| c.is_ascii_whitespace();
+    let mut rest = attrs;
+    let raw = loop {
+        rest = rest.trim_start_matches(|c: char| is_space(c) || c == '/');
+        if rest.is_empty() {
+            return None;
+        }
+        let name_end = rest
+            .find(|c: char| is_space(c) || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        rest = rest[name_end..].trim_start_matches(is_space);
+        let mut value = None;
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start_matches(is_space);
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let quoted = &after[1..];
+                    let end = quoted.find(quote)?;
+                    (&quoted[..end], &quoted[end + 1..])
+                }
+                _ => after.split_at(after.find(is_space).unwrap_or(after.len())),
+            };
+            value = Some(raw);
+            rest = remaining;
+        }
+        // the first of duplicated attributes wins
+        if name.eq_ignore_ascii_case("href") {
+            break value?;
+        }
+    };
+    let mut url = String::new();
+    let mut rest = raw;
+    while let Some(i) = rest.find('&') {
+        url.push_str(&rest[..i]);
+        let (decoded, len) = decode_entity(&rest[i..]);
+        url.push_str(&decoded);
+        rest = &rest[i + len..];
+    }
+    url.push_str(rest);
+    Some(url.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(marks: &[Mark<'_>]) -> Vec<(String, usize, usize)> {
+        marks
+            .iter()
+            .map(|m| (m.name().to_string(), m.start, m.end))
+            .collect()
+    }
+
+    #[test]
+    fn parse_formatting() {
+        let (text, marks) = parse_html(
+            "<p>a <b>bold</b> &amp; <em>it</em></p><a href=\"https://x.y/?a=1&amp;b=2\">link</a>",
+        );
+        assert_eq!(text, "a bold & it\nlink");
+        assert_eq!(
+            ranges(&marks),
+            vec![
+                ("bold".to_string(), 2, 6),
+                ("italic".to_string(), 9, 11),
+                ("link".to_string(), 12, 16),
+            ]
+        );
+        assert_eq!(marks[2].value(), &"https://x.y/?a=1&b=2".into());
+    }
+
+    #[test]
+    fn parse_href_attribute() {
+        let (_, marks) = parse_html(
+            "<a data-href='javascript:x' title=\"href=javascript:y\" HREF = 'http://a/'>x</a>\
+             <a hreflang=en href=/b href=/c>y</a><a href>z</a>",
+        );
+        assert_eq!(
+            ranges(&marks),
+            vec![("link".to_string(), 0, 1), ("link".to_string(), 1, 2)]
+        );
+        assert_eq!(marks[0].value(), &"http://a/".into());
+        assert_eq!(marks[1].value(), &"/b".into());
+    }
+
+    #[test]
+    fn parse_sanitizes() {
+        let (text, marks) = parse_html(
+            "<script>alert(1)</script><a href='javascript:alert(1)'>x</a>  <img src=x onerror=y>y",
+        );
+        assert_eq!(text, "x y");
+        assert!(marks.is_empty());
+    }
+}
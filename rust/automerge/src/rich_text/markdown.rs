@@ -0,0 +1,397 @@
+use crate::exid::ExId;
+use crate::marks::Mark;
+use crate::{AutomergeError, ReadDoc};
+
+use super::{
+    is_safe_url, write_spans, Format, MarkedText, Position, Writer, BOLD, CODE, ITALIC, LINK,
+};
+
+/// Render the text object `text` as Markdown
+///
+/// Bold is written as `**bold**`, italic as `*italic*`, code as `` `code` `` and links as
+/// `[text](url)`. Characters which Markdown would interpret as formatting or HTML are escaped
+/// with a backslash. Links with a URL which isn't `http`, `https`, `mailto` or relative are left
+/// out, keeping their text.
+pub fn to_markdown<R: ReadDoc, O: AsRef<ExId>>(doc: &R, text: O) -> Result<String, AutomergeError> {
+    let mut writer = MarkdownWriter::default();
+    write_spans(doc, text.as_ref(), &mut writer)?;
+    Ok(writer.out)
+}
+
+/// Parse the inline formatting of `markdown` into plain text and the marks on it
+///
+/// This understands `**bold**` and `__bold__`, `*italic*` and `_italic_`, code spans and inline
+/// links. Everything else, including block level syntax such as headings and lists, is kept as
+/// text. Delimiters which are never closed are kept as text too.
+pub fn parse_markdown(markdown: &str) -> (String, Vec<Mark<'static>>) {
+    MarkdownParser::new(markdown).parse()
+}
+
+#[derive(Default)]
+struct MarkdownWriter {
+    out: String,
+    // the contents of the code span we're in, which we need to see before we can pick a fence
+    code: Option<String>,
+}
+
+impl Writer for MarkdownWriter {
+    fn open(&mut self, format: &Format) {
+        match format {
+            Format::Link(url) if is_safe_url(url) => self.out.push('['),
+            Format::Link(_) => {}
+            Format::Bold => self.out.push_str("**"),
+            Format::Italic => self.out.push('*'),
+            Format::Code => self.code = Some(String::new()),
+        }
+    }
+
+    fn close(&mut self, format: &Format) {
+        match format {
+            Format::Link(url) if is_safe_url(url) => {
+                self.out.push_str("](");
+                write_destination(&mut self.out, url);
+                self.out.push(')');
+            }
+            Format::Link(_) => {}
+            Format::Bold => self.out.push_str("**"),
+            Format::Italic => self.out.push('*'),
+            Format::Code => {
+                let code = self.code.take().unwrap_or_default();
+                let fence = "`".repeat(longest_run(&code, '`') + 1);
+                // a space either side stops backticks at the edges merging with the fence
+                let pad = if code.starts_with('`') || code.ends_with('`') {
+                    " "
+                } else {
+                    ""
+                };
+                self.out.push_str(&fence);
+                self.out.push_str(pad);
+                self.out.push_str(&code);
+                self.out.push_str(pad);
+                self.out.push_str(&fence);
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(code) = &mut self.code {
+            code.push_str(text);
+            return;
+        }
+        for c in text.chars() {
+            if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>') {
+                self.out.push('\\');
+            }
+            self.out.push(c);
+        }
+    }
+}
+
+/// Write `url` as the destination of a link, in angle brackets if it contains anything which
+/// would end a bare destination
+fn write_destination(out: &mut String, url: &str) {
+    let bracket = url.is_empty()
+        || url.contains(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '<' | '>'));
+    if bracket {
+        out.push('<');
+    }
+    for c in url.chars() {
+        match c {
+            // line breaks can't appear in a destination at all
+            '\n' => out.push_str("%0A"),
+            '\r' => out.push_str("%0D"),
+            '\\' | '<' | '>' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    if bracket {
+        out.push('>');
+    }
+}
+
+/// Parse the destination of a link from `s`, which follows the `(`, returning the URL and the
+/// length of the destination including the closing `)`
+fn parse_destination(s: &str) -> Option<(String, usize)> {
+    let trimmed = s.trim_start();
+    let (bracketed, mut chars) = match trimmed.strip_prefix('<') {
+        Some(inner) => (true, inner.char_indices()),
+        None => (false, trimmed.char_indices()),
+    };
+    let start = s.len() - chars.as_str().len();
+    let mut url = String::new();
+    let end = loop {
+        match chars.next()? {
+            (_, '\\') => match chars.clone().next() {
+                Some((_, e)) if e.is_ascii_punctuation() => {
+                    url.push(e);
+                    chars.next();
+                }
+                _ => url.push('\\'),
+            },
+            (i, '>') if bracketed => break start + i + 1,
+            (_, '<' | '\n') if bracketed => return None,
+            (i, ')') if !bracketed => break start + i,
+            (i, c) if !bracketed && c.is_whitespace() => break start + i,
+            (_, c) => url.push(c),
+        }
+    };
+    let rest = s[end..].trim_start();
+    rest.starts_with(')')
+        .then(|| (url, s.len() - rest.len() + 1))
+}
+
+fn longest_run(s: &str, c: char) -> usize {
+    s.split(|x| x != c).map(str::len).max().unwrap_or(0)
+}
+
+struct MarkdownParser<'a> {
+    src: &'a str,
+    pos: usize,
+    out: MarkedText,
+    // where the open bold and italic runs start in the output, and the delimiter which opened them
+    bold: Option<(Position, &'static str)>,
+    italic: Option<(Position, &'static str)>,
+    // where each open link starts in the output, where its `]` and `)` are in the source and its
+    // URL
+    links: Vec<(usize, usize, usize, String)>,
+}
+
+impl<'a> MarkdownParser<'a> {
+    fn new(src: &'a str) -> Self {
+        MarkdownParser {
+            src,
+            pos: 0,
+            out: MarkedText::default(),
+            bold: None,
+            italic: None,
+            links: Vec::new(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn parse(mut self) -> (String, Vec<Mark<'static>>) {
+        while let Some(c) = self.rest().chars().next() {
+            if let Some((start, close, end, url)) = self.links.last() {
+                if *close == self.pos {
+                    let (start, end, url) = (*start, *end, url.clone());
+                    self.links.pop();
+                    self.out.mark(LINK, url, start);
+                    self.pos = end;
+                    continue;
+                }
+            }
+            match c {
+                '\\' => {
+                    let escaped = self.rest()[1..].chars().next();
+                    match escaped {
+                        Some(e) if e.is_ascii_punctuation() => {
+                            self.out.push(e);
+                            self.pos += 1 + e.len_utf8();
+                        }
+                        _ => {
+                            self.out.push('\\');
+                            self.pos += 1;
+                        }
+                    }
+                }
+                '`' => self.code_span(),
+                '*' | '_' => self.emphasis(c),
+                '[' => {
+                    if let Some((close, end, url)) = self.link_target() {
+                        self.links.push((self.out.len, close, end, url));
+                    } else {
+                        self.out.push('[');
+                    }
+                    self.pos += 1;
+                }
+                c => {
+                    self.out.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+        // delimiters which were never closed are just text
+        let mut unclosed = self.bold.into_iter().chain(self.italic).collect::<Vec<_>>();
+        unclosed.sort_by_key(|(position, _)| std::cmp::Reverse(position.byte));
+        for (position, delim) in unclosed {
+            self.out.insert(position, delim);
+        }
+        self.out.finish()
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            byte: self.out.text.len(),
+            index: self.out.len,
+        }
+    }
+
+    fn code_span(&mut self) {
+        let fence_len = self.rest().len() - self.rest().trim_start_matches('`').len();
+        let after = self.pos + fence_len;
+        let mut search = after;
+        while let Some(i) = self.src[search..].find('`') {
+            let start = search + i;
+            let len = self.src[start..].len() - self.src[start..].trim_start_matches('`').len();
+            if len == fence_len {
+                let mut code = &self.src[after..start];
+                if code.len() > 1
+                    && code.starts_with(' ')
+                    && code.ends_with(' ')
+                    && !code.trim().is_empty()
+                {
+                    code = &code[1..code.len() - 1];
+                }
+                let begin = self.out.len;
+                self.out.push_str(code);
+                self.out.mark(CODE, true, begin);
+                self.pos = start + len;
+                return;
+            }
+            search = start + len;
+        }
+        // no closing fence, the backticks are just text
+        self.out.push_str(&self.src[self.pos..after]);
+        self.pos = after;
+    }
+
+    fn emphasis(&mut self, c: char) {
+        let (single, double) = if c == '*' { ("*", "**") } else { ("_", "__") };
+        let delim = if self.rest().starts_with(double) {
+            double
+        } else {
+            single
+        };
+        let prev = self.src[..self.pos].chars().next_back();
+        let next = self.rest()[delim.len()..].chars().next();
+        let is_space = |c: Option<char>| c.map(char::is_whitespace).unwrap_or(true);
+        // underscores inside words are not emphasis
+        let intraword = c == '_'
+            && prev.map(char::is_alphanumeric).unwrap_or(false)
+            && next.map(char::is_alphanumeric).unwrap_or(false);
+        let here = self.position();
+        let (state, name) = if delim == double {
+            (&mut self.bold, BOLD)
+        } else {
+            (&mut self.italic, ITALIC)
+        };
+        match *state {
+            Some((start, open)) if open == delim && !is_space(prev) && !intraword => {
+                *state = None;
+                self.out.mark(name, true, start.index);
+            }
+            None if !is_space(next) && !intraword => {
+                *state = Some((here, delim));
+            }
+            _ => self.out.push_str(delim),
+        }
+        self.pos += delim.len();
+    }
+
+    /// If the `[` at the current position starts an inline link, the positions of its `]` and the
+    /// end of the link, and the URL
+    fn link_target(&self) -> Option<(usize, usize, String)> {
+        let mut depth = 0;
+        let mut chars = self.rest().char_indices();
+        let close = loop {
+            match chars.next()? {
+                (_, '\\') => {
+                    chars.next();
+                }
+                (_, '[') => depth += 1,
+                (i, ']') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break self.pos + i;
+                    }
+                }
+                _ => {}
+            }
+        };
+        let target = self.src[close + 1..].strip_prefix('(')?;
+        let (url, len) = parse_destination(target)?;
+        if is_safe_url(&url) {
+            Some((close, close + 2 + len, url))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(marks: &[Mark<'_>]) -> Vec<(String, usize, usize)> {
+        marks
+            .iter()
+            .map(|m| (m.name().to_string(), m.start, m.end))
+            .collect()
+    }
+
+    #[test]
+    fn parse_inline_formatting() {
+        let (text, marks) = parse_markdown("a **bold** and _it_ `co*de` [link](http://x.y)");
+        assert_eq!(text, "a bold and it co*de link");
+        assert_eq!(
+            ranges(&marks),
+            vec![
+                ("bold".to_string(), 2, 6),
+                ("italic".to_string(), 11, 13),
+                ("code".to_string(), 14, 19),
+                ("link".to_string(), 20, 24),
+            ]
+        );
+        assert_eq!(marks[3].value(), &"http://x.y".into());
+    }
+
+    #[test]
+    fn unclosed_and_escaped_delimiters_are_text() {
+        let (text, marks) = parse_markdown(r"snake_case 2 * 3 \*not\* [x] `tick");
+        assert_eq!(text, "snake_case 2 * 3 *not* [x] `tick");
+        assert!(marks.is_empty());
+    }
+
+    #[test]
+    fn unsafe_links_are_text() {
+        let (text, marks) = parse_markdown("[x](javascript:alert(1))");
+        assert_eq!(text, "[x](javascript:alert(1))");
+        assert!(marks.is_empty());
+    }
+
+    #[test]
+    fn writes_safe_markdown() {
+        use crate::{marks::ExpandMark, transaction::Transactable, AutoCommit, ObjType, ROOT};
+
+        let mut doc = AutoCommit::new();
+        let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+        doc.splice_text(&text, 0, 0, "bad <b>good</b> odd").unwrap();
+        for (start, end, url) in [
+            (0, 3, "javascript:alert(1)"),
+            (4, 15, "https://x.y/"),
+            (16, 19, "/a (b)<c>\\d"),
+        ] {
+            let link = Mark::new(LINK.to_string(), url, start, end);
+            doc.mark(&text, link, ExpandMark::None).unwrap();
+        }
+        let markdown = to_markdown(&doc, &text).unwrap();
+        assert_eq!(
+            markdown,
+            "bad [\\<b\\>good\\</b\\>](https://x.y/) [odd](</a (b)\\<c\\>\\\\d>)"
+        );
+
+        let (parsed, marks) = parse_markdown(&markdown);
+        assert_eq!(parsed, "bad <b>good</b> odd");
+        assert_eq!(
+            ranges(&marks),
+            vec![("link".to_string(), 4, 15), ("link".to_string(), 16, 19)]
+        );
+        assert_eq!(marks[1].value(), &"/a (b)<c>\\d".into());
+    }
+}
@@ -8,12 +8,16 @@ use std::{
 use sha2::{Digest, Sha256};
 
 use super::{change::Unverified, parse, Change, Compressed, Document, MAGIC_BYTES};
-use crate::{columnar::encoding::leb128::ulebsize, ChangeHash};
+use crate::{columnar::encoding::leb128::ulebsize, marks::MarkPolicies, refs::Refs, ChangeHash};
 
 pub(crate) enum Chunk<'a> {
     Document(Document<'a>),
     Change(Change<'a, Unverified>),
     CompressedChange(Change<'static, Unverified>, Compressed<'a>),
+    /// The refs of a document, along with whether the checksum in the header was valid
+    Refs(Refs, bool),
+    /// The mark policies of a document, along with whether the checksum in the header was valid
+    MarkPolicies(MarkPolicies, bool),
 }
 
 pub(crate) mod error {
@@ -34,6 +38,8 @@ pub(crate) mod error {
         Document(#[from] document::ParseError),
         #[error("unable to decompresse compressed chunk")]
         Deflate,
+        #[error("bad refs or mark policies chunk: {0}")]
+        Refs(#[from] parse::InvalidUtf8),
     }
 
     #[derive(thiserror::Error, Debug)]
@@ -95,6 +101,20 @@ impl<'a> Chunk<'a> {
                     Compressed::new(header.checksum, Cow::Borrowed(chunk_input.bytes())),
                 )
             }
+            ChunkType::Refs => {
+                let (remaining, refs) = Refs::parse(chunk_input)?;
+                if !remaining.is_empty() {
+                    return Err(parse::ParseError::Error(error::Chunk::LeftoverData));
+                }
+                Chunk::Refs(refs, header.checksum_valid())
+            }
+            ChunkType::MarkPolicies => {
+                let (remaining, policies) = MarkPolicies::parse(chunk_input)?;
+                if !remaining.is_empty() {
+                    return Err(parse::ParseError::Error(error::Chunk::LeftoverData));
+                }
+                Chunk::MarkPolicies(policies, header.checksum_valid())
+            }
         };
         Ok((remaining, chunk))
     }
@@ -106,6 +126,9 @@ impl<'a> Chunk<'a> {
             Self::CompressedChange(change, compressed) => {
                 compressed.checksum() == change.checksum() && change.checksum_valid()
             }
+            Self::Refs(_, checksum_valid) | Self::MarkPolicies(_, checksum_valid) => {
+                *checksum_valid
+            }
         }
     }
 }
@@ -115,6 +138,8 @@ pub(crate) enum ChunkType {
     Document,
     Change,
     Compressed,
+    Refs,
+    MarkPolicies,
 }
 
 impl TryFrom<u8> for ChunkType {
@@ -125,6 +150,8 @@ impl TryFrom<u8> for ChunkType {
             0 => Ok(Self::Document),
             1 => Ok(Self::Change),
             2 => Ok(Self::Compressed),
+            3 => Ok(Self::Refs),
+            4 => Ok(Self::MarkPolicies),
             other => Err(other),
         }
     }
@@ -136,6 +163,8 @@ impl From<ChunkType> for u8 {
             ChunkType::Document => 0,
             ChunkType::Change => 1,
             ChunkType::Compressed => 2,
+            ChunkType::Refs => 3,
+            ChunkType::MarkPolicies => 4,
         }
     }
 }
@@ -2,6 +2,8 @@ use tracing::instrument;
 
 use crate::{
     change::Change,
+    marks::MarkPolicies,
+    refs::Refs,
     storage::{self, parse},
 };
 
@@ -51,10 +53,20 @@ pub(crate) enum LoadedChanges<'a> {
 /// chunks are valid. This function returns a `LoadedChanges` which you can examine to determine if
 /// this is the case.
 #[instrument(skip(data))]
-pub(crate) fn load_changes<'a>(mut data: parse::Input<'a>) -> LoadedChanges<'a> {
+pub(crate) fn load_changes<'a>(data: parse::Input<'a>) -> LoadedChanges<'a> {
+    load_changes_and_metadata(data, &mut Refs::default(), &mut MarkPolicies::default())
+}
+
+/// Like [`load_changes()`] but also merge any refs chunks into `refs` and any mark policies
+/// chunks into `policies`
+pub(crate) fn load_changes_and_metadata<'a>(
+    mut data: parse::Input<'a>,
+    refs: &mut Refs,
+    policies: &mut MarkPolicies,
+) -> LoadedChanges<'a> {
     let mut changes = Vec::new();
     while !data.is_empty() {
-        let remaining = match load_next_change(data, &mut changes) {
+        let remaining = match load_next_change(data, &mut changes, refs, policies) {
             Ok(d) => d,
             Err(e) => {
                 return LoadedChanges::Partial {
@@ -72,6 +84,8 @@ pub(crate) fn load_changes<'a>(mut data: parse::Input<'a>) -> LoadedChanges<'a>
 fn load_next_change<'a>(
     data: parse::Input<'a>,
     changes: &mut Vec<Change>,
+    refs: &mut Refs,
+    policies: &mut MarkPolicies,
 ) -> Result<parse::Input<'a>, Error> {
     let (remaining, chunk) = storage::Chunk::parse(data).map_err(|e| Error::Parse(Box::new(e)))?;
     if !chunk.checksum_valid() {
@@ -105,6 +119,14 @@ fn load_next_change<'a>(
                     .map_err(|e| Error::InvalidChangeColumns(Box::new(e)))?;
             changes.push(change);
         }
+        storage::Chunk::Refs(chunk_refs, _) => {
+            tracing::trace!("loading refs chunk");
+            refs.merge(&chunk_refs);
+        }
+        storage::Chunk::MarkPolicies(chunk_policies, _) => {
+            tracing::trace!("loading mark policies chunk");
+            policies.merge(&chunk_policies);
+        }
     };
     Ok(remaining)
 }
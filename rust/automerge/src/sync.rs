@@ -69,23 +69,35 @@
 
 use itertools::Itertools;
 use serde::ser::SerializeMap;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use crate::{
+    marks::MarkPolicies,
     patches::{PatchLog, TextRepresentation},
-    storage::{parse, ReadChangeOpError},
-    Automerge, AutomergeError, Change, ChangeHash, ReadDoc,
+    refs::Refs,
+    storage::{load, parse, ReadChangeOpError},
+    Automerge, AutomergeError, Change, ChangeHash,
 };
 
 mod bloom;
+mod change_store;
+#[cfg(feature = "async")]
+mod driver;
 mod message_builder;
+mod policy;
+mod relay;
 mod state;
+use change_store::ChangeStore;
 use message_builder::MessageBuilder;
 
 #[cfg(test)]
 mod v1_compat_test;
 
-pub use bloom::{BloomFilter, DecodeError as DecodeBloomError};
+pub use bloom::{BloomFilter, BloomParams, DecodeError as DecodeBloomError};
+#[cfg(feature = "async")]
+pub use driver::{DriverError, RunError, SharedDoc, SyncDriver, DEFAULT_MAX_FRAME_LEN};
+pub use policy::{ChangeInfo, ChangeOpInfo, RefInfo, RejectReason, RejectedChange, SyncPolicy};
+pub use relay::Relay;
 pub use state::DecodeError as DecodeStateError;
 pub use state::{Have, State};
 
@@ -131,6 +143,28 @@ pub trait SyncDoc {
         message: Message,
         patch_log: &mut PatchLog,
     ) -> Result<(), AutomergeError>;
+
+    /// Like [`Self::generate_sync_message()`] but hide any changes which `policy` withholds from
+    /// the remote peer
+    ///
+    /// See [`SyncPolicy::allow_outgoing()`]
+    fn generate_sync_message_with_policy(
+        &self,
+        sync_state: &mut State,
+        policy: &dyn SyncPolicy,
+    ) -> Option<Message>;
+
+    /// Like [`Self::receive_sync_message()`] but only apply changes which `policy` accepts
+    ///
+    /// Returns the changes which were rejected. These are also recorded in `sync_state` and
+    /// reported to the remote peer in the next message generated from `sync_state`. See
+    /// [`SyncPolicy::check_incoming()`]
+    fn receive_sync_message_with_policy(
+        &mut self,
+        sync_state: &mut State,
+        message: Message,
+        policy: &dyn SyncPolicy,
+    ) -> Result<Vec<RejectedChange>, AutomergeError>;
 }
 
 const MESSAGE_TYPE_SYNC: u8 = 0x42; // first byte of a sync message, for identification
@@ -165,126 +199,7 @@ impl MessageVersion {
 
 impl SyncDoc for Automerge {
     fn generate_sync_message(&self, sync_state: &mut State) -> Option<Message> {
-        let our_heads = self.get_heads();
-
-        let our_need = self.get_missing_deps(sync_state.their_heads.as_ref().unwrap_or(&vec![]));
-
-        let their_heads_set = if let Some(ref heads) = sync_state.their_heads {
-            heads.iter().collect::<HashSet<_>>()
-        } else {
-            HashSet::new()
-        };
-        let our_have = if our_need.iter().all(|hash| their_heads_set.contains(hash)) {
-            vec![self.make_bloom_filter(sync_state.shared_heads.clone())]
-        } else {
-            Vec::new()
-        };
-
-        if let Some(ref their_have) = sync_state.their_have {
-            if let Some(first_have) = their_have.first().as_ref() {
-                if !first_have
-                    .last_sync
-                    .iter()
-                    .all(|hash| self.get_change_by_hash(hash).is_some())
-                {
-                    let reset_msg = Message {
-                        heads: our_heads,
-                        need: Vec::new(),
-                        have: vec![Have::default()],
-                        changes: ChunkList::empty(),
-                        supported_capabilities: Some(vec![
-                            Capability::MessageV1,
-                            Capability::MessageV2,
-                        ]),
-                        version: MessageVersion::V1,
-                    };
-                    return Some(reset_msg);
-                }
-            }
-        }
-
-        let (message_builder, sent_hashes) = if let (Some(their_have), Some(their_need)) = (
-            sync_state.their_have.as_ref(),
-            sync_state.their_need.as_ref(),
-        ) {
-            let send_doc = sync_state
-                .their_heads
-                .as_ref()
-                .map(|h| h.is_empty())
-                .unwrap_or(false)
-                && !sync_state.have_responded
-                && sync_state.supports_v2_messages();
-
-            if send_doc {
-                let hashes = self
-                    .get_changes(&[])
-                    .iter()
-                    .map(|c| c.hash())
-                    .collect::<Vec<_>>();
-                (MessageBuilder::new_v2(self.save()), hashes)
-            } else {
-                let all_changes = self
-                    .get_changes_to_send(their_have, their_need)
-                    .expect("Should have only used hashes that are in the document");
-                // deduplicate the changes to send with those we have already sent and clone it now
-                let changes = all_changes
-                    .into_iter()
-                    .filter(|change| !sync_state.sent_hashes.contains(&change.hash()));
-                let hashes = changes.clone().map(|c| c.hash()).collect::<Vec<_>>();
-                if sync_state.supports_v2_messages() {
-                    let encoded = changes
-                        .into_iter()
-                        .flat_map(|c| c.raw_bytes().to_vec())
-                        .collect::<Vec<_>>();
-                    (MessageBuilder::new_v2(encoded), hashes)
-                } else {
-                    (MessageBuilder::new_v1(changes), hashes)
-                }
-            }
-        } else if sync_state.supports_v2_messages() {
-            (MessageBuilder::new_v2(Vec::new()), Vec::new())
-        } else {
-            (MessageBuilder::new_v1(std::iter::empty()), Vec::new())
-        };
-
-        let heads_unchanged = sync_state.last_sent_heads == our_heads;
-
-        let heads_equal = if let Some(their_heads) = sync_state.their_heads.as_ref() {
-            their_heads == &our_heads
-        } else {
-            false
-        };
-
-        if heads_unchanged && sync_state.have_responded {
-            if heads_equal && !message_builder.has_changes_to_send() {
-                return None;
-            }
-            if sync_state.in_flight {
-                return None;
-            }
-        }
-
-        // Only send the supported capabilities in the first message, the other end will store them
-        // in it's sync state and use them for subsequent messages
-        let supported_capabilities = if sync_state.have_responded {
-            None
-        } else {
-            Some(vec![Capability::MessageV1, Capability::MessageV2])
-        };
-
-        sync_state.have_responded = true;
-        sync_state.last_sent_heads = our_heads.clone();
-        sync_state.sent_hashes.extend(sent_hashes);
-
-        let sync_message = message_builder
-            .heads(our_heads)
-            .have(our_have)
-            .need(our_need)
-            .supported_capabilities(supported_capabilities)
-            .build();
-
-        sync_state.in_flight = true;
-        Some(sync_message)
+        self.generate_sync_message_inner(sync_state, None)
     }
 
     fn receive_sync_message(
@@ -293,7 +208,8 @@ impl SyncDoc for Automerge {
         message: Message,
     ) -> Result<(), AutomergeError> {
         let mut patch_log = PatchLog::inactive(TextRepresentation::default());
-        self.receive_sync_message_inner(sync_state, message, &mut patch_log)
+        self.receive_sync_message_inner(sync_state, message, &mut patch_log, None)
+            .map(|_| ())
     }
 
     fn receive_sync_message_log_patches(
@@ -302,160 +218,607 @@ impl SyncDoc for Automerge {
         message: Message,
         patch_log: &mut PatchLog,
     ) -> Result<(), AutomergeError> {
-        self.receive_sync_message_inner(sync_state, message, patch_log)
+        self.receive_sync_message_inner(sync_state, message, patch_log, None)
+            .map(|_| ())
+    }
+
+    fn generate_sync_message_with_policy(
+        &self,
+        sync_state: &mut State,
+        policy: &dyn SyncPolicy,
+    ) -> Option<Message> {
+        self.generate_sync_message_inner(sync_state, Some(policy))
+    }
+
+    fn receive_sync_message_with_policy(
+        &mut self,
+        sync_state: &mut State,
+        message: Message,
+        policy: &dyn SyncPolicy,
+    ) -> Result<Vec<RejectedChange>, AutomergeError> {
+        let mut patch_log = PatchLog::inactive(TextRepresentation::default());
+        self.receive_sync_message_inner(sync_state, message, &mut patch_log, Some(policy))
     }
 }
 
 impl Automerge {
-    fn make_bloom_filter(&self, last_sync: Vec<ChangeHash>) -> Have {
-        let new_changes = self.get_changes(&last_sync);
-        let hashes = new_changes.iter().map(|change| change.hash());
-        Have {
-            last_sync,
-            bloom: BloomFilter::from_hashes(hashes),
+    pub(crate) fn generate_sync_message_inner(
+        &self,
+        sync_state: &mut State,
+        policy: Option<&dyn SyncPolicy>,
+    ) -> Option<Message> {
+        match policy {
+            Some(policy) => generate_sync_message(
+                self,
+                sync_state,
+                Some(&|change: &Change| policy.allow_outgoing(&ChangeInfo::new(self, change))),
+                Some(&|r: &RefInfo<'_>| policy.allow_outgoing_ref(r)),
+            ),
+            None => generate_sync_message(self, sync_state, None, None),
         }
     }
 
-    fn get_changes_to_send(
-        &self,
-        have: &[Have],
-        need: &[ChangeHash],
-    ) -> Result<Vec<&Change>, AutomergeError> {
-        if have.is_empty() {
-            Ok(need
+    pub(crate) fn receive_sync_message_inner(
+        &mut self,
+        sync_state: &mut State,
+        message: Message,
+        patch_log: &mut PatchLog,
+        policy: Option<&dyn SyncPolicy>,
+    ) -> Result<Vec<RejectedChange>, AutomergeError> {
+        let check_ref = |r: &RefInfo<'_>| policy.map_or(true, |policy| policy.check_ref(r));
+        receive_sync_message(
+            self,
+            sync_state,
+            message,
+            Some(&check_ref),
+            |doc, sync_state, changes| {
+                if let Some(policy) = policy {
+                    doc.apply_changes_with_policy(sync_state, changes, policy, patch_log)
+                } else {
+                    for change in changes.iter() {
+                        doc.load_incremental_log_patches(change, patch_log)?;
+                    }
+                    Ok(Vec::new())
+                }
+            },
+        )
+    }
+
+    fn apply_changes_with_policy(
+        &mut self,
+        sync_state: &mut State,
+        chunks: &ChunkList,
+        policy: &dyn SyncPolicy,
+        patch_log: &mut PatchLog,
+    ) -> Result<Vec<RejectedChange>, AutomergeError> {
+        apply_changes_with_policy(
+            self,
+            sync_state,
+            chunks,
+            |doc, change| policy.check_incoming(&ChangeInfo::new(doc, change)),
+            |doc, change| doc.apply_changes_log_patches([change], patch_log),
+        )
+    }
+}
+
+/// Load the changes in `chunks` and `apply` those which `check` accepts to any [`ChangeStore`]
+///
+/// Changes are checked and applied one at a time so that the check sees the effects of earlier
+/// changes when it examines later ones.
+pub(crate) fn apply_changes_with_policy<S, C, A>(
+    store: &mut S,
+    sync_state: &mut State,
+    chunks: &ChunkList,
+    check: C,
+    mut apply: A,
+) -> Result<Vec<RejectedChange>, AutomergeError>
+where
+    S: ChangeStore,
+    C: Fn(&S, &Change) -> Result<(), String>,
+    A: FnMut(&mut S, Change) -> Result<(), AutomergeError>,
+{
+    let mut rejected = Vec::new();
+    for chunk in chunks.iter() {
+        let changes = match load::load_changes(parse::Input::new(chunk)) {
+            load::LoadedChanges::Complete(c) => c,
+            load::LoadedChanges::Partial { error, loaded, .. } => {
+                tracing::warn!(successful_chunks=loaded.len(), err=?error, "partial load");
+                loaded
+            }
+        };
+        for change in changes {
+            if store.change_by_hash(&change.hash()).is_some() {
+                continue;
+            }
+            let rejected_dep = change
+                .deps()
+                .iter()
+                .find(|dep| sync_state.rejected_hashes.contains(dep));
+            let reason = match rejected_dep {
+                Some(dep) => Some(RejectReason::RejectedDependency(*dep)),
+                None => check(store, &change).err().map(RejectReason::Policy),
+            };
+            if let Some(reason) = reason {
+                tracing::debug!(hash=?change.hash(), ?reason, "rejecting change");
+                sync_state.rejected_hashes.insert(change.hash());
+                sync_state.pending_rejections.push(change.hash());
+                rejected.push(RejectedChange {
+                    hash: change.hash(),
+                    actor: change.actor_id().clone(),
+                    reason,
+                });
+            } else {
+                apply(store, change)?;
+            }
+        }
+    }
+    Ok(rejected)
+}
+
+/// Generate a sync message for any [`ChangeStore`]
+///
+/// `allow_outgoing` decides whether a change may be sent to the other end, see
+/// [`SyncPolicy::allow_outgoing()`], and `allow_outgoing_ref` whether a ref may, see
+/// [`SyncPolicy::allow_outgoing_ref()`]
+pub(crate) fn generate_sync_message<S: ChangeStore>(
+    store: &S,
+    sync_state: &mut State,
+    allow_outgoing: Option<&dyn Fn(&Change) -> bool>,
+    allow_outgoing_ref: Option<&dyn Fn(&RefInfo<'_>) -> bool>,
+) -> Option<Message> {
+    let withheld = withheld_changes(store, sync_state, allow_outgoing);
+    let our_heads = visible_heads(store, &withheld);
+    let resuming = try_resume(store, sync_state);
+
+    let our_need = store
+        .missing_deps(sync_state.their_heads.as_ref().unwrap_or(&vec![]))
+        .into_iter()
+        .filter(|hash| !sync_state.rejected_hashes.contains(hash))
+        .collect::<Vec<_>>();
+
+    let their_heads_set = if let Some(ref heads) = sync_state.their_heads {
+        heads.iter().collect::<HashSet<_>>()
+    } else {
+        HashSet::new()
+    };
+    let our_have = if resuming {
+        // Everything they are missing goes in this message, so once they have applied it they
+        // will know our heads and can work out exactly what we are missing without a filter
+        vec![Have {
+            last_sync: our_heads.clone(),
+            bloom: BloomFilter::default(),
+        }]
+    } else if our_need.iter().all(|hash| their_heads_set.contains(hash)) {
+        vec![make_bloom_filter(
+            store,
+            sync_state.shared_heads.clone(),
+            sync_state.effective_bloom_params(),
+        )]
+    } else {
+        Vec::new()
+    };
+
+    if let Some(ref their_have) = sync_state.their_have {
+        if let Some(first_have) = their_have.first().as_ref() {
+            if !first_have
+                .last_sync
+                .iter()
+                .all(|hash| store.change_by_hash(hash).is_some())
+            {
+                let reset_msg = Message {
+                    heads: our_heads,
+                    need: Vec::new(),
+                    have: vec![Have::default()],
+                    changes: ChunkList::empty(),
+                    supported_capabilities: Some(sync_state.our_capabilities()),
+                    version: MessageVersion::V1,
+                    rejected: Vec::new(),
+                    refs: None,
+                    mark_policies: None,
+                };
+                return Some(reset_msg);
+            }
+        }
+    }
+
+    let (message_builder, sent_hashes) = if let (Some(their_have), Some(their_need)) = (
+        sync_state.their_have.as_ref(),
+        sync_state.their_need.as_ref(),
+    ) {
+        let send_doc = sync_state
+            .their_heads
+            .as_ref()
+            .map(|h| h.is_empty())
+            .unwrap_or(false)
+            && !sync_state.have_responded
+            && sync_state.supports_v2_messages()
+            && withheld.is_empty();
+
+        let document = if send_doc {
+            store.encode_document()
+        } else {
+            None
+        };
+
+        if let Some(document) = document {
+            let hashes = store
+                .changes_since(&[])
                 .iter()
-                .filter_map(|hash| self.get_change_by_hash(hash))
-                .collect())
+                .map(|c| c.hash())
+                .collect::<Vec<_>>();
+            (MessageBuilder::new_v2(document), hashes)
         } else {
-            let mut last_sync_hashes = HashSet::new();
-            let mut bloom_filters = Vec::with_capacity(have.len());
+            let all_changes = get_changes_to_send(store, their_have, their_need)
+                .expect("Should have only used hashes that are in the document");
+            // deduplicate the changes to send with those we have already sent and clone it now
+            let changes = all_changes.into_iter().filter(|change| {
+                !sync_state.sent_hashes.contains(&change.hash())
+                    && !withheld.contains(&change.hash())
+            });
+            let hashes = changes.clone().map(|c| c.hash()).collect::<Vec<_>>();
+            if sync_state.supports_v2_messages() {
+                let encoded = changes
+                    .into_iter()
+                    .flat_map(|c| c.raw_bytes().to_vec())
+                    .collect::<Vec<_>>();
+                (MessageBuilder::new_v2(encoded), hashes)
+            } else {
+                (MessageBuilder::new_v1(changes), hashes)
+            }
+        }
+    } else if sync_state.supports_v2_messages() {
+        (MessageBuilder::new_v2(Vec::new()), Vec::new())
+    } else {
+        (MessageBuilder::new_v1(std::iter::empty()), Vec::new())
+    };
+
+    let heads_unchanged = sync_state.last_sent_heads == our_heads;
+
+    let heads_equal = if let Some(their_heads) = sync_state.their_heads.as_ref() {
+        their_heads == &our_heads
+    } else {
+        false
+    };
+
+    // We always have to tell the other end about changes we rejected, otherwise they will
+    // keep advertising heads we are never going to have
+    let has_rejections = !sync_state.pending_rejections.is_empty();
+
+    // Send our refs and mark policies whenever they contain something the other end doesn't
+    // have. Refs pointing at changes we are withholding are left out.
+    let outgoing_refs = store.refs().filter(&|r: &RefInfo<'_>| {
+        !r.heads
+            .map_or(false, |heads| heads.iter().any(|h| withheld.contains(h)))
+            && allow_outgoing_ref.map_or(true, |allow| allow(r))
+    });
+    let mut their_refs = sync_state.their_refs.clone();
+    let refs = if their_refs.merge(&outgoing_refs) {
+        Some(outgoing_refs)
+    } else {
+        None
+    };
+    let mut their_mark_policies = sync_state.their_mark_policies.clone();
+    let mark_policies = if their_mark_policies.merge(store.mark_policies()) {
+        Some(store.mark_policies().clone())
+    } else {
+        None
+    };
+    let has_metadata = refs.is_some() || mark_policies.is_some();
+
+    if heads_unchanged && sync_state.have_responded && !has_rejections && !has_metadata {
+        if heads_equal && !message_builder.has_changes_to_send() {
+            return None;
+        }
+        if sync_state.in_flight {
+            return None;
+        }
+    }
+
+    // Only send the supported capabilities in the first message, the other end will store them
+    // in it's sync state and use them for subsequent messages. The rejected hashes, refs and mark
+    // policies are encoded after the capabilities so if we have any we need to send the
+    // capabilities again.
+    let supported_capabilities = if sync_state.have_responded && !has_rejections && !has_metadata {
+        None
+    } else {
+        Some(sync_state.our_capabilities())
+    };
+
+    sync_state.have_responded = true;
+    sync_state.last_sent_heads = our_heads.clone();
+    sync_state.sent_hashes.extend(sent_hashes);
+    sync_state.their_refs = their_refs;
+    sync_state.their_mark_policies = their_mark_policies;
+
+    let mut rejected = std::mem::take(&mut sync_state.pending_rejections);
+    rejected.sort();
+
+    let sync_message = message_builder
+        .heads(our_heads)
+        .have(our_have)
+        .need(our_need)
+        .supported_capabilities(supported_capabilities)
+        .rejected(rejected)
+        .refs(refs)
+        .mark_policies(mark_policies)
+        .build();
+
+    sync_state.in_flight = true;
+    Some(sync_message)
+}
+
+/// The changes which we must hide from the other end, either because `allow_outgoing` withholds
+/// them or because the other end has rejected them, along with everything which depends on them
+fn withheld_changes<S: ChangeStore>(
+    store: &S,
+    sync_state: &State,
+    allow_outgoing: Option<&dyn Fn(&Change) -> bool>,
+) -> HashSet<ChangeHash> {
+    let mut withheld = HashSet::new();
+    if allow_outgoing.is_none() && sync_state.their_rejected.is_empty() {
+        return withheld;
+    }
+    // `changes_since` returns changes in topological order so dependencies are always visited
+    // before their dependents
+    for change in store.changes_since(&sync_state.shared_heads) {
+        let hash = change.hash();
+        let hidden = sync_state.their_rejected.contains(&hash)
+            || change.deps().iter().any(|dep| withheld.contains(dep))
+            || allow_outgoing.map(|allow| !allow(change)).unwrap_or(false);
+        if hidden {
+            withheld.insert(hash);
+        }
+    }
+    withheld
+}
 
-            for h in have {
-                let Have { last_sync, bloom } = h;
-                last_sync_hashes.extend(last_sync);
-                bloom_filters.push(bloom);
+/// The heads of the document once the `withheld` changes are removed
+fn visible_heads<S: ChangeStore>(store: &S, withheld: &HashSet<ChangeHash>) -> Vec<ChangeHash> {
+    let heads = store.heads();
+    if withheld.is_empty() {
+        return heads;
+    }
+    // Every head of the remaining graph is either one of our heads or a dependency of a
+    // withheld change, so collect those and then remove any which are ancestors of the others
+    let mut candidates = BTreeSet::new();
+    let mut seen = HashSet::new();
+    let mut to_visit = heads;
+    while let Some(hash) = to_visit.pop() {
+        if !seen.insert(hash) {
+            continue;
+        }
+        if withheld.contains(&hash) {
+            if let Some(change) = store.change_by_hash(&hash) {
+                to_visit.extend(change.deps());
             }
-            let last_sync_hashes = last_sync_hashes.into_iter().copied().collect::<Vec<_>>();
+        } else {
+            candidates.insert(hash);
+        }
+    }
+    let parents = candidates
+        .iter()
+        .filter_map(|hash| store.change_by_hash(hash))
+        .flat_map(|change| change.deps().iter().copied())
+        .collect::<Vec<_>>();
+    store.remove_ancestors(&parents, &mut candidates);
+    candidates.into_iter().collect()
+}
+/// Prepare to resume a session from a persisted [`State`]
+///
+/// If this is the first message of the session and we have all the heads the other end had
+/// when the state was persisted, then everything they could be missing is a descendant of
+/// those heads. In that case we pretend they sent us those heads with an empty bloom filter,
+/// so that the usual machinery sends every change since then, and return `true`.
+fn try_resume<S: ChangeStore>(store: &S, sync_state: &mut State) -> bool {
+    if !sync_state.resume {
+        return false;
+    }
+    let resume_heads = match sync_state.resume_heads.take() {
+        Some(heads) => heads,
+        None => return false,
+    };
+    if sync_state.have_responded
+        || sync_state.their_heads.is_some()
+        || !resume_heads
+            .iter()
+            .all(|hash| store.change_by_hash(hash).is_some())
+    {
+        return false;
+    }
+    sync_state.their_have = Some(vec![Have {
+        last_sync: resume_heads,
+        bloom: BloomFilter::default(),
+    }]);
+    sync_state.their_need = Some(Vec::new());
+    true
+}
 
-            let changes = self.get_changes(&last_sync_hashes);
+fn make_bloom_filter<S: ChangeStore>(
+    store: &S,
+    last_sync: Vec<ChangeHash>,
+    params: BloomParams,
+) -> Have {
+    let new_changes = store.changes_since(&last_sync);
+    let hashes = new_changes.iter().map(|change| change.hash());
+    Have {
+        last_sync,
+        bloom: BloomFilter::from_hashes_with_params(hashes, params),
+    }
+}
 
-            let mut change_hashes = HashSet::with_capacity(changes.len());
-            let mut dependents: HashMap<ChangeHash, Vec<ChangeHash>> = HashMap::new();
-            let mut hashes_to_send = HashSet::new();
+fn get_changes_to_send<'a, S: ChangeStore>(
+    store: &'a S,
+    have: &[Have],
+    need: &[ChangeHash],
+) -> Result<Vec<&'a Change>, AutomergeError> {
+    if have.is_empty() {
+        Ok(need
+            .iter()
+            .filter_map(|hash| store.change_by_hash(hash))
+            .collect())
+    } else {
+        let mut last_sync_hashes = HashSet::new();
+        let mut bloom_filters = Vec::with_capacity(have.len());
+
+        for h in have {
+            let Have { last_sync, bloom } = h;
+            last_sync_hashes.extend(last_sync);
+            bloom_filters.push(bloom);
+        }
+        let last_sync_hashes = last_sync_hashes.into_iter().copied().collect::<Vec<_>>();
 
-            for change in &changes {
-                change_hashes.insert(change.hash());
+        let changes = store.changes_since(&last_sync_hashes);
 
-                for dep in change.deps() {
-                    dependents.entry(*dep).or_default().push(change.hash());
-                }
+        let mut change_hashes = HashSet::with_capacity(changes.len());
+        let mut dependents: HashMap<ChangeHash, Vec<ChangeHash>> = HashMap::new();
+        let mut hashes_to_send = HashSet::new();
 
-                if bloom_filters
-                    .iter()
-                    .all(|bloom| !bloom.contains_hash(&change.hash()))
-                {
-                    hashes_to_send.insert(change.hash());
-                }
+        for change in &changes {
+            change_hashes.insert(change.hash());
+
+            for dep in change.deps() {
+                dependents.entry(*dep).or_default().push(change.hash());
             }
 
-            let mut stack = hashes_to_send.iter().copied().collect::<Vec<_>>();
-            while let Some(hash) = stack.pop() {
-                if let Some(deps) = dependents.get(&hash) {
-                    for dep in deps {
-                        if hashes_to_send.insert(*dep) {
-                            stack.push(*dep);
-                        }
-                    }
-                }
+            if bloom_filters
+                .iter()
+                .all(|bloom| !bloom.contains_hash(&change.hash()))
+            {
+                hashes_to_send.insert(change.hash());
             }
+        }
 
-            let mut changes_to_send = Vec::new();
-            for hash in need {
-                if !hashes_to_send.contains(hash) {
-                    if let Some(change) = self.get_change_by_hash(hash) {
-                        changes_to_send.push(change);
+        let mut stack = hashes_to_send.iter().copied().collect::<Vec<_>>();
+        while let Some(hash) = stack.pop() {
+            if let Some(deps) = dependents.get(&hash) {
+                for dep in deps {
+                    if hashes_to_send.insert(*dep) {
+                        stack.push(*dep);
                     }
                 }
             }
+        }
 
-            for change in changes {
-                if hashes_to_send.contains(&change.hash()) {
+        let mut changes_to_send = Vec::new();
+        for hash in need {
+            if !hashes_to_send.contains(hash) {
+                if let Some(change) = store.change_by_hash(hash) {
                     changes_to_send.push(change);
                 }
             }
-            Ok(changes_to_send)
         }
-    }
 
-    pub(crate) fn receive_sync_message_inner(
-        &mut self,
-        sync_state: &mut State,
-        message: Message,
-        patch_log: &mut PatchLog,
-    ) -> Result<(), AutomergeError> {
-        sync_state.in_flight = false;
-        let before_heads = self.get_heads();
-
-        let Message {
-            heads: message_heads,
-            changes: message_changes,
-            need: message_need,
-            have: message_have,
-            supported_capabilities,
-            ..
-        } = message;
+        for change in changes {
+            if hashes_to_send.contains(&change.hash()) {
+                changes_to_send.push(change);
+            }
+        }
+        Ok(changes_to_send)
+    }
+}
 
-        if let Some(caps) = supported_capabilities {
-            sync_state.their_capabilities = Some(caps);
+/// Receive a sync message into any [`ChangeStore`]
+///
+/// `check_ref` decides whether to accept each ref in the message, see
+/// [`SyncPolicy::check_ref()`]. `apply_changes` is called with the changes in the message, if
+/// there are any, and returns the changes it rejected.
+pub(crate) fn receive_sync_message<S, F>(
+    store: &mut S,
+    sync_state: &mut State,
+    message: Message,
+    check_ref: Option<&dyn Fn(&RefInfo<'_>) -> bool>,
+    apply_changes: F,
+) -> Result<Vec<RejectedChange>, AutomergeError>
+where
+    S: ChangeStore,
+    F: FnOnce(&mut S, &mut State, &ChunkList) -> Result<Vec<RejectedChange>, AutomergeError>,
+{
+    sync_state.in_flight = false;
+    let before_heads = store.heads();
+
+    let Message {
+        heads: message_heads,
+        changes: message_changes,
+        need: message_need,
+        have: message_have,
+        supported_capabilities,
+        rejected: message_rejected,
+        refs: message_refs,
+        mark_policies: message_mark_policies,
+        ..
+    } = message;
+
+    if let Some(caps) = supported_capabilities {
+        sync_state.their_capabilities = Some(caps);
+    }
+    sync_state.their_rejected.extend(message_rejected);
+    if let Some(refs) = message_refs {
+        match check_ref {
+            Some(check) => store.merge_refs(&refs.filter(check)),
+            None => store.merge_refs(&refs),
         }
+        sync_state.their_refs.merge(&refs);
+    }
+    if let Some(policies) = message_mark_policies {
+        store.merge_mark_policies(&policies);
+        sync_state.their_mark_policies.merge(&policies);
+    }
 
-        let changes_is_empty = message_changes.is_empty();
-        if !changes_is_empty {
-            for change in &message_changes.0 {
-                self.load_incremental_log_patches(change, patch_log)?;
-            }
-            sync_state.shared_heads = advance_heads(
-                &before_heads.iter().collect(),
-                &self.get_heads().into_iter().collect(),
-                &sync_state.shared_heads,
-            );
+    let mut rejected = Vec::new();
+    let changes_is_empty = message_changes.is_empty();
+    if !changes_is_empty {
+        rejected = apply_changes(store, sync_state, &message_changes)?;
+        sync_state.shared_heads = advance_heads(
+            &before_heads.iter().collect(),
+            &store.heads().into_iter().collect(),
+            &sync_state.shared_heads,
+        );
+        // The other end sends every change our bloom filter says we don't have along with
+        // everything which depends on those changes. If we are still missing dependencies
+        // (other than their heads, which we may simply not have asked for yet) then our
+        // filter must have contained false positives.
+        let missing = store.missing_deps(&message_heads);
+        if missing.iter().any(|hash| !message_heads.contains(hash)) {
+            sync_state.observed_false_positives();
         }
+    }
 
-        // trim down the sent hashes to those that we know they haven't seen
-        self.filter_changes(&message_heads, &mut sync_state.sent_hashes)?;
+    // trim down the sent hashes to those that we know they haven't seen
+    store.remove_ancestors(&message_heads, &mut sync_state.sent_hashes);
 
-        if changes_is_empty && message_heads == before_heads {
-            sync_state.last_sent_heads = message_heads.clone();
-        }
+    if changes_is_empty && message_heads == before_heads {
+        sync_state.last_sent_heads = message_heads.clone();
+    }
 
-        let known_heads = message_heads
+    let known_heads = message_heads
+        .iter()
+        .filter(|head| store.change_by_hash(head).is_some())
+        .collect::<Vec<_>>();
+    if known_heads.len() == message_heads.len() {
+        sync_state.shared_heads = message_heads.clone();
+        // If the remote peer has lost all its data, reset our state to perform a full resync
+        if message_heads.is_empty() {
+            sync_state.last_sent_heads = Default::default();
+            sync_state.sent_hashes = Default::default();
+            sync_state.their_refs = Default::default();
+            sync_state.their_mark_policies = Default::default();
+        }
+    } else {
+        sync_state.shared_heads = sync_state
+            .shared_heads
             .iter()
-            .filter(|head| self.get_change_by_hash(head).is_some())
+            .chain(known_heads)
+            .copied()
+            .unique()
+            .sorted()
             .collect::<Vec<_>>();
-        if known_heads.len() == message_heads.len() {
-            sync_state.shared_heads = message_heads.clone();
-            // If the remote peer has lost all its data, reset our state to perform a full resync
-            if message_heads.is_empty() {
-                sync_state.last_sent_heads = Default::default();
-                sync_state.sent_hashes = Default::default();
-            }
-        } else {
-            sync_state.shared_heads = sync_state
-                .shared_heads
-                .iter()
-                .chain(known_heads)
-                .copied()
-                .unique()
-                .sorted()
-                .collect::<Vec<_>>();
-        }
+    }
 
-        sync_state.their_have = Some(message_have);
-        sync_state.their_heads = Some(message_heads);
-        sync_state.their_need = Some(message_need);
+    sync_state.their_have = Some(message_have);
+    sync_state.their_heads = Some(message_heads);
+    sync_state.their_need = Some(message_need);
 
-        Ok(())
-    }
+    Ok(rejected)
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -476,6 +839,12 @@ impl From<parse::leb128::Error> for ReadMessageError {
     }
 }
 
+impl From<parse::InvalidUtf8> for ReadMessageError {
+    fn from(e: parse::InvalidUtf8) -> Self {
+        ReadMessageError::Parse(e.to_string())
+    }
+}
+
 impl From<bloom::ParseError> for ReadMessageError {
     fn from(e: bloom::ParseError) -> Self {
         ReadMessageError::Parse(e.to_string())
@@ -519,6 +888,12 @@ impl From<parse::ParseError<ReadMessageError>> for ReadMessageError {
 /// the advertised capabilities on the sync state. This allows new implementations to discover if
 /// the remote peer supports the V2 message format (the `Capability::MessageV2` capability) and if
 /// so send a V2 message.
+///
+/// The `MessageV1` and `MessageV2` capabilities are a single byte, every other capability is
+/// followed by a length prefixed payload so that implementations can skip capabilities they don't
+/// know about. Implementations from before payloads were added read every capability as a single
+/// byte, so capabilities with a payload are always encoded after those without one and the
+/// payloads then look like trailing data.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Message {
     /// The heads of the sender.
@@ -538,6 +913,21 @@ pub struct Message {
     pub supported_capabilities: Option<Vec<Capability>>,
     /// What version to encode this message as
     pub version: MessageVersion,
+    /// The hashes of changes the recipient sent us which we have rejected, see [`SyncPolicy`]
+    ///
+    /// These are appended after the capabilities and so are only encoded if
+    /// `supported_capabilities` is not `None`. Older implementations ignore them.
+    pub rejected: Vec<ChangeHash>,
+    /// The sender's branches and tags, if they have changed since it last sent them
+    ///
+    /// These are appended after the rejected hashes, so are also only encoded if
+    /// `supported_capabilities` is not `None`.
+    pub refs: Option<Refs>,
+    /// The sender's mark policies, if they have changed since it last sent them
+    ///
+    /// These are appended after the refs, which are encoded (empty if need be) whenever there are
+    /// mark policies to send.
+    pub mark_policies: Option<MarkPolicies>,
 }
 
 /// An array of changes, each of which should be passed to [`Automerge::load_incremental()`]
@@ -628,6 +1018,23 @@ impl Message {
         } else {
             (i, None)
         };
+        let (i, rejected) = if !i.is_empty() {
+            parse::length_prefixed(parse::change_hash)(i)?
+        } else {
+            (i, Vec::new())
+        };
+        let (i, refs) = if !i.is_empty() {
+            let (i, refs) = Refs::parse(i)?;
+            (i, Some(refs))
+        } else {
+            (i, None)
+        };
+        let (i, mark_policies) = if !i.is_empty() {
+            let (i, policies) = MarkPolicies::parse(i)?;
+            (i, Some(policies))
+        } else {
+            (i, None)
+        };
         Ok((
             i,
             Message {
@@ -637,6 +1044,9 @@ impl Message {
                 changes,
                 supported_capabilities,
                 version: message_version,
+                rejected,
+                refs,
+                mark_policies,
             },
         ))
     }
@@ -657,10 +1067,20 @@ impl Message {
             buf.extend::<&[u8]>(change.as_ref())
         });
 
-        if let Some(supported_capabilities) = self.supported_capabilities {
+        if let Some(mut supported_capabilities) = self.supported_capabilities {
+            supported_capabilities.sort_by_key(Capability::has_payload);
             encode_many(&mut buf, supported_capabilities.iter(), |buf, cap| {
                 cap.encode(buf);
             });
+            if !self.rejected.is_empty() || self.refs.is_some() || self.mark_policies.is_some() {
+                encode_hashes(&mut buf, &self.rejected);
+            }
+            if let Some(policies) = &self.mark_policies {
+                self.refs.clone().unwrap_or_default().encode(&mut buf);
+                policies.encode(&mut buf);
+            } else if let Some(refs) = &self.refs {
+                refs.encode(&mut buf);
+            }
         }
 
         buf
@@ -672,24 +1092,51 @@ pub enum Capability {
     #[default]
     MessageV1,
     MessageV2,
+    /// The bloom filter parameters the sender would like both ends to use
+    BloomParams(BloomParams),
     Unknown(u8),
 }
 
 impl Capability {
-    fn encode(&self, out: &mut Vec<u8>) {
+    /// Whether the capability is followed by a length prefixed payload, see the notes on
+    /// [`Message`]
+    fn has_payload(&self) -> bool {
+        !matches!(self, Capability::MessageV1 | Capability::MessageV2)
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        let mut payload = Vec::new();
         match self {
             Capability::MessageV1 => out.push(0x01),
             Capability::MessageV2 => out.push(0x02),
+            Capability::BloomParams(params) => {
+                out.push(0x03);
+                params.encode(&mut payload);
+            }
             Capability::Unknown(v) => out.push(*v),
         }
+        if self.has_payload() {
+            leb128::write::unsigned(out, payload.len() as u64).unwrap();
+            out.extend(payload);
+        }
     }
 
-    fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, ReadMessageError> {
+    pub(crate) fn parse<E>(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, E>
+    where
+        E: From<parse::leb128::Error>,
+    {
         let (i, v) = parse::take1(input)?;
         match v {
             0x01 => Ok((i, Self::MessageV1)),
             0x02 => Ok((i, Self::MessageV2)),
-            _ => Ok((i, Self::Unknown(v))),
+            _ => {
+                let (i, payload) = parse::length_prefixed_bytes(i)?;
+                // a payload we can't read is treated like a capability we don't know about
+                match (v, BloomParams::parse::<E>(parse::Input::new(payload))) {
+                    (0x03, Ok((_, params))) => Ok((i, Self::BloomParams(params))),
+                    _ => Ok((i, Self::Unknown(v))),
+                }
+            }
         }
     }
 }
@@ -747,7 +1194,7 @@ mod tests {
     use crate::storage::Chunk;
     use crate::transaction::Transactable;
     use crate::types::gen::gen_hash;
-    use crate::ActorId;
+    use crate::{ActorId, ReadDoc};
     use proptest::prelude::*;
 
     prop_compose! {
@@ -778,11 +1225,17 @@ mod tests {
             need in gen_sorted_hashes(0..10),
             have in proptest::collection::vec(gen_have(), 0..10),
             changes in proptest::collection::vec(gen_change(), 0..10),
+            rejected in gen_sorted_hashes(0..10),
             supported_capabilities in prop_oneof![
                 Just(None),
                 Just(Some(vec![Capability::MessageV1])),
                 Just(Some(vec![Capability::MessageV2])),
                 Just(Some(vec![Capability::MessageV1, Capability::MessageV2])),
+                Just(Some(vec![
+                    Capability::MessageV1,
+                    Capability::MessageV2,
+                    Capability::BloomParams(BloomParams::new(15, 10)),
+                ])),
             ],
         ) -> Message {
             Message {
@@ -790,8 +1243,12 @@ mod tests {
                 need,
                 have,
                 changes: changes.into_iter().map(|c| c.raw_bytes().to_vec()).collect::<Vec<Vec<u8>>>().into(),
+                // rejected hashes are only encoded along with the capabilities
+                rejected: if supported_capabilities.is_some() { rejected } else { Vec::new() },
                 supported_capabilities,
                 version: MessageVersion::V1,
+                refs: None,
+                mark_policies: None,
             }
         }
     }
@@ -816,6 +1273,9 @@ mod tests {
                 changes: ChunkList::from(raw),
                 supported_capabilities,
                 version: MessageVersion::V2,
+                rejected: Vec::new(),
+                refs: None,
+                mark_policies: None,
             }
         }
     }
@@ -833,6 +1293,9 @@ mod tests {
             changes: ChunkList::empty(),
             supported_capabilities: None,
             version: MessageVersion::V2,
+            rejected: Vec::new(),
+            refs: None,
+            mark_policies: None,
         };
         let encoded = msg.encode();
         Message::parse(Input::new(&encoded)).unwrap();
@@ -1261,6 +1724,452 @@ mod tests {
         }
     }
 
+    struct RejectKey(&'static str);
+
+    impl SyncPolicy for RejectKey {
+        fn check_incoming(&self, change: &ChangeInfo<'_>) -> Result<(), String> {
+            if change
+                .ops()
+                .any(|op| op.obj == crate::ROOT && op.key.as_deref() == Some(self.0))
+            {
+                Err(format!("{} is read only", self.0))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    struct Authorized(std::cell::Cell<bool>);
+
+    impl SyncPolicy for Authorized {
+        fn allow_outgoing(&self, _change: &ChangeInfo<'_>) -> bool {
+            self.0.get()
+        }
+    }
+
+    // Sync `a` and `b` where `b` applies `policy`, returning everything `b` rejected
+    fn sync_with_policy(
+        a: &mut crate::AutoCommit,
+        b: &mut crate::AutoCommit,
+        a_sync_state: &mut State,
+        b_sync_state: &mut State,
+        policy: &dyn SyncPolicy,
+    ) -> Vec<RejectedChange> {
+        const MAX_ITER: usize = 10;
+        let mut rejected = Vec::new();
+        let mut iterations = 0;
+
+        loop {
+            let a_to_b = a.sync().generate_sync_message(a_sync_state);
+            let b_to_a = b
+                .sync()
+                .generate_sync_message_with_policy(b_sync_state, policy);
+            if a_to_b.is_none() && b_to_a.is_none() {
+                break;
+            }
+            if iterations > MAX_ITER {
+                panic!("failed to sync in {} iterations", MAX_ITER);
+            }
+            if let Some(msg) = a_to_b {
+                rejected.extend(
+                    b.sync()
+                        .receive_sync_message_with_policy(b_sync_state, msg, policy)
+                        .unwrap(),
+                );
+            }
+            if let Some(msg) = b_to_a {
+                a.sync().receive_sync_message(a_sync_state, msg).unwrap()
+            }
+            iterations += 1;
+        }
+        rejected
+    }
+
+    #[test]
+    fn rejected_changes_are_not_applied_and_are_reported() {
+        let mut client = crate::AutoCommit::new();
+        let mut server = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+
+        server.put(crate::ROOT, "owner", "server").unwrap();
+        sync(&mut client, &mut server, &mut s1, &mut s2);
+
+        client.put(crate::ROOT, "name", "ok").unwrap();
+        client.commit();
+        let allowed = client.get_heads()[0];
+        client.put(crate::ROOT, "owner", "client").unwrap();
+        client.commit();
+        let forbidden = client.get_heads()[0];
+        client.put(crate::ROOT, "name", "still ok").unwrap();
+        client.commit();
+        let dependent = client.get_heads()[0];
+
+        let rejected = sync_with_policy(
+            &mut client,
+            &mut server,
+            &mut s1,
+            &mut s2,
+            &RejectKey("owner"),
+        );
+
+        assert_eq!(
+            rejected,
+            vec![
+                RejectedChange {
+                    hash: forbidden,
+                    actor: client.get_actor().clone(),
+                    reason: RejectReason::Policy("owner is read only".to_string()),
+                },
+                RejectedChange {
+                    hash: dependent,
+                    actor: client.get_actor().clone(),
+                    reason: RejectReason::RejectedDependency(forbidden),
+                },
+            ]
+        );
+        assert_eq!(server.get_heads(), vec![allowed]);
+        assert_eq!(
+            server.get(crate::ROOT, "owner").unwrap().unwrap().0,
+            "server".into()
+        );
+        assert_eq!(
+            s1.their_rejected,
+            [forbidden, dependent].into_iter().collect()
+        );
+        assert_eq!(s1.shared_heads, vec![allowed]);
+    }
+
+    #[test]
+    fn withheld_changes_are_sent_once_allowed() {
+        let mut client = crate::AutoCommit::new();
+        let mut server = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+
+        server.put(crate::ROOT, "key", "value").unwrap();
+        let policy = Authorized(std::cell::Cell::new(false));
+
+        let rejected = sync_with_policy(&mut client, &mut server, &mut s1, &mut s2, &policy);
+        assert!(rejected.is_empty());
+        assert!(client.get_heads().is_empty());
+        assert_eq!(client.get(crate::ROOT, "key").unwrap(), None);
+
+        policy.0.set(true);
+        sync_with_policy(&mut client, &mut server, &mut s1, &mut s2, &policy);
+        assert_eq!(client.get_heads(), server.get_heads());
+    }
+
+    #[test]
+    fn bloom_params_are_advertised_and_adopted() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "x", 1).unwrap();
+        doc2.put(crate::ROOT, "y", 2).unwrap();
+
+        let params = BloomParams::new(20, 14);
+        let mut s1 = State::new().with_bloom_params(params);
+        let mut s2 = State::new();
+
+        let m1 = doc1.sync().generate_sync_message(&mut s1).unwrap();
+        assert_eq!(m1.have[0].bloom.params(), params);
+        assert!(m1
+            .supported_capabilities
+            .as_ref()
+            .unwrap()
+            .contains(&Capability::BloomParams(params)));
+
+        doc2.sync().receive_sync_message(&mut s2, m1).unwrap();
+        let m2 = doc2.sync().generate_sync_message(&mut s2).unwrap();
+        assert_eq!(m2.have[0].bloom.params(), params);
+
+        // an explicitly configured state is not overridden by the other end
+        let mut s3 = State::new().with_bloom_params(BloomParams::new(5, 3));
+        let m1 = doc1
+            .sync()
+            .generate_sync_message(&mut State::new().with_bloom_params(params))
+            .unwrap();
+        doc2.sync().receive_sync_message(&mut s3, m1).unwrap();
+        let m3 = doc2.sync().generate_sync_message(&mut s3).unwrap();
+        assert_eq!(m3.have[0].bloom.params(), BloomParams::new(5, 3));
+    }
+
+    #[test]
+    fn more_precise_bloom_params_grow_until_the_maximum() {
+        let mut params = BloomParams::default();
+        for _ in 0..20 {
+            let next = params.more_precise();
+            assert!(next.bits_per_entry() >= params.bits_per_entry());
+            params = next;
+        }
+        assert_eq!(params, params.more_precise());
+        assert_eq!(params.num_probes(), 31);
+    }
+
+    #[test]
+    fn hostile_bloom_params_are_clamped() {
+        let mut payload = Vec::new();
+        leb128::write::unsigned(&mut payload, u32::MAX as u64).unwrap();
+        leb128::write::unsigned(&mut payload, u32::MAX as u64).unwrap();
+        let mut bytes = vec![0x03, payload.len() as u8];
+        bytes.extend(payload);
+        let (_, cap) =
+            Capability::parse::<parse::leb128::Error>(parse::Input::new(&bytes)).unwrap();
+        let params = match cap {
+            Capability::BloomParams(params) => params,
+            other => panic!("unexpected capability {:?}", other),
+        };
+        assert_eq!(params.bits_per_entry(), 45);
+        assert_eq!(params.num_probes(), 32);
+
+        let mut doc = crate::AutoCommit::new();
+        for i in 0..100 {
+            doc.put(crate::ROOT, "x", i).unwrap();
+            doc.commit();
+        }
+        let mut state = State::new();
+        state.their_capabilities = Some(vec![Capability::MessageV2, cap]);
+        let message = doc.sync().generate_sync_message(&mut state).unwrap();
+        assert_eq!(message.have[0].bloom.params(), params);
+        assert!(message.have[0].bloom.to_bytes().len() < 100 * 45 / 8 + 16);
+    }
+
+    #[test]
+    fn capability_payloads_are_skippable_and_come_last() {
+        // a capability from the future with a payload, followed by ones we know about
+        let mut bytes = vec![3, 0x09, 2, 0xaa, 0xbb, 0x02, 0x03, 2];
+        BloomParams::new(20, 14).encode(&mut bytes);
+        let (rest, caps) = parse::length_prefixed(Capability::parse::<parse::leb128::Error>)(
+            parse::Input::new(&bytes),
+        )
+        .unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            caps,
+            vec![
+                Capability::Unknown(0x09),
+                Capability::MessageV2,
+                Capability::BloomParams(BloomParams::new(20, 14)),
+            ]
+        );
+
+        let message = Message {
+            heads: Vec::new(),
+            need: Vec::new(),
+            have: Vec::new(),
+            changes: ChunkList::empty(),
+            supported_capabilities: Some(vec![
+                Capability::BloomParams(BloomParams::new(20, 14)),
+                Capability::MessageV1,
+                Capability::MessageV2,
+            ]),
+            version: MessageVersion::V1,
+            rejected: Vec::new(),
+            refs: None,
+            mark_policies: None,
+        };
+        let encoded = message.encode();
+        // an implementation which reads every capability as one byte still sees the ones it
+        // knows about, the bloom payload is left over after them
+        let (_, old) = parse::length_prefixed(parse::take1::<parse::leb128::Error>)(
+            parse::Input::new(&encoded[5..]),
+        )
+        .unwrap();
+        assert_eq!(old, vec![0x01, 0x02, 0x03]);
+        let decoded = Message::decode(&encoded).unwrap();
+        assert_eq!(
+            decoded.supported_capabilities,
+            Some(vec![
+                Capability::MessageV1,
+                Capability::MessageV2,
+                Capability::BloomParams(BloomParams::new(20, 14)),
+            ])
+        );
+    }
+
+    #[test]
+    fn adaptive_bloom_increases_precision_after_false_positives() {
+        // Same scenario as `should_handle_chains_of_false_positives`, doc1 receives n2c3 without
+        // n2c1 and n2c2 because its bloom filter claimed to have them
+        let mut doc1 = crate::AutoCommit::new().with_actor(ActorId::try_from("abc123").unwrap());
+        let mut doc2 = crate::AutoCommit::new().with_actor(ActorId::try_from("def456").unwrap());
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+
+        for i in 0..10 {
+            doc1.put(crate::ROOT, "x", i).unwrap();
+            doc1.commit();
+        }
+
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+
+        doc1.put(crate::ROOT, "x", 5).unwrap();
+        doc1.commit();
+        let bloom = BloomFilter::from_hashes(doc1.get_heads().into_iter());
+
+        let mut doc2 = doc2;
+        for round in 0..2 {
+            let mut i = 0;
+            doc2 = loop {
+                let mut doc = doc2
+                    .fork()
+                    .with_actor(ActorId::try_from("89abcdef").unwrap());
+                doc.put(crate::ROOT, "x", format!("{} in round {}", i, round))
+                    .unwrap();
+                doc.commit();
+                if bloom.contains_hash(&doc.get_heads()[0]) {
+                    break doc;
+                }
+                i += 1;
+            };
+        }
+        doc2.put(crate::ROOT, "x", "final @ 89abcdef").unwrap();
+
+        let (_, s1) = State::parse(Input::new(s1.encode().as_slice())).unwrap();
+        let mut s1 = s1.with_adaptive_bloom(true);
+        let (_, mut s2) = State::parse(Input::new(s2.encode().as_slice())).unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+        let adapted = s1.bloom_params.expect("no false positives observed");
+        assert!(adapted.bits_per_entry() > BloomParams::default().bits_per_entry());
+        assert_eq!(s2.bloom_params, None);
+    }
+
+    #[test]
+    fn encoded_state_round_trips_resumable_fields() {
+        let mut state = State::new()
+            .with_bloom_params(BloomParams::new(15, 10))
+            .with_adaptive_bloom(true);
+        state.shared_heads = vec![ChangeHash([1; 32])];
+        state.their_heads = Some(vec![ChangeHash([3; 32]), ChangeHash([2; 32])]);
+        state.their_capabilities = Some(vec![Capability::MessageV1, Capability::MessageV2]);
+        state.rejected_hashes.insert(ChangeHash([4; 32]));
+        state.their_rejected.insert(ChangeHash([5; 32]));
+        state.pending_rejections = vec![ChangeHash([6; 32])];
+        state.their_refs.set(
+            "main",
+            crate::refs::RefKind::Branch,
+            Some(vec![ChangeHash([7; 32])]),
+            &ActorId::random(),
+        );
+        state.their_mark_policies.set(
+            "bold",
+            Some(crate::marks::MarkPolicy::default().with_merge(true)),
+            &ActorId::random(),
+        );
+
+        let decoded = State::decode(&state.encode()).unwrap();
+        assert_eq!(decoded.shared_heads, state.shared_heads);
+        assert_eq!(
+            decoded.resume_heads,
+            Some(vec![ChangeHash([2; 32]), ChangeHash([3; 32])])
+        );
+        assert_eq!(decoded.their_heads, None);
+        assert_eq!(decoded.their_capabilities, state.their_capabilities);
+        assert_eq!(decoded.bloom_params, state.bloom_params);
+        assert!(decoded.adaptive_bloom);
+        assert_eq!(decoded.rejected_hashes, state.rejected_hashes);
+        assert_eq!(decoded.their_rejected, state.their_rejected);
+        assert_eq!(decoded.pending_rejections, state.pending_rejections);
+        assert_eq!(decoded.their_refs, state.their_refs);
+        assert_eq!(decoded.their_mark_policies, state.their_mark_policies);
+        // resuming is up to the caller
+        assert!(!decoded.resume);
+
+        // Encoding a state which hasn't been used yet keeps the heads it was resumed from
+        assert_eq!(State::decode(&decoded.encode()).unwrap(), decoded);
+    }
+
+    #[test]
+    fn decodes_states_without_resumable_fields() {
+        // The original encoding, which only contained the shared heads
+        let mut encoded = vec![0x43, 1];
+        encoded.extend_from_slice(&[7; 32]);
+        let state = State::decode(&encoded).unwrap();
+        assert_eq!(state.shared_heads, vec![ChangeHash([7; 32])]);
+        assert_eq!(state.resume_heads, None);
+        assert_eq!(state.their_capabilities, None);
+
+        // Versions we don't know about are rejected rather than misread
+        let mut newer = State::new().encode();
+        newer[2] = 2;
+        assert!(matches!(
+            State::decode(&newer),
+            Err(DecodeStateError::UnknownVersion(2))
+        ));
+    }
+
+    #[test]
+    fn resumed_sync_exchanges_missing_changes_in_the_first_messages() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        for i in 0..10 {
+            doc1.put(crate::ROOT, "x", i).unwrap();
+            doc1.commit();
+        }
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+
+        // Both ends make changes while disconnected
+        for i in 0..5 {
+            doc1.put(crate::ROOT, "one", i).unwrap();
+            doc1.commit();
+            doc2.put(crate::ROOT, "two", i).unwrap();
+            doc2.commit();
+        }
+
+        // Without opting in a decoded state starts with bloom filters as it always has
+        let mut plain = State::decode(&s1.encode()).unwrap();
+        let msg = doc1.sync().generate_sync_message(&mut plain).unwrap();
+        assert!(msg.changes.is_empty());
+
+        let mut s1 = State::decode(&s1.encode()).unwrap().with_resume();
+        let mut s2 = State::decode(&s2.encode()).unwrap().with_resume();
+        let one_to_two = doc1.sync().generate_sync_message(&mut s1).unwrap();
+        let two_to_one = doc2.sync().generate_sync_message(&mut s2).unwrap();
+        for msg in [&one_to_two, &two_to_one] {
+            assert_eq!(msg.have.len(), 1);
+            assert_eq!(msg.have[0].bloom, BloomFilter::default());
+            assert!(!msg.changes.is_empty());
+        }
+
+        doc2.sync()
+            .receive_sync_message(&mut s2, one_to_two)
+            .unwrap();
+        doc1.sync()
+            .receive_sync_message(&mut s1, two_to_one)
+            .unwrap();
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn resuming_falls_back_to_bloom_filters_if_we_lost_their_changes() {
+        let mut doc1 = crate::AutoCommit::new();
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        doc1.put(crate::ROOT, "x", 1).unwrap();
+        doc1.commit();
+        let mut doc1_backup = doc1.fork();
+        doc2.put(crate::ROOT, "y", 1).unwrap();
+        doc2.commit();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+
+        // doc1 is restored from a backup which doesn't have doc2's change
+        let mut s1 = State::decode(&s1.encode()).unwrap().with_resume();
+        let msg = doc1_backup.sync().generate_sync_message(&mut s1).unwrap();
+        assert_eq!(msg.have[0].last_sync, s1.shared_heads);
+        assert!(msg.changes.is_empty());
+        doc2.sync().receive_sync_message(&mut s2, msg).unwrap();
+
+        sync(&mut doc1_backup, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1_backup.get_heads(), doc2.get_heads());
+    }
+
     #[test]
     fn if_first_message_has_no_heads_and_supports_v2_message_send_whole_doc() {
         let mut doc1 = crate::AutoCommit::new();
@@ -1289,4 +2198,64 @@ mod tests {
         let (_, chunk) = Chunk::parse(Input::new(&changes.0[0])).unwrap();
         assert!(matches!(chunk, Chunk::Document(_)));
     }
+
+    #[test]
+    fn refs_are_synced() {
+        let mut doc1 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "foo", "bar").unwrap();
+        doc1.create_branch("main").unwrap();
+        let mut doc2 = crate::AutoCommit::new();
+        let (mut s1, mut s2) = (State::new(), State::new());
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc2.get_ref("main"), doc1.get_ref("main"));
+
+        // moving a ref without changing the document is enough to send a message
+        doc2.create_tag("v1").unwrap();
+        doc2.delete_ref("main").unwrap();
+        let msg = doc2.sync().generate_sync_message(&mut s2).unwrap();
+        let msg = Message::decode(&msg.encode()).unwrap();
+        assert!(msg.changes.is_empty());
+        doc1.sync().receive_sync_message(&mut s1, msg).unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_ref("main"), None);
+        assert_eq!(doc1.get_ref("v1").unwrap().heads, doc2.get_heads());
+        assert_eq!(doc1.refs(), doc2.refs());
+    }
+
+    #[test]
+    fn sync_policies_filter_refs() {
+        struct Policy;
+        impl SyncPolicy for Policy {
+            fn allow_outgoing(&self, change: &ChangeInfo<'_>) -> bool {
+                !change.ops().any(|op| op.key.as_deref() == Some("secret"))
+            }
+            fn check_ref(&self, update: &RefInfo<'_>) -> bool {
+                update.kind == crate::refs::RefKind::Branch
+            }
+            fn allow_outgoing_ref(&self, update: &RefInfo<'_>) -> bool {
+                update.name != "private"
+            }
+        }
+
+        let mut a = crate::AutoCommit::new();
+        let mut b = crate::AutoCommit::new();
+        b.put(crate::ROOT, "public", 1).unwrap();
+        b.commit();
+        b.create_branch("public").unwrap();
+        b.create_branch("private").unwrap();
+        b.put(crate::ROOT, "secret", 1).unwrap();
+        b.commit();
+        // points at a change which is withheld from `a`
+        b.create_branch("hidden").unwrap();
+        a.create_branch("feature").unwrap();
+        a.create_tag("v1").unwrap();
+
+        let (mut s1, mut s2) = (State::new(), State::new());
+        sync_with_policy(&mut a, &mut b, &mut s1, &mut s2, &Policy);
+        assert!(a.get_ref("public").is_some());
+        assert_eq!(a.get_ref("private"), None);
+        assert_eq!(a.get_ref("hidden"), None);
+        assert!(b.get_ref("feature").is_some());
+        assert_eq!(b.get_ref("v1"), None);
+    }
 }
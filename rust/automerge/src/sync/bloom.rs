@@ -9,6 +9,88 @@ use crate::ChangeHash;
 const BITS_PER_ENTRY: u32 = 10;
 const NUM_PROBES: u32 = 7;
 
+// Each step of adaptive sizing adds this many bits per entry, which roughly divides the false
+// positive rate by ten. We stop growing at `MAX_BITS_PER_ENTRY` (around one in a billion).
+const BITS_PER_ENTRY_STEP: u32 = 5;
+const MAX_BITS_PER_ENTRY: u32 = 45;
+// The optimal number of probes for `MAX_BITS_PER_ENTRY` is 31, anything beyond this just costs
+// time when building and querying the filter.
+const MAX_NUM_PROBES: u32 = 32;
+
+/// The parameters used to construct a [`BloomFilter`]
+///
+/// The defaults correspond to a 1% false positive rate. More bits per entry make the filter larger
+/// but reduce the number of false positives, each of which costs an extra round trip in the sync
+/// protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BloomParams {
+    bits_per_entry: u32,
+    num_probes: u32,
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        Self {
+            bits_per_entry: BITS_PER_ENTRY,
+            num_probes: NUM_PROBES,
+        }
+    }
+}
+
+impl BloomParams {
+    /// Create a new set of parameters
+    ///
+    /// `bits_per_entry` is clamped to between 1 and 45 and `num_probes` to between 1 and 32. The
+    /// parameters the other end asks for are clamped in the same way, so a peer can't make us
+    /// build an enormous filter.
+    pub fn new(bits_per_entry: u32, num_probes: u32) -> Self {
+        Self {
+            bits_per_entry: bits_per_entry.clamp(1, MAX_BITS_PER_ENTRY),
+            num_probes: num_probes.clamp(1, MAX_NUM_PROBES),
+        }
+    }
+
+    /// Create parameters with `bits_per_entry` and the number of probes which minimises the false
+    /// positive rate for that size
+    pub fn with_bits_per_entry(bits_per_entry: u32) -> Self {
+        let num_probes = (f64::from(bits_per_entry) * std::f64::consts::LN_2).round() as u32;
+        Self::new(bits_per_entry, num_probes)
+    }
+
+    pub fn bits_per_entry(&self) -> u32 {
+        self.bits_per_entry
+    }
+
+    pub fn num_probes(&self) -> u32 {
+        self.num_probes
+    }
+
+    /// Parameters with a lower false positive rate than these, or `self` if we are already at the
+    /// maximum precision
+    pub fn more_precise(&self) -> Self {
+        let bits_per_entry = self
+            .bits_per_entry
+            .saturating_add(BITS_PER_ENTRY_STEP)
+            .min(MAX_BITS_PER_ENTRY)
+            .max(self.bits_per_entry);
+        Self::with_bits_per_entry(bits_per_entry)
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        leb128::write::unsigned(out, self.bits_per_entry as u64).unwrap();
+        leb128::write::unsigned(out, self.num_probes as u64).unwrap();
+    }
+
+    pub(crate) fn parse<E>(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, E>
+    where
+        E: From<parse::leb128::Error>,
+    {
+        let (i, bits_per_entry) = parse::leb128_u32(input)?;
+        let (i, num_probes) = parse::leb128_u32(i)?;
+        Ok((i, Self::new(bits_per_entry, num_probes)))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
 pub struct BloomFilter {
     num_entries: u32,
@@ -66,6 +148,14 @@ impl BloomFilter {
         }
     }
 
+    /// The parameters this filter was built with
+    pub fn params(&self) -> BloomParams {
+        BloomParams {
+            bits_per_entry: self.num_bits_per_entry,
+            num_probes: self.num_probes,
+        }
+    }
+
     fn get_probes(&self, hash: &ChangeHash) -> Vec<u32> {
         let hash_bytes = hash.0;
         let modulo = 8 * self.bits.len() as u32;
@@ -123,9 +213,17 @@ impl BloomFilter {
     }
 
     pub fn from_hashes<H: Borrow<ChangeHash>>(hashes: impl ExactSizeIterator<Item = H>) -> Self {
+        Self::from_hashes_with_params(hashes, BloomParams::default())
+    }
+
+    /// Build a filter containing `hashes` using the given `params`
+    pub fn from_hashes_with_params<H: Borrow<ChangeHash>>(
+        hashes: impl ExactSizeIterator<Item = H>,
+        params: BloomParams,
+    ) -> Self {
         let num_entries = hashes.len() as u32;
-        let num_bits_per_entry = BITS_PER_ENTRY;
-        let num_probes = NUM_PROBES;
+        let num_bits_per_entry = params.bits_per_entry;
+        let num_probes = params.num_probes;
         let bits = vec![0; bits_capacity(num_entries, num_bits_per_entry)];
         let mut filter = Self {
             num_entries,
@@ -0,0 +1,84 @@
+use std::collections::BTreeSet;
+
+use crate::marks::MarkPolicies;
+use crate::refs::Refs;
+use crate::{Automerge, Change, ChangeHash, ReadDoc};
+
+/// The parts of a document which the sync protocol needs
+///
+/// This is implemented by [`Automerge`] and by [`super::Relay`], which stores changes without
+/// applying them.
+pub(crate) trait ChangeStore {
+    /// The current heads, sorted
+    fn heads(&self) -> Vec<ChangeHash>;
+
+    fn change_by_hash(&self, hash: &ChangeHash) -> Option<&Change>;
+
+    /// Every change which is not an ancestor of `heads`, in topological order
+    fn changes_since(&self, heads: &[ChangeHash]) -> Vec<&Change>;
+
+    /// Any of `heads` we don't have, along with the missing dependencies of any changes we are
+    /// waiting to apply
+    fn missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash>;
+
+    /// Remove any hashes from `changes` which are ancestors of `heads`
+    fn remove_ancestors(&self, heads: &[ChangeHash], changes: &mut BTreeSet<ChangeHash>);
+
+    /// The whole document in the compressed document format, if this store can produce it
+    fn encode_document(&self) -> Option<Vec<u8>>;
+
+    /// The branches and tags, see [`crate::refs`]
+    fn refs(&self) -> &Refs;
+
+    /// Merge refs received from the other end
+    fn merge_refs(&mut self, refs: &Refs);
+
+    /// The mark policies, see [`MarkPolicies`]
+    fn mark_policies(&self) -> &MarkPolicies;
+
+    /// Merge mark policies received from the other end
+    fn merge_mark_policies(&mut self, policies: &MarkPolicies);
+}
+
+impl ChangeStore for Automerge {
+    fn heads(&self) -> Vec<ChangeHash> {
+        self.get_heads()
+    }
+
+    fn change_by_hash(&self, hash: &ChangeHash) -> Option<&Change> {
+        self.get_change_by_hash(hash)
+    }
+
+    fn changes_since(&self, heads: &[ChangeHash]) -> Vec<&Change> {
+        self.get_changes(heads)
+    }
+
+    fn missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
+        self.get_missing_deps(heads)
+    }
+
+    fn remove_ancestors(&self, heads: &[ChangeHash], changes: &mut BTreeSet<ChangeHash>) {
+        self.filter_changes(heads, changes)
+            .expect("filtering changes is infallible")
+    }
+
+    fn encode_document(&self) -> Option<Vec<u8>> {
+        Some(self.save())
+    }
+
+    fn refs(&self) -> &Refs {
+        Automerge::refs(self)
+    }
+
+    fn merge_refs(&mut self, refs: &Refs) {
+        Automerge::merge_refs(self, refs);
+    }
+
+    fn mark_policies(&self) -> &MarkPolicies {
+        Automerge::mark_policies(self)
+    }
+
+    fn merge_mark_policies(&mut self, policies: &MarkPolicies) {
+        Automerge::merge_mark_policies(self, policies);
+    }
+}
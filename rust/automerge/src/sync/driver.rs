@@ -0,0 +1,487 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::watch;
+
+use super::{Message, ReadMessageError, State, SyncDoc};
+use crate::{AutoCommit, AutomergeError};
+
+/// The default value of [`SyncDriver::with_max_frame_len()`], 64MiB
+pub const DEFAULT_MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// The size of the buffer we read into from the transport
+const READ_CHUNK_LEN: usize = 8 * 1024;
+
+/// An [`AutoCommit`] which is shared between the application and any number of [`SyncDriver`]s
+///
+/// Cloning a `SharedDoc` produces another handle to the same document. Every modification made
+/// through [`Self::change()`], or received from a peer by a [`SyncDriver`], wakes all the drivers
+/// for the document so that they can send the new changes to their peers.
+#[derive(Debug, Clone)]
+pub struct SharedDoc {
+    doc: Arc<Mutex<AutoCommit>>,
+    changed: Arc<watch::Sender<()>>,
+}
+
+impl SharedDoc {
+    pub fn new(doc: AutoCommit) -> Self {
+        let (changed, _) = watch::channel(());
+        Self {
+            doc: Arc::new(Mutex::new(doc)),
+            changed: Arc::new(changed),
+        }
+    }
+
+    /// Read from the document
+    pub fn read<F, O>(&self, f: F) -> O
+    where
+        F: FnOnce(&AutoCommit) -> O,
+    {
+        f(&self.lock())
+    }
+
+    /// Modify the document and notify every [`SyncDriver`] for it
+    ///
+    /// Any transaction `f` leaves open is committed before the drivers are woken.
+    pub fn change<F, O>(&self, f: F) -> O
+    where
+        F: FnOnce(&mut AutoCommit) -> O,
+    {
+        let result = {
+            let mut doc = self.lock();
+            let result = f(&mut doc);
+            doc.commit();
+            result
+        };
+        self.changed.send_replace(());
+        result
+    }
+
+    /// A receiver which is marked as changed whenever the document changes
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AutoCommit> {
+        self.doc.lock().expect("the document lock was poisoned")
+    }
+}
+
+/// Errors returned by [`SyncDriver::run()`]
+#[derive(Debug, thiserror::Error)]
+pub enum DriverError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("the peer sent a frame of {len} bytes which is larger than the maximum of {max}")]
+    FrameTooLarge { len: usize, max: usize },
+    #[error("the stream ended part way through a frame")]
+    TruncatedFrame,
+    #[error("unable to decode sync message: {0}")]
+    Decode(#[from] ReadMessageError),
+    #[error(transparent)]
+    Automerge(#[from] AutomergeError),
+}
+
+/// The error returned by [`SyncDriver::run()`], along with the sync state at the time
+///
+/// The state is as valid as one returned after a clean shutdown, so it can be persisted and
+/// passed to [`SyncDriver::with_state()`] when reconnecting.
+#[derive(Debug, thiserror::Error)]
+#[error("{error}")]
+pub struct RunError {
+    #[source]
+    pub error: DriverError,
+    pub state: State,
+}
+
+/// Runs the sync protocol for a [`SharedDoc`] over a byte stream
+///
+/// Each sync message is sent as a frame consisting of the length of the encoded message as a
+/// big-endian `u32` followed by the encoded message. The driver sends a message whenever
+/// [`SyncDoc::generate_sync_message()`] produces one, applies every message it receives and waits
+/// for the document to change when there is nothing to do. At most one outgoing message is
+/// buffered at a time, so a slow peer slows down message generation rather than causing messages
+/// to pile up in memory.
+///
+/// ## Example
+///
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// use automerge::{
+///     sync::{SharedDoc, SyncDriver}, transaction::Transactable, AutoCommit, ReadDoc, ROOT,
+/// };
+///
+/// let (left, right) = tokio::io::duplex(1024);
+/// let doc = SharedDoc::new(AutoCommit::new());
+/// doc.change(|d| d.put(ROOT, "key", "value")).unwrap();
+///
+/// tokio::spawn(SyncDriver::new(doc, left).run());
+///
+/// let other = SharedDoc::new(AutoCommit::new());
+/// let mut changed = other.subscribe();
+/// tokio::spawn(SyncDriver::new(other.clone(), right).run());
+/// while other.read(|d| d.get(ROOT, "key").unwrap().is_none()) {
+///     changed.changed().await.unwrap();
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SyncDriver<S> {
+    doc: SharedDoc,
+    stream: S,
+    state: State,
+    max_frame_len: usize,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SyncDriver<S> {
+    pub fn new(doc: SharedDoc, stream: S) -> Self {
+        Self {
+            doc,
+            stream,
+            state: State::new(),
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+
+    /// Start from a previously persisted [`State`] rather than an empty one
+    pub fn with_state(mut self, state: State) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// The largest frame the peer may send us, larger frames are an error
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Sync until the peer closes the stream
+    ///
+    /// Returns the sync state once the peer has gone away, or with the error if the connection
+    /// fails, so that it can be persisted and passed to [`Self::with_state()`] next time we
+    /// connect to the same peer.
+    pub async fn run(self) -> Result<State, RunError> {
+        let SyncDriver {
+            doc,
+            stream,
+            mut state,
+            max_frame_len,
+        } = self;
+        match sync(doc, stream, &mut state, max_frame_len).await {
+            Ok(()) => Ok(state),
+            Err(error) => Err(RunError { error, state }),
+        }
+    }
+}
+
+// `tokio::select!` expands to std APIs newer than our MSRV, which is fine as tokio itself needs
+// a newer compiler than that
+#[allow(clippy::incompatible_msrv)]
+async fn sync<S: AsyncRead + AsyncWrite + Unpin>(
+    doc: SharedDoc,
+    stream: S,
+    state: &mut State,
+    max_frame_len: usize,
+) -> Result<(), DriverError> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut changed = doc.subscribe();
+    let mut frames = FrameReader::new(max_frame_len);
+    let mut outgoing = Vec::new();
+    let mut written = 0;
+    let mut needs_flush = false;
+
+    loop {
+        if written == outgoing.len() {
+            outgoing.clear();
+            written = 0;
+            let message = doc
+                .lock()
+                .sync()
+                .generate_sync_message(state)
+                .map(Message::encode);
+            if let Some(message) = message {
+                encode_frame(&message, &mut outgoing);
+            }
+        }
+
+        // Every branch here is cancel safe: partially read frames live in `frames` and
+        // partially written ones in `outgoing`, so whichever branch wins nothing is lost
+        tokio::select! {
+            n = write_or_flush(&mut writer, &outgoing[written..]), if needs_flush || written < outgoing.len() => {
+                let n = n?;
+                written += n;
+                needs_flush = n > 0;
+            }
+            frame = frames.next(&mut reader) => {
+                let frame = match frame? {
+                    Some(frame) => frame,
+                    None => return Ok(()),
+                };
+                let message = Message::decode(&frame)?;
+                let heads_changed = {
+                    let mut doc = doc.lock();
+                    let before = doc.get_heads();
+                    doc.sync().receive_sync_message(state, message)?;
+                    doc.get_heads() != before
+                };
+                if heads_changed {
+                    doc.changed.send_replace(());
+                }
+            }
+            result = changed.changed() => {
+                // The sender lives in `doc` so this can't fail
+                debug_assert!(result.is_ok());
+            }
+        }
+    }
+}
+
+/// Write some of `pending` or, if everything has been written, flush the writer
+///
+/// Returns the number of bytes written, which is zero if we flushed.
+async fn write_or_flush<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    pending: &[u8],
+) -> std::io::Result<usize> {
+    if pending.is_empty() {
+        writer.flush().await?;
+        return Ok(0);
+    }
+    match writer.write(pending).await? {
+        0 => Err(std::io::ErrorKind::WriteZero.into()),
+        n => Ok(n),
+    }
+}
+
+fn encode_frame(message: &[u8], out: &mut Vec<u8>) {
+    // Messages are limited to `u32::MAX` bytes by the framing, which is far larger than any
+    // sensible `max_frame_len`
+    let len = u32::try_from(message.len()).expect("sync message too large to frame");
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(message);
+}
+
+/// Accumulates bytes from a reader until a complete frame is available
+struct FrameReader {
+    buf: Vec<u8>,
+    max_frame_len: usize,
+}
+
+impl FrameReader {
+    fn new(max_frame_len: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            max_frame_len,
+        }
+    }
+
+    /// Read the next frame, returning [`None`] if the stream ended cleanly between frames
+    ///
+    /// This is cancel safe, any bytes read before cancellation are kept for the next call.
+    async fn next<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<Option<Vec<u8>>, DriverError> {
+        let mut chunk = [0; READ_CHUNK_LEN];
+        loop {
+            if let Some(frame) = self.take_frame()? {
+                return Ok(Some(frame));
+            }
+            let n = reader.read(&mut chunk).await?;
+            if n == 0 {
+                return if self.buf.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(DriverError::TruncatedFrame)
+                };
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    fn take_frame(&mut self) -> Result<Option<Vec<u8>>, DriverError> {
+        let header = match self.buf.get(..4) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let len = u32::from_be_bytes(header.try_into().unwrap()) as usize;
+        if len > self.max_frame_len {
+            return Err(DriverError::FrameTooLarge {
+                len,
+                max: self.max_frame_len,
+            });
+        }
+        if self.buf.len() < 4 + len {
+            return Ok(None);
+        }
+        let frame = self.buf[4..4 + len].to_vec();
+        self.buf.drain(..4 + len);
+        Ok(Some(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{transaction::Transactable, ReadDoc, ROOT};
+
+    async fn wait_until<F: Fn(&AutoCommit) -> bool>(doc: &SharedDoc, pred: F) {
+        let mut changed = doc.subscribe();
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !doc.read(&pred) {
+                changed.changed().await.unwrap();
+            }
+        })
+        .await
+        .expect("timed out waiting for the document to change")
+    }
+
+    fn has_key(key: &'static str) -> impl Fn(&AutoCommit) -> bool {
+        move |doc| doc.get(ROOT, key).unwrap().is_some()
+    }
+
+    fn doc_with(key: &str) -> SharedDoc {
+        let doc = SharedDoc::new(AutoCommit::new());
+        doc.change(|d| d.put(ROOT, key, "value")).unwrap();
+        doc
+    }
+
+    #[tokio::test]
+    async fn syncs_existing_changes_both_ways() {
+        let left = doc_with("left");
+        let right = doc_with("right");
+        // A tiny buffer forces messages to be written in several pieces
+        let (left_io, right_io) = tokio::io::duplex(16);
+        let left_task = tokio::spawn(SyncDriver::new(left.clone(), left_io).run());
+        let right_task = tokio::spawn(SyncDriver::new(right.clone(), right_io).run());
+
+        wait_until(&left, has_key("right")).await;
+        wait_until(&right, has_key("left")).await;
+
+        left_task.abort();
+        let state = right_task.await.unwrap().unwrap();
+        assert_eq!(state.shared_heads, right.lock().get_heads());
+    }
+
+    #[tokio::test]
+    async fn sends_changes_made_while_connected() {
+        let left = SharedDoc::new(AutoCommit::new());
+        let right = SharedDoc::new(AutoCommit::new());
+        let (left_io, right_io) = tokio::io::duplex(1024);
+        tokio::spawn(SyncDriver::new(left.clone(), left_io).run());
+        tokio::spawn(SyncDriver::new(right.clone(), right_io).run());
+
+        left.change(|d| d.put(ROOT, "first", 1)).unwrap();
+        wait_until(&right, has_key("first")).await;
+        right.change(|d| d.put(ROOT, "second", 2)).unwrap();
+        wait_until(&left, has_key("second")).await;
+    }
+
+    #[tokio::test]
+    async fn relays_changes_between_peers() {
+        let left = SharedDoc::new(AutoCommit::new());
+        let middle = SharedDoc::new(AutoCommit::new());
+        let right = SharedDoc::new(AutoCommit::new());
+        let (a, b) = tokio::io::duplex(1024);
+        let (c, d) = tokio::io::duplex(1024);
+        tokio::spawn(SyncDriver::new(left.clone(), a).run());
+        tokio::spawn(SyncDriver::new(middle.clone(), b).run());
+        tokio::spawn(SyncDriver::new(middle.clone(), c).run());
+        tokio::spawn(SyncDriver::new(right.clone(), d).run());
+
+        left.change(|d| d.put(ROOT, "key", "value")).unwrap();
+        wait_until(&right, has_key("key")).await;
+    }
+
+    #[tokio::test]
+    async fn frames_larger_than_the_maximum_are_rejected() {
+        let doc = SharedDoc::new(AutoCommit::new());
+        let (ours, mut theirs) = tokio::io::duplex(1024);
+        let task = tokio::spawn(SyncDriver::new(doc, ours).with_max_frame_len(100).run());
+
+        theirs.write_all(&1000_u32.to_be_bytes()).await.unwrap();
+        match task.await.unwrap() {
+            Err(RunError {
+                error:
+                    DriverError::FrameTooLarge {
+                        len: 1000,
+                        max: 100,
+                    },
+                ..
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_return_the_state() {
+        let left = doc_with("left");
+        let right = SharedDoc::new(AutoCommit::new());
+        let (left_io, mut right_io) = tokio::io::duplex(1024);
+        let task = tokio::spawn(SyncDriver::new(left, left_io).run());
+
+        let mut frames = FrameReader::new(DEFAULT_MAX_FRAME_LEN);
+        let mut state = State::new();
+        let first = Message::decode(&frames.next(&mut right_io).await.unwrap().unwrap()).unwrap();
+        let reply = right.change(|d| {
+            d.sync().receive_sync_message(&mut state, first).unwrap();
+            d.sync().generate_sync_message(&mut state).unwrap()
+        });
+        let mut frame = Vec::new();
+        encode_frame(&reply.encode(), &mut frame);
+        // followed by garbage
+        encode_frame(&[0xff; 8], &mut frame);
+        right_io.write_all(&frame).await.unwrap();
+
+        let err = task.await.unwrap().unwrap_err();
+        assert!(matches!(err.error, DriverError::Decode(_)));
+        assert!(err.state.have_responded);
+        assert_eq!(err.state.their_heads, Some(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn resumes_from_a_previous_state() {
+        let left = doc_with("first");
+        let right = SharedDoc::new(AutoCommit::new());
+
+        let (left_io, right_io) = tokio::io::duplex(1024);
+        let left_task = tokio::spawn(SyncDriver::new(left.clone(), left_io).run());
+        let right_task = tokio::spawn(SyncDriver::new(right.clone(), right_io).run());
+        wait_until(&right, has_key("first")).await;
+        left_task.abort();
+        let right_state = right_task.await.unwrap().unwrap();
+
+        left.change(|d| d.put(ROOT, "second", "value")).unwrap();
+        right.change(|d| d.put(ROOT, "third", "value")).unwrap();
+        let (mut left_io, right_io) = tokio::io::duplex(1024);
+        tokio::spawn(
+            SyncDriver::new(right.clone(), right_io)
+                .with_state(State::decode(&right_state.encode()).unwrap().with_resume())
+                .run(),
+        );
+
+        // The first message already has the change the left doesn't have and the exact heads
+        // of the right, rather than a bloom filter for the left to answer
+        let mut frames = FrameReader::new(DEFAULT_MAX_FRAME_LEN);
+        let first = Message::decode(&frames.next(&mut left_io).await.unwrap().unwrap()).unwrap();
+        assert_eq!(first.changes.len(), 1);
+        assert_eq!(first.have[0].last_sync, right.lock().get_heads());
+        let mut left_state = State::new();
+        let reply = left.change(|d| {
+            d.sync()
+                .receive_sync_message(&mut left_state, first)
+                .unwrap();
+            d.sync().generate_sync_message(&mut left_state).unwrap()
+        });
+        assert!(left.read(has_key("third")));
+
+        // So a single reply brings the right up to date
+        let mut frame = Vec::new();
+        encode_frame(&reply.encode(), &mut frame);
+        left_io.write_all(&frame).await.unwrap();
+        wait_until(&right, has_key("second")).await;
+    }
+}
@@ -1,3 +1,5 @@
+use crate::marks::MarkPolicies;
+use crate::refs::Refs;
 use crate::{Change, ChangeHash};
 
 use super::{Capability, Have, Message, MessageVersion};
@@ -9,6 +11,9 @@ pub(super) struct MessageBuilder {
     changes: Vec<Vec<u8>>,
     supported_capabilities: Option<Vec<Capability>>,
     version: MessageVersion,
+    rejected: Vec<ChangeHash>,
+    refs: Option<Refs>,
+    mark_policies: Option<MarkPolicies>,
 }
 
 impl MessageBuilder {
@@ -20,6 +25,9 @@ impl MessageBuilder {
             changes: changes.map(|c| c.raw_bytes().to_vec()).collect(),
             supported_capabilities: None,
             version: MessageVersion::V1,
+            rejected: Vec::new(),
+            refs: None,
+            mark_policies: None,
         }
     }
 
@@ -35,6 +43,9 @@ impl MessageBuilder {
             have: Vec::new(),
             supported_capabilities: None,
             version: MessageVersion::V2,
+            rejected: Vec::new(),
+            refs: None,
+            mark_policies: None,
         }
     }
 
@@ -61,6 +72,21 @@ impl MessageBuilder {
         self
     }
 
+    pub(super) fn rejected(mut self, rejected: Vec<ChangeHash>) -> Self {
+        self.rejected = rejected;
+        self
+    }
+
+    pub(super) fn refs(mut self, refs: Option<Refs>) -> Self {
+        self.refs = refs;
+        self
+    }
+
+    pub(super) fn mark_policies(mut self, mark_policies: Option<MarkPolicies>) -> Self {
+        self.mark_policies = mark_policies;
+        self
+    }
+
     pub(super) fn build(self) -> Message {
         Message {
             heads: self.heads,
@@ -69,6 +95,9 @@ impl MessageBuilder {
             changes: super::ChunkList::from(self.changes),
             supported_capabilities: self.supported_capabilities,
             version: self.version,
+            rejected: self.rejected,
+            refs: self.refs,
+            mark_policies: self.mark_policies,
         }
     }
 
@@ -0,0 +1,191 @@
+use std::collections::BTreeSet;
+
+#[cfg(doc)]
+use super::SyncDoc;
+use crate::{
+    columnar::Key as EncodedKey, exid::ExId, refs::RefKind, ActorId, Automerge, Change, ChangeHash,
+    OpType, Prop, ReadDoc,
+};
+
+/// Decides which changes may be exchanged with a particular peer
+///
+/// A policy is consulted by [`SyncDoc::generate_sync_message_with_policy()`] for every change we
+/// might send to the other end and by [`SyncDoc::receive_sync_message_with_policy()`] for every
+/// change the other end sends us. Both methods default to allowing everything so implementations
+/// only need to override the direction they care about.
+///
+/// ## Example
+///
+/// ```
+/// use automerge::sync::{ChangeInfo, SyncPolicy};
+///
+/// // A policy for a peer which may read the document but not write to it
+/// struct ReadOnly;
+///
+/// impl SyncPolicy for ReadOnly {
+///     fn check_incoming(&self, change: &ChangeInfo<'_>) -> Result<(), String> {
+///         Err(format!("{} is read only", change.actor()))
+///     }
+/// }
+/// ```
+pub trait SyncPolicy {
+    /// Decide whether to apply `change`, which the other end has sent us
+    ///
+    /// Returning an error rejects the change, and any changes which depend on it, for the given
+    /// reason. Rejected changes are not applied to the document. They are returned from
+    /// [`SyncDoc::receive_sync_message_with_policy()`] and reported to the other end so that it
+    /// stops offering them.
+    fn check_incoming(&self, change: &ChangeInfo<'_>) -> Result<(), String> {
+        let _ = change;
+        Ok(())
+    }
+
+    /// Decide whether the other end may receive `change`
+    ///
+    /// Withheld changes, and any changes which depend on them, are hidden from the other end: they
+    /// are not sent and our heads are advertised as if we did not have them. If this later returns
+    /// `true` for a previously withheld change it will be sent in the next sync message.
+    fn allow_outgoing(&self, change: &ChangeInfo<'_>) -> bool {
+        let _ = change;
+        true
+    }
+
+    /// Decide whether to accept an update to a ref from the other end, see [`crate::refs`]
+    ///
+    /// Refused updates are dropped and the ref keeps its current value here.
+    fn check_ref(&self, update: &RefInfo<'_>) -> bool {
+        let _ = update;
+        true
+    }
+
+    /// Decide whether the other end may receive our version of a ref
+    ///
+    /// Refs which point at changes withheld by [`Self::allow_outgoing()`] are never sent,
+    /// whatever this returns.
+    fn allow_outgoing_ref(&self, update: &RefInfo<'_>) -> bool {
+        let _ = update;
+        true
+    }
+}
+
+/// A version of a ref being considered by a [`SyncPolicy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefInfo<'a> {
+    pub name: &'a str,
+    pub kind: RefKind,
+    /// The heads the ref points at, [`None`] if the ref was deleted
+    pub heads: Option<&'a [ChangeHash]>,
+}
+
+/// A change being considered by a [`SyncPolicy`]
+#[derive(Debug, Clone, Copy)]
+pub struct ChangeInfo<'a> {
+    doc: Option<&'a Automerge>,
+    change: &'a Change,
+}
+
+/// A single operation in a change being considered by a [`SyncPolicy`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeOpInfo {
+    /// The object this operation modifies
+    pub obj: ExId,
+    /// The map key this operation modifies, or [`None`] for operations on sequences
+    pub key: Option<String>,
+    /// What the operation does
+    pub action: OpType,
+}
+
+impl<'a> ChangeInfo<'a> {
+    pub(crate) fn new(doc: &'a Automerge, change: &'a Change) -> Self {
+        Self {
+            doc: Some(doc),
+            change,
+        }
+    }
+
+    /// A change passing through a [`super::Relay`], which has no document
+    pub(crate) fn without_doc(change: &'a Change) -> Self {
+        Self { doc: None, change }
+    }
+
+    /// The document the change is being applied to or sent from, [`None`] if the change is
+    /// passing through a [`super::Relay`]
+    pub fn doc(&self) -> Option<&'a Automerge> {
+        self.doc
+    }
+
+    pub fn change(&self) -> &'a Change {
+        self.change
+    }
+
+    pub fn hash(&self) -> ChangeHash {
+        self.change.hash()
+    }
+
+    pub fn actor(&self) -> &'a ActorId {
+        self.change.actor_id()
+    }
+
+    /// The operations in this change
+    pub fn ops(&self) -> impl Iterator<Item = ChangeOpInfo> + 'a {
+        let change = self.change;
+        let doc = self.doc;
+        change.iter_ops().map(move |op| {
+            let obj = if op.obj.is_root() {
+                ExId::Root
+            } else {
+                let opid = op.obj.opid();
+                let actor = if opid.actor() == 0 {
+                    change.actor_id()
+                } else {
+                    &change.other_actor_ids()[opid.actor() - 1]
+                };
+                let hint = doc
+                    .and_then(|doc| doc.ops().osd.actors.lookup(actor))
+                    .unwrap_or(0);
+                ExId::Id(opid.counter(), actor.clone(), hint)
+            };
+            let key = match op.key {
+                EncodedKey::Prop(p) => Some(p.to_string()),
+                EncodedKey::Elem(_) => None,
+            };
+            ChangeOpInfo {
+                obj,
+                key,
+                action: OpType::from_action_and_value(op.action, op.val, op.mark_name, op.expand),
+            }
+        })
+    }
+
+    /// The objects this change modifies
+    pub fn touched_objects(&self) -> BTreeSet<ExId> {
+        self.ops().map(|op| op.obj).collect()
+    }
+
+    /// The path from the root of the document to `obj`
+    ///
+    /// Returns [`None`] if `obj` does not exist in the document yet, which is the case for
+    /// objects created by this change. Every such object is created by an operation on an object
+    /// which does exist, so checking the path of every touched object covers everything the change
+    /// does. A [`super::Relay`] has no document, so there are no paths for its changes.
+    pub fn path(&self, obj: &ExId) -> Option<Vec<(ExId, Prop)>> {
+        self.doc?.parents(obj).ok().map(|p| p.path())
+    }
+}
+
+/// A change which was rejected by a [`SyncPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedChange {
+    pub hash: ChangeHash,
+    pub actor: ActorId,
+    pub reason: RejectReason,
+}
+
+/// Why a change was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectReason {
+    /// [`SyncPolicy::check_incoming()`] returned an error
+    Policy(String),
+    /// The change depends on a change which was rejected
+    RejectedDependency(ChangeHash),
+}
@@ -0,0 +1,665 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use super::{
+    apply_changes_with_policy, change_store::ChangeStore, generate_sync_message,
+    receive_sync_message,
+};
+use super::{ChangeInfo, Message, RefInfo, RejectedChange, State, SyncPolicy};
+use crate::{
+    change_graph::ChangeGraph,
+    marks::MarkPolicies,
+    refs::Refs,
+    storage::{load, parse},
+    ActorId, Automerge, AutomergeError, Change, ChangeHash,
+};
+
+/// Takes part in the sync protocol without building a document
+///
+/// A relay stores the changes it receives and the graph of their dependencies, but never applies
+/// the operations they contain. This is much cheaper than maintaining an [`crate::Automerge`] for
+/// servers which only pass changes between peers and never need to read the documents they hold.
+///
+/// There are two exceptions, both when syncing with an empty peer which supports whole document
+/// messages. When such a peer sends a relay a whole document the changes must be reconstructed
+/// from the document once, and when a relay sends one to such a peer the document is built from
+/// the changes in order to encode it.
+///
+/// A [`SyncPolicy`] can be used to filter the changes passing through a relay. There is no
+/// document to inspect, so [`ChangeInfo::doc()`] is [`None`] and policies can only look at the
+/// changes themselves.
+///
+/// ## Example
+///
+/// ```
+/// use automerge::{sync::{self, Relay, SyncDoc}, transaction::Transactable, AutoCommit, ROOT};
+/// # fn main() -> Result<(), automerge::AutomergeError> {
+/// let mut doc = AutoCommit::new();
+/// doc.put(ROOT, "key", "value")?;
+///
+/// let mut relay = Relay::new();
+/// let mut doc_state = sync::State::new();
+/// let mut relay_state = sync::State::new();
+/// loop {
+///     let to_relay = doc.sync().generate_sync_message(&mut doc_state);
+///     if let Some(message) = to_relay.clone() {
+///         relay.receive_sync_message(&mut relay_state, message)?;
+///     }
+///     let to_doc = relay.generate_sync_message(&mut relay_state);
+///     if let Some(message) = to_doc.clone() {
+///         doc.sync().receive_sync_message(&mut doc_state, message)?;
+///     }
+///     if to_relay.is_none() && to_doc.is_none() {
+///         break;
+///     }
+/// }
+/// assert_eq!(relay.get_heads(), doc.get_heads());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Relay {
+    history: Vec<Change>,
+    history_index: HashMap<ChangeHash, usize>,
+    /// The indices in `history` of the changes made by each actor, in order of sequence number.
+    /// Actors are referred to by their index in `actors`.
+    states: HashMap<usize, Vec<usize>>,
+    actors: HashMap<ActorId, usize>,
+    change_graph: ChangeGraph,
+    heads: HashSet<ChangeHash>,
+    /// Changes we have received whose dependencies we don't have yet
+    queue: Vec<Change>,
+    /// Branches and tags, which are relayed along with the changes
+    refs: Refs,
+    /// The mark policies of the document, which are relayed in the same way
+    mark_policies: MarkPolicies,
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Relay {
+    pub fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            history_index: HashMap::new(),
+            states: HashMap::new(),
+            actors: HashMap::new(),
+            change_graph: ChangeGraph::new(),
+            heads: HashSet::new(),
+            queue: Vec::new(),
+            refs: Refs::default(),
+            mark_policies: MarkPolicies::default(),
+        }
+    }
+
+    /// Load the output of [`Self::save()`], or of [`crate::Automerge::save()`], along with any
+    /// refs and mark policies saved with it
+    pub fn load(data: &[u8]) -> Result<Self, AutomergeError> {
+        let mut relay = Self::new();
+        match load::load_changes_and_metadata(
+            parse::Input::new(data),
+            &mut relay.refs,
+            &mut relay.mark_policies,
+        ) {
+            load::LoadedChanges::Complete(changes) => relay.apply_changes(changes)?,
+            load::LoadedChanges::Partial { error, .. } => return Err(error.into()),
+        }
+        Ok(relay)
+    }
+
+    /// Load changes in any format [`crate::Automerge::load_incremental()`] accepts
+    ///
+    /// Returns the number of new changes
+    pub fn load_incremental(&mut self, data: &[u8]) -> Result<usize, AutomergeError> {
+        let changes = match load::load_changes_and_metadata(
+            parse::Input::new(data),
+            &mut self.refs,
+            &mut self.mark_policies,
+        ) {
+            load::LoadedChanges::Complete(c) => c,
+            load::LoadedChanges::Partial { error, loaded, .. } => {
+                tracing::warn!(successful_chunks=loaded.len(), err=?error, "partial load");
+                loaded
+            }
+        };
+        let before = self.history.len();
+        self.apply_changes(changes)?;
+        Ok(self.history.len() - before)
+    }
+
+    /// All the changes in the relay as a sequence of change chunks
+    ///
+    /// Changes which are waiting for their dependencies are included so that nothing is lost.
+    /// They are followed by the refs and mark policies, if there are any, as in
+    /// [`crate::Automerge::save()`].
+    pub fn save(&self) -> Vec<u8> {
+        let mut bytes = self
+            .history
+            .iter()
+            .chain(self.queue.iter())
+            .flat_map(|c| c.raw_bytes().iter().copied())
+            .collect::<Vec<_>>();
+        if !self.refs.is_empty() {
+            bytes.extend(self.refs.encode_chunk());
+        }
+        if !self.mark_policies.is_empty() {
+            bytes.extend(self.mark_policies.encode_chunk());
+        }
+        bytes
+    }
+
+    /// Add `changes`, ignoring any we already have
+    ///
+    /// Changes whose dependencies are missing are held until the dependencies arrive.
+    pub fn apply_changes(
+        &mut self,
+        changes: impl IntoIterator<Item = Change>,
+    ) -> Result<(), AutomergeError> {
+        for change in changes {
+            if self.history_index.contains_key(&change.hash()) {
+                continue;
+            }
+            if self.duplicate_seq(&change) {
+                return Err(AutomergeError::DuplicateSeqNumber(
+                    change.seq(),
+                    change.actor_id().clone(),
+                ));
+            }
+            if self.is_causally_ready(&change) {
+                self.add_change(change);
+            } else {
+                self.queue.push(change);
+            }
+        }
+        while let Some(change) = self.pop_next_causally_ready_change() {
+            if self.history_index.contains_key(&change.hash()) {
+                continue;
+            }
+            // Another change with the same seq may have arrived while this one was waiting
+            if self.duplicate_seq(&change) {
+                return Err(AutomergeError::DuplicateSeqNumber(
+                    change.seq(),
+                    change.actor_id().clone(),
+                ));
+            }
+            self.add_change(change);
+        }
+        Ok(())
+    }
+
+    pub fn get_heads(&self) -> Vec<ChangeHash> {
+        let mut heads = self.heads.iter().copied().collect::<Vec<_>>();
+        heads.sort_unstable();
+        heads
+    }
+
+    /// The changes which are not ancestors of `have_deps`
+    pub fn get_changes(&self, have_deps: &[ChangeHash]) -> Vec<&Change> {
+        let clock = self.change_graph.clock_for_heads(have_deps);
+        let mut change_indexes: Vec<usize> = Vec::new();
+        for (actor_index, actor_changes) in &self.states {
+            if let Some(clock_data) = clock.get_for_actor(actor_index) {
+                change_indexes.extend(&actor_changes[clock_data.seq as usize..]);
+            } else {
+                change_indexes.extend(&actor_changes[..]);
+            }
+        }
+        change_indexes.sort_unstable();
+        change_indexes
+            .into_iter()
+            .map(|i| &self.history[i])
+            .collect()
+    }
+
+    pub fn get_change_by_hash(&self, hash: &ChangeHash) -> Option<&Change> {
+        self.history_index
+            .get(hash)
+            .and_then(|index| self.history.get(*index))
+    }
+
+    /// Any of `heads` we don't have, along with the missing dependencies of changes which are
+    /// waiting for them
+    pub fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
+        let in_queue: HashSet<_> = self.queue.iter().map(|change| change.hash()).collect();
+        self.queue
+            .iter()
+            .flat_map(|change| change.deps())
+            .chain(heads)
+            .filter(|hash| !self.history_index.contains_key(hash) && !in_queue.contains(hash))
+            .copied()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// The number of changes in the relay, not counting those waiting for their dependencies
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// Generate a sync message for the remote peer represented by `sync_state`
+    ///
+    /// See [`super::SyncDoc::generate_sync_message()`]
+    pub fn generate_sync_message(&self, sync_state: &mut State) -> Option<Message> {
+        generate_sync_message(self, sync_state, None, None)
+    }
+
+    /// Store the changes in a received sync message and update `sync_state`
+    ///
+    /// See [`super::SyncDoc::receive_sync_message()`]
+    pub fn receive_sync_message(
+        &mut self,
+        sync_state: &mut State,
+        message: Message,
+    ) -> Result<(), AutomergeError> {
+        receive_sync_message(self, sync_state, message, None, |relay, _, changes| {
+            for change in changes.iter() {
+                relay.load_incremental(change)?;
+            }
+            Ok(Vec::new())
+        })
+        .map(|_| ())
+    }
+
+    /// Like [`Self::generate_sync_message()`] but only sends the changes `policy` allows
+    ///
+    /// See [`super::SyncDoc::generate_sync_message_with_policy()`]
+    pub fn generate_sync_message_with_policy(
+        &self,
+        sync_state: &mut State,
+        policy: &dyn SyncPolicy,
+    ) -> Option<Message> {
+        generate_sync_message(
+            self,
+            sync_state,
+            Some(&|change: &Change| policy.allow_outgoing(&ChangeInfo::without_doc(change))),
+            Some(&|r: &RefInfo<'_>| policy.allow_outgoing_ref(r)),
+        )
+    }
+
+    /// Like [`Self::receive_sync_message()`] but only stores the changes `policy` accepts
+    ///
+    /// See [`super::SyncDoc::receive_sync_message_with_policy()`]
+    pub fn receive_sync_message_with_policy(
+        &mut self,
+        sync_state: &mut State,
+        message: Message,
+        policy: &dyn SyncPolicy,
+    ) -> Result<Vec<RejectedChange>, AutomergeError> {
+        let check_ref = |r: &RefInfo<'_>| policy.check_ref(r);
+        receive_sync_message(
+            self,
+            sync_state,
+            message,
+            Some(&check_ref),
+            |relay, sync_state, changes| {
+                apply_changes_with_policy(
+                    relay,
+                    sync_state,
+                    changes,
+                    |_, change| policy.check_incoming(&ChangeInfo::without_doc(change)),
+                    |relay, change| relay.apply_changes([change]),
+                )
+            },
+        )
+    }
+
+    fn add_change(&mut self, change: Change) {
+        let history_index = self.history.len();
+        let next_actor = self.actors.len();
+        let actor_index = *self
+            .actors
+            .entry(change.actor_id().clone())
+            .or_insert(next_actor);
+        self.states
+            .entry(actor_index)
+            .or_default()
+            .push(history_index);
+        for dep in change.deps() {
+            self.heads.remove(dep);
+        }
+        self.heads.insert(change.hash());
+        self.history_index.insert(change.hash(), history_index);
+        self.change_graph
+            .add_change(&change, actor_index)
+            .expect("Change's deps should already be in the relay");
+        self.history.push(change);
+    }
+
+    fn duplicate_seq(&self, change: &Change) -> bool {
+        self.actors
+            .get(change.actor_id())
+            .and_then(|actor_index| self.states.get(actor_index))
+            .map(|changes| changes.len() >= change.seq() as usize)
+            .unwrap_or(false)
+    }
+
+    fn is_causally_ready(&self, change: &Change) -> bool {
+        change
+            .deps()
+            .iter()
+            .all(|d| self.history_index.contains_key(d))
+    }
+
+    fn pop_next_causally_ready_change(&mut self) -> Option<Change> {
+        let index = self
+            .queue
+            .iter()
+            .position(|change| self.is_causally_ready(change))?;
+        Some(self.queue.swap_remove(index))
+    }
+}
+
+impl ChangeStore for Relay {
+    fn heads(&self) -> Vec<ChangeHash> {
+        self.get_heads()
+    }
+
+    fn change_by_hash(&self, hash: &ChangeHash) -> Option<&Change> {
+        self.get_change_by_hash(hash)
+    }
+
+    fn changes_since(&self, heads: &[ChangeHash]) -> Vec<&Change> {
+        self.get_changes(heads)
+    }
+
+    fn missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
+        self.get_missing_deps(heads)
+    }
+
+    fn remove_ancestors(&self, heads: &[ChangeHash], changes: &mut BTreeSet<ChangeHash>) {
+        let heads = heads
+            .iter()
+            .filter(|hash| self.history_index.contains_key(hash))
+            .copied()
+            .collect::<Vec<_>>();
+        self.change_graph.remove_ancestors(changes, &heads);
+    }
+
+    fn encode_document(&self) -> Option<Vec<u8>> {
+        let mut doc = Automerge::new();
+        doc.merge_mark_policies(&self.mark_policies);
+        match doc.apply_changes(self.history.iter().cloned()) {
+            Ok(()) => Some(doc.save()),
+            Err(e) => {
+                tracing::warn!(err=?e, "unable to build a document from the relayed changes");
+                None
+            }
+        }
+    }
+
+    fn refs(&self) -> &Refs {
+        &self.refs
+    }
+
+    fn merge_refs(&mut self, refs: &Refs) {
+        self.refs.merge(refs);
+    }
+
+    fn mark_policies(&self) -> &MarkPolicies {
+        &self.mark_policies
+    }
+
+    fn merge_mark_policies(&mut self, policies: &MarkPolicies) {
+        self.mark_policies.merge(policies);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sync::{RejectReason, SyncDoc},
+        transaction::Transactable,
+        AutoCommit, ReadDoc, ROOT,
+    };
+
+    fn sync_doc(
+        doc: &mut AutoCommit,
+        relay: &mut Relay,
+        doc_state: &mut State,
+        relay_state: &mut State,
+    ) {
+        for _ in 0..10 {
+            let to_relay = doc.sync().generate_sync_message(doc_state);
+            if let Some(message) = to_relay.clone() {
+                relay.receive_sync_message(relay_state, message).unwrap();
+            }
+            let to_doc = relay.generate_sync_message(relay_state);
+            if let Some(message) = to_doc.clone() {
+                doc.sync().receive_sync_message(doc_state, message).unwrap();
+            }
+            if to_relay.is_none() && to_doc.is_none() {
+                return;
+            }
+        }
+        panic!("failed to sync in 10 iterations");
+    }
+
+    #[test]
+    fn relays_changes_between_peers() {
+        let mut relay = Relay::new();
+        let mut alice = AutoCommit::new();
+        let mut bob = AutoCommit::new();
+        let (mut alice_state, mut relay_alice) = (State::new(), State::new());
+        let (mut bob_state, mut relay_bob) = (State::new(), State::new());
+
+        for i in 0..5 {
+            alice.put(ROOT, "alice", i).unwrap();
+            alice.commit();
+            bob.put(ROOT, "bob", i).unwrap();
+            bob.commit();
+        }
+        sync_doc(&mut alice, &mut relay, &mut alice_state, &mut relay_alice);
+        sync_doc(&mut bob, &mut relay, &mut bob_state, &mut relay_bob);
+        sync_doc(&mut alice, &mut relay, &mut alice_state, &mut relay_alice);
+
+        assert_eq!(alice.get_heads(), bob.get_heads());
+        assert_eq!(relay.get_heads(), alice.get_heads());
+        assert_eq!(relay.len(), 10);
+        assert_eq!(bob.get(ROOT, "alice").unwrap().unwrap().0, 4.into());
+
+        // Later changes only send what is new
+        alice.put(ROOT, "alice", "again").unwrap();
+        alice.commit();
+        sync_doc(&mut alice, &mut relay, &mut alice_state, &mut relay_alice);
+        sync_doc(&mut bob, &mut relay, &mut bob_state, &mut relay_bob);
+        assert_eq!(alice.get_heads(), bob.get_heads());
+    }
+
+    #[test]
+    fn receives_whole_documents() {
+        let mut doc = AutoCommit::new();
+        for i in 0..5 {
+            doc.put(ROOT, "key", i).unwrap();
+            doc.commit();
+        }
+        // An empty peer which supports v2 messages is sent the whole document
+        let mut relay = Relay::new();
+        let (mut doc_state, mut relay_state) = (State::new(), State::new());
+        sync_doc(&mut doc, &mut relay, &mut doc_state, &mut relay_state);
+        assert_eq!(relay.get_heads(), doc.get_heads());
+        assert_eq!(relay.len(), 5);
+
+        let mut other = AutoCommit::new();
+        let (mut other_state, mut relay_other) = (State::new(), State::new());
+        sync_doc(&mut other, &mut relay, &mut other_state, &mut relay_other);
+        assert_eq!(other.get_heads(), doc.get_heads());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut doc = AutoCommit::new();
+        doc.put(ROOT, "key", "value").unwrap();
+        doc.commit();
+        let mut relay = Relay::load(&doc.save()).unwrap();
+        assert_eq!(relay.get_heads(), doc.get_heads());
+
+        doc.put(ROOT, "key", "other").unwrap();
+        doc.commit();
+        relay.load_incremental(&doc.save_incremental()).unwrap();
+
+        let loaded = Relay::load(&relay.save()).unwrap();
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+        let reloaded = AutoCommit::load(&relay.save()).unwrap();
+        assert_eq!(
+            reloaded.get(ROOT, "key").unwrap().unwrap().0,
+            "other".into()
+        );
+    }
+
+    #[test]
+    fn holds_changes_until_their_dependencies_arrive() {
+        let mut doc = AutoCommit::new();
+        doc.put(ROOT, "key", 1).unwrap();
+        let first = doc.save_incremental();
+        doc.put(ROOT, "key", 2).unwrap();
+        let second = doc.save_incremental();
+
+        let mut relay = Relay::new();
+        assert_eq!(relay.load_incremental(&second).unwrap(), 0);
+        let missing = relay.get_missing_deps(&[]);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(relay.load_incremental(&first).unwrap(), 2);
+        assert!(relay.get_missing_deps(&[]).is_empty());
+        assert_eq!(relay.get_heads(), doc.get_heads());
+    }
+
+    #[test]
+    fn rejects_queued_changes_with_duplicate_seqs() {
+        let mut doc = AutoCommit::new();
+        doc.put(ROOT, "key", 1).unwrap();
+        doc.commit();
+        let mut fork = doc.fork().with_actor(doc.get_actor().clone());
+        doc.put(ROOT, "key", 2).unwrap();
+        doc.commit();
+        fork.put(ROOT, "key", 3).unwrap();
+        fork.commit();
+        let first = doc.get_changes(&[])[0].clone();
+        let second = doc.get_changes(&[first.hash()])[0].clone();
+        let forked = fork.get_changes(&[first.hash()])[0].clone();
+        assert_eq!(second.seq(), forked.seq());
+
+        let mut relay = Relay::new();
+        relay.apply_changes([second]).unwrap();
+        assert!(matches!(
+            relay.apply_changes([forked, first]),
+            Err(AutomergeError::DuplicateSeqNumber(2, _))
+        ));
+        assert_eq!(relay.len(), 2);
+    }
+
+    struct RejectKey(&'static str);
+
+    impl SyncPolicy for RejectKey {
+        fn check_incoming(&self, change: &ChangeInfo<'_>) -> Result<(), String> {
+            assert!(change.doc().is_none());
+            if change.ops().any(|op| op.key.as_deref() == Some(self.0)) {
+                Err(format!("{} is read only", self.0))
+            } else {
+                Ok(())
+            }
+        }
+
+        fn allow_outgoing(&self, change: &ChangeInfo<'_>) -> bool {
+            change.ops().all(|op| op.key.as_deref() != Some(self.0))
+        }
+    }
+
+    #[test]
+    fn applies_sync_policies() {
+        let mut doc = AutoCommit::new();
+        doc.put(ROOT, "open", 1).unwrap();
+        doc.commit();
+        doc.put(ROOT, "secret", 2).unwrap();
+        doc.commit();
+
+        let policy = RejectKey("secret");
+        let mut relay = Relay::new();
+        let (mut doc_state, mut relay_state) = (State::new(), State::new());
+        let mut rejected = Vec::new();
+        for _ in 0..10 {
+            let to_relay = doc.sync().generate_sync_message(&mut doc_state);
+            if let Some(message) = to_relay.clone() {
+                rejected.extend(
+                    relay
+                        .receive_sync_message_with_policy(&mut relay_state, message, &policy)
+                        .unwrap(),
+                );
+            }
+            let to_doc = relay.generate_sync_message_with_policy(&mut relay_state, &policy);
+            if let Some(message) = to_doc.clone() {
+                doc.sync()
+                    .receive_sync_message(&mut doc_state, message)
+                    .unwrap();
+            }
+            if to_relay.is_none() && to_doc.is_none() {
+                break;
+            }
+        }
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(relay.len(), 1);
+        assert!(matches!(rejected[0].reason, RejectReason::Policy(_)));
+
+        // Changes the relay already has are withheld from other peers
+        let mut relay = Relay::load(&doc.save()).unwrap();
+        let mut other = AutoCommit::new();
+        let (mut other_state, mut relay_other) = (State::new(), State::new());
+        for _ in 0..10 {
+            let to_relay = other.sync().generate_sync_message(&mut other_state);
+            if let Some(message) = to_relay.clone() {
+                relay
+                    .receive_sync_message_with_policy(&mut relay_other, message, &policy)
+                    .unwrap();
+            }
+            let to_other = relay.generate_sync_message_with_policy(&mut relay_other, &policy);
+            if let Some(message) = to_other.clone() {
+                other
+                    .sync()
+                    .receive_sync_message(&mut other_state, message)
+                    .unwrap();
+            }
+            if to_relay.is_none() && to_other.is_none() {
+                break;
+            }
+        }
+        assert_eq!(other.get(ROOT, "open").unwrap().unwrap().0, 1.into());
+        assert!(other.get(ROOT, "secret").unwrap().is_none());
+    }
+
+    #[test]
+    fn sends_whole_documents() {
+        let mut doc = AutoCommit::new();
+        for i in 0..5 {
+            doc.put(ROOT, "key", i).unwrap();
+            doc.commit();
+        }
+        let relay = Relay::load(&doc.save()).unwrap();
+        let mut state = State::new();
+        // An empty peer which supports v2 messages
+        let mut empty = AutoCommit::new();
+        let mut empty_state = State::new();
+        let message = empty
+            .sync()
+            .generate_sync_message(&mut empty_state)
+            .unwrap();
+        let mut relay_copy = relay.clone();
+        relay_copy
+            .receive_sync_message(&mut state, message)
+            .unwrap();
+        let reply = relay_copy.generate_sync_message(&mut state).unwrap();
+        assert_eq!(reply.changes.len(), 1);
+        empty
+            .sync()
+            .receive_sync_message(&mut empty_state, reply)
+            .unwrap();
+        assert_eq!(empty.get_heads(), doc.get_heads());
+    }
+}
@@ -2,12 +2,19 @@ use std::collections::BTreeSet;
 
 #[cfg(doc)]
 use super::SyncDoc;
-use super::{encode_hashes, BloomFilter, Capability};
+use super::{encode_hashes, encode_many, BloomFilter, BloomParams, Capability};
+use crate::marks::MarkPolicies;
+use crate::refs::Refs;
 use crate::storage::parse;
 use crate::ChangeHash;
 
 const SYNC_STATE_TYPE: u8 = 0x43; // first byte of an encoded sync state, for identification
 
+// The version of the data following the shared heads in an encoded sync state. Originally only the
+// shared heads were encoded. Implementations from before then stop reading after the shared heads
+// so they can still decode newer states, but we reject versions we don't know.
+const RESUMABLE_STATE_VERSION: u8 = 1;
+
 #[derive(Debug, thiserror::Error)]
 pub enum DecodeError {
     #[error("{0:?}")]
@@ -16,6 +23,8 @@ pub enum DecodeError {
     WrongType { expected_one_of: Vec<u8>, found: u8 },
     #[error("not enough input")]
     NotEnoughInput,
+    #[error("unknown sync state version: {0}")]
+    UnknownVersion(u8),
 }
 
 impl From<parse::leb128::Error> for DecodeError {
@@ -24,11 +33,26 @@ impl From<parse::leb128::Error> for DecodeError {
     }
 }
 
+impl From<parse::InvalidUtf8> for DecodeError {
+    fn from(_: parse::InvalidUtf8) -> Self {
+        Self::Parse("invalid utf8".to_string())
+    }
+}
+
 /// The state of synchronisation with a peer.
 ///
 /// This should be persisted using [`Self::encode()`] when you know you will be interacting with the
 /// same peer in multiple sessions. [`Self::encode()`] only encodes state which should be reused
 /// across connections.
+///
+/// ## Resuming
+///
+/// Along with the shared heads the encoded state records the heads and capabilities the other end
+/// last told us about. If resuming is enabled with [`Self::with_resume()`] when a decoded state is
+/// used to generate the first message of a new session (see [`Self::resume_heads`]), and we still
+/// have every change the other end had, that message contains all the changes they are missing
+/// and our exact heads in place of a bloom filter. The other end can then send us exactly what we
+/// are missing in its reply, which saves a round trip and the bloom filters.
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct State {
     /// The hashes which we know both peers have
@@ -60,6 +84,47 @@ pub struct State {
 
     /// The capabilities the other side has said they have
     pub their_capabilities: Option<Vec<Capability>>,
+
+    /// The parameters to use for the bloom filters we send to the other end.
+    ///
+    /// If this is set it is also advertised to the other end (via [`Capability::BloomParams`]) in
+    /// our first message. If it is [`None`] we use whatever the other end advertised, or the
+    /// default parameters if they didn't advertise any.
+    pub bloom_params: Option<BloomParams>,
+
+    /// Whether to increase the precision of our bloom filters when we observe false positives.
+    ///
+    /// A false positive shows up as changes the other end sent us which depend on changes they
+    /// didn't send, because our bloom filter claimed we already had them. When this is `true`
+    /// each such observation moves [`Self::bloom_params`] to [`BloomParams::more_precise()`].
+    pub adaptive_bloom: bool,
+
+    /// The changes the other end sent us which we rejected, see [`super::SyncPolicy`]
+    pub rejected_hashes: BTreeSet<ChangeHash>,
+    /// Rejected changes we have not yet told the other end about
+    pub pending_rejections: Vec<ChangeHash>,
+    /// The changes we sent which the other end has told us it rejected. These, and anything which
+    /// depends on them, are not offered to the other end again.
+    pub their_rejected: BTreeSet<ChangeHash>,
+
+    /// The heads the other end had when this state was encoded, if they were known
+    ///
+    /// This is set by [`Self::decode()`] and, if [`Self::resume`] is set, consumed by the first
+    /// call to [`SyncDoc::generate_sync_message()`], which uses it to resume the session. If the
+    /// other end has since lost changes we sent it the resumption fails and we fall back to a full
+    /// resync.
+    pub resume_heads: Option<Vec<ChangeHash>>,
+
+    /// Whether to resume the session from [`Self::resume_heads`], see [`Self::with_resume()`].
+    /// This is not persisted.
+    pub resume: bool,
+
+    /// The refs we know the other end has, see [`crate::refs`]. We send our refs whenever they
+    /// contain something newer than these.
+    pub their_refs: Refs,
+
+    /// The mark policies we know the other end has, see [`MarkPolicies`]
+    pub their_mark_policies: MarkPolicies,
 }
 
 /// A summary of the changes that the sender of the message already has.
@@ -82,6 +147,36 @@ impl State {
     pub fn encode(&self) -> Vec<u8> {
         let mut buf = vec![SYNC_STATE_TYPE];
         encode_hashes(&mut buf, &self.shared_heads);
+
+        buf.push(RESUMABLE_STATE_VERSION);
+        // If we haven't heard from the other end since this state was decoded then the heads they
+        // had last time are still the best we know
+        let their_heads = self.their_heads.as_ref().or(self.resume_heads.as_ref());
+        encode_optional(&mut buf, their_heads, |buf, heads| {
+            let mut heads = heads.clone();
+            heads.sort();
+            encode_hashes(buf, &heads)
+        });
+        encode_optional(&mut buf, self.their_capabilities.as_ref(), |buf, caps| {
+            encode_many(buf, caps.iter(), |buf, cap| cap.encode(buf))
+        });
+        encode_optional(&mut buf, self.bloom_params.as_ref(), |buf, params| {
+            params.encode(buf)
+        });
+        buf.push(u8::from(self.adaptive_bloom));
+        encode_hashes(
+            &mut buf,
+            &self.rejected_hashes.iter().copied().collect::<Vec<_>>(),
+        );
+        encode_hashes(
+            &mut buf,
+            &self.their_rejected.iter().copied().collect::<Vec<_>>(),
+        );
+        let mut pending_rejections = self.pending_rejections.clone();
+        pending_rejections.sort();
+        encode_hashes(&mut buf, &pending_rejections);
+        self.their_refs.encode(&mut buf);
+        self.their_mark_policies.encode(&mut buf);
         buf
     }
 
@@ -104,20 +199,106 @@ impl State {
         }
 
         let (i, shared_heads) = parse::length_prefixed(parse::change_hash)(i)?;
-        Ok((
-            i,
-            Self {
-                shared_heads,
-                last_sent_heads: Vec::new(),
-                their_heads: None,
-                their_need: None,
-                their_have: Some(Vec::new()),
-                sent_hashes: BTreeSet::new(),
-                in_flight: false,
-                have_responded: false,
-                their_capabilities: None,
-            },
-        ))
+        let mut state = Self {
+            shared_heads,
+            last_sent_heads: Vec::new(),
+            their_heads: None,
+            their_need: None,
+            their_have: Some(Vec::new()),
+            sent_hashes: BTreeSet::new(),
+            in_flight: false,
+            have_responded: false,
+            their_capabilities: None,
+            bloom_params: None,
+            adaptive_bloom: false,
+            rejected_hashes: BTreeSet::new(),
+            pending_rejections: Vec::new(),
+            their_rejected: BTreeSet::new(),
+            resume_heads: None,
+            resume: false,
+            their_refs: Refs::default(),
+            their_mark_policies: MarkPolicies::default(),
+        };
+        if i.is_empty() {
+            return Ok((i, state));
+        }
+
+        let (i, version) = parse::take1(i)?;
+        if version != RESUMABLE_STATE_VERSION {
+            return Err(parse::ParseError::Error(DecodeError::UnknownVersion(
+                version,
+            )));
+        }
+        let (i, resume_heads) = parse_optional(i, parse::length_prefixed(parse::change_hash))?;
+        let (i, their_capabilities) = parse_optional(i, parse::length_prefixed(Capability::parse))?;
+        let (i, bloom_params) = parse_optional(i, BloomParams::parse)?;
+        let (i, adaptive_bloom) = parse::take1(i)?;
+        let (i, rejected_hashes) = parse::length_prefixed(parse::change_hash)(i)?;
+        let (i, their_rejected) = parse::length_prefixed(parse::change_hash)(i)?;
+        let (i, pending_rejections) = parse::length_prefixed(parse::change_hash)(i)?;
+        let (i, their_refs) = Refs::parse(i)?;
+        let (i, their_mark_policies) = MarkPolicies::parse(i)?;
+        state.resume_heads = resume_heads;
+        state.their_capabilities = their_capabilities;
+        state.bloom_params = bloom_params;
+        state.adaptive_bloom = adaptive_bloom != 0;
+        state.rejected_hashes = rejected_hashes.into_iter().collect();
+        state.their_rejected = their_rejected.into_iter().collect();
+        state.pending_rejections = pending_rejections;
+        state.their_refs = their_refs;
+        state.their_mark_policies = their_mark_policies;
+        Ok((i, state))
+    }
+
+    /// Use `params` for the bloom filters we send, see [`Self::bloom_params`]
+    pub fn with_bloom_params(mut self, params: BloomParams) -> Self {
+        self.bloom_params = Some(params);
+        self
+    }
+
+    /// Resume the session from the heads the other end had when this state was encoded, see
+    /// [`Self::resume`]
+    pub fn with_resume(mut self) -> Self {
+        self.resume = true;
+        self
+    }
+
+    /// Enable or disable adaptive bloom filter sizing, see [`Self::adaptive_bloom`]
+    pub fn with_adaptive_bloom(mut self, adaptive: bool) -> Self {
+        self.adaptive_bloom = adaptive;
+        self
+    }
+
+    /// The parameters we will use for the next bloom filter we send
+    pub(crate) fn effective_bloom_params(&self) -> BloomParams {
+        self.bloom_params
+            .or_else(|| self.their_bloom_params())
+            .unwrap_or_default()
+    }
+
+    fn their_bloom_params(&self) -> Option<BloomParams> {
+        self.their_capabilities.as_ref().and_then(|caps| {
+            caps.iter().find_map(|c| match c {
+                Capability::BloomParams(p) => Some(*p),
+                _ => None,
+            })
+        })
+    }
+
+    /// Record that the bloom filter we last sent produced false positives
+    pub(crate) fn observed_false_positives(&mut self) {
+        if self.adaptive_bloom {
+            self.bloom_params = Some(self.effective_bloom_params().more_precise());
+        }
+    }
+
+    /// The capabilities we advertise to the other end
+    pub(crate) fn our_capabilities(&self) -> Vec<Capability> {
+        let mut caps = vec![Capability::MessageV1, Capability::MessageV2];
+        if let Some(params) = self.bloom_params {
+            caps.push(Capability::BloomParams(params));
+        }
+        caps
     }
 
     pub(crate) fn supports_v2_messages(&self) -> bool {
@@ -127,3 +308,32 @@ impl State {
             .unwrap_or(false)
     }
 }
+
+fn encode_optional<T, F>(out: &mut Vec<u8>, value: Option<&T>, f: F)
+where
+    F: FnOnce(&mut Vec<u8>, &T),
+{
+    match value {
+        Some(value) => {
+            out.push(1);
+            f(out, value);
+        }
+        None => out.push(0),
+    }
+}
+
+fn parse_optional<'a, O, P>(
+    input: parse::Input<'a>,
+    mut parser: P,
+) -> parse::ParseResult<'a, Option<O>, DecodeError>
+where
+    P: FnMut(parse::Input<'a>) -> parse::ParseResult<'a, O, DecodeError>,
+{
+    let (i, present) = parse::take1(input)?;
+    if present == 0 {
+        Ok((i, None))
+    } else {
+        let (i, value) = parser(i)?;
+        Ok((i, Some(value)))
+    }
+}
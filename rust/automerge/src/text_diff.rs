@@ -1,52 +1,243 @@
+use std::cell::Cell;
+use std::ops::Range;
+
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     text_value::TextValue, transaction::TransactionInner, Automerge, ObjId as ExId, PatchLog,
     ReadDoc,
 };
+mod histogram;
 mod myers;
+mod patience;
 mod utils;
 
-pub(crate) fn myers_diff<'a, S: AsRef<str>>(
+/// The algorithm [`crate::transaction::Transactable::update_text_with_options()`] uses to find
+/// the differences between the old and new text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    /// Myers' algorithm, which finds the smallest diff but takes time proportional to the size
+    /// of the text multiplied by the size of the diff, so can be slow for large rewrites
+    Myers,
+    /// Patience diff, which first matches up elements occurring exactly once in both texts. This
+    /// is fast for large rewrites and produces diffs which follow the structure of the text.
+    Patience,
+    /// Histogram diff, an extension of patience diff which also uses elements occurring more
+    /// than once, preferring the rarest
+    Histogram,
+}
+
+/// The units [`crate::transaction::Transactable::update_text_with_options()`] diffs the text in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffGranularity {
+    /// Diff grapheme clusters
+    Grapheme,
+    /// Diff words and the whitespace and punctuation between them. A changed word is replaced
+    /// as a whole, which interleaves less with concurrent edits.
+    Word,
+    /// Diff lines, then diff the grapheme clusters of changed lines. Unchanged lines are left
+    /// untouched even when the lines around them change.
+    LineThenGrapheme,
+}
+
+/// Options for [`crate::transaction::Transactable::update_text_with_options()`]
+///
+/// The default is a [`DiffAlgorithm::Myers`] diff of grapheme clusters with no cost limit, which
+/// is what [`crate::transaction::Transactable::update_text()`] does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UpdateTextOptions {
+    algorithm: DiffAlgorithm,
+    granularity: DiffGranularity,
+    max_cost: Option<usize>,
+}
+
+impl Default for UpdateTextOptions {
+    fn default() -> Self {
+        UpdateTextOptions {
+            algorithm: DiffAlgorithm::Myers,
+            granularity: DiffGranularity::Grapheme,
+            max_cost: None,
+        }
+    }
+}
+
+impl UpdateTextOptions {
+    pub fn new(algorithm: DiffAlgorithm, granularity: DiffGranularity) -> Self {
+        UpdateTextOptions {
+            algorithm,
+            granularity,
+            max_cost: None,
+        }
+    }
+
+    /// Limit how much work the diff does
+    ///
+    /// The cost is roughly the number of comparisons between elements of the old and new text.
+    /// Once the limit is reached the rest of the text is diffed without looking for matches
+    /// inside changed regions, i.e. each changed region is replaced as a whole (a changed line,
+    /// for [`DiffGranularity::LineThenGrapheme`]). The result is a correct but larger edit.
+    pub fn with_max_cost(mut self, max_cost: usize) -> Self {
+        self.max_cost = Some(max_cost);
+        self
+    }
+
+    pub fn algorithm(&self) -> DiffAlgorithm {
+        self.algorithm
+    }
+
+    pub fn granularity(&self) -> DiffGranularity {
+        self.granularity
+    }
+
+    pub fn max_cost(&self) -> Option<usize> {
+        self.max_cost
+    }
+}
+
+/// How much work a diff can still do
+///
+/// This is shared between the line and grapheme diffs of [`DiffGranularity::LineThenGrapheme`],
+/// hence the `Cell`.
+#[derive(Debug)]
+struct Budget(Cell<Option<usize>>);
+
+impl Budget {
+    fn new(max_cost: Option<usize>) -> Self {
+        Budget(Cell::new(max_cost))
+    }
+
+    fn unlimited() -> Self {
+        Budget::new(None)
+    }
+
+    /// Spend `cost`, returning false if there wasn't enough left
+    fn spend(&self, cost: usize) -> bool {
+        match self.0.get() {
+            None => true,
+            Some(remaining) if remaining >= cost => {
+                self.0.set(Some(remaining - cost));
+                true
+            }
+            Some(_) => {
+                self.0.set(Some(0));
+                false
+            }
+        }
+    }
+}
+
+pub(crate) fn update_text<'a, S: AsRef<str>>(
     doc: &'a mut Automerge,
     tx: &'a mut TransactionInner,
     patch_log: &mut PatchLog,
     text_obj: &ExId,
     new: S,
+    options: UpdateTextOptions,
 ) -> Result<(), crate::AutomergeError> {
     let old = doc.text(text_obj)?;
     let new = new.as_ref();
-    let old_graphemes = old.graphemes(true).collect::<Vec<&str>>();
-    let new_graphemes = new.graphemes(true).collect::<Vec<&str>>();
+    let budget = Budget::new(options.max_cost);
     let mut hook = TxHook {
         tx,
         doc,
         patch_log,
         obj: text_obj,
         idx: 0,
-        old: &old_graphemes,
-        new: &new_graphemes,
     };
-    myers::diff(
-        &mut hook,
-        &old_graphemes,
-        0..old_graphemes.len(),
-        &new_graphemes,
-        0..new_graphemes.len(),
-    )
+    match options.granularity {
+        DiffGranularity::Grapheme => {
+            let old = old.graphemes(true).collect::<Vec<_>>();
+            let new = new.graphemes(true).collect::<Vec<_>>();
+            let mut tokens = Tokens::new(&mut hook, &old, &new);
+            diff(options.algorithm, &mut tokens, &old, &new, &budget)
+        }
+        DiffGranularity::Word => {
+            let old = old.split_word_bounds().collect::<Vec<_>>();
+            let new = new.split_word_bounds().collect::<Vec<_>>();
+            let mut tokens = Tokens::new(&mut hook, &old, &new);
+            diff(options.algorithm, &mut tokens, &old, &new, &budget)
+        }
+        DiffGranularity::LineThenGrapheme => {
+            let old_lines = old.split_inclusive('\n').collect::<Vec<_>>();
+            let new_lines = new.split_inclusive('\n').collect::<Vec<_>>();
+            let mut lines = Lines {
+                hook: &mut hook,
+                old: &old_lines,
+                new: &new_lines,
+                algorithm: options.algorithm,
+                budget: &budget,
+                pending: None,
+            };
+            diff(
+                options.algorithm,
+                &mut lines,
+                &old_lines,
+                &new_lines,
+                &budget,
+            )
+        }
+    }
+}
+
+fn diff<D: myers::DiffHook>(
+    algorithm: DiffAlgorithm,
+    d: &mut D,
+    old: &[&str],
+    new: &[&str],
+    budget: &Budget,
+) -> Result<(), D::Error> {
+    let (old_range, new_range) = (0..old.len(), 0..new.len());
+    match algorithm {
+        DiffAlgorithm::Myers => myers::diff(d, old, old_range, new, new_range, budget),
+        DiffAlgorithm::Patience => patience::diff(d, old, old_range, new, new_range, budget),
+        DiffAlgorithm::Histogram => histogram::diff(d, old, old_range, new, new_range, budget),
+    }
 }
 
+/// Splices the result of a diff into the text object, keeping track of where we are in it
 struct TxHook<'a> {
     doc: &'a mut Automerge,
     tx: &'a mut TransactionInner,
     patch_log: &'a mut PatchLog,
-    old: &'a [&'a str],
-    new: &'a [&'a str],
     obj: &'a ExId,
     idx: usize,
 }
 
-impl<'a> myers::DiffHook for TxHook<'a> {
+impl<'a> TxHook<'a> {
+    fn skip(&mut self, old: &[&str]) {
+        self.idx += old.iter().map(|s| TextValue::width(s)).sum::<usize>();
+    }
+
+    fn replace(&mut self, old: &[&str], new: &[&str]) -> Result<(), crate::AutomergeError> {
+        let deleted = old.iter().map(|s| TextValue::width(s)).sum::<usize>();
+        let new_chars = new.concat();
+        self.tx.splice_text(
+            self.doc,
+            self.patch_log,
+            self.obj,
+            self.idx,
+            deleted as isize,
+            &new_chars,
+        )?;
+        self.idx += TextValue::width(&new_chars);
+        Ok(())
+    }
+}
+
+/// Applies a diff of `old` and `new` to the text object
+struct Tokens<'a, 'b> {
+    hook: &'b mut TxHook<'a>,
+    old: &'b [&'b str],
+    new: &'b [&'b str],
+}
+
+impl<'a, 'b> Tokens<'a, 'b> {
+    fn new(hook: &'b mut TxHook<'a>, old: &'b [&'b str], new: &'b [&'b str]) -> Self {
+        Tokens { hook, old, new }
+    }
+}
+
+impl<'a, 'b> myers::DiffHook for Tokens<'a, 'b> {
     type Error = crate::AutomergeError;
 
     fn equal(
@@ -55,10 +246,7 @@ impl<'a> myers::DiffHook for TxHook<'a> {
         _new_index: usize,
         len: usize,
     ) -> Result<(), Self::Error> {
-        self.idx += self.old[old_index..old_index + len]
-            .iter()
-            .map(|c| TextValue::width(c))
-            .sum::<usize>();
+        self.hook.skip(&self.old[old_index..old_index + len]);
         Ok(())
     }
 
@@ -69,21 +257,10 @@ impl<'a> myers::DiffHook for TxHook<'a> {
         new_index: usize,
         new_len: usize,
     ) -> Result<(), Self::Error> {
-        let new_chars = self.new[new_index..new_index + new_len].concat();
-        let deleted = self.old[old_index..old_index + old_len]
-            .iter()
-            .map(|s| TextValue::width(s))
-            .sum::<usize>();
-        self.tx.splice_text(
-            self.doc,
-            self.patch_log,
-            self.obj,
-            self.idx,
-            deleted as isize,
-            &new_chars,
-        )?;
-        self.idx += TextValue::width(&new_chars);
-        Ok(())
+        self.hook.replace(
+            &self.old[old_index..old_index + old_len],
+            &self.new[new_index..new_index + new_len],
+        )
     }
 
     fn finish(&mut self) -> Result<(), Self::Error> {
@@ -96,19 +273,8 @@ impl<'a> myers::DiffHook for TxHook<'a> {
         old_len: usize,
         _new_index: usize,
     ) -> Result<(), Self::Error> {
-        let deleted_len: usize = self.old[old_index..old_index + old_len]
-            .iter()
-            .map(|s| TextValue::width(s))
-            .sum();
-        self.tx.splice_text(
-            self.doc,
-            self.patch_log,
-            self.obj,
-            self.idx,
-            deleted_len as isize,
-            "",
-        )?;
-        Ok(())
+        self.hook
+            .replace(&self.old[old_index..old_index + old_len], &[])
     }
 
     fn insert(
@@ -117,10 +283,194 @@ impl<'a> myers::DiffHook for TxHook<'a> {
         new_index: usize,
         new_len: usize,
     ) -> Result<(), Self::Error> {
-        let new_chars = self.new[new_index..new_index + new_len].concat();
-        self.tx
-            .splice_text(self.doc, self.patch_log, self.obj, self.idx, 0, &new_chars)?;
-        self.idx += TextValue::width(&new_chars);
+        self.hook
+            .replace(&[], &self.new[new_index..new_index + new_len])
+    }
+}
+
+/// Applies a diff of the lines `old` and `new` to the text object, diffing the grapheme clusters
+/// of lines which were replaced
+struct Lines<'a, 'b> {
+    hook: &'b mut TxHook<'a>,
+    old: &'b [&'b str],
+    new: &'b [&'b str],
+    algorithm: DiffAlgorithm,
+    budget: &'b Budget,
+    // the lines deleted and inserted since the last unchanged line, Myers' algorithm reports a
+    // replacement as a deletion followed by an insertion so we collect them up here
+    pending: Option<(Range<usize>, Range<usize>)>,
+}
+
+impl<'a, 'b> Lines<'a, 'b> {
+    fn diff_graphemes(
+        &mut self,
+        old: Range<usize>,
+        new: Range<usize>,
+    ) -> Result<(), crate::AutomergeError> {
+        let old = self.old[old].concat();
+        let new = self.new[new].concat();
+        let old = old.graphemes(true).collect::<Vec<_>>();
+        let new = new.graphemes(true).collect::<Vec<_>>();
+        let mut tokens = Tokens::new(self.hook, &old, &new);
+        diff(self.algorithm, &mut tokens, &old, &new, self.budget)
+    }
+
+    fn change(&mut self, old: Range<usize>, new: Range<usize>) {
+        self.pending = match self.pending.take() {
+            Some((pending_old, pending_new)) => Some((
+                pending_old.start..pending_old.end.max(old.end),
+                pending_new.start..pending_new.end.max(new.end),
+            )),
+            None => Some((old, new)),
+        };
+    }
+
+    fn flush(&mut self) -> Result<(), crate::AutomergeError> {
+        match self.pending.take() {
+            Some((old, new)) if old.is_empty() || new.is_empty() => {
+                self.hook.replace(&self.old[old], &self.new[new])
+            }
+            Some((old, new)) => self.diff_graphemes(old, new),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, 'b> myers::DiffHook for Lines<'a, 'b> {
+    type Error = crate::AutomergeError;
+
+    fn equal(
+        &mut self,
+        old_index: usize,
+        _new_index: usize,
+        len: usize,
+    ) -> Result<(), Self::Error> {
+        self.flush()?;
+        self.hook.skip(&self.old[old_index..old_index + len]);
+        Ok(())
+    }
+
+    fn replace(
+        &mut self,
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+        new_len: usize,
+    ) -> Result<(), Self::Error> {
+        self.change(
+            old_index..old_index + old_len,
+            new_index..new_index + new_len,
+        );
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+
+    fn delete(
+        &mut self,
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+    ) -> Result<(), Self::Error> {
+        self.change(old_index..old_index + old_len, new_index..new_index);
+        Ok(())
+    }
+
+    fn insert(
+        &mut self,
+        old_index: usize,
+        new_index: usize,
+        new_len: usize,
+    ) -> Result<(), Self::Error> {
+        self.change(old_index..old_index, new_index..new_index + new_len);
+        Ok(())
+    }
+}
+
+/// A run of elements in a diff of two sequences, returned by [`diff_sequences()`]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Run {
+    /// The elements of the old sequence in the first range are the same as the elements of the
+    /// new sequence in the second
+    Equal(Range<usize>, Range<usize>),
+    /// The elements of the old sequence in the first range were replaced by the elements of the
+    /// new sequence in the second. Either range may be empty.
+    Changed(Range<usize>, Range<usize>),
+}
+
+/// A Myers diff of `old` and `new`, with adjacent deletions and insertions combined
+pub(crate) fn diff_sequences<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Run> {
+    let mut runs = Runs(Vec::new());
+    let budget = Budget::unlimited();
+    match myers::diff(&mut runs, old, 0..old.len(), new, 0..new.len(), &budget) {
+        Ok(()) => runs.0,
+        Err(never) => match never {},
+    }
+}
+
+struct Runs(Vec<Run>);
+
+impl Runs {
+    fn change(&mut self, old: Range<usize>, new: Range<usize>) {
+        if let Some(Run::Changed(last_old, last_new)) = self.0.last_mut() {
+            if last_old.end == old.start && last_new.end == new.start {
+                last_old.end = old.end;
+                last_new.end = new.end;
+                return;
+            }
+        }
+        self.0.push(Run::Changed(old, new));
+    }
+}
+
+impl myers::DiffHook for Runs {
+    type Error = std::convert::Infallible;
+
+    fn equal(&mut self, old_index: usize, new_index: usize, len: usize) -> Result<(), Self::Error> {
+        self.0.push(Run::Equal(
+            old_index..old_index + len,
+            new_index..new_index + len,
+        ));
+        Ok(())
+    }
+
+    fn delete(
+        &mut self,
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+    ) -> Result<(), Self::Error> {
+        self.change(old_index..old_index + old_len, new_index..new_index);
+        Ok(())
+    }
+
+    fn insert(
+        &mut self,
+        old_index: usize,
+        new_index: usize,
+        new_len: usize,
+    ) -> Result<(), Self::Error> {
+        self.change(old_index..old_index, new_index..new_index + new_len);
+        Ok(())
+    }
+
+    fn replace(
+        &mut self,
+        old_index: usize,
+        old_len: usize,
+        new_index: usize,
+        new_len: usize,
+    ) -> Result<(), Self::Error> {
+        self.change(
+            old_index..old_index + old_len,
+            new_index..new_index + new_len,
+        );
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
 }
@@ -0,0 +1,208 @@
+//! Histogram diff
+//!
+//! An extension of patience diff which also handles elements occurring more than once. The
+//! longest run of matching elements whose elements are the rarest in the old sequence is matched
+//! up and the ranges either side of it are diffed recursively. Ranges where every common element
+//! is very frequent are diffed with Myers' algorithm.
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::myers::{self, DiffHook};
+use super::utils::{common_prefix_len, common_suffix_len, is_empty_range};
+use super::Budget;
+
+/// Elements which occur more often than this in the old range are not used to find matches
+const MAX_OCCURRENCES: usize = 64;
+
+pub(super) fn diff<D: DiffHook>(
+    d: &mut D,
+    old: &[&str],
+    old_range: Range<usize>,
+    new: &[&str],
+    new_range: Range<usize>,
+    budget: &Budget,
+) -> Result<(), D::Error> {
+    conquer(d, old, old_range, new, new_range, budget)?;
+    d.finish()
+}
+
+fn conquer<D: DiffHook>(
+    d: &mut D,
+    old: &[&str],
+    mut old_range: Range<usize>,
+    new: &[&str],
+    mut new_range: Range<usize>,
+    budget: &Budget,
+) -> Result<(), D::Error> {
+    let prefix = common_prefix_len(old, old_range.clone(), new, new_range.clone());
+    if prefix > 0 {
+        d.equal(old_range.start, new_range.start, prefix)?;
+    }
+    old_range.start += prefix;
+    new_range.start += prefix;
+
+    let suffix = common_suffix_len(old, old_range.clone(), new, new_range.clone());
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if is_empty_range(&old_range) && is_empty_range(&new_range) {
+        // Do nothing
+    } else if is_empty_range(&new_range) {
+        d.delete(old_range.start, old_range.len(), new_range.start)?;
+    } else if is_empty_range(&old_range) {
+        d.insert(old_range.start, new_range.start, new_range.len())?;
+    } else if !budget.spend(old_range.len() + new_range.len()) {
+        d.replace(
+            old_range.start,
+            old_range.len(),
+            new_range.start,
+            new_range.len(),
+        )?;
+    } else {
+        match find_match(old, old_range.clone(), new, new_range.clone(), budget) {
+            Match::Found {
+                old_index,
+                new_index,
+                len,
+            } => {
+                conquer(
+                    d,
+                    old,
+                    old_range.start..old_index,
+                    new,
+                    new_range.start..new_index,
+                    budget,
+                )?;
+                d.equal(old_index, new_index, len)?;
+                conquer(
+                    d,
+                    old,
+                    old_index + len..old_range.end,
+                    new,
+                    new_index + len..new_range.end,
+                    budget,
+                )?;
+            }
+            Match::TooFrequent => {
+                myers::diff_range(d, old, old_range.clone(), new, new_range.clone(), budget)?;
+            }
+            Match::None => {
+                d.replace(
+                    old_range.start,
+                    old_range.len(),
+                    new_range.start,
+                    new_range.len(),
+                )?;
+            }
+        }
+    }
+
+    if suffix > 0 {
+        d.equal(old_range.end, new_range.end, suffix)?;
+    }
+    Ok(())
+}
+
+enum Match {
+    Found {
+        old_index: usize,
+        new_index: usize,
+        len: usize,
+    },
+    /// There are common elements but they all occur too often to be useful
+    TooFrequent,
+    /// There are no common elements
+    None,
+}
+
+fn find_match(
+    old: &[&str],
+    old_range: Range<usize>,
+    new: &[&str],
+    new_range: Range<usize>,
+    budget: &Budget,
+) -> Match {
+    let mut occurrences: HashMap<&str, Vec<usize>> = HashMap::new();
+    for i in old_range.clone() {
+        occurrences.entry(old[i]).or_default().push(i);
+    }
+
+    // the best match so far as (rarity, old index, new index, length)
+    let mut best: Option<(usize, usize, usize, usize)> = None;
+    let mut too_frequent = false;
+    let mut j = new_range.start;
+    while j < new_range.end {
+        let positions = match occurrences.get(new[j]) {
+            Some(positions) => positions,
+            None => {
+                j += 1;
+                continue;
+            }
+        };
+        if positions.len() > MAX_OCCURRENCES {
+            too_frequent = true;
+            j += 1;
+            continue;
+        }
+        if !budget.spend(positions.len()) {
+            break;
+        }
+        let mut next_j = j + 1;
+        for &i in positions {
+            // extend the match in both directions as far as it goes
+            let mut start = 0;
+            while i - start > old_range.start
+                && j - start > new_range.start
+                && old[i - start - 1] == new[j - start - 1]
+            {
+                start += 1;
+            }
+            let mut end = 1;
+            while i + end < old_range.end && j + end < new_range.end && old[i + end] == new[j + end]
+            {
+                end += 1;
+            }
+            let (old_index, new_index, len) = (i - start, j - start, start + end);
+            budget.spend(len);
+            let rarity = (old_index..old_index + len)
+                .map(|k| occurrences.get(old[k]).map(Vec::len).unwrap_or(0))
+                .min()
+                .unwrap_or(0);
+            let better = match best {
+                None => true,
+                Some((best_rarity, _, _, best_len)) => {
+                    rarity < best_rarity || (rarity == best_rarity && len > best_len)
+                }
+            };
+            if better {
+                best = Some((rarity, old_index, new_index, len));
+                next_j = next_j.max(new_index + len);
+            }
+        }
+        j = next_j;
+    }
+
+    match best {
+        Some((_, old_index, new_index, len)) => Match::Found {
+            old_index,
+            new_index,
+            len,
+        },
+        None if too_frequent => Match::TooFrequent,
+        None => Match::None,
+    }
+}
+
+#[test]
+fn test_find_match_prefers_rare_elements() {
+    let old = ["a", "x", "a", "b", "c", "a"];
+    let new = ["a", "b", "c", "y", "a"];
+    match find_match(&old, 0..6, &new, 0..5, &Budget::unlimited()) {
+        Match::Found {
+            old_index,
+            new_index,
+            len,
+        } => assert_eq!((old_index, new_index, len), (2, 0, 3)),
+        _ => panic!("expected a match"),
+    }
+}
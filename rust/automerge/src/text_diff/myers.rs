@@ -3,7 +3,8 @@
 // The original license is in the LICENSE file in the same directory as this file
 //
 // This file was modified to use a Diff trait defined in this file rather than the DiffHook trait
-// defined in `similar` and to remote the deadline parameter to the `diff` function.
+// defined in `similar` and to replace the deadline parameter to the `diff` function with a
+// `Budget`.
 //! Myers' diff algorithm.
 //!
 //! * time: `O((N+M)D)`
@@ -25,6 +26,7 @@
 use std::ops::{Index, IndexMut, Range};
 
 use super::utils::{common_prefix_len, common_suffix_len, is_empty_range};
+use super::Budget;
 
 pub(super) trait DiffHook: Sized {
     type Error;
@@ -53,13 +55,34 @@ pub(super) trait DiffHook: Sized {
 
 /// Myers' diff algorithm.
 ///
-/// Diff `old`, between indices `old_range` and `new` between indices `new_range`.
+/// Diff `old`, between indices `old_range` and `new` between indices `new_range`. Once `budget`
+/// runs out the remaining differences are reported as a deletion followed by an insertion.
 pub(super) fn diff<Old, New, D>(
     d: &mut D,
     old: &Old,
     old_range: Range<usize>,
     new: &New,
     new_range: Range<usize>,
+    budget: &Budget,
+) -> Result<(), D::Error>
+where
+    Old: Index<usize> + ?Sized,
+    New: Index<usize> + ?Sized,
+    D: DiffHook,
+    New::Output: PartialEq<Old::Output>,
+{
+    diff_range(d, old, old_range, new, new_range, budget)?;
+    d.finish()
+}
+
+/// Like [`diff`] but without calling [`DiffHook::finish`], for diffing part of a larger sequence
+pub(super) fn diff_range<Old, New, D>(
+    d: &mut D,
+    old: &Old,
+    old_range: Range<usize>,
+    new: &New,
+    new_range: Range<usize>,
+    budget: &Budget,
 ) -> Result<(), D::Error>
 where
     Old: Index<usize> + ?Sized,
@@ -70,8 +93,7 @@ where
     let max_d = max_d(old_range.len(), new_range.len());
     let mut vb = V::new(max_d);
     let mut vf = V::new(max_d);
-    conquer(d, old, old_range, new, new_range, &mut vf, &mut vb)?;
-    d.finish()
+    conquer(d, old, old_range, new, new_range, &mut vf, &mut vb, budget)
 }
 
 // A D-path is a path which starts at (0,0) that has exactly D non-diagonal
@@ -149,6 +171,7 @@ fn find_middle_snake<Old, New>(
     new_range: Range<usize>,
     vf: &mut V,
     vb: &mut V,
+    budget: &Budget,
 ) -> Option<(usize, usize)>
 where
     Old: Index<usize> + ?Sized,
@@ -174,6 +197,10 @@ where
     assert!(vb.len() >= d_max);
 
     for d in 0..d_max as isize {
+        if !budget.spend(2 * d as usize + 2) {
+            break;
+        }
+
         // Forward path
         for k in (-d..=d).rev().step_by(2) {
             let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) {
@@ -248,7 +275,7 @@ where
         // TODO: Maybe there's an opportunity to optimize and bail early?
     }
 
-    // deadline reached
+    // budget exhausted
     None
 }
 
@@ -261,6 +288,7 @@ fn conquer<Old, New, D>(
     mut new_range: Range<usize>,
     vf: &mut V,
     vb: &mut V,
+    budget: &Budget,
 ) -> Result<(), D::Error>
 where
     Old: Index<usize> + ?Sized,
@@ -291,13 +319,19 @@ where
         d.delete(old_range.start, old_range.len(), new_range.start)?;
     } else if is_empty_range(&old_range) {
         d.insert(old_range.start, new_range.start, new_range.len())?;
-    } else if let Some((x_start, y_start)) =
-        find_middle_snake(old, old_range.clone(), new, new_range.clone(), vf, vb)
-    {
+    } else if let Some((x_start, y_start)) = find_middle_snake(
+        old,
+        old_range.clone(),
+        new,
+        new_range.clone(),
+        vf,
+        vb,
+        budget,
+    ) {
         let (old_a, old_b) = split_at(old_range, x_start);
         let (new_a, new_b) = split_at(new_range, y_start);
-        conquer(d, old, old_a, new, new_a, vf, vb)?;
-        conquer(d, old, old_b, new, new_b, vf, vb)?;
+        conquer(d, old, old_a, new, new_a, vf, vb, budget)?;
+        conquer(d, old, old_b, new, new_b, vf, vb, budget)?;
     } else {
         d.delete(
             old_range.start,
@@ -325,8 +359,16 @@ fn test_find_middle_snake() {
     let max_d = max_d(a.len(), b.len());
     let mut vf = V::new(max_d);
     let mut vb = V::new(max_d);
-    let (x_start, y_start) =
-        find_middle_snake(a, 0..a.len(), b, 0..b.len(), &mut vf, &mut vb).unwrap();
+    let (x_start, y_start) = find_middle_snake(
+        a,
+        0..a.len(),
+        b,
+        0..b.len(),
+        &mut vf,
+        &mut vb,
+        &Budget::unlimited(),
+    )
+    .unwrap();
     assert_eq!(x_start, 4);
     assert_eq!(y_start, 1);
 }
@@ -0,0 +1,156 @@
+//! Patience diff
+//!
+//! Elements which occur exactly once in both sequences are matched up first, using the longest
+//! subsequence of them which appears in the same order in both, and the gaps between them are
+//! diffed recursively. Gaps without any unique elements are diffed with Myers' algorithm.
+//!
+//! This produces diffs which follow the structure of the text (unique lines or words are rarely
+//! matched up by accident) and is fast when large parts of the text have been rewritten.
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::myers::{self, DiffHook};
+use super::utils::{common_prefix_len, common_suffix_len, is_empty_range};
+use super::Budget;
+
+pub(super) fn diff<D: DiffHook>(
+    d: &mut D,
+    old: &[&str],
+    old_range: Range<usize>,
+    new: &[&str],
+    new_range: Range<usize>,
+    budget: &Budget,
+) -> Result<(), D::Error> {
+    conquer(d, old, old_range, new, new_range, budget)?;
+    d.finish()
+}
+
+fn conquer<D: DiffHook>(
+    d: &mut D,
+    old: &[&str],
+    mut old_range: Range<usize>,
+    new: &[&str],
+    mut new_range: Range<usize>,
+    budget: &Budget,
+) -> Result<(), D::Error> {
+    let prefix = common_prefix_len(old, old_range.clone(), new, new_range.clone());
+    if prefix > 0 {
+        d.equal(old_range.start, new_range.start, prefix)?;
+    }
+    old_range.start += prefix;
+    new_range.start += prefix;
+
+    let suffix = common_suffix_len(old, old_range.clone(), new, new_range.clone());
+    old_range.end -= suffix;
+    new_range.end -= suffix;
+
+    if is_empty_range(&old_range) && is_empty_range(&new_range) {
+        // Do nothing
+    } else if is_empty_range(&new_range) {
+        d.delete(old_range.start, old_range.len(), new_range.start)?;
+    } else if is_empty_range(&old_range) {
+        d.insert(old_range.start, new_range.start, new_range.len())?;
+    } else if !budget.spend(old_range.len() + new_range.len()) {
+        d.replace(
+            old_range.start,
+            old_range.len(),
+            new_range.start,
+            new_range.len(),
+        )?;
+    } else {
+        let anchors = unique_anchors(old, old_range.clone(), new, new_range.clone());
+        if anchors.is_empty() {
+            myers::diff_range(d, old, old_range.clone(), new, new_range.clone(), budget)?;
+        } else {
+            let (mut old_pos, mut new_pos) = (old_range.start, new_range.start);
+            for (old_index, new_index) in anchors {
+                conquer(d, old, old_pos..old_index, new, new_pos..new_index, budget)?;
+                d.equal(old_index, new_index, 1)?;
+                old_pos = old_index + 1;
+                new_pos = new_index + 1;
+            }
+            conquer(
+                d,
+                old,
+                old_pos..old_range.end,
+                new,
+                new_pos..new_range.end,
+                budget,
+            )?;
+        }
+    }
+
+    if suffix > 0 {
+        d.equal(old_range.end, new_range.end, suffix)?;
+    }
+    Ok(())
+}
+
+/// The longest sequence of elements which occur exactly once in both ranges and appear in the
+/// same order in both, as pairs of indices into `old` and `new`
+fn unique_anchors(
+    old: &[&str],
+    old_range: Range<usize>,
+    new: &[&str],
+    new_range: Range<usize>,
+) -> Vec<(usize, usize)> {
+    // for each element the number of times it occurs in old and new and where it last occurred
+    let mut seen: HashMap<&str, (usize, usize, usize, usize)> = HashMap::new();
+    for i in old_range {
+        let entry = seen.entry(old[i]).or_insert((0, 0, 0, 0));
+        entry.0 += 1;
+        entry.1 = i;
+    }
+    for j in new_range {
+        if let Some(entry) = seen.get_mut(new[j]) {
+            entry.2 += 1;
+            entry.3 = j;
+        }
+    }
+    let mut unique = seen
+        .into_iter()
+        .filter(|(_, (old_count, _, new_count, _))| *old_count == 1 && *new_count == 1)
+        .map(|(_, (_, i, _, j))| (i, j))
+        .collect::<Vec<_>>();
+    unique.sort_unstable();
+    longest_increasing(&unique)
+}
+
+/// The longest subsequence of `pairs`, which are sorted by their first element, whose second
+/// elements are increasing. This is the "patience sorting" the algorithm is named after.
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // the index in `pairs` of the top card of each pile
+    let mut piles: Vec<usize> = Vec::new();
+    // for each card, the card on top of the previous pile when it was placed
+    let mut back: Vec<Option<usize>> = Vec::with_capacity(pairs.len());
+    for (i, (_, j)) in pairs.iter().enumerate() {
+        let pile = piles.partition_point(|&top| pairs[top].1 < *j);
+        back.push(if pile > 0 {
+            Some(piles[pile - 1])
+        } else {
+            None
+        });
+        if pile == piles.len() {
+            piles.push(i);
+        } else {
+            piles[pile] = i;
+        }
+    }
+    let mut result = Vec::with_capacity(piles.len());
+    let mut card = piles.last().copied();
+    while let Some(i) = card {
+        result.push(pairs[i]);
+        card = back[i];
+    }
+    result.reverse();
+    result
+}
+
+#[test]
+fn test_longest_increasing() {
+    assert_eq!(
+        longest_increasing(&[(0, 3), (1, 0), (2, 4), (3, 1), (4, 2), (5, 5)]),
+        vec![(1, 0), (3, 1), (4, 2), (5, 5)]
+    );
+    assert_eq!(longest_increasing(&[]), vec![]);
+}
@@ -0,0 +1,128 @@
+use std::ops::Range;
+
+use crate::TextEncoding;
+
+/// The lines of a text object, returned by [`crate::ReadDoc::lines()`]
+///
+/// Lines are separated by `\n`, which is not included in the line. Text ending with a newline
+/// has an empty last line and empty text has a single empty line, so every index in the text
+/// object, including its length, is in exactly one line. This is how most editors number lines.
+///
+/// Indexes are positions in the text object, measured in the same units as
+/// [`crate::ReadDoc::length()`], so they can be passed directly to
+/// [`crate::transaction::Transactable::splice_text()`] or [`crate::ReadDoc::get_cursor()`].
+/// Line numbers start at zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLines {
+    lines: Vec<String>,
+    // the index of the start of each line, followed by the length of the text plus one
+    starts: Vec<usize>,
+}
+
+impl TextLines {
+    pub(crate) fn new(text: &str) -> Self {
+        let encoding = TextEncoding::native();
+        let mut lines = Vec::new();
+        let mut starts = vec![0];
+        let mut start = 0;
+        for line in text.split('\n') {
+            // the newline is one unit wide in every encoding
+            start += encoding.width(line) + 1;
+            lines.push(line.to_string());
+            starts.push(start);
+        }
+        TextLines { lines, starts }
+    }
+
+    /// The number of lines
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Always false, there is at least one line
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The text of line `line`, without its newline
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.lines.get(line).map(String::as_str)
+    }
+
+    /// Iterate over the text of each line
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(String::as_str)
+    }
+
+    /// The index in the text object where line `line` starts
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.lines.get(line).map(|_| self.starts[line])
+    }
+
+    /// The indexes in the text object of the contents of line `line`, not including its newline
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        self.lines
+            .get(line)
+            .map(|_| self.starts[line]..self.starts[line + 1] - 1)
+    }
+
+    /// The line containing `index`, an index just before a newline is in the line the newline
+    /// ends. Returns `None` if `index` is past the end of the text.
+    pub fn line_at(&self, index: usize) -> Option<usize> {
+        let line = self.starts.partition_point(|start| *start <= index) - 1;
+        if line < self.lines.len() {
+            Some(line)
+        } else {
+            None
+        }
+    }
+
+    /// The line and column of `index`, where the column is measured in the same units as the
+    /// index
+    pub fn position(&self, index: usize) -> Option<(usize, usize)> {
+        self.line_at(index)
+            .map(|line| (line, index - self.starts[line]))
+    }
+
+    /// The index of `column` in line `line`, `None` if either is out of range
+    pub fn index(&self, line: usize, column: usize) -> Option<usize> {
+        self.line_range(line)
+            .map(|range| range.start + column)
+            .filter(|index| *index < self.starts[line + 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_positions() {
+        let lines = TextLines::new("one\n\nthree\n");
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines.iter().collect::<Vec<_>>(),
+            vec!["one", "", "three", ""]
+        );
+        assert_eq!(lines.line_range(0), Some(0..3));
+        assert_eq!(lines.line_range(1), Some(4..4));
+        assert_eq!(lines.line_range(2), Some(5..10));
+        assert_eq!(lines.line_range(3), Some(11..11));
+        assert_eq!(lines.line_range(4), None);
+
+        assert_eq!(lines.line_at(0), Some(0));
+        assert_eq!(lines.line_at(3), Some(0));
+        assert_eq!(lines.line_at(4), Some(1));
+        assert_eq!(lines.line_at(11), Some(3));
+        assert_eq!(lines.line_at(12), None);
+
+        assert_eq!(lines.position(7), Some((2, 2)));
+        assert_eq!(lines.index(2, 2), Some(7));
+        assert_eq!(lines.index(2, 5), Some(10));
+        assert_eq!(lines.index(2, 6), None);
+
+        let empty = TextLines::new("");
+        assert_eq!(empty.len(), 1);
+        assert_eq!(empty.line_at(0), Some(0));
+    }
+}
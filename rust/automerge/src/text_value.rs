@@ -1,6 +1,10 @@
 use core::fmt::Debug;
 
+use crate::exid::ExId;
+use crate::{AutomergeError, ChangeHash, ObjType, ReadDoc};
+
 use crate::sequence_tree::SequenceTree;
+use unicode_segmentation::UnicodeSegmentation;
 
 #[cfg(not(any(target_family = "wasm", feature = "utf8-indexing")))]
 #[derive(Clone, PartialEq, Default)]
@@ -156,3 +160,229 @@ impl From<&TextValue> for String {
         s.make_string()
     }
 }
+
+/// The unit in which positions in text objects are measured
+///
+/// By default automerge measures text in the unit chosen at compile time: unicode code points,
+/// or UTF-16 code units when compiled to wasm, or UTF-8 code units with the `utf8-indexing`
+/// feature (see [`Self::native()`]). Methods with a `_with_encoding` suffix, and patches generated
+/// by a [`crate::PatchLog`] with a text encoding set, take and return positions in whichever unit
+/// you choose instead. Converting to or from grapheme clusters requires the text itself, so
+/// those methods are much slower than their native counterparts for grapheme clusters.
+///
+/// [`Self::GraphemeCluster`] positions are extended grapheme clusters as defined by UAX #29.
+/// Positions which fall inside a cluster (e.g. because another peer inserted a combining
+/// character) are rounded to the start of the cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextEncoding {
+    UnicodeCodePoint,
+    Utf8CodeUnit,
+    Utf16CodeUnit,
+    GraphemeCluster,
+}
+
+impl TextEncoding {
+    /// The encoding automerge was compiled to use
+    #[cfg(not(any(target_family = "wasm", feature = "utf8-indexing")))]
+    pub const fn native() -> Self {
+        TextEncoding::UnicodeCodePoint
+    }
+
+    /// The encoding automerge was compiled to use
+    #[cfg(all(target_family = "wasm", not(feature = "utf8-indexing")))]
+    pub const fn native() -> Self {
+        TextEncoding::Utf16CodeUnit
+    }
+
+    /// The encoding automerge was compiled to use
+    #[cfg(feature = "utf8-indexing")]
+    pub const fn native() -> Self {
+        TextEncoding::Utf8CodeUnit
+    }
+
+    pub(crate) fn is_native(self) -> bool {
+        self == Self::native()
+    }
+
+    /// Whether the width of two strings concatenated is the sum of their widths, which is the
+    /// case for everything but grapheme clusters
+    pub(crate) fn is_additive(self) -> bool {
+        self != TextEncoding::GraphemeCluster
+    }
+
+    /// The length of `s` in this encoding
+    pub fn width(self, s: &str) -> usize {
+        match self {
+            TextEncoding::UnicodeCodePoint => s.chars().count(),
+            TextEncoding::Utf8CodeUnit => s.len(),
+            TextEncoding::Utf16CodeUnit => s.encode_utf16().count(),
+            TextEncoding::GraphemeCluster => s.graphemes(true).count(),
+        }
+    }
+
+    /// The smallest pieces of `s` which this encoding can address, along with their byte offsets
+    fn segments(self, s: &str) -> Box<dyn Iterator<Item = (usize, &str)> + '_> {
+        match self {
+            TextEncoding::GraphemeCluster => Box::new(s.grapheme_indices(true)),
+            _ => Box::new(
+                s.char_indices()
+                    .map(move |(i, c)| (i, &s[i..i + c.len_utf8()])),
+            ),
+        }
+    }
+
+    fn segment_width(self, segment: &str) -> usize {
+        match self {
+            TextEncoding::GraphemeCluster => 1,
+            other => other.width(segment),
+        }
+    }
+
+    /// The byte offset in `s` of the position `index`, or [`None`] if `index` is past the end of
+    /// `s`. Positions inside a character are rounded down to the start of the character.
+    pub(crate) fn byte_offset(self, s: &str, index: usize) -> Option<usize> {
+        let mut current = 0;
+        for (start, segment) in self.segments(s) {
+            let width = self.segment_width(segment);
+            if index < current + width {
+                return Some(start);
+            }
+            current += width;
+        }
+        if index == current {
+            Some(s.len())
+        } else {
+            None
+        }
+    }
+
+    /// The position in this encoding of the byte offset `byte` in `s`. If `byte` is inside a
+    /// segment this encoding can't split it is rounded down, or up if `round_up` is true.
+    pub(crate) fn index_of_byte(self, s: &str, byte: usize, round_up: bool) -> usize {
+        if self == TextEncoding::Utf8CodeUnit {
+            return byte.min(s.len());
+        }
+        let mut index = 0;
+        for (start, segment) in self.segments(s) {
+            if start >= byte {
+                break;
+            }
+            if start + segment.len() <= byte || round_up {
+                index += self.segment_width(segment);
+            }
+        }
+        index
+    }
+
+    /// Widen the byte range `start..end` of `s` so that it doesn't split any segment which this
+    /// encoding can't address the inside of
+    pub(crate) fn expand(self, s: &str, start: usize, end: usize) -> (usize, usize) {
+        let mut lo = start;
+        let mut hi = end;
+        for (seg_start, segment) in self.segments(s) {
+            let seg_end = seg_start + segment.len();
+            if seg_start < start && start < seg_end {
+                lo = seg_start;
+            }
+            if seg_start < end && end < seg_end {
+                hi = seg_end;
+                break;
+            }
+            if seg_start >= end {
+                break;
+            }
+        }
+        (lo, hi)
+    }
+
+    /// Convert `index`, a position in `s` in this encoding, to a position in `to`
+    pub(crate) fn convert(self, s: &str, index: usize, to: TextEncoding) -> Option<usize> {
+        if self == to {
+            return Some(index);
+        }
+        self.byte_offset(s, index)
+            .map(|byte| to.index_of_byte(s, byte, false))
+    }
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self::native()
+    }
+}
+
+/// Converts positions in a text object between a [`TextEncoding`] and the native encoding
+///
+/// Conversions go through [`ReadDoc::convert_text_index()`], except for grapheme clusters, where
+/// the text is read once up front so that converting many positions doesn't read it every time.
+pub(crate) struct IndexConverter<'a, R: ?Sized> {
+    doc: &'a R,
+    obj: &'a ExId,
+    at: Option<&'a [ChangeHash]>,
+    encoding: TextEncoding,
+    // `Some` for encodings which can't be converted without the text
+    text: Option<String>,
+}
+
+impl<'a, R: ReadDoc + ?Sized> IndexConverter<'a, R> {
+    pub(crate) fn new(
+        doc: &'a R,
+        obj: &'a ExId,
+        at: Option<&'a [ChangeHash]>,
+        encoding: TextEncoding,
+    ) -> Result<Self, AutomergeError> {
+        let text = if encoding.is_additive() || doc.object_type(obj)? != ObjType::Text {
+            None
+        } else {
+            match at {
+                Some(heads) => Some(doc.text_at(obj, heads)?),
+                None => Some(doc.text(obj)?),
+            }
+        };
+        Ok(Self {
+            doc,
+            obj,
+            at,
+            encoding,
+            text,
+        })
+    }
+
+    pub(crate) fn to_native(&self, index: usize) -> Result<usize, AutomergeError> {
+        match &self.text {
+            Some(text) => self
+                .encoding
+                .convert(text, index, TextEncoding::native())
+                .ok_or(AutomergeError::InvalidIndex(index)),
+            None => self.doc.convert_text_index(
+                self.obj,
+                index,
+                self.encoding,
+                TextEncoding::native(),
+                self.at,
+            ),
+        }
+    }
+
+    pub(crate) fn to_encoding(
+        &self,
+        index: usize,
+        round_up: bool,
+    ) -> Result<usize, AutomergeError> {
+        match &self.text {
+            Some(text) => {
+                let byte = TextEncoding::native()
+                    .byte_offset(text, index)
+                    .unwrap_or(text.len());
+                Ok(self.encoding.index_of_byte(text, byte, round_up))
+            }
+            None => self.doc.convert_text_index(
+                self.obj,
+                index,
+                TextEncoding::native(),
+                self.encoding,
+                self.at,
+            ),
+        }
+    }
+}
@@ -1,14 +1,16 @@
 use std::num::NonZeroU64;
 use std::sync::Arc;
 
+use crate::blocks::{Block, BLOCK_PLACEHOLDER};
 use crate::exid::ExId;
 use crate::marks::{ExpandMark, Mark, MarkSet};
-use crate::op_set::{ChangeOpIter, OpIdx, OpIdxRange};
+use crate::op_set::{ChangeOpIter, Op, OpIdx, OpIdxRange};
 use crate::patches::{PatchLog, TextRepresentation};
 use crate::query::{self, OpIdSearch};
 use crate::storage::Change as StoredChange;
-use crate::types::{Clock, Key, ListEncoding, ObjId, OpId};
-use crate::{op_tree::OpSetData, types::OpBuilder, Automerge, Change, ChangeHash, Prop};
+use crate::text_value::TextValue;
+use crate::types::{Clock, Key, ListEncoding, ObjId, ObjMeta, OpId};
+use crate::{hydrate, op_tree::OpSetData, types::OpBuilder, Automerge, Change, ChangeHash, Prop};
 use crate::{AutomergeError, ObjType, OpType, ScalarValue};
 
 #[derive(Debug, Clone)]
@@ -119,6 +121,7 @@ impl TransactionInner {
             tracing::trace!(commit=?hash, ?ops, deps=?change.deps(), "committing transaction");
         }
         doc.update_history(change, num_ops);
+        doc.advance_checked_out_branch(hash);
         //debug_assert_eq!(doc.get_heads(), vec![hash]);
         hash
     }
@@ -558,6 +561,109 @@ impl TransactionInner {
         Ok(())
     }
 
+    /// Delete every value of `prop` except `winner`
+    pub(crate) fn resolve_conflict<P: Into<Prop>>(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        prop: P,
+        winner: &ExId,
+    ) -> Result<(), AutomergeError> {
+        let obj = doc.exid_to_obj(ex_obj)?;
+        let prop = prop.into();
+        let invalid = || AutomergeError::InvalidContender(winner.to_string());
+        let winner = doc.exid_to_opid(winner).map_err(|_| invalid())?;
+        let losers = {
+            let (ops, _) = self.seek_values(doc, &obj, &prop)?;
+            if !ops.iter().any(|op| *op.id() == winner) {
+                return Err(invalid());
+            }
+            ops.iter()
+                .map(|op| *op.id())
+                .filter(|id| *id != winner)
+                .collect::<Vec<_>>()
+        };
+        self.delete_values(doc, patch_log, &obj, prop, &losers)
+    }
+
+    /// Delete the values of `prop` which were set by the ops in `ids`, leaving the others in
+    /// place. Does nothing if none of them are visible.
+    pub(crate) fn delete_values(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        obj: &ObjMeta,
+        prop: Prop,
+        ids: &[OpId],
+    ) -> Result<(), AutomergeError> {
+        let (ops, ops_pos) = self.seek_values(doc, obj, &prop)?;
+        let mut removed = Vec::new();
+        let mut remaining = Vec::new();
+        for (op, pos) in ops.iter().zip(ops_pos) {
+            if ids.contains(op.id()) {
+                removed.push(pos);
+            } else {
+                remaining.push(op);
+            }
+        }
+        if removed.is_empty() {
+            return Ok(());
+        }
+        let key = ops[0].elemid_or_key();
+        let winner: Option<(hydrate::Value, _, _)> = remaining
+            .last()
+            .map(|op| (op.value().into(), *op.id(), remaining.len() > 1));
+
+        let op = OpBuilder {
+            id: self.next_id(),
+            action: OpType::Delete,
+            key,
+            insert: false,
+        };
+        let idx = doc
+            .ops_mut()
+            .load_with_range(obj.id, op, &mut self.idx_range);
+        doc.ops_mut().add_succ(&obj.id, &removed, idx);
+        if patch_log.is_active() {
+            match winner {
+                Some((value, id, conflict)) => {
+                    patch_log.put(obj.id, &prop, value, id, conflict, true)
+                }
+                None => patch_log.delete(obj.id, &prop),
+            }
+        }
+        Ok(())
+    }
+
+    /// The visible ops at `prop` and their positions in the op set
+    fn seek_values<'a>(
+        &self,
+        doc: &'a Automerge,
+        obj: &ObjMeta,
+        prop: &Prop,
+    ) -> Result<(Vec<Op<'a>>, Vec<usize>), AutomergeError> {
+        match (prop, obj.typ) {
+            (Prop::Map(_), ObjType::Map) => {
+                let query = doc.ops().seek_ops_by_prop(
+                    &obj.id,
+                    prop.clone(),
+                    obj.encoding,
+                    self.scope.as_ref(),
+                );
+                Ok((query.ops, query.ops_pos))
+            }
+            (Prop::Seq(index), typ) if typ.is_sequence() => {
+                let query = doc.ops().search(
+                    &obj.id,
+                    query::Nth::new(*index, obj.encoding, self.scope.clone(), doc.osd()),
+                );
+                Ok((query.ops, query.ops_pos))
+            }
+            _ => Err(AutomergeError::InvalidOp(obj.typ)),
+        }
+    }
+
     /// Splice new elements into the given sequence. Returns a vector of the OpIds used to insert
     /// the new elements
     pub(crate) fn splice(
@@ -639,6 +745,11 @@ impl TransactionInner {
 
         //let ex_obj = doc.ops().id_to_exid(obj.0);
         let encoding = splice_type.encoding();
+        // the patch log needs the deleted text to convert indices to a non-native encoding
+        let mut deleted_text = match splice_type {
+            SpliceType::Text(_) if patch_log.is_active() => Some(String::new()),
+            _ => None,
+        };
         // delete `del` items - performing the query for each one
         let mut deleted: usize = 0;
         while deleted < (del as usize) {
@@ -657,6 +768,9 @@ impl TransactionInner {
             }
 
             let step = if let Some(op) = query.ops.last() {
+                if let Some(text) = deleted_text.as_mut() {
+                    text.push_str(op.as_str());
+                }
                 op.width(encoding)
             } else {
                 break;
@@ -673,7 +787,10 @@ impl TransactionInner {
         }
 
         if deleted > 0 && patch_log.is_active() {
-            patch_log.delete_seq(obj, index, deleted);
+            match deleted_text {
+                Some(text) => patch_log.delete_text(obj, index, &text),
+                None => patch_log.delete_seq(obj, index, deleted),
+            }
         }
 
         // do the insert query for the first item and then
@@ -732,7 +849,76 @@ impl TransactionInner {
         Ok(())
     }
 
+    /// Add `mark`, following the [`crate::marks::MarkPolicy`] registered for its name
     pub(crate) fn mark(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        mark: Mark<'_>,
+        expand: Option<ExpandMark>,
+    ) -> Result<(), AutomergeError> {
+        let policy = doc.mark_policies().get(mark.name());
+        let expand = expand.unwrap_or(policy.expand);
+        if mark.value().is_null() || !(policy.exclusive || policy.merge) {
+            return self.add_mark(doc, patch_log, ex_obj, mark, expand);
+        }
+        let existing = self.overlapping_marks(doc, ex_obj, mark.name(), mark.start, mark.end)?;
+        if policy.exclusive {
+            // replace any other marks with this name entirely, and absorb any with the same value
+            let mut mark = mark.into_owned();
+            for (start, end, value) in existing {
+                if &value == mark.value() {
+                    mark.start = mark.start.min(start);
+                    mark.end = mark.end.max(end);
+                } else {
+                    let unmark = Mark::new(mark.name().to_string(), ScalarValue::Null, start, end);
+                    self.add_mark(doc, patch_log, ex_obj, unmark, expand)?;
+                }
+            }
+            self.add_mark(doc, patch_log, ex_obj, mark, expand)
+        } else {
+            // only fill in the parts of the range which aren't already marked
+            let mut from = mark.start;
+            let mut gaps = Vec::new();
+            for (start, end, _) in existing {
+                if start > from {
+                    gaps.push((from, start));
+                }
+                from = from.max(end);
+            }
+            if from < mark.end {
+                gaps.push((from, mark.end));
+            }
+            for (start, end) in gaps {
+                let gap = Mark::new(mark.name().to_string(), mark.value().clone(), start, end);
+                self.add_mark(doc, patch_log, ex_obj, gap, expand)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// The start, end and value of each mark named `name` in `ex_obj` which overlaps `start..end`
+    fn overlapping_marks(
+        &self,
+        doc: &Automerge,
+        ex_obj: &ExId,
+        name: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<(usize, usize, ScalarValue)>, AutomergeError> {
+        let mut marks = doc
+            .marks_for(ex_obj, self.scope.clone())?
+            .into_iter()
+            .filter(|m| m.name() == name && m.start < end && start < m.end)
+            .filter(|m| !m.value().is_null())
+            .map(|m| (m.start, m.end, m.value().clone()))
+            .collect::<Vec<_>>();
+        marks.sort_by_key(|(start, _, _)| *start);
+        Ok(marks)
+    }
+
+    fn add_mark(
         &mut self,
         doc: &mut Automerge,
         patch_log: &mut PatchLog,
@@ -767,10 +953,139 @@ impl TransactionInner {
         name: &str,
         start: usize,
         end: usize,
-        expand: ExpandMark,
+        expand: Option<ExpandMark>,
     ) -> Result<(), AutomergeError> {
+        let policy = doc.mark_policies().get(name);
+        let expand = expand.unwrap_or(policy.expand);
+        let (mut start, mut end) = (start, end);
+        if policy.exclusive {
+            for (s, e, _) in self.overlapping_marks(doc, ex_obj, name, start, end)? {
+                start = start.min(s);
+                end = end.max(e);
+            }
+        }
         let mark = Mark::new(name.to_string(), ScalarValue::Null, start, end);
-        self.mark(doc, patch_log, ex_obj, mark, expand)
+        self.add_mark(doc, patch_log, ex_obj, mark, expand)
+    }
+
+    pub(crate) fn split_block(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        block: &Block,
+    ) -> Result<ExId, AutomergeError> {
+        let obj = doc.exid_to_obj(ex_obj)?;
+        if obj.typ != ObjType::Text {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        let idx = self.do_insert(
+            doc,
+            patch_log,
+            obj.id,
+            index,
+            obj.encoding,
+            OpType::Make(ObjType::Map),
+        )?;
+        let block_id = idx.as_op(doc.osd()).exid();
+        self.write_block(doc, patch_log, &block_id, block, None)?;
+        Ok(block_id)
+    }
+
+    pub(crate) fn join_block(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+    ) -> Result<(), AutomergeError> {
+        let obj = doc.exid_to_obj(ex_obj)?;
+        self.block_at(doc, &obj.id, obj.typ, index)?;
+        self.inner_splice(
+            doc,
+            patch_log,
+            SpliceArgs {
+                obj: obj.id,
+                index,
+                del: TextValue::width(BLOCK_PLACEHOLDER) as isize,
+                values: vec![],
+                splice_type: SpliceType::Text(""),
+            },
+        )
+    }
+
+    pub(crate) fn update_block(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        block: &Block,
+    ) -> Result<(), AutomergeError> {
+        let obj = doc.exid_to_obj(ex_obj)?;
+        let block_id = self.block_at(doc, &obj.id, obj.typ, index)?;
+        let current = Block::from_hydrated(&doc.hydrate_map(&block_id.into(), self.scope.as_ref()));
+        let block_id = doc.id_to_exid(block_id);
+        self.write_block(doc, patch_log, &block_id, block, Some(&current))
+    }
+
+    /// The ID of the block marker at `index` in `obj`
+    fn block_at(
+        &self,
+        doc: &Automerge,
+        obj: &ObjId,
+        typ: ObjType,
+        index: usize,
+    ) -> Result<OpId, AutomergeError> {
+        if typ != ObjType::Text {
+            return Err(AutomergeError::InvalidOp(typ));
+        }
+        let found =
+            doc.ops()
+                .seek_ops_by_prop(obj, index.into(), ListEncoding::Text, self.scope.as_ref());
+        match found.ops.last() {
+            Some(op) if matches!(op.action(), OpType::Make(ObjType::Map)) => Ok(*op.id()),
+            _ => Err(AutomergeError::NotABlock(index)),
+        }
+    }
+
+    /// Write the fields of `block` to the map `block_id`, skipping any which are the same in
+    /// `current` so that concurrent changes to other fields are preserved
+    fn write_block(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        block_id: &ExId,
+        block: &Block,
+        current: Option<&Block>,
+    ) -> Result<(), AutomergeError> {
+        let empty = Block::default();
+        let current_block = current.unwrap_or(&empty);
+        if current.is_none() || current_block.block_type != block.block_type {
+            self.put(doc, patch_log, block_id, "type", block.block_type.as_str())?;
+        }
+        if current.is_none() || current_block.parents != block.parents {
+            let parents = self.put_object(doc, patch_log, block_id, "parents", ObjType::List)?;
+            for (i, parent) in block.parents.iter().enumerate() {
+                self.insert(doc, patch_log, &parents, i, parent.as_str())?;
+            }
+        }
+        let attrs = match doc.get_for(block_id, "attrs".into(), self.scope.clone())? {
+            Some((crate::Value::Object(ObjType::Map), id)) => id,
+            _ => self.put_object(doc, patch_log, block_id, "attrs", ObjType::Map)?,
+        };
+        for name in current_block.attrs.keys() {
+            if !block.attrs.contains_key(name) {
+                self.delete(doc, patch_log, &attrs, name.as_str())?;
+            }
+        }
+        for (name, value) in &block.attrs {
+            if current_block.attrs.get(name) != Some(value) {
+                self.put(doc, patch_log, &attrs, name.as_str(), value.clone())?;
+            }
+        }
+        Ok(())
     }
 
     fn finalize_op(
@@ -806,6 +1121,8 @@ impl TransactionInner {
                                     false,
                                     marks,
                                 );
+                            } else if let OpType::Make(ObjType::Map) = op.action() {
+                                patch_log.split_block(obj, index, *op.id());
                             } else {
                                 patch_log.splice(obj, index, op.as_str(), marks);
                             }
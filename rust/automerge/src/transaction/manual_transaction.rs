@@ -1,14 +1,21 @@
 use std::ops::RangeBounds;
 
+use crate::blocks::Block;
+use crate::conflicts::{Conflict, Conflicts};
 use crate::exid::ExId;
-use crate::iter::{Keys, ListRange, MapRange, Values};
+use crate::history::{ObjRevision, PropRevision};
+use crate::iter::{Keys, ListRange, MapRange, Spans, Values};
 use crate::marks::{ExpandMark, Mark, MarkSet};
 use crate::patches::PatchLog;
+use crate::revert::Revert;
 use crate::types::Clock;
 use crate::AutomergeError;
-use crate::{Automerge, ChangeHash, Cursor, ObjType, Parents, Prop, ReadDoc, ScalarValue, Value};
+use crate::{
+    Automerge, ChangeHash, Cursor, CursorResolution, ObjType, Parents, Prop, ReadDoc, ScalarValue,
+    StickyCursor, TextEncoding, Value,
+};
 
-use super::{CommitOptions, Transactable, TransactionArgs, TransactionInner};
+use super::{CommitOptions, Transactable, TransactionArgs, TransactionInner, UpdateTextOptions};
 
 /// A transaction on a document.
 /// Transactions group operations into a single change so that no other operations can happen
@@ -203,6 +210,31 @@ impl<'a> ReadDoc for Transaction<'a> {
         self.doc.text_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn convert_text_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        from: TextEncoding,
+        to: TextEncoding,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .convert_text_index_for(obj.as_ref(), index, from, to, self.get_scope(at))
+    }
+
+    fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
+        self.doc.spans_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn spans_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Spans<'_>, AutomergeError> {
+        self.doc
+            .spans_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
     fn get_cursor<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -218,9 +250,34 @@ impl<'a> ReadDoc for Transaction<'a> {
         obj: O,
         address: &Cursor,
         at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc.get_cursor_position_for(
+            obj.as_ref(),
+            address,
+            CursorResolution::Following,
+            self.get_scope(at),
+        )
+    }
+
+    fn get_cursor_position_with_resolution<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        resolution: CursorResolution,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        self.doc
+            .get_cursor_position_for(obj.as_ref(), cursor, resolution, self.get_scope(at))
+    }
+
+    fn get_sticky_cursor_position<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &StickyCursor,
+        at: Option<&[ChangeHash]>,
     ) -> Result<usize, AutomergeError> {
         self.doc
-            .get_cursor_position_for(obj.as_ref(), address, self.get_scope(at))
+            .get_sticky_cursor_position_for(obj.as_ref(), cursor, self.get_scope(at))
     }
 
     fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError> {
@@ -297,6 +354,36 @@ impl<'a> ReadDoc for Transaction<'a> {
             .parents_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn conflicts<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Conflict<'_>>, AutomergeError> {
+        self.doc.conflicts_for(obj.as_ref(), self.get_scope(at))
+    }
+
+    fn all_conflicts(&self, at: Option<&[ChangeHash]>) -> Conflicts<'_> {
+        Conflicts::new(self.doc, self.get_scope(at))
+    }
+
+    fn history_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<ObjRevision<'_>>, AutomergeError> {
+        self.doc.history_of_for(obj.as_ref(), self.get_scope(at))
+    }
+
+    fn history_of_prop<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<PropRevision<'_>>, AutomergeError> {
+        self.doc
+            .history_of_prop_for(obj.as_ref(), prop.into(), self.get_scope(at))
+    }
+
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
         self.doc.get_missing_deps(heads)
     }
@@ -373,6 +460,27 @@ impl<'a> Transactable for Transaction<'a> {
         self.do_tx(|tx, doc, hist| tx.delete(doc, hist, obj.as_ref(), prop))
     }
 
+    fn resolve_conflict<O: AsRef<ExId>, P: Into<Prop>>(
+        &mut self,
+        obj: O,
+        prop: P,
+        winner: &ExId,
+    ) -> Result<(), AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.resolve_conflict(doc, hist, obj.as_ref(), prop, winner))
+    }
+
+    fn revert(&mut self, change: ChangeHash) -> Result<Revert, AutomergeError> {
+        self.do_tx(|tx, doc, hist| crate::revert::revert(tx, doc, hist, change, None))
+    }
+
+    fn revert_range(
+        &mut self,
+        change: ChangeHash,
+        heads: &[ChangeHash],
+    ) -> Result<Revert, AutomergeError> {
+        self.do_tx(|tx, doc, hist| crate::revert::revert(tx, doc, hist, change, Some(heads)))
+    }
+
     /// Splice new elements into the given sequence. Returns a vector of the OpIds used to insert
     /// the new elements
     fn splice<O: AsRef<ExId>, V: IntoIterator<Item = ScalarValue>>(
@@ -397,23 +505,47 @@ impl<'a> Transactable for Transaction<'a> {
         Ok(())
     }
 
-    fn mark<O: AsRef<ExId>>(
+    fn mark<O: AsRef<ExId>, E: Into<Option<ExpandMark>>>(
         &mut self,
         obj: O,
         mark: Mark<'_>,
-        expand: ExpandMark,
+        expand: E,
     ) -> Result<(), AutomergeError> {
+        let expand = expand.into();
         self.do_tx(|tx, doc, hist| tx.mark(doc, hist, obj.as_ref(), mark, expand))
     }
 
-    fn unmark<O: AsRef<ExId>>(
+    fn split_block<O: AsRef<ExId>>(
+        &mut self,
+        text: O,
+        index: usize,
+        block: Block,
+    ) -> Result<ExId, AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.split_block(doc, hist, text.as_ref(), index, &block))
+    }
+
+    fn join_block<O: AsRef<ExId>>(&mut self, text: O, index: usize) -> Result<(), AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.join_block(doc, hist, text.as_ref(), index))
+    }
+
+    fn update_block<O: AsRef<ExId>>(
+        &mut self,
+        text: O,
+        index: usize,
+        block: Block,
+    ) -> Result<(), AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.update_block(doc, hist, text.as_ref(), index, &block))
+    }
+
+    fn unmark<O: AsRef<ExId>, E: Into<Option<ExpandMark>>>(
         &mut self,
         obj: O,
         name: &str,
         start: usize,
         end: usize,
-        expand: ExpandMark,
+        expand: E,
     ) -> Result<(), AutomergeError> {
+        let expand = expand.into();
         self.do_tx(|tx, doc, hist| tx.unmark(doc, hist, obj.as_ref(), name, start, end, expand))
     }
 
@@ -424,12 +556,15 @@ impl<'a> Transactable for Transaction<'a> {
             .unwrap_or_default()
     }
 
-    fn update_text<S: AsRef<str>>(
+    fn update_text_with_options<S: AsRef<str>>(
         &mut self,
         obj: &ExId,
         new_text: S,
+        options: UpdateTextOptions,
     ) -> Result<(), AutomergeError> {
-        self.do_tx(|tx, doc, hist| crate::text_diff::myers_diff(doc, tx, hist, obj, new_text))
+        self.do_tx(|tx, doc, hist| {
+            crate::text_diff::update_text(doc, tx, hist, obj, new_text, options)
+        })
     }
 }
 
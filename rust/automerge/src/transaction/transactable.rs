@@ -1,5 +1,9 @@
+use crate::blocks::Block;
 use crate::exid::ExId;
 use crate::marks::{ExpandMark, Mark};
+use crate::revert::Revert;
+use crate::text_diff::{DiffAlgorithm, DiffGranularity, UpdateTextOptions};
+use crate::text_value::{IndexConverter, TextEncoding};
 use crate::{AutomergeError, ChangeHash, ObjType, Prop, ReadDoc, ScalarValue};
 
 /// A way of mutating a document within a single change.
@@ -72,6 +76,44 @@ pub trait Transactable: ReadDoc {
         prop: P,
     ) -> Result<(), AutomergeError>;
 
+    /// Resolve a conflict at `prop` in favour of the value set by the op `winner`, see
+    /// [`crate::conflicts`]
+    ///
+    /// This deletes every other value, so `winner` keeps its id and an object which wins keeps
+    /// its contents. Values added concurrently with the resolution will still conflict with it.
+    /// Does nothing if `winner` is the only value.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::InvalidContender`] if `winner` is not one of the values of `prop`
+    fn resolve_conflict<O: AsRef<ExId>, P: Into<Prop>>(
+        &mut self,
+        obj: O,
+        prop: P,
+        winner: &ExId,
+    ) -> Result<(), AutomergeError>;
+
+    /// Make ops which undo the effects of `change`, see [`crate::revert`]
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingHash`] if `change` is not in the document
+    fn revert(&mut self, change: ChangeHash) -> Result<Revert, AutomergeError>;
+
+    /// Make ops which undo the effects of `change` and of every change which depends on it and is
+    /// an ancestor of `heads`, see [`crate::revert`]
+    ///
+    /// Nothing is reverted if `change` is not an ancestor of `heads`.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingHash`] if `change` or one of `heads` is not in the document
+    fn revert_range(
+        &mut self,
+        change: ChangeHash,
+        heads: &[ChangeHash],
+    ) -> Result<Revert, AutomergeError>;
+
     /// replace a section of a list. If `del` is positive then N values
     /// are deleted after position `pos` and the new values inserted. If
     /// it is negative then N values are deleted before position `pos` instead.
@@ -93,21 +135,112 @@ pub trait Transactable: ReadDoc {
     ) -> Result<(), AutomergeError>;
 
     /// Mark a sequence
-    fn mark<O: AsRef<ExId>>(
+    ///
+    /// `expand` may be an [`ExpandMark`] or `None`, in which case the expansion registered for the
+    /// mark's name is used. The mark is applied according to the [`crate::marks::MarkPolicy`]
+    /// registered for its name, see [`crate::Automerge::set_mark_policy()`].
+    fn mark<O: AsRef<ExId>, E: Into<Option<ExpandMark>>>(
         &mut self,
         obj: O,
         mark: Mark<'_>,
-        expand: ExpandMark,
+        expand: E,
     ) -> Result<(), AutomergeError>;
 
+    /// Like [`Self::splice_text`] but `pos` and `del` are measured in `encoding`
+    fn splice_text_with_encoding<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: isize,
+        text: &str,
+        encoding: TextEncoding,
+    ) -> Result<(), AutomergeError> {
+        let (start, end) = if del < 0 {
+            let start = pos
+                .checked_sub(del.unsigned_abs())
+                .ok_or(AutomergeError::InvalidIndex(pos))?;
+            (start, pos)
+        } else {
+            (pos, pos + del as usize)
+        };
+        let converter = IndexConverter::new(self, obj.as_ref(), None, encoding)?;
+        let start = converter.to_native(start)?;
+        let end = converter.to_native(end)?;
+        self.splice_text(obj, start, (end - start) as isize, text)
+    }
+
+    /// Like [`Self::mark`] but the start and end of `mark` are measured in `encoding`
+    fn mark_with_encoding<O: AsRef<ExId>, E: Into<Option<ExpandMark>>>(
+        &mut self,
+        obj: O,
+        mut mark: Mark<'_>,
+        expand: E,
+        encoding: TextEncoding,
+    ) -> Result<(), AutomergeError> {
+        let converter = IndexConverter::new(self, obj.as_ref(), None, encoding)?;
+        mark.start = converter.to_native(mark.start)?;
+        mark.end = converter.to_native(mark.end)?;
+        self.mark(obj, mark, expand)
+    }
+
+    /// Like [`Self::unmark`] but `start` and `end` are measured in `encoding`
+    fn unmark_with_encoding<O: AsRef<ExId>, E: Into<Option<ExpandMark>>>(
+        &mut self,
+        obj: O,
+        key: &str,
+        start: usize,
+        end: usize,
+        expand: E,
+        encoding: TextEncoding,
+    ) -> Result<(), AutomergeError> {
+        let converter = IndexConverter::new(self, obj.as_ref(), None, encoding)?;
+        let start = converter.to_native(start)?;
+        let end = converter.to_native(end)?;
+        self.unmark(obj, key, start, end, expand)
+    }
+
     /// Remove a Mark from a sequence
-    fn unmark<O: AsRef<ExId>>(
+    ///
+    /// As with [`Self::mark()`] `expand` may be `None` to use the expansion registered for `key`.
+    /// If the mark is exclusive (see [`crate::marks::MarkPolicy::exclusive`]) any mark named
+    /// `key` which overlaps the range is removed entirely.
+    fn unmark<O: AsRef<ExId>, E: Into<Option<ExpandMark>>>(
         &mut self,
         obj: O,
         key: &str,
         start: usize,
         end: usize,
-        expand: ExpandMark,
+        expand: E,
+    ) -> Result<(), AutomergeError>;
+
+    /// Insert a block marker at `index` in the text object `text`, splitting the block which
+    /// contains `index` in two. See [`Block`].
+    ///
+    /// Returns the ID of the map which stores the marker.
+    fn split_block<O: AsRef<ExId>>(
+        &mut self,
+        text: O,
+        index: usize,
+        block: Block,
+    ) -> Result<ExId, AutomergeError>;
+
+    /// Remove the block marker at `index` in the text object `text`, merging its block into the
+    /// previous one
+    ///
+    /// Returns [`AutomergeError::NotABlock`] if there is no block marker at `index`.
+    fn join_block<O: AsRef<ExId>>(&mut self, text: O, index: usize) -> Result<(), AutomergeError>;
+
+    /// Replace the type, parents and attributes of the block marker at `index` in the text object
+    /// `text` with those of `block`
+    ///
+    /// Only fields which differ from the current value are written, so concurrent updates to
+    /// different fields of the same block merge. Returns [`AutomergeError::NotABlock`] if there is
+    /// no block marker at `index`.
+    fn update_block<O: AsRef<ExId>>(
+        &mut self,
+        text: O,
+        index: usize,
+        block: Block,
     ) -> Result<(), AutomergeError>;
 
     /// The heads this transaction will be based on
@@ -120,6 +253,56 @@ pub trait Transactable: ReadDoc {
     /// which don't merge as well as directly capturing the user input actions, but
     /// sometimes it's not possible to capture user input and this is the best you
     /// can do.
-    fn update_text<S: AsRef<str>>(&mut self, obj: &ExId, new_text: S)
-        -> Result<(), AutomergeError>;
+    ///
+    /// This diffs grapheme clusters using Myers' algorithm, see [`Self::update_text_with_options()`]
+    /// for other ways of diffing.
+    fn update_text<S: AsRef<str>>(
+        &mut self,
+        obj: &ExId,
+        new_text: S,
+    ) -> Result<(), AutomergeError> {
+        self.update_text_with_options(obj, new_text, UpdateTextOptions::default())
+    }
+
+    /// Update the value of a text object line by line
+    ///
+    /// Like [`Self::update_text()`] but the lines of the text are diffed first, so unchanged
+    /// lines are left untouched and cursors and marks on them stay where they were, then the
+    /// characters of changed lines are diffed. This suits source code and configuration files.
+    /// See [`crate::ReadDoc::lines()`] to read the text back line by line.
+    fn update_lines<S: AsRef<str>>(
+        &mut self,
+        obj: &ExId,
+        new_text: S,
+    ) -> Result<(), AutomergeError> {
+        let options =
+            UpdateTextOptions::new(DiffAlgorithm::Histogram, DiffGranularity::LineThenGrapheme);
+        self.update_text_with_options(obj, new_text, options)
+    }
+
+    /// Like [`Self::update_text()`] but `options` choose how the diff is calculated
+    ///
+    /// Myers' algorithm on grapheme clusters finds the smallest edit but can be very slow when
+    /// large parts of the text have changed, and can produce edits which interleave oddly with
+    /// concurrent changes. Patience or histogram diffs of words or lines are much faster on
+    /// large rewrites and produce edits which follow the structure of the text, and
+    /// [`UpdateTextOptions::with_max_cost()`] puts a bound on how long the diff can take.
+    ///
+    /// ```
+    /// # use automerge::{AutoCommit, ObjType, ReadDoc, ROOT};
+    /// # use automerge::transaction::{DiffAlgorithm, DiffGranularity, Transactable, UpdateTextOptions};
+    /// let mut doc = AutoCommit::new();
+    /// let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// doc.update_text(&text, "one\ntwo\nthree\n").unwrap();
+    /// let options = UpdateTextOptions::new(DiffAlgorithm::Histogram, DiffGranularity::LineThenGrapheme)
+    ///     .with_max_cost(10_000);
+    /// doc.update_text_with_options(&text, "one\n2\nthree\n", options).unwrap();
+    /// assert_eq!(doc.text(&text).unwrap(), "one\n2\nthree\n");
+    /// ```
+    fn update_text_with_options<S: AsRef<str>>(
+        &mut self,
+        obj: &ExId,
+        new_text: S,
+        options: UpdateTextOptions,
+    ) -> Result<(), AutomergeError>;
 }
@@ -0,0 +1,548 @@
+//! Structured diffs between two points in history
+//!
+//! [`crate::Automerge::diff()`] returns the patches which update a copy of the document from one
+//! set of heads to another, which suits keeping a UI in sync. [`crate::Automerge::tree_diff()`]
+//! describes the same differences for a person to read instead: grouped by object, with the
+//! values on both sides, subtrees which were deleted or moved as a whole, text as hunks of lines
+//! and a summary of the whole diff.
+//!
+//! ```
+//! # use automerge::{AutoCommit, ObjType, ROOT, transaction::Transactable};
+//! # use automerge::tree_diff::{Line, ObjChanges, ValueChange};
+//! let mut doc = AutoCommit::new();
+//! doc.put(ROOT, "title", "draft").unwrap();
+//! let notes = doc.put_object(ROOT, "notes", ObjType::Text).unwrap();
+//! doc.splice_text(&notes, 0, 0, "one\ntwo\nthree").unwrap();
+//! let before = doc.get_heads();
+//! doc.put(ROOT, "title", "final").unwrap();
+//! doc.splice_text(&notes, 4, 3, "2").unwrap();
+//! let after = doc.get_heads();
+//!
+//! let diff = doc.tree_diff(&before, &after);
+//! assert_eq!(diff.summary.updated, 1);
+//! assert_eq!((diff.summary.lines_added, diff.summary.lines_removed), (1, 1));
+//! assert_eq!(
+//!     diff.objects[0].changes,
+//!     ObjChanges::Values(vec![ValueChange::Updated {
+//!         prop: "title".into(),
+//!         before: "draft".into(),
+//!         after: "final".into(),
+//!     }])
+//! );
+//! let hunks = match &diff.objects[1].changes {
+//!     ObjChanges::Text(hunks) => hunks,
+//!     _ => unreachable!(),
+//! };
+//! assert_eq!(
+//!     hunks[0].lines,
+//!     vec![
+//!         Line::Context("one".to_string()),
+//!         Line::Removed("two".to_string()),
+//!         Line::Added("2".to_string()),
+//!         Line::Context("three".to_string()),
+//!     ]
+//! );
+//! ```
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::exid::ExId;
+use crate::hydrate;
+use crate::text_diff::{diff_sequences, Run};
+use crate::{Automerge, ChangeHash, ObjType, Prop, ReadDoc, Value, View};
+
+/// The number of unchanged lines around the changed lines of a [`Hunk`]
+const CONTEXT_LINES: usize = 3;
+
+/// The differences between two sets of heads, returned by [`crate::Automerge::tree_diff()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeDiff {
+    /// The objects which exist at both heads and whose contents differ, depth first from the
+    /// root. Objects which were added or deleted are part of the [`ValueChange`] which added or
+    /// deleted them.
+    pub objects: Vec<ObjDiff>,
+    /// Objects which were deleted from one place and an identical copy added at another
+    pub moved: Vec<Move>,
+    pub summary: Summary,
+}
+
+/// The differences in one object
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjDiff {
+    pub obj: ExId,
+    /// The path from the root to the object, as at the later heads
+    pub path: Vec<Prop>,
+    pub changes: ObjChanges,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjChanges {
+    /// The changed values of a map or list, in the order of their keys or indexes
+    Values(Vec<ValueChange>),
+    /// The changed lines of a text object
+    Text(Vec<Hunk>),
+}
+
+/// A value of a map or list which is different at the two heads
+///
+/// Values are hydrated as they are at the heads they come from, so a deleted object includes
+/// everything that was in it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueChange {
+    /// A value which is only at the later heads. The index is at the later heads.
+    Added { prop: Prop, value: hydrate::Value },
+    /// A value which is only at the earlier heads. The index is at the earlier heads.
+    Deleted { prop: Prop, value: hydrate::Value },
+    /// A value which was replaced by a different one, or a counter which was incremented. The
+    /// index is at the later heads.
+    Updated {
+        prop: Prop,
+        before: hydrate::Value,
+        after: hydrate::Value,
+    },
+}
+
+/// An object which was deleted and a copy of it, with the same contents, added somewhere else
+#[derive(Debug, Clone, PartialEq)]
+pub struct Move {
+    /// The path of the object which was deleted
+    pub from: Vec<Prop>,
+    /// The path of the copy which was added
+    pub to: Vec<Prop>,
+    pub value: hydrate::Value,
+}
+
+/// A run of changed lines in a text object, with up to three unchanged lines either side
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    /// The line the hunk starts at in the text at the earlier heads, counting from zero
+    pub before_start: usize,
+    /// The number of lines of the hunk which are in the text at the earlier heads
+    pub before_len: usize,
+    /// The line the hunk starts at in the text at the later heads, counting from zero
+    pub after_start: usize,
+    /// The number of lines of the hunk which are in the text at the later heads
+    pub after_len: usize,
+    pub lines: Vec<Line>,
+}
+
+/// A line of a [`Hunk`], without its newline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    /// A line which is the same at both heads
+    Context(String),
+    /// A line which is only at the earlier heads
+    Removed(String),
+    /// A line which is only at the later heads
+    Added(String),
+}
+
+/// The number of changes of each kind in a [`TreeDiff`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub added: usize,
+    pub deleted: usize,
+    pub updated: usize,
+    pub moved: usize,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+}
+
+impl TreeDiff {
+    pub(crate) fn new(doc: &Automerge, before: &[ChangeHash], after: &[ChangeHash]) -> Self {
+        let mut walker = Walker {
+            doc,
+            before: doc.view_at(before),
+            after: doc.view_at(after),
+            objects: Vec::new(),
+            touched: HashSet::new(),
+            routes: HashMap::new(),
+        };
+        walker.find_touched();
+        walker.walk(ExId::Root, ObjType::Map, Vec::new());
+        let mut diff = TreeDiff {
+            objects: walker.objects,
+            moved: Vec::new(),
+            summary: Summary::default(),
+        };
+        diff.find_moves();
+        diff.summarise();
+        diff
+    }
+
+    /// Whether nothing differs between the heads
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty() && self.moved.is_empty()
+    }
+
+    /// Pair up deleted objects with identical objects added elsewhere
+    fn find_moves(&mut self) {
+        let mut deleted = Vec::new();
+        let mut added = Vec::new();
+        for (o, obj) in self.objects.iter().enumerate() {
+            if let ObjChanges::Values(changes) = &obj.changes {
+                for (c, change) in changes.iter().enumerate() {
+                    match change {
+                        ValueChange::Deleted { value, .. } if value.is_object() => {
+                            deleted.push((o, c))
+                        }
+                        ValueChange::Added { value, .. } if value.is_object() => added.push((o, c)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let mut moves = Vec::new();
+        let mut moved = BTreeSet::new();
+        for (from_obj, from_change) in deleted {
+            let (from, value) = match self.value_change(from_obj, from_change) {
+                ValueChange::Deleted { prop, value } => (prop, value),
+                _ => continue,
+            };
+            let found = added.iter().position(|(o, c)| {
+                matches!(self.value_change(*o, *c), ValueChange::Added { value: v, .. } if v == value)
+            });
+            if let Some(found) = found {
+                let (to_obj, to_change) = added.remove(found);
+                let to = match self.value_change(to_obj, to_change) {
+                    ValueChange::Added { prop, .. } => prop,
+                    _ => continue,
+                };
+                moves.push(Move {
+                    from: self.path_to(from_obj, from.clone()),
+                    to: self.path_to(to_obj, to.clone()),
+                    value: value.clone(),
+                });
+                moved.insert((from_obj, from_change));
+                moved.insert((to_obj, to_change));
+            }
+        }
+        self.moved = moves;
+        for (o, obj) in self.objects.iter_mut().enumerate() {
+            if let ObjChanges::Values(changes) = &mut obj.changes {
+                let mut c = 0;
+                changes.retain(|_| {
+                    c += 1;
+                    !moved.contains(&(o, c - 1))
+                });
+            }
+        }
+        self.objects.retain(|obj| !obj.changes.is_empty());
+    }
+
+    fn value_change(&self, obj: usize, change: usize) -> &ValueChange {
+        match &self.objects[obj].changes {
+            ObjChanges::Values(changes) => &changes[change],
+            ObjChanges::Text(_) => unreachable!("only maps and lists have value changes"),
+        }
+    }
+
+    fn path_to(&self, obj: usize, prop: Prop) -> Vec<Prop> {
+        let mut path = self.objects[obj].path.clone();
+        path.push(prop);
+        path
+    }
+
+    fn summarise(&mut self) {
+        let mut summary = Summary {
+            moved: self.moved.len(),
+            ..Summary::default()
+        };
+        for obj in &self.objects {
+            match &obj.changes {
+                ObjChanges::Values(changes) => {
+                    for change in changes {
+                        match change {
+                            ValueChange::Added { .. } => summary.added += 1,
+                            ValueChange::Deleted { .. } => summary.deleted += 1,
+                            ValueChange::Updated { .. } => summary.updated += 1,
+                        }
+                    }
+                }
+                ObjChanges::Text(hunks) => {
+                    for line in hunks.iter().flat_map(|h| &h.lines) {
+                        match line {
+                            Line::Added(_) => summary.lines_added += 1,
+                            Line::Removed(_) => summary.lines_removed += 1,
+                            Line::Context(_) => {}
+                        }
+                    }
+                }
+            }
+        }
+        self.summary = summary;
+    }
+}
+
+impl ObjChanges {
+    fn is_empty(&self) -> bool {
+        match self {
+            ObjChanges::Values(changes) => changes.is_empty(),
+            ObjChanges::Text(hunks) => hunks.is_empty(),
+        }
+    }
+}
+
+struct Walker<'a> {
+    doc: &'a Automerge,
+    before: View<'a>,
+    after: View<'a>,
+    objects: Vec<ObjDiff>,
+    /// The objects which the changes between the heads modified and which are at the same place
+    /// at both heads. Nothing else can differ.
+    touched: HashSet<ExId>,
+    /// The children which lead to touched objects, by the object they are in
+    routes: HashMap<ExId, BTreeMap<Prop, ExId>>,
+}
+
+/// A value as read from a view
+type Item = (Value<'static>, ExId);
+
+impl<'a> Walker<'a> {
+    fn find_touched(&mut self) {
+        let objs = self
+            .before
+            .symmetric_difference(&self.after)
+            .into_iter()
+            .flat_map(|change| self.doc.change_op_keys(change))
+            .map(|(obj, _)| obj)
+            .collect::<HashSet<_>>();
+        for obj in objs {
+            let obj = self.doc.id_to_exid(obj.0);
+            let path = |view: &View<'_>| view.parents(&obj).ok()?.visible_path();
+            let (before, after) = match (path(&self.before), path(&self.after)) {
+                (Some(before), Some(after)) => (before, after),
+                _ => continue,
+            };
+            // an object which was added, deleted or moved is part of the changes to its parent
+            if !before
+                .iter()
+                .map(|(o, _)| o)
+                .eq(after.iter().map(|(o, _)| o))
+            {
+                continue;
+            }
+            let mut child = obj.clone();
+            for (parent, prop) in after.into_iter().rev() {
+                self.routes
+                    .entry(parent.clone())
+                    .or_default()
+                    .insert(prop, child);
+                child = parent;
+            }
+            self.touched.insert(obj);
+        }
+    }
+
+    fn walk(&mut self, obj: ExId, typ: ObjType, path: Vec<Prop>) {
+        let mut children = Vec::new();
+        if self.touched.contains(&obj) {
+            let changes = match typ {
+                ObjType::Map | ObjType::Table => {
+                    ObjChanges::Values(self.diff_map(&obj, &mut children))
+                }
+                ObjType::List => ObjChanges::Values(self.diff_list(&obj, &mut children)),
+                ObjType::Text => ObjChanges::Text(self.diff_text(&obj)),
+            };
+            if !changes.is_empty() {
+                self.objects.push(ObjDiff {
+                    obj: obj.clone(),
+                    path: path.clone(),
+                    changes,
+                });
+            }
+            children.retain(|(_, child, _)| {
+                self.touched.contains(child) || self.routes.contains_key(child)
+            });
+        } else if let Some(route) = self.routes.get(&obj) {
+            children = route
+                .iter()
+                .filter_map(|(prop, child)| {
+                    let typ = self.doc.object_type(child).ok()?;
+                    Some((prop.clone(), child.clone(), typ))
+                })
+                .collect();
+        }
+        for (prop, child, typ) in children {
+            let mut path = path.clone();
+            path.push(prop);
+            self.walk(child, typ, path);
+        }
+    }
+
+    fn diff_map(&self, obj: &ExId, children: &mut Vec<(Prop, ExId, ObjType)>) -> Vec<ValueChange> {
+        let read = |view: &View<'_>| {
+            view.map_range(obj, ..)
+                .map(|item| (item.key.to_string(), (item.value.into_owned(), item.id)))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let (before, after) = (read(&self.before), read(&self.after));
+        let keys = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+        let mut changes = Vec::new();
+        for key in keys {
+            let prop = Prop::Map(key.clone());
+            match (before.get(key), after.get(key)) {
+                (Some(b), Some(a)) => self.compare(prop, b, a, &mut changes, children),
+                (Some(b), None) => changes.push(ValueChange::Deleted {
+                    prop,
+                    value: self.hydrate(&self.before, b),
+                }),
+                (None, Some(a)) => changes.push(ValueChange::Added {
+                    prop,
+                    value: self.hydrate(&self.after, a),
+                }),
+                (None, None) => {}
+            }
+        }
+        changes
+    }
+
+    fn diff_list(&self, obj: &ExId, children: &mut Vec<(Prop, ExId, ObjType)>) -> Vec<ValueChange> {
+        let read = |view: &View<'_>| {
+            view.list_range(obj, ..)
+                .map(|item| (item.value.into_owned(), item.id))
+                .collect::<Vec<_>>()
+        };
+        let (before, after) = (read(&self.before), read(&self.after));
+        let before_ids = before.iter().map(|(_, id)| id).collect::<Vec<_>>();
+        let after_ids = after.iter().map(|(_, id)| id).collect::<Vec<_>>();
+        let mut changes = Vec::new();
+        for run in diff_sequences(&before_ids, &after_ids) {
+            match run {
+                Run::Equal(old, new) => {
+                    for (i, j) in old.zip(new) {
+                        let prop = Prop::Seq(j);
+                        self.compare(prop, &before[i], &after[j], &mut changes, children);
+                    }
+                }
+                Run::Changed(old, new) => {
+                    // elements replaced one for one are updates
+                    let replaced = old.len().min(new.len());
+                    for k in 0..replaced {
+                        changes.push(ValueChange::Updated {
+                            prop: Prop::Seq(new.start + k),
+                            before: self.hydrate(&self.before, &before[old.start + k]),
+                            after: self.hydrate(&self.after, &after[new.start + k]),
+                        });
+                    }
+                    let deleted = old.start + replaced..old.end;
+                    for (i, value) in deleted.clone().zip(&before[deleted]) {
+                        changes.push(ValueChange::Deleted {
+                            prop: Prop::Seq(i),
+                            value: self.hydrate(&self.before, value),
+                        });
+                    }
+                    let added = new.start + replaced..new.end;
+                    for (j, value) in added.clone().zip(&after[added]) {
+                        changes.push(ValueChange::Added {
+                            prop: Prop::Seq(j),
+                            value: self.hydrate(&self.after, value),
+                        });
+                    }
+                }
+            }
+        }
+        changes
+    }
+
+    fn diff_text(&self, obj: &ExId) -> Vec<Hunk> {
+        let before = self.before.text(obj).unwrap_or_default();
+        let after = self.after.text(obj).unwrap_or_default();
+        if before == after {
+            return Vec::new();
+        }
+        let old = before.split('\n').collect::<Vec<_>>();
+        let new = after.split('\n').collect::<Vec<_>>();
+
+        // every line of both texts in order, with its line number in each
+        let mut lines = Vec::new();
+        for run in diff_sequences(&old, &new) {
+            match run {
+                Run::Equal(old_lines, new_lines) => {
+                    for (i, j) in old_lines.zip(new_lines) {
+                        lines.push((Line::Context(old[i].to_string()), i, j));
+                    }
+                }
+                Run::Changed(old_lines, new_lines) => {
+                    for i in old_lines.clone() {
+                        lines.push((Line::Removed(old[i].to_string()), i, new_lines.start));
+                    }
+                    for j in new_lines {
+                        lines.push((Line::Added(new[j].to_string()), old_lines.end, j));
+                    }
+                }
+            }
+        }
+
+        let changed = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, (line, _, _))| !matches!(line, Line::Context(_)))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let mut hunks = Vec::new();
+        let mut next = 0;
+        while next < changed.len() {
+            let first = changed[next];
+            let mut last = first;
+            next += 1;
+            // changes with few enough unchanged lines between them share a hunk
+            while next < changed.len() && changed[next] - last <= 2 * CONTEXT_LINES + 1 {
+                last = changed[next];
+                next += 1;
+            }
+            let hunk = &lines
+                [first.saturating_sub(CONTEXT_LINES)..(last + CONTEXT_LINES + 1).min(lines.len())];
+            let (_, before_start, after_start) = hunk[0];
+            hunks.push(Hunk {
+                before_start,
+                before_len: hunk
+                    .iter()
+                    .filter(|(line, _, _)| !matches!(line, Line::Added(_)))
+                    .count(),
+                after_start,
+                after_len: hunk
+                    .iter()
+                    .filter(|(line, _, _)| !matches!(line, Line::Removed(_)))
+                    .count(),
+                lines: hunk.iter().map(|(line, _, _)| line.clone()).collect(),
+            });
+        }
+        hunks
+    }
+
+    /// Compare the values of a key or element at both heads
+    fn compare(
+        &self,
+        prop: Prop,
+        before: &Item,
+        after: &Item,
+        changes: &mut Vec<ValueChange>,
+        children: &mut Vec<(Prop, ExId, ObjType)>,
+    ) {
+        if before.1 != after.1 {
+            changes.push(ValueChange::Updated {
+                prop,
+                before: self.hydrate(&self.before, before),
+                after: self.hydrate(&self.after, after),
+            });
+        } else if let Value::Object(typ) = after.0 {
+            children.push((prop, after.1.clone(), typ));
+        } else if before.0 != after.0 {
+            // the same counter, incremented
+            changes.push(ValueChange::Updated {
+                prop,
+                before: before.0.clone().into(),
+                after: after.0.clone().into(),
+            });
+        }
+    }
+
+    fn hydrate(&self, view: &View<'_>, (value, id): &Item) -> hydrate::Value {
+        match (value, self.doc.exid_to_obj(id)) {
+            (Value::Object(typ), Ok(obj)) => {
+                self.doc.hydrate_obj(&obj.id, *typ, Some(view.clock()))
+            }
+            (value, _) => value.clone().into(),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error;
+use crate::links::Link;
 use crate::types::ObjType;
 use serde::{Deserialize, Serialize, Serializer};
 use smol_str::SmolStr;
@@ -240,6 +241,13 @@ impl<'a> Value<'a> {
             _ => None,
         }
     }
+
+    pub fn to_link(&self) -> Option<&Link> {
+        match self {
+            Value::Scalar(s) => s.to_link(),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> fmt::Display for Value<'a> {
@@ -275,6 +283,12 @@ impl<'a> From<SmolStr> for Value<'a> {
     }
 }
 
+impl<'a> From<Link> for Value<'a> {
+    fn from(link: Link) -> Self {
+        Value::Scalar(Cow::Owned(ScalarValue::from(link)))
+    }
+}
+
 impl<'a> From<char> for Value<'a> {
     fn from(c: char) -> Self {
         Value::Scalar(Cow::Owned(ScalarValue::Str(SmolStr::new(c.to_string()))))
@@ -446,6 +460,8 @@ pub enum ScalarValue {
     Counter(Counter),
     Timestamp(i64),
     Boolean(bool),
+    /// A link to another document, see [`crate::links`]
+    Link(Box<Link>),
     /// A value from a future version of automerge
     Unknown {
         type_code: u8,
@@ -584,6 +600,10 @@ impl ScalarValue {
         matches!(self, Self::Null)
     }
 
+    pub fn is_link(&self) -> bool {
+        matches!(self, Self::Link(_))
+    }
+
     pub fn into_bytes(self) -> Result<Vec<u8>, Self> {
         match self {
             ScalarValue::Bytes(b) => Ok(b),
@@ -653,6 +673,13 @@ impl ScalarValue {
         }
     }
 
+    pub fn to_link(&self) -> Option<&Link> {
+        match self {
+            ScalarValue::Link(l) => Some(l.as_ref()),
+            _ => None,
+        }
+    }
+
     pub fn counter(n: i64) -> ScalarValue {
         ScalarValue::Counter(n.into())
     }
@@ -724,6 +751,12 @@ impl From<()> for ScalarValue {
     }
 }
 
+impl From<Link> for ScalarValue {
+    fn from(link: Link) -> Self {
+        ScalarValue::Link(Box::new(link))
+    }
+}
+
 impl From<char> for ScalarValue {
     fn from(c: char) -> Self {
         ScalarValue::Str(SmolStr::new(c.to_string()))
@@ -742,6 +775,7 @@ impl fmt::Display for ScalarValue {
             ScalarValue::Timestamp(i) => write!(f, "Timestamp: {}", i),
             ScalarValue::Boolean(b) => write!(f, "{}", b),
             ScalarValue::Null => write!(f, "null"),
+            ScalarValue::Link(l) => write!(f, "Link: {}", l),
             ScalarValue::Unknown { type_code, .. } => write!(f, "unknown type {}", type_code),
         }
     }
@@ -0,0 +1,168 @@
+use automerge::annotations::{self, AnnotationChange};
+use automerge::transaction::Transactable;
+use automerge::{AutoCommit, ObjType, ReadDoc, ROOT};
+
+#[test]
+fn overlapping_annotations_survive_merges() {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let comments = doc1.put_object(ROOT, "comments", ObjType::List).unwrap();
+    doc1.splice_text(&text, 0, 0, "the quick brown fox")
+        .unwrap();
+    let mut doc2 = doc1.fork();
+
+    // two concurrent, overlapping comment threads
+    let first =
+        annotations::add_annotation(&mut doc1, &comments, &text, 4..15, ObjType::Map).unwrap();
+    doc1.put(&first.data, "body", "which fox?").unwrap();
+    let second =
+        annotations::add_annotation(&mut doc2, &comments, &text, 10..19, ObjType::List).unwrap();
+    doc2.insert(&second.data, 0, "first!").unwrap();
+    doc2.splice_text(&text, 0, 0, "see ").unwrap();
+
+    doc1.merge(&mut doc2).unwrap();
+    doc2.merge(&mut doc1).unwrap();
+    for doc in [&doc1, &doc2] {
+        let all = annotations::annotations(doc, &comments, &text, None).unwrap();
+        let ranges = all.iter().map(|a| a.range.clone()).collect::<Vec<_>>();
+        assert_eq!(all.len(), 2);
+        assert!(ranges.contains(&(8..19)));
+        assert!(ranges.contains(&(14..23)));
+    }
+
+    let found = annotations::annotations_in(&doc1, &comments, &text, 0..10).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, first.id);
+    assert_eq!(
+        doc1.get(&found[0].data, "body")
+            .unwrap()
+            .unwrap()
+            .0
+            .to_str(),
+        Some("which fox?")
+    );
+    assert_eq!(
+        annotations::annotations_in(&doc1, &comments, &text, 16..17)
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // deleting the annotated text collapses the annotation
+    doc1.splice_text(&text, 14, 9, "").unwrap();
+    let found = annotations::annotations(&doc1, &comments, &text, None).unwrap();
+    let second = found.iter().find(|a| a.id == second.id).unwrap();
+    assert_eq!(second.range, 14..14);
+
+    annotations::remove_annotation(&mut doc1, &comments, &second.id).unwrap();
+    assert_eq!(
+        annotations::annotations(&doc1, &comments, &text, None)
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn annotation_changes_between_heads() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let comments = doc.put_object(ROOT, "comments", ObjType::List).unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    let a = annotations::add_annotation(&mut doc, &comments, &text, 0..5, ObjType::Map).unwrap();
+    let b = annotations::add_annotation(&mut doc, &comments, &text, 6..11, ObjType::Map).unwrap();
+    let before = doc.get_heads();
+
+    // typing inside "world" moves the end of `b`
+    doc.splice_text(&text, 8, 0, "r").unwrap();
+    let c = annotations::add_annotation(&mut doc, &comments, &text, 0..1, ObjType::Map).unwrap();
+    annotations::move_annotation(&mut doc, &text, &a.id, 1..3).unwrap();
+    annotations::remove_annotation(&mut doc, &comments, &c.id).unwrap();
+    let d = annotations::add_annotation(&mut doc, &comments, &text, 2..2, ObjType::Map).unwrap();
+    let after = doc.get_heads();
+
+    let mut changes =
+        annotations::diff_annotations(&doc, &comments, &text, &before, &after).unwrap();
+    changes.sort_by_key(|c| c.after.as_ref().map(|r| r.start));
+    assert_eq!(
+        changes,
+        vec![
+            AnnotationChange {
+                id: a.id,
+                before: Some(0..5),
+                after: Some(1..3),
+            },
+            AnnotationChange {
+                id: d.id,
+                before: None,
+                after: Some(2..2),
+            },
+            AnnotationChange {
+                id: b.id,
+                before: Some(6..11),
+                after: Some(6..12),
+            },
+        ]
+    );
+}
+
+#[test]
+fn annotation_changes_from_incremental_diffs() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let comments = doc.put_object(ROOT, "comments", ObjType::List).unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    let a = annotations::add_annotation(&mut doc, &comments, &text, 0..5, ObjType::Map).unwrap();
+    let b = annotations::add_annotation(&mut doc, &comments, &text, 6..11, ObjType::Map).unwrap();
+    doc.update_diff_cursor();
+
+    let next = |doc: &mut AutoCommit| {
+        let before = doc.diff_cursor();
+        let after = doc.get_heads();
+        let patches = doc.diff_incremental();
+        let mut changes =
+            annotations::annotation_changes(doc, &comments, &text, &before, &patches).unwrap();
+        let mut expected =
+            annotations::diff_annotations(doc, &comments, &text, &before, &after).unwrap();
+        changes.sort_by(|x, y| x.id.cmp(&y.id));
+        expected.sort_by(|x, y| x.id.cmp(&y.id));
+        assert_eq!(changes, expected);
+        changes
+    };
+
+    // editing the attached objects doesn't move anything
+    doc.put(&a.data, "body", "hi").unwrap();
+    assert!(next(&mut doc).is_empty());
+
+    annotations::move_annotation(&mut doc, &text, &a.id, 1..3).unwrap();
+    let c = annotations::add_annotation(&mut doc, &comments, &text, 2..2, ObjType::Map).unwrap();
+    assert_eq!(
+        next(&mut doc),
+        vec![
+            AnnotationChange {
+                id: a.id.clone(),
+                before: Some(0..5),
+                after: Some(1..3),
+            },
+            AnnotationChange {
+                id: c.id.clone(),
+                before: None,
+                after: Some(2..2),
+            },
+        ]
+    );
+
+    doc.splice_text(&text, 0, 0, "oh ").unwrap();
+    annotations::remove_annotation(&mut doc, &comments, &c.id).unwrap();
+    assert_eq!(next(&mut doc).len(), 3);
+
+    annotations::remove_annotation(&mut doc, &comments, &b.id).unwrap();
+    assert_eq!(
+        next(&mut doc),
+        vec![AnnotationChange {
+            id: b.id,
+            before: Some(9..14),
+            after: None,
+        }]
+    );
+}
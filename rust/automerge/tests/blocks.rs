@@ -0,0 +1,176 @@
+use automerge::blocks::Block;
+use automerge::iter::Span;
+use automerge::patches::TextRepresentation;
+use automerge::transaction::Transactable;
+use automerge::{AutoCommit, AutomergeError, ObjType, PatchAction, ReadDoc, ROOT};
+
+fn heading(level: i64) -> Block {
+    Block::new("heading").with_attr("level", level)
+}
+
+#[test]
+fn split_and_read_blocks() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "Titlebody").unwrap();
+    doc.split_block(&text, 0, heading(1)).unwrap();
+    doc.split_block(&text, 6, Block::new("paragraph")).unwrap();
+
+    assert_eq!(doc.text(&text).unwrap(), "\u{fffc}Title\u{fffc}body");
+    assert_eq!(
+        doc.spans(&text).unwrap().collect::<Vec<_>>(),
+        vec![
+            Span::Block(heading(1)),
+            Span::Text("Title".to_string(), None),
+            Span::Block(Block::new("paragraph")),
+            Span::Text("body".to_string(), None),
+        ]
+    );
+
+    let nested = Block::new("list-item").with_parents(["ordered-list", "unordered-list"]);
+    doc.update_block(&text, 6, nested.clone()).unwrap();
+    assert_eq!(doc.spans(&text).unwrap().nth(2), Some(Span::Block(nested)));
+
+    doc.join_block(&text, 6).unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "\u{fffc}Titlebody");
+
+    assert!(matches!(
+        doc.join_block(&text, 2),
+        Err(AutomergeError::NotABlock(2))
+    ));
+    assert!(matches!(
+        doc.update_block(&text, 2, heading(2)),
+        Err(AutomergeError::NotABlock(2))
+    ));
+}
+
+#[test]
+fn concurrent_block_changes_merge() {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.split_block(&text, 0, Block::new("paragraph")).unwrap();
+    doc1.splice_text(&text, 1, 0, "onetwo").unwrap();
+    let mut doc2 = doc1.fork();
+
+    // concurrent updates to different fields of the same block
+    doc1.update_block(
+        &text,
+        0,
+        Block::new("paragraph").with_attr("align", "center"),
+    )
+    .unwrap();
+    doc2.update_block(&text, 0, Block::new("heading")).unwrap();
+    // and a split on one side
+    doc2.split_block(&text, 4, Block::new("paragraph")).unwrap();
+
+    doc1.merge(&mut doc2).unwrap();
+    doc2.merge(&mut doc1).unwrap();
+
+    let expected = vec![
+        Span::Block(Block::new("heading").with_attr("align", "center")),
+        Span::Text("one".to_string(), None),
+        Span::Block(Block::new("paragraph")),
+        Span::Text("two".to_string(), None),
+    ];
+    assert_eq!(doc1.spans(&text).unwrap().collect::<Vec<_>>(), expected);
+    assert_eq!(doc2.spans(&text).unwrap().collect::<Vec<_>>(), expected);
+
+    // joining the same block concurrently removes it once
+    let mut doc3 = doc1.fork();
+    doc1.join_block(&text, 4).unwrap();
+    doc3.join_block(&text, 4).unwrap();
+    doc1.merge(&mut doc3).unwrap();
+    assert_eq!(doc1.text(&text).unwrap(), "\u{fffc}onetwo");
+}
+
+#[test]
+fn block_patches() {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "onetwo").unwrap();
+    let mut doc2 = doc1.fork().with_text_rep(TextRepresentation::String);
+    doc2.update_diff_cursor();
+
+    doc1.split_block(&text, 3, heading(1)).unwrap();
+    doc2.merge(&mut doc1).unwrap();
+    let patches = doc2.diff_incremental();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(
+        patches[0].action,
+        PatchAction::SplitBlock {
+            index: 3,
+            block: heading(1),
+        }
+    );
+
+    doc1.update_block(&text, 3, heading(2)).unwrap();
+    doc2.merge(&mut doc1).unwrap();
+    let patches = doc2.diff_incremental();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].obj, text);
+    assert_eq!(
+        patches[0].action,
+        PatchAction::UpdateBlock {
+            index: 3,
+            block: heading(2),
+        }
+    );
+
+    doc1.join_block(&text, 3).unwrap();
+    doc2.merge(&mut doc1).unwrap();
+    let patches = doc2.diff_incremental();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(
+        patches[0].action,
+        PatchAction::DeleteSeq {
+            index: 3,
+            length: 1,
+        }
+    );
+
+    // local changes are reported the same way
+    doc2.split_block(&text, 0, heading(1)).unwrap();
+    doc2.update_block(&text, 0, heading(3)).unwrap();
+    let patches = doc2.diff_incremental();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(
+        patches[0].action,
+        PatchAction::SplitBlock {
+            index: 0,
+            block: heading(3),
+        }
+    );
+}
+
+#[test]
+fn block_patches_from_scratch() {
+    let mut doc = AutoCommit::new().with_text_rep(TextRepresentation::String);
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.split_block(&text, 0, heading(1)).unwrap();
+    doc.splice_text(&text, 1, 0, "Title").unwrap();
+    doc.commit();
+
+    let mut loaded = AutoCommit::load(&doc.save())
+        .unwrap()
+        .with_text_rep(TextRepresentation::String);
+    for doc in [&mut doc, &mut loaded] {
+        let heads = doc.get_heads();
+        let actions = doc
+            .diff(&[], &heads)
+            .into_iter()
+            .filter(|p| p.obj == text)
+            .map(|p| p.action)
+            .collect::<Vec<_>>();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(
+            actions[0],
+            PatchAction::SplitBlock {
+                index: 0,
+                block: heading(1),
+            }
+        );
+        assert!(
+            matches!(&actions[1], PatchAction::SpliceText { index: 1, value, .. } if value.make_string() == "Title")
+        );
+    }
+}
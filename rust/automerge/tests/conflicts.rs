@@ -0,0 +1,116 @@
+use automerge::transaction::Transactable;
+use automerge::{
+    hydrate, hydrate_list, hydrate_map, ActorId, AutoCommit, AutomergeError, ObjType, PatchAction,
+    Prop, ReadDoc, Value, ROOT,
+};
+
+#[test]
+fn listing_conflicts() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "x").unwrap();
+    doc1.insert(&list, 1, "y").unwrap();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+
+    doc1.put(ROOT, "title", "ours").unwrap();
+    doc1.put(&list, 1, "ours").unwrap();
+    let ours = doc1.commit().unwrap();
+    let before = doc1.get_heads();
+    doc2.put(ROOT, "title", "theirs").unwrap();
+    doc2.put_object(&list, 1, ObjType::Map).unwrap();
+    let theirs = doc2.commit().unwrap();
+    doc1.merge(&mut doc2).unwrap();
+
+    let conflicts = doc1.conflicts(ROOT, None).unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].prop, Prop::from("title"));
+    let contenders = conflicts[0]
+        .values
+        .iter()
+        .map(|c| (c.value.to_str(), c.actor.clone(), c.hash))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        contenders,
+        vec![
+            (Some("ours"), ActorId::from(b"aaaa"), Some(ours)),
+            (Some("theirs"), ActorId::from(b"bbbb"), Some(theirs)),
+        ]
+    );
+
+    let in_list = doc1.conflicts(&list, None).unwrap();
+    assert_eq!(in_list.len(), 1);
+    assert_eq!(in_list[0].prop, Prop::Seq(1));
+    assert_eq!(in_list[0].values[1].value, Value::Object(ObjType::Map));
+
+    let all = doc1.all_conflicts(None).collect::<Vec<_>>();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].obj, ROOT);
+    assert_eq!(all[1].obj, list);
+    assert_eq!(doc1.all_conflicts(Some(&before)).count(), 0);
+}
+
+#[test]
+fn resolving_conflicts() {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc1.insert(&list, 0, "x").unwrap();
+    let mut doc2 = doc1.fork();
+    let mut doc3 = doc1.fork();
+
+    doc1.put(ROOT, "title", "ours").unwrap();
+    doc1.put(&list, 0, "ours").unwrap();
+    doc2.put(ROOT, "title", "theirs").unwrap();
+    let map = doc2.put_object(&list, 0, ObjType::Map).unwrap();
+    doc2.put(&map, "key", "value").unwrap();
+    doc3.put(ROOT, "title", "other").unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    doc1.merge(&mut doc3).unwrap();
+    assert_eq!(doc1.get_all(ROOT, "title").unwrap().len(), 3);
+
+    let theirs = doc1
+        .get_all(ROOT, "title")
+        .unwrap()
+        .into_iter()
+        .find(|(v, _)| v.to_str() == Some("theirs"))
+        .unwrap()
+        .1;
+    assert!(matches!(
+        doc1.resolve_conflict(&list, 0, &theirs),
+        Err(AutomergeError::InvalidContender(_))
+    ));
+
+    doc1.update_diff_cursor();
+    doc1.resolve_conflict(ROOT, "title", &theirs).unwrap();
+    doc1.resolve_conflict(&list, 0, &map).unwrap();
+    // resolving again does nothing
+    doc1.resolve_conflict(ROOT, "title", &theirs).unwrap();
+    assert_eq!(
+        doc1.get_all(ROOT, "title").unwrap(),
+        vec![(Value::from("theirs"), theirs.clone())]
+    );
+    assert_eq!(doc1.all_conflicts(None).count(), 0);
+    assert_eq!(
+        doc1.hydrate(None),
+        hydrate_map! {
+            "title" => "theirs",
+            "list" => hydrate_list![hydrate_map! {"key" => "value"}],
+        }
+    );
+
+    let patches = doc1.diff_incremental();
+    assert!(patches.iter().any(|p| matches!(
+        &p.action,
+        PatchAction::PutMap { key, conflict: false, .. } if key == "title"
+    )));
+
+    // the resolution survives saving and merging
+    let mut loaded = AutoCommit::load(&doc1.save()).unwrap();
+    assert_eq!(loaded.all_conflicts(None).count(), 0);
+    doc2.merge(&mut doc1).unwrap();
+    assert_eq!(doc2.hydrate(None), loaded.hydrate(None));
+    assert_eq!(
+        doc2.get(ROOT, "title").unwrap().unwrap().0.to_str(),
+        Some("theirs")
+    );
+    assert_eq!(loaded.get_heads(), doc2.get_heads());
+}
@@ -0,0 +1,156 @@
+use automerge::transaction::Transactable;
+use automerge::{
+    AutoCommit, CursorResolution, ObjType, ReadDoc, Selection, Stickiness, StickyCursor, ROOT,
+};
+
+#[test]
+fn stickiness_decides_where_concurrent_inserts_go() {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "ab").unwrap();
+    let mut doc2 = doc1.fork();
+
+    let left = doc1
+        .get_sticky_cursor(&text, 1, Stickiness::Left, None)
+        .unwrap();
+    let right = doc1
+        .get_sticky_cursor(&text, 1, Stickiness::Right, None)
+        .unwrap();
+    let start = doc1
+        .get_sticky_cursor(&text, 0, Stickiness::Left, None)
+        .unwrap();
+    let end = doc1
+        .get_sticky_cursor(&text, 2, Stickiness::Right, None)
+        .unwrap();
+    assert_eq!(start, StickyCursor::start());
+    assert_eq!(end, StickyCursor::end());
+
+    doc2.splice_text(&text, 2, 0, "!").unwrap();
+    doc2.splice_text(&text, 1, 0, "xyz").unwrap();
+    doc2.splice_text(&text, 0, 0, "<").unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(doc1.text(&text).unwrap(), "<axyzb!");
+
+    let position = |doc: &AutoCommit, c: &StickyCursor| {
+        doc.get_sticky_cursor_position(&text, c, None).unwrap()
+    };
+    assert_eq!(position(&doc1, &left), 2);
+    assert_eq!(position(&doc1, &right), 5);
+    assert_eq!(position(&doc1, &start), 0);
+    assert_eq!(position(&doc1, &end), 7);
+
+    // the element a cursor is attached to was deleted
+    doc1.delete(&text, 1).unwrap();
+    doc1.delete(&text, 4).unwrap();
+    assert_eq!(doc1.text(&text).unwrap(), "<xyz!");
+    assert_eq!(position(&doc1, &left), 1);
+    assert_eq!(position(&doc1, &right), 4);
+}
+
+#[test]
+fn selections_survive_merges() {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "hello world").unwrap();
+    let mut doc2 = doc1.fork();
+
+    // select "world" backwards
+    let selection = doc1.get_selection(&text, 11, 6, None).unwrap();
+    assert_eq!(
+        doc1.get_selection_position(&text, &selection, None)
+            .unwrap(),
+        (11, 6)
+    );
+    let caret = doc1.get_selection(&text, 5, 5, None).unwrap();
+
+    // inserts at the edges of the selection are not selected, inserts inside are
+    doc2.splice_text(&text, 11, 0, "!").unwrap();
+    doc2.splice_text(&text, 8, 0, "--").unwrap();
+    doc2.splice_text(&text, 6, 0, "big ").unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(doc1.text(&text).unwrap(), "hello big wo--rld!");
+    assert_eq!(
+        doc1.get_selection_position(&text, &selection, None)
+            .unwrap(),
+        (17, 10)
+    );
+    assert_eq!(
+        doc1.get_selection_position(&text, &caret, None).unwrap(),
+        (5, 5)
+    );
+
+    // deleting everything which was selected collapses the selection
+    doc2.merge(&mut doc1).unwrap();
+    doc2.splice_text(&text, 9, 8, "").unwrap();
+    doc1.splice_text(&text, 0, 0, ">> ").unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(doc1.text(&text).unwrap(), ">> hello big!");
+    assert_eq!(
+        doc1.get_selection_position(&text, &selection, None)
+            .unwrap(),
+        (12, 12)
+    );
+}
+
+#[test]
+fn selection_serialization() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello").unwrap();
+
+    for (anchor, head) in [(1, 3), (3, 1), (0, 5), (2, 2)] {
+        let selection = doc.get_selection(&text, anchor, head, None).unwrap();
+        let bytes = selection.to_bytes();
+        assert_eq!(Selection::try_from(bytes.as_slice()).unwrap(), selection);
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Selection::try_from(trailing.as_slice()).is_err());
+        let string = selection.to_string();
+        assert_eq!(Selection::try_from(string.as_str()).unwrap(), selection);
+    }
+    assert!(Selection::try_from("start").is_err());
+    assert!(Selection::try_from(&[0, 4][..]).is_err());
+}
+
+#[test]
+fn resolving_cursors_on_deleted_elements() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "a😀bcd").unwrap();
+    // how wide the emoji is depends on the encoding automerge was compiled with
+    let wide = doc.length(&text) - 4;
+    let cursor = doc.get_cursor(&text, 2 + wide, None).unwrap();
+    let first = doc.get_cursor(&text, 0, None).unwrap();
+    let last = doc.get_cursor(&text, 3 + wide, None).unwrap();
+    let heads = doc.get_heads();
+
+    doc.splice_text(&text, 1 + wide, 2, "").unwrap();
+    doc.delete(&text, 0).unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "😀d");
+
+    let position = |doc: &AutoCommit, cursor, resolution| {
+        doc.get_cursor_position_with_resolution(&text, cursor, resolution, None)
+            .unwrap()
+    };
+    assert_eq!(position(&doc, &cursor, CursorResolution::Following), wide);
+    assert_eq!(position(&doc, &cursor, CursorResolution::Preceding), 0);
+    assert_eq!(doc.get_cursor_position(&text, &cursor, None).unwrap(), wide);
+    assert_eq!(position(&doc, &first, CursorResolution::Preceding), 0);
+    assert_eq!(position(&doc, &first, CursorResolution::Following), 0);
+
+    doc.delete(&text, wide).unwrap();
+    assert_eq!(position(&doc, &last, CursorResolution::Preceding), 0);
+    assert_eq!(position(&doc, &last, CursorResolution::Following), wide);
+
+    // visible elements resolve to their own position whatever the resolution
+    assert_eq!(
+        doc.get_cursor_position_with_resolution(
+            &text,
+            &cursor,
+            CursorResolution::Preceding,
+            Some(&heads)
+        )
+        .unwrap(),
+        2 + wide
+    );
+}
@@ -0,0 +1,237 @@
+use automerge::transaction::{CommitOptions, Transactable};
+use automerge::{
+    hydrate, hydrate_list, hydrate_map, ActorId, AutoCommit, ChangeHash, ObjType, Prop, ReadDoc,
+    ScalarValue, Value, ROOT,
+};
+
+fn commit(doc: &mut AutoCommit, message: &str, time: i64) -> ChangeHash {
+    doc.commit_with(
+        CommitOptions::default()
+            .with_message(message)
+            .with_time(time),
+    )
+    .unwrap()
+}
+
+#[test]
+fn filtering_the_log() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    let first = commit(&mut doc1, "create list", 1000);
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+
+    doc1.insert(&list, 0, "a").unwrap();
+    let second = commit(&mut doc1, "insert a", 2000);
+    doc1.put(ROOT, "title", "hello").unwrap();
+    let third = commit(&mut doc1, "set title", 3000);
+
+    doc2.insert(&list, 0, "b").unwrap();
+    let concurrent = commit(&mut doc2, "insert b", 2500);
+
+    doc1.merge(&mut doc2).unwrap();
+    doc1.delete(&list, 0).unwrap();
+    let last = commit(&mut doc1, "delete", 4000);
+
+    let log = doc1.log().changes().unwrap();
+    assert_eq!(log.len(), 5);
+    assert_eq!(log[0].hash, first);
+    assert_eq!(log[0].message, Some("create list"));
+    assert_eq!(log[0].num_ops, 1);
+    assert_eq!(log[0].deps, &[] as &[ChangeHash]);
+    assert_eq!(log[4].hash, last);
+    assert_eq!(log[4].deps.len(), 2);
+
+    let hashes = |log: Vec<automerge::history::ChangeMeta<'_>>| {
+        log.into_iter().map(|c| c.hash).collect::<Vec<_>>()
+    };
+    let log = doc1.document().log();
+    assert_eq!(
+        hashes(
+            log.clone()
+                .actor(&ActorId::from(b"bbbb"))
+                .changes()
+                .unwrap()
+        ),
+        vec![concurrent]
+    );
+    assert_eq!(
+        hashes(log.clone().time_range(2000..3000).changes().unwrap()),
+        vec![second, concurrent]
+    );
+    assert_eq!(
+        hashes(log.clone().ancestors_of(&[second]).changes().unwrap()),
+        vec![first, second]
+    );
+    assert_eq!(
+        hashes(log.clone().descendants_of(&[third]).changes().unwrap()),
+        vec![third, last]
+    );
+    assert_eq!(
+        hashes(log.clone().touching(&list).changes().unwrap()),
+        vec![first, second, concurrent, last]
+    );
+    assert_eq!(
+        hashes(log.clone().touching(&ROOT).changes().unwrap()),
+        vec![first, third]
+    );
+    assert_eq!(
+        hashes(
+            log.actor(&ActorId::from(b"aaaa"))
+                .touching(&list)
+                .time_range(..4000)
+                .changes()
+                .unwrap()
+        ),
+        vec![first, second]
+    );
+}
+
+#[test]
+fn revisions_of_a_key() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    doc1.put(ROOT, "title", "draft").unwrap();
+    let first = doc1.commit().unwrap();
+    doc1.put(ROOT, "other", 1).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+
+    doc1.put(ROOT, "title", "final").unwrap();
+    let second = doc1.commit().unwrap();
+    doc2.put(ROOT, "title", "done").unwrap();
+    let concurrent = doc2.commit().unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    doc1.delete(ROOT, "title").unwrap();
+    let deleted = doc1.commit().unwrap();
+
+    let history = doc1.history_of_prop(ROOT, "title", None).unwrap();
+    let values = history
+        .iter()
+        .map(|r| (r.hash, r.value.as_ref().and_then(|(v, _)| v.to_str())))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        values,
+        vec![
+            (first, Some("draft")),
+            (second, Some("final")),
+            (concurrent, Some("done")),
+            (deleted, None),
+        ]
+    );
+
+    let history = doc1
+        .history_of_prop(ROOT, "title", Some(&[second]))
+        .unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(doc1
+        .history_of_prop(ROOT, "missing", None)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn revisions_of_objects_and_list_elements() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    let created = doc.commit().unwrap();
+    doc.insert(&list, 0, "a").unwrap();
+    doc.insert(&list, 1, ScalarValue::counter(1)).unwrap();
+    let inserted = doc.commit().unwrap();
+    let nested = doc.insert_object(&list, 0, ObjType::Map).unwrap();
+    let nested_created = doc.commit().unwrap();
+    doc.put(&nested, "x", 1).unwrap();
+    doc.commit();
+    doc.increment(&list, 2, 2).unwrap();
+    let incremented = doc.commit().unwrap();
+
+    let history = doc.history_of(&list, None).unwrap();
+    assert_eq!(
+        history.iter().map(|r| r.hash).collect::<Vec<_>>(),
+        vec![created, inserted, nested_created, incremented]
+    );
+    let values = |i: usize| {
+        history[i]
+            .value
+            .iter()
+            .map(|(prop, value)| (prop.clone(), value.clone().map(|v| v.0)))
+            .collect::<Vec<_>>()
+    };
+    assert!(values(0).is_empty());
+    assert_eq!(
+        values(1),
+        vec![
+            (Prop::Seq(0), Some(Value::from("a"))),
+            (Prop::Seq(1), Some(Value::counter(1)))
+        ]
+    );
+    assert_eq!(
+        values(2),
+        vec![(Prop::Seq(0), Some(Value::Object(ObjType::Map)))]
+    );
+    assert_eq!(values(3), vec![(Prop::Seq(2), Some(Value::counter(3)))]);
+    // the whole object as at a revision
+    assert_eq!(
+        doc.hydrate(Some(&[incremented])),
+        hydrate_map! {
+            "list" => hydrate_list![hydrate_map! {"x" => 1}, "a", ScalarValue::counter(3)],
+        }
+    );
+
+    // incremented counters are visible in historical reads
+    assert_eq!(
+        doc.get_at(&list, 2, &[incremented]).unwrap().unwrap().0,
+        Value::counter(3)
+    );
+
+    let counter = doc.history_of_prop(&list, 2, None).unwrap();
+    assert_eq!(
+        counter
+            .iter()
+            .map(|r| (r.hash, r.value.clone().unwrap().0))
+            .collect::<Vec<_>>(),
+        vec![
+            (inserted, Value::counter(1)),
+            (incremented, Value::counter(3))
+        ]
+    );
+}
+
+#[test]
+fn revisions_only_hold_the_keys_each_change_touched() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.put(ROOT, "b", 2).unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.splice(&list, 0, 0, ["x", "y", "z"].map(ScalarValue::from))
+        .unwrap();
+    doc.commit();
+    doc.delete(ROOT, "a").unwrap();
+    doc.delete(&list, 1).unwrap();
+    doc.commit();
+
+    let root = doc.history_of(ROOT, None).unwrap();
+    let props = root
+        .iter()
+        .map(|r| {
+            r.value
+                .iter()
+                .map(|(prop, value)| (prop.clone(), value.is_some()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        props,
+        vec![
+            vec![
+                (Prop::from("a"), true),
+                (Prop::from("b"), true),
+                (Prop::from("list"), true)
+            ],
+            vec![(Prop::from("a"), false)],
+        ]
+    );
+
+    // a deleted element has the index of the element after it
+    let list_history = doc.history_of(&list, None).unwrap();
+    assert_eq!(list_history.len(), 2);
+    assert_eq!(list_history[1].value, vec![(Prop::Seq(1), None)]);
+}
@@ -0,0 +1,120 @@
+use std::collections::{BTreeSet, HashMap};
+
+use automerge::links::{self, Link, OutboundLink, SyncTarget};
+use automerge::transaction::Transactable;
+use automerge::{AutoCommit, Automerge, AutomergeError, ChangeHash, ObjType, ReadDoc, ROOT};
+
+#[test]
+fn links_are_saved_and_enumerated() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "title", "post").unwrap();
+    doc.put(ROOT, "author", Link::new("ada")).unwrap();
+    let refs = doc.put_object(ROOT, "refs", ObjType::List).unwrap();
+    let pinned = Link::at("spec", &[ChangeHash([1; 32]), ChangeHash([2; 32])]);
+    doc.insert(&refs, 0, "not a link").unwrap();
+    doc.insert(&refs, 1, pinned.clone()).unwrap();
+    let heads = doc.get_heads();
+    doc.delete(ROOT, "author").unwrap();
+
+    let loaded = AutoCommit::load(&doc.save()).unwrap();
+    assert_eq!(
+        loaded.get(&refs, 1).unwrap().unwrap().0.to_link(),
+        Some(&pinned)
+    );
+    assert_eq!(
+        links::links(&loaded),
+        vec![OutboundLink {
+            obj: refs.clone(),
+            prop: 1.into(),
+            link: pinned,
+        }]
+    );
+    // the deleted link is still there in the history
+    let before = links::links(&loaded.view_at(&heads));
+    assert_eq!(before.len(), 2);
+    assert_eq!(before[0].link, Link::new("ada"));
+}
+
+#[test]
+fn following_links() {
+    let mut author = AutoCommit::new();
+    author.put(ROOT, "name", "Ada").unwrap();
+    let first = author.get_heads();
+    author.put(ROOT, "name", "Ada Lovelace").unwrap();
+
+    let mut docs = HashMap::new();
+    docs.insert("ada".to_string(), author.document().clone());
+
+    let latest = links::follow(&Link::new("ada"), &docs).unwrap();
+    assert_eq!(
+        latest.get(ROOT, "name").unwrap().unwrap().0.to_str(),
+        Some("Ada Lovelace")
+    );
+    let pinned = links::follow(&Link::at("ada", &first), &docs).unwrap();
+    assert_eq!(
+        pinned.get(ROOT, "name").unwrap().unwrap().0.to_str(),
+        Some("Ada")
+    );
+
+    assert!(matches!(
+        links::follow(&Link::new("bob"), &docs),
+        Err(AutomergeError::UnresolvedLink(doc)) if doc == "bob"
+    ));
+    assert!(matches!(
+        links::follow(&Link::at("ada", &[ChangeHash([7; 32])]), &docs),
+        Err(AutomergeError::MissingHash(_))
+    ));
+}
+
+#[test]
+fn sync_set_follows_links_transitively() {
+    let mut index = AutoCommit::new();
+    index.put(ROOT, "back", Link::new("root")).unwrap();
+    let old = index.get_heads();
+    index.put(ROOT, "old", Link::new("missing")).unwrap();
+    let pinned = index.get_heads();
+    index.delete(ROOT, "old").unwrap();
+    index.put(ROOT, "new", Link::new("chapter")).unwrap();
+
+    let mut docs: HashMap<String, Automerge> = HashMap::new();
+    docs.insert("index".to_string(), index.document().clone());
+    docs.insert("chapter".to_string(), Automerge::new());
+
+    let mut root = AutoCommit::new();
+    root.put(ROOT, "latest", Link::new("index")).unwrap();
+    root.put(ROOT, "pinned", Link::at("index", &pinned))
+        .unwrap();
+    root.put(ROOT, "older", Link::at("index", &old)).unwrap();
+
+    let mut index_heads = old.iter().copied().collect::<BTreeSet<_>>();
+    index_heads.extend(pinned.iter().copied());
+    assert_eq!(
+        links::sync_set(&root, &docs),
+        vec![
+            SyncTarget {
+                doc: "chapter".to_string(),
+                heads: BTreeSet::new(),
+                latest: true,
+                resolved: true,
+            },
+            SyncTarget {
+                doc: "index".to_string(),
+                heads: index_heads,
+                latest: true,
+                resolved: true,
+            },
+            SyncTarget {
+                doc: "missing".to_string(),
+                heads: BTreeSet::new(),
+                latest: true,
+                resolved: false,
+            },
+            SyncTarget {
+                doc: "root".to_string(),
+                heads: BTreeSet::new(),
+                latest: true,
+                resolved: false,
+            },
+        ]
+    );
+}
@@ -0,0 +1,141 @@
+use automerge::refs::RefKind;
+use automerge::transaction::Transactable;
+use automerge::{AutoCommit, Automerge, AutomergeError, ReadDoc, SaveOptions, ROOT};
+
+#[test]
+fn checked_out_branches_follow_commits() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "title", "draft").unwrap();
+    doc.create_branch("main").unwrap();
+    doc.create_branch("feature").unwrap();
+    doc.create_tag("v1").unwrap();
+    let v1 = doc.get_heads();
+
+    let mut feature = doc.checkout("feature").unwrap();
+    assert_eq!(feature.checked_out_branch(), Some("feature"));
+    feature.put(ROOT, "title", "final").unwrap();
+    let first = feature.commit().unwrap();
+    feature.put(ROOT, "done", true).unwrap();
+    let second = feature.commit().unwrap();
+    assert_eq!(feature.get_ref("feature").unwrap().heads, vec![second]);
+    assert_eq!(feature.get_ref("main").unwrap().heads, v1);
+
+    // concurrently, on main
+    let mut main = doc.checkout("main").unwrap();
+    main.put(ROOT, "author", "alex").unwrap();
+    let on_main = main.commit().unwrap();
+
+    doc.merge(&mut feature).unwrap();
+    doc.merge(&mut main).unwrap();
+    assert_eq!(doc.get_ref("feature").unwrap().heads, vec![second]);
+    assert_eq!(doc.get_ref("main").unwrap().heads, vec![on_main]);
+
+    let mut heads = doc.merge_branch("feature", "main").unwrap();
+    heads.sort();
+    let mut expected = vec![second, on_main];
+    expected.sort();
+    assert_eq!(heads, expected);
+    let merged = doc.checkout("main").unwrap().hydrate(None);
+    assert_eq!(merged, doc.hydrate(None));
+
+    // fast forwarding drops the old head
+    doc.create_branch_at("old", &[first]).unwrap();
+    assert_eq!(doc.merge_branch("feature", "old").unwrap(), vec![second]);
+
+    // tags can be checked out but don't move
+    let mut tagged = doc.checkout("v1").unwrap();
+    assert_eq!(tagged.checked_out_branch(), None);
+    assert_eq!(
+        tagged.get(ROOT, "title").unwrap().unwrap().0.to_str(),
+        Some("draft")
+    );
+    tagged.put(ROOT, "title", "other").unwrap();
+    tagged.commit();
+    assert_eq!(tagged.get_ref("v1").unwrap().heads, v1);
+    assert_eq!(tagged.get_ref("v1").unwrap().kind, RefKind::Tag);
+}
+
+#[test]
+fn ref_errors() {
+    let mut doc = Automerge::new();
+    doc.create_branch("main").unwrap();
+    doc.create_tag("v1").unwrap();
+    assert_eq!(
+        doc.create_tag("main"),
+        Err(AutomergeError::RefExists("main".to_string()))
+    );
+    assert_eq!(
+        doc.merge_branch("main", "v1"),
+        Err(AutomergeError::RefIsTag("v1".to_string()))
+    );
+    assert!(matches!(
+        doc.checkout("missing"),
+        Err(AutomergeError::MissingRef(_))
+    ));
+    assert!(matches!(
+        doc.create_branch_at("other", &[automerge::ChangeHash([1; 32])]),
+        Err(AutomergeError::InvalidHash(_))
+    ));
+
+    // a deleted name can be reused
+    doc.delete_ref("main").unwrap();
+    assert!(doc.checkout("main").is_err());
+    doc.create_tag("main").unwrap();
+    assert_eq!(doc.get_ref("main").unwrap().kind, RefKind::Tag);
+}
+
+#[test]
+fn refs_are_saved_and_loaded() {
+    let mut doc = AutoCommit::new();
+    doc.create_branch("empty").unwrap();
+    let saved = doc.save();
+    let loaded = AutoCommit::load(&saved).unwrap();
+    assert_eq!(loaded.refs(), doc.refs());
+
+    doc.put(ROOT, "key", "value").unwrap();
+    doc.create_tag("v1").unwrap();
+    // refs can be left out for loaders from before refs were added
+    let mut loaded = AutoCommit::load(&doc.save_with_options(SaveOptions {
+        refs: false,
+        ..Default::default()
+    }))
+    .unwrap();
+    assert_eq!(loaded.refs().iter().count(), 0);
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+
+    let saved = doc.save();
+    let loaded = AutoCommit::load(&saved).unwrap();
+    assert_eq!(loaded.refs(), doc.refs());
+    assert_eq!(loaded.get_ref("v1").unwrap().heads, doc.get_heads());
+
+    let mut other = AutoCommit::new();
+    other.load_incremental(&saved).unwrap();
+    assert_eq!(other.refs(), doc.refs());
+    assert_eq!(
+        other.get(ROOT, "key").unwrap().unwrap().0.to_str(),
+        Some("value")
+    );
+}
+
+#[test]
+fn refs_are_saved_incrementally() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "key", "value").unwrap();
+    let mut saved = doc.save();
+
+    doc.create_branch("main").unwrap();
+    // the refs are written even though there are no new changes
+    saved.extend(doc.save_incremental());
+    doc.put(ROOT, "key", "other").unwrap();
+    doc.create_tag("v1").unwrap();
+    saved.extend(doc.save_incremental());
+    doc.delete_ref("main").unwrap();
+    saved.extend(doc.save_incremental());
+    // nothing has changed since the last save
+    assert!(doc.save_incremental().is_empty());
+
+    let mut loaded = AutoCommit::load(&saved).unwrap();
+    assert_eq!(loaded.refs(), doc.refs());
+    assert_eq!(loaded.get_ref("main"), None);
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+}
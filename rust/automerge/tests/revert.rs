@@ -0,0 +1,161 @@
+use automerge::revert::{SkipReason, Skipped};
+use automerge::transaction::Transactable;
+use automerge::{
+    hydrate, hydrate_map, ActorId, AutoCommit, AutomergeError, ChangeHash, ObjType, Prop, ReadDoc,
+    ScalarValue, ROOT,
+};
+
+#[test]
+fn reverting_a_change() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "title", "draft").unwrap();
+    doc.put(ROOT, "removed", "here").unwrap();
+    let settings = doc.put_object(ROOT, "settings", ObjType::Map).unwrap();
+    doc.put(&settings, "theme", "dark").unwrap();
+    doc.put(ROOT, "count", ScalarValue::counter(1)).unwrap();
+    doc.commit();
+
+    doc.put(ROOT, "title", "final").unwrap();
+    doc.put(ROOT, "added", true).unwrap();
+    doc.delete(ROOT, "removed").unwrap();
+    doc.put(ROOT, "settings", "none").unwrap();
+    doc.increment(ROOT, "count", 5).unwrap();
+    let change = doc.commit().unwrap();
+    doc.increment(ROOT, "count", 2).unwrap();
+    doc.commit();
+
+    let revert = doc.revert(change).unwrap();
+    assert_eq!(revert.changes, vec![change]);
+    assert!(revert.is_clean());
+    assert_eq!(
+        doc.hydrate(None),
+        hydrate_map! {
+            "title" => "draft",
+            "removed" => "here",
+            "settings" => hydrate_map! {"theme" => "dark"},
+            "count" => ScalarValue::counter(3),
+        }
+    );
+    // the restored map is a copy of the original
+    assert_ne!(doc.get(ROOT, "settings").unwrap().unwrap().1, settings);
+
+    assert!(matches!(
+        doc.revert(ChangeHash([7; 32])),
+        Err(AutomergeError::MissingHash(_))
+    ));
+}
+
+#[test]
+fn reverting_sequences() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice(&list, 0, 0, ["a", "b", "c", "d"].map(ScalarValue::from))
+        .unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    doc.commit();
+
+    doc.delete(&list, 1).unwrap();
+    doc.insert(&list, 2, "x").unwrap();
+    doc.put(&list, 0, "A").unwrap();
+    doc.splice_text(&text, 0, 5, "goodbye").unwrap();
+    let change = doc.commit().unwrap();
+    doc.insert(&list, 0, "later").unwrap();
+    doc.splice_text(&text, 13, 0, "!").unwrap();
+    doc.commit();
+
+    let revert = doc.revert(change).unwrap();
+    assert!(revert.is_clean());
+    let values = doc
+        .list_range(&list, ..)
+        .map(|item| item.value.into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec!["later", "a", "b", "c", "d"]);
+    assert_eq!(doc.text(&text).unwrap(), "hello world!");
+}
+
+#[test]
+fn reverting_a_replaced_list_element() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.splice(&list, 0, 0, ["a", "b", "c"].map(ScalarValue::from))
+        .unwrap();
+    let other = doc.put_object(ROOT, "other", ObjType::List).unwrap();
+    doc.insert(&other, 0, "untouched").unwrap();
+    doc.commit();
+
+    doc.delete(&list, 1).unwrap();
+    doc.insert(&list, 1, "y").unwrap();
+    let change = doc.commit().unwrap();
+
+    let revert = doc.revert(change).unwrap();
+    assert!(revert.is_clean());
+    let values = doc
+        .list_range(&list, ..)
+        .map(|item| item.value.into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec!["a", "b", "c"]);
+    assert_eq!(doc.length(&other), 1);
+}
+
+#[test]
+fn later_and_concurrent_edits_take_priority() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.put(ROOT, "b", 1).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+
+    doc1.put(ROOT, "a", 2).unwrap();
+    doc1.put(ROOT, "b", 2).unwrap();
+    doc1.put(ROOT, "c", 2).unwrap();
+    let change = doc1.commit().unwrap();
+    doc2.put(ROOT, "c", 3).unwrap();
+    doc2.commit();
+    doc1.merge(&mut doc2).unwrap();
+    doc1.put(ROOT, "b", 4).unwrap();
+    doc1.commit();
+
+    let revert = doc1.revert(change).unwrap();
+    assert_eq!(
+        revert.skipped,
+        vec![Skipped {
+            obj: ROOT,
+            prop: Prop::from("b"),
+            reason: SkipReason::Modified,
+        }]
+    );
+    // the concurrent value of "c" is kept and no longer conflicts
+    assert_eq!(
+        doc1.hydrate(None),
+        hydrate_map! {"a" => 1, "b" => 4, "c" => 3}
+    );
+    assert!(doc1.conflicts(ROOT, None).unwrap().is_empty());
+}
+
+#[test]
+fn reverting_a_range_of_changes() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.commit();
+    let mut changes = Vec::new();
+    for (i, value) in ["a", "b", "c"].iter().enumerate() {
+        doc.insert(&list, i, *value).unwrap();
+        changes.push(doc.commit().unwrap());
+    }
+    let heads = doc.get_heads();
+    doc.insert(&list, 3, "d").unwrap();
+    doc.commit();
+
+    let revert = doc.revert_range(changes[1], &heads).unwrap();
+    assert_eq!(revert.changes, changes[1..].to_vec());
+    assert_eq!(doc.length(&list), 2);
+    assert_eq!(
+        doc.get(&list, 1).unwrap().unwrap().0.into_string().unwrap(),
+        "d"
+    );
+
+    // nothing is reverted when the change isn't an ancestor of the heads
+    let revert = doc.revert_range(changes[2], &[changes[0]]).unwrap();
+    assert!(revert.changes.is_empty());
+}
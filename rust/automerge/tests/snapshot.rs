@@ -0,0 +1,80 @@
+use automerge::blocks::Block;
+use automerge::marks::{ExpandMark, Mark};
+use automerge::transaction::Transactable;
+use automerge::{
+    hydrate, hydrate_list, hydrate_map, ActorId, AutoCommit, Automerge, AutomergeError, ChangeHash,
+    ObjType, ReadDoc, ScalarValue, ROOT,
+};
+
+#[test]
+fn snapshot_has_only_the_state_at_the_heads() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    doc1.put(ROOT, "draft", "secret draft").unwrap();
+    let items = doc1.put_object(ROOT, "items", ObjType::List).unwrap();
+    doc1.insert(&items, 0, "a").unwrap();
+    let nested = doc1.insert_object(&items, 1, ObjType::Map).unwrap();
+    doc1.put(&nested, "count", ScalarValue::counter(1)).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+    doc1.put(ROOT, "title", "ours").unwrap();
+    doc2.put(ROOT, "title", "theirs").unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    doc1.delete(ROOT, "draft").unwrap();
+    doc1.increment(&nested, "count", 2).unwrap();
+    let heads = doc1.get_heads();
+    doc1.put(ROOT, "later", true).unwrap();
+    doc1.commit();
+
+    let actor = ActorId::from(b"publisher");
+    let snapshot = doc1.document().snapshot_at(&heads, actor.clone()).unwrap();
+    assert_eq!(
+        snapshot.hydrate(None),
+        hydrate_map! {
+            "items" => hydrate_list!["a", hydrate_map! {"count" => ScalarValue::counter(3)}],
+            "title" => "theirs",
+        }
+    );
+    let changes = snapshot.get_changes(&[]);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].actor_id(), &actor);
+    assert!(snapshot.conflicts(ROOT, None).unwrap().is_empty());
+    let bytes = snapshot.save_nocompress();
+    assert!(!bytes.windows(6).any(|w| w == b"secret"));
+    assert!(!bytes.windows(4).any(|w| w == b"ours"));
+
+    let loaded = Automerge::load(&doc1.save_snapshot_at(&heads, actor).unwrap()).unwrap();
+    assert_eq!(loaded.hydrate(None), snapshot.hydrate(None));
+
+    assert!(matches!(
+        doc1.save_snapshot_at(&[ChangeHash([7; 32])], ActorId::random()),
+        Err(AutomergeError::InvalidHash(_))
+    ));
+}
+
+#[test]
+fn snapshot_keeps_rich_text() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "héllo 🌍 world").unwrap();
+    doc.split_block(&text, 0, Block::new("heading")).unwrap();
+    doc.split_block(&text, 9, Block::new("paragraph")).unwrap();
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 1, 6),
+        ExpandMark::After,
+    )
+    .unwrap();
+    let heads = doc.get_heads();
+
+    let snapshot = doc
+        .document()
+        .snapshot_at(&heads, ActorId::random())
+        .unwrap();
+    let copy = snapshot.get(ROOT, "text").unwrap().unwrap().1;
+    assert_eq!(snapshot.text(&copy).unwrap(), doc.text(&text).unwrap());
+    assert_eq!(
+        snapshot.spans(&copy).unwrap().collect::<Vec<_>>(),
+        doc.spans(&text).unwrap().collect::<Vec<_>>()
+    );
+    assert_eq!(snapshot.marks(&copy).unwrap(), doc.marks(&text).unwrap());
+}
@@ -1,6 +1,13 @@
 use std::str::FromStr;
 
-use automerge::{transaction::Transactable, ActorId, AutoCommit, ObjType, ReadDoc, ROOT};
+use automerge::iter::Span;
+use automerge::marks::{ExpandMark, Mark, MarkPolicy};
+use automerge::patches::TextRepresentation;
+use automerge::rich_text;
+use automerge::transaction::{DiffAlgorithm, DiffGranularity, Transactable, UpdateTextOptions};
+use automerge::{
+    ActorId, AutoCommit, CursorResolution, ObjType, PatchAction, ReadDoc, TextEncoding, ROOT,
+};
 use test_log::test;
 
 #[test]
@@ -41,3 +48,687 @@ fn update_text_big_ole_graphemes() {
     // should render as a family of three followed by a family of four
     assert_eq!(doc.text(&text).unwrap(), "left👨‍👩‍👧👨‍👩‍👦‍👦right");
 }
+
+#[test]
+fn update_text_with_options() {
+    let cases = [
+        ("Hello, world!", "Goodbye, world!"),
+        ("one\ntwo\nthree\n", "zero\none\n2\nthree\nfour"),
+        ("the quick brown fox", "a quick red fox jumps"),
+        ("left👨‍👩‍👦right", "left👨‍👩‍👧right👍"),
+        ("", "something"),
+        ("something", ""),
+        ("a a a b a a", "a b a a a a b"),
+    ];
+    let algorithms = [
+        DiffAlgorithm::Myers,
+        DiffAlgorithm::Patience,
+        DiffAlgorithm::Histogram,
+    ];
+    let granularities = [
+        DiffGranularity::Grapheme,
+        DiffGranularity::Word,
+        DiffGranularity::LineThenGrapheme,
+    ];
+    for (old, new) in cases {
+        for algorithm in algorithms {
+            for granularity in granularities {
+                for max_cost in [None, Some(0), Some(5)] {
+                    let mut doc = AutoCommit::new();
+                    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+                    doc.splice_text(&text, 0, 0, old).unwrap();
+                    let mut options = UpdateTextOptions::new(algorithm, granularity);
+                    if let Some(max_cost) = max_cost {
+                        options = options.with_max_cost(max_cost);
+                    }
+                    doc.update_text_with_options(&text, new, options).unwrap();
+                    assert_eq!(
+                        doc.text(&text).unwrap(),
+                        new,
+                        "{:?} -> {:?} with {:?}",
+                        old,
+                        new,
+                        options
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn update_text_granularity_and_cost() {
+    // whether the element `cursor` points at is still there
+    fn survives(doc: &AutoCommit, text: &automerge::ObjId, cursor: &automerge::Cursor) -> bool {
+        let position = |resolution| {
+            doc.get_cursor_position_with_resolution(text, cursor, resolution, None)
+                .unwrap()
+        };
+        position(CursorResolution::Preceding) == position(CursorResolution::Following)
+    }
+
+    let update = |old: &str, at: usize, new: &str, options: UpdateTextOptions| {
+        let mut doc = AutoCommit::new();
+        let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+        doc.splice_text(&text, 0, 0, old).unwrap();
+        let cursor = doc.get_cursor(&text, at, None).unwrap();
+        doc.update_text_with_options(&text, new, options).unwrap();
+        assert_eq!(doc.text(&text).unwrap(), new);
+        survives(&doc, &text, &cursor)
+    };
+
+    let graphemes = UpdateTextOptions::new(DiffAlgorithm::Myers, DiffGranularity::Grapheme);
+    let words = UpdateTextOptions::new(DiffAlgorithm::Histogram, DiffGranularity::Word);
+    // the "w" of "world"
+    assert!(update("hello world", 6, "hello word", graphemes));
+    assert!(!update("hello world", 6, "hello word", words));
+
+    // the "b", which a minimal diff keeps but replacing the changed region doesn't
+    assert!(update("a1b2c3", 2, "a9b8c7", graphemes));
+    assert!(!update("a1b2c3", 2, "a9b8c7", graphemes.with_max_cost(0)));
+
+    // the "x" in the unchanged line
+    let lines = UpdateTextOptions::new(DiffAlgorithm::Patience, DiffGranularity::LineThenGrapheme);
+    assert!(update(
+        "fn a() {\n    x\n}\n",
+        13,
+        "fn a() {\n    let y = 1;\n    x\n}\n",
+        lines
+    ));
+}
+
+#[test]
+fn update_lines_keeps_unchanged_lines() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.update_lines(&text, "fn main() {\n    run();\n}\n")
+        .unwrap();
+
+    // a comment anchored to the call
+    let lines = doc.lines(&text).unwrap();
+    let call = lines.index(1, 4).unwrap();
+    let cursor = doc.get_cursor(&text, call, None).unwrap();
+
+    let mut doc2 = doc.fork();
+    doc2.update_lines(&text, "fn main() {\n    run();\n}\n\nfn run() {}\n")
+        .unwrap();
+    doc.update_lines(
+        &text,
+        "// entry point\nfn main() {\n    setup();\n    run();\n}\n",
+    )
+    .unwrap();
+    doc.merge(&mut doc2).unwrap();
+    assert_eq!(
+        doc.text(&text).unwrap(),
+        "// entry point\nfn main() {\n    setup();\n    run();\n}\n\nfn run() {}\n"
+    );
+
+    let lines = doc.lines(&text).unwrap();
+    assert_eq!(lines.len(), 8);
+    assert_eq!(lines.line(3), Some("    run();"));
+    let position = doc.get_cursor_position(&text, &cursor, None).unwrap();
+    assert_eq!(lines.position(position), Some((3, 4)));
+    assert_eq!(lines.line_at(position), Some(3));
+
+    let heads = doc2.get_heads();
+    assert_eq!(doc.lines_at(&text, &heads).unwrap().len(), 6);
+}
+
+#[test]
+fn splice_and_read_text_with_encoding() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "a😀b").unwrap();
+
+    assert_eq!(
+        doc.text_length(&text, None, TextEncoding::Utf8CodeUnit)
+            .unwrap(),
+        6
+    );
+    assert_eq!(
+        doc.text_length(&text, None, TextEncoding::Utf16CodeUnit)
+            .unwrap(),
+        4
+    );
+    assert_eq!(
+        doc.text_length(&text, None, TextEncoding::UnicodeCodePoint)
+            .unwrap(),
+        3
+    );
+
+    // after the emoji
+    doc.splice_text_with_encoding(&text, 3, 0, "x", TextEncoding::Utf16CodeUnit)
+        .unwrap();
+    doc.splice_text_with_encoding(&text, 5, 0, "y", TextEncoding::Utf8CodeUnit)
+        .unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "a😀yxb");
+
+    // delete the emoji
+    doc.splice_text_with_encoding(&text, 5, -4, "", TextEncoding::Utf8CodeUnit)
+        .unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "ayxb");
+
+    // positions which are past the end are rejected
+    assert!(doc
+        .splice_text_with_encoding(&text, 5, 0, "z", TextEncoding::Utf16CodeUnit)
+        .is_err());
+}
+
+#[test]
+fn cursors_with_encoding() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "a😀b").unwrap();
+
+    let cursor = doc
+        .get_cursor_with_encoding(&text, 3, None, TextEncoding::Utf16CodeUnit)
+        .unwrap();
+    assert_eq!(doc.get_cursor(&text, 2, None).unwrap(), cursor);
+    assert_eq!(
+        doc.get_cursor_position_with_encoding(&text, &cursor, None, TextEncoding::Utf8CodeUnit)
+            .unwrap(),
+        5
+    );
+    assert_eq!(
+        doc.get_cursor_position_with_encoding(&text, &cursor, None, TextEncoding::GraphemeCluster)
+            .unwrap(),
+        2
+    );
+}
+
+#[test]
+fn grapheme_cluster_positions() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    // "e" followed by a combining acute accent is one grapheme cluster
+    doc.splice_text(&text, 0, 0, "e\u{301}x👨‍👩‍👦z").unwrap();
+    assert_eq!(
+        doc.text_length(&text, None, TextEncoding::GraphemeCluster)
+            .unwrap(),
+        4
+    );
+
+    doc.splice_text_with_encoding(&text, 3, -1, "", TextEncoding::GraphemeCluster)
+        .unwrap();
+    doc.splice_text_with_encoding(&text, 1, 0, "y", TextEncoding::GraphemeCluster)
+        .unwrap();
+    assert_eq!(doc.text(&text).unwrap(), "e\u{301}yxz");
+}
+
+#[test]
+fn marks_with_encoding() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "a😀b").unwrap();
+
+    let bold = Mark::new("bold".to_string(), true, 1, 5);
+    doc.mark_with_encoding(&text, bold, ExpandMark::None, TextEncoding::Utf8CodeUnit)
+        .unwrap();
+
+    let marks = doc.marks(&text).unwrap();
+    assert_eq!((marks[0].start, marks[0].end), (1, 2));
+
+    let marks = doc
+        .marks_with_encoding(&text, None, TextEncoding::Utf16CodeUnit)
+        .unwrap();
+    assert_eq!((marks[0].start, marks[0].end), (1, 3));
+
+    doc.unmark_with_encoding(
+        &text,
+        "bold",
+        0,
+        3,
+        ExpandMark::None,
+        TextEncoding::Utf16CodeUnit,
+    )
+    .unwrap();
+    assert!(doc.marks(&text).unwrap().is_empty());
+}
+
+#[test]
+fn converting_positions_in_long_text() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let chunk = "ab😀cé";
+    for _ in 0..60 {
+        let end = doc.length(&text);
+        doc.splice_text(&text, end, 0, chunk).unwrap();
+    }
+    let heads = doc.get_heads();
+    // a conflicting put on an element means the tree can't skip ahead using its index
+    let mut other = doc.fork();
+    doc.put(&text, 1, "x").unwrap();
+    other.put(&text, 1, "😀").unwrap();
+    doc.merge(&mut other).unwrap();
+    doc.splice_text(&text, 0, 0, "😀").unwrap();
+
+    let encodings = [
+        TextEncoding::UnicodeCodePoint,
+        TextEncoding::Utf8CodeUnit,
+        TextEncoding::Utf16CodeUnit,
+        TextEncoding::GraphemeCluster,
+    ];
+    for at in [None, Some(heads.as_slice())] {
+        let s = match at {
+            Some(heads) => doc.text_at(&text, heads).unwrap(),
+            None => doc.text(&text).unwrap(),
+        };
+        for (byte, c) in s.char_indices().chain([(s.len(), ' ')]) {
+            let prefix = &s[..byte];
+            for from in encodings {
+                for to in encodings {
+                    let index = from.width(prefix);
+                    assert_eq!(
+                        doc.convert_text_index(&text, index, from, to, at).unwrap(),
+                        to.width(prefix),
+                        "{:?} to {:?} at {:?} before {:?}",
+                        from,
+                        to,
+                        at,
+                        c
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            doc.text_length(&text, at, TextEncoding::Utf8CodeUnit)
+                .unwrap(),
+            s.len()
+        );
+        // inside the emoji, and past the end
+        let emoji = s.find('😀').unwrap();
+        let inside = TextEncoding::Utf16CodeUnit.width(&s[..emoji]) + 1;
+        assert!(doc
+            .convert_text_index(
+                &text,
+                inside,
+                TextEncoding::Utf16CodeUnit,
+                TextEncoding::UnicodeCodePoint,
+                at
+            )
+            .is_err());
+        assert!(doc
+            .convert_text_index(
+                &text,
+                s.len() + 1,
+                TextEncoding::Utf8CodeUnit,
+                TextEncoding::Utf16CodeUnit,
+                at
+            )
+            .is_err());
+    }
+}
+
+#[test]
+fn patches_with_encoding() {
+    let mut doc = AutoCommit::new()
+        .with_text_rep(TextRepresentation::String)
+        .with_text_encoding(TextEncoding::Utf16CodeUnit);
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "a😀b").unwrap();
+    doc.update_diff_cursor();
+
+    doc.splice_text(&text, 2, 0, "x").unwrap();
+    doc.delete(&text, 0).unwrap();
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 0, 1),
+        ExpandMark::None,
+    )
+    .unwrap();
+
+    let actions = doc
+        .diff_incremental()
+        .into_iter()
+        .map(|p| p.action)
+        .collect::<Vec<_>>();
+    match actions.as_slice() {
+        [PatchAction::SpliceText { index, value, .. }, PatchAction::DeleteSeq {
+            index: 0,
+            length: 1,
+        }, PatchAction::Mark { marks }] => {
+            assert_eq!(*index, 3);
+            assert_eq!(value.make_string(), "x");
+            assert_eq!((marks[0].start, marks[0].end), (0, 2));
+        }
+        other => panic!("unexpected patches: {:?}", other),
+    }
+}
+
+#[test]
+fn patches_regenerate_text_which_cannot_be_converted() {
+    let mut doc = AutoCommit::new()
+        .with_text_rep(TextRepresentation::String)
+        .with_text_encoding(TextEncoding::Utf16CodeUnit);
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "a😀b").unwrap();
+    doc.put(ROOT, "other", 1).unwrap();
+    doc.update_diff_cursor();
+
+    // a put on a character can't be replayed to convert the positions of the edits around it
+    doc.splice_text(&text, 0, 0, "x").unwrap();
+    doc.put(&text, 3, "c").unwrap();
+    doc.put(ROOT, "other", 2).unwrap();
+
+    let patches = doc.diff_incremental();
+    let actions = patches.iter().map(|p| &p.action).collect::<Vec<_>>();
+    match actions.as_slice() {
+        [PatchAction::PutMap { key: other, .. }, PatchAction::PutMap {
+            key,
+            value: (value, id),
+            ..
+        }, PatchAction::SpliceText {
+            index: 0, value: s, ..
+        }] => {
+            assert_eq!(other, "other");
+            assert_eq!(key, "text");
+            assert_eq!(value, &automerge::Value::Object(ObjType::Text));
+            assert_eq!(id, &text);
+            assert_eq!(s.make_string(), "xa😀c");
+        }
+        other => panic!("unexpected patches: {:?}", other),
+    }
+}
+
+#[test]
+fn patches_convert_interleaved_edits_after_changing_encoding() {
+    let mut doc = AutoCommit::new().with_text_rep(TextRepresentation::String);
+    let a = doc.put_object(ROOT, "a", ObjType::Text).unwrap();
+    let b = doc.put_object(ROOT, "b", ObjType::Text).unwrap();
+    doc.splice_text(&a, 0, 0, "a😀b").unwrap();
+    doc.splice_text(&b, 0, 0, "😀😀").unwrap();
+    doc.update_diff_cursor();
+
+    doc.delete(&a, 2).unwrap();
+    doc.splice_text(&b, 2, 0, "x").unwrap();
+    doc.splice_text(&a, 0, 0, "y").unwrap();
+    // the encoding applies to patches made after it is set, including for earlier edits
+    doc.set_text_encoding(TextEncoding::Utf16CodeUnit);
+
+    let actions = doc
+        .diff_incremental()
+        .into_iter()
+        .map(|p| p.action)
+        .collect::<Vec<_>>();
+    match actions.as_slice() {
+        [PatchAction::DeleteSeq {
+            index: 3,
+            length: 1,
+        }, PatchAction::SpliceText {
+            index: 0, value: y, ..
+        }, PatchAction::SpliceText {
+            index: 4, value: x, ..
+        }] => {
+            assert_eq!(y.make_string(), "y");
+            assert_eq!(x.make_string(), "x");
+        }
+        other => panic!("unexpected patches: {:?}", other),
+    }
+}
+
+#[test]
+fn patches_which_split_grapheme_clusters_replace_the_cluster() {
+    let mut doc = AutoCommit::new()
+        .with_text_rep(TextRepresentation::String)
+        .with_text_encoding(TextEncoding::GraphemeCluster);
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "e\u{301}x").unwrap();
+    doc.update_diff_cursor();
+
+    // remove the accent
+    doc.splice_text(&text, 1, 1, "").unwrap();
+
+    let actions = doc
+        .diff_incremental()
+        .into_iter()
+        .map(|p| p.action)
+        .collect::<Vec<_>>();
+    match actions.as_slice() {
+        [PatchAction::DeleteSeq {
+            index: 0,
+            length: 1,
+        }, PatchAction::SpliceText {
+            index: 0, value, ..
+        }] => {
+            assert_eq!(value.make_string(), "e");
+        }
+        other => panic!("unexpected patches: {:?}", other),
+    }
+}
+
+#[test]
+fn spans_combine_text_and_marks() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello big world").unwrap();
+    let before_marks = doc.get_heads();
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 0, 9),
+        ExpandMark::After,
+    )
+    .unwrap();
+    doc.mark(
+        &text,
+        Mark::new("italic".to_string(), true, 6, 15),
+        ExpandMark::None,
+    )
+    .unwrap();
+
+    let spans = doc
+        .spans(&text)
+        .unwrap()
+        .map(|span| match span {
+            Span::Text(text, marks) => {
+                let mut marks = marks
+                    .map(|m| {
+                        m.iter()
+                            .map(|(name, _)| name.to_string())
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                marks.sort();
+                (text, marks)
+            }
+            Span::Block(_) => panic!("unexpected block"),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        spans,
+        vec![
+            ("hello ".to_string(), vec!["bold".to_string()]),
+            (
+                "big".to_string(),
+                vec!["bold".to_string(), "italic".to_string()]
+            ),
+            (" world".to_string(), vec!["italic".to_string()]),
+        ]
+    );
+
+    assert_eq!(
+        doc.spans_at(&text, &before_marks)
+            .unwrap()
+            .collect::<Vec<_>>(),
+        vec![Span::Text("hello big world".to_string(), None)]
+    );
+}
+
+#[test]
+fn markdown_and_html_round_trip() {
+    let mut doc = AutoCommit::new();
+    for name in [
+        rich_text::BOLD,
+        rich_text::ITALIC,
+        rich_text::CODE,
+        rich_text::LINK,
+    ] {
+        doc.set_mark_policy(name, MarkPolicy::new(rich_text::expand_mark(name)));
+    }
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let markdown = "plain **bold *both*** *it\\_al* `a*b` [**link**](https://example.com) \\[x\\]";
+    rich_text::splice_markdown(&mut doc, &text, 0, markdown).unwrap();
+    assert_eq!(
+        doc.text(&text).unwrap(),
+        "plain bold both it_al a*b link [x]"
+    );
+    assert_eq!(rich_text::to_markdown(&doc, &text).unwrap(), markdown);
+
+    let html = rich_text::to_html(&doc, &text).unwrap();
+    assert_eq!(
+        html,
+        "plain <strong>bold <em>both</em></strong> <em>it_al</em> <code>a*b</code> \
+         <a href=\"https://example.com\"><strong>link</strong></a> [x]"
+    );
+    let mut doc2 = AutoCommit::new();
+    let text2 = doc2.put_object(ROOT, "text", ObjType::Text).unwrap();
+    rich_text::splice_html(&mut doc2, &text2, 0, &html).unwrap();
+    assert_eq!(doc2.text(&text2).unwrap(), doc.text(&text).unwrap());
+    assert_eq!(doc2.marks(&text2).unwrap(), doc.marks(&text).unwrap());
+
+    // with the suggested policies bold and italic grow as you type at their end, links and code
+    // don't
+    let len = doc.length(&text);
+    doc.splice_text(&text, len, 0, "!").unwrap();
+    doc.splice_text(&text, 15, 0, "!").unwrap();
+    doc.splice_text(&text, 26, 0, "!").unwrap();
+    assert_eq!(
+        rich_text::to_markdown(&doc, &text).unwrap(),
+        "plain **bold *both!*** *it\\_al* `a*b`! [**link**](https://example.com) \\[x\\]!"
+    );
+}
+
+#[test]
+fn imported_marks_use_the_registered_policies() {
+    let mut doc = AutoCommit::new();
+    doc.set_mark_policy(rich_text::LINK, MarkPolicy::new(ExpandMark::After));
+    doc.set_mark_policy(rich_text::BOLD, MarkPolicy::new(ExpandMark::None));
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    rich_text::splice_markdown(&mut doc, &text, 0, "[a](/x) **b**").unwrap();
+    doc.splice_text(&text, 1, 0, "!").unwrap();
+    let len = doc.length(&text);
+    doc.splice_text(&text, len, 0, "!").unwrap();
+    assert_eq!(
+        rich_text::to_markdown(&doc, &text).unwrap(),
+        "[a!](/x) **b**!"
+    );
+}
+
+#[test]
+fn mark_policies() {
+    let mut doc = AutoCommit::new();
+    doc.set_mark_policy("bold", MarkPolicy::new(ExpandMark::None));
+    doc.set_mark_policy(
+        "link",
+        MarkPolicy::new(ExpandMark::None).with_exclusive(true),
+    );
+    doc.set_mark_policy("comment", MarkPolicy::default().with_merge(true));
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "0123456789").unwrap();
+
+    let ranges = |doc: &AutoCommit, name: &str| {
+        doc.marks(&text)
+            .unwrap()
+            .into_iter()
+            .filter(|m| m.name() == name)
+            .map(|m| (m.start, m.end, m.value().to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    // the registered expansion is used when none is given
+    doc.mark(&text, Mark::new("bold".to_string(), true, 2, 4), None)
+        .unwrap();
+    doc.splice_text(&text, 4, 0, "x").unwrap();
+    assert_eq!(ranges(&doc, "bold"), vec![(2, 4, "true".to_string())]);
+    doc.splice_text(&text, 4, 1, "").unwrap();
+
+    // a new link replaces any link it overlaps entirely
+    doc.mark(&text, Mark::new("link".to_string(), "a", 0, 5), None)
+        .unwrap();
+    doc.mark(&text, Mark::new("link".to_string(), "b", 4, 8), None)
+        .unwrap();
+    assert_eq!(ranges(&doc, "link"), vec![(4, 8, "\"b\"".to_string())]);
+    // and unmarking part of a link removes all of it
+    doc.unmark(&text, "link", 6, 7, None).unwrap();
+    assert!(ranges(&doc, "link").is_empty());
+
+    // merged marks keep existing values where they overlap
+    doc.mark(&text, Mark::new("comment".to_string(), "c1", 0, 4), None)
+        .unwrap();
+    doc.mark(&text, Mark::new("comment".to_string(), "c2", 2, 8), None)
+        .unwrap();
+    assert_eq!(
+        ranges(&doc, "comment"),
+        vec![(0, 4, "\"c1\"".to_string()), (4, 8, "\"c2\"".to_string())]
+    );
+
+    // an explicit expansion overrides the registered one
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 8, 10),
+        ExpandMark::After,
+    )
+    .unwrap();
+    doc.splice_text(&text, 10, 0, "x").unwrap();
+    assert_eq!(
+        ranges(&doc, "bold"),
+        vec![(2, 4, "true".to_string()), (8, 11, "true".to_string())]
+    );
+}
+
+#[test]
+fn mark_policies_are_saved_synced_and_merged() {
+    use automerge::sync::{self, SyncDoc};
+
+    let mut doc = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    doc.set_mark_policy("bold", MarkPolicy::new(ExpandMark::None));
+    doc.set_mark_policy("link", MarkPolicy::default().with_exclusive(true));
+    doc.remove_mark_policy("link");
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "abc").unwrap();
+
+    // a peer which loads the document gets the policies
+    let mut loaded = AutoCommit::load(&doc.save()).unwrap();
+    assert_eq!(loaded.mark_policies(), doc.mark_policies());
+    loaded
+        .mark(&text, Mark::new("bold".to_string(), true, 0, 3), None)
+        .unwrap();
+    loaded.splice_text(&text, 3, 0, "d").unwrap();
+    assert_eq!(loaded.marks(&text).unwrap()[0].end, 3);
+
+    // and so does one which syncs with it
+    let mut synced = AutoCommit::new().with_actor(ActorId::from(b"bbbb"));
+    let (mut s1, mut s2) = (sync::State::new(), sync::State::new());
+    loop {
+        let m1 = doc.sync().generate_sync_message(&mut s1);
+        if let Some(m) = &m1 {
+            let m = sync::Message::decode(&m.clone().encode()).unwrap();
+            synced.sync().receive_sync_message(&mut s2, m).unwrap();
+        }
+        let m2 = synced.sync().generate_sync_message(&mut s2);
+        if let Some(m) = &m2 {
+            doc.sync().receive_sync_message(&mut s1, m.clone()).unwrap();
+        }
+        if m1.is_none() && m2.is_none() {
+            break;
+        }
+    }
+    assert_eq!(synced.mark_policies(), doc.mark_policies());
+    assert_eq!(
+        synced.mark_policies().get("bold"),
+        MarkPolicy::new(ExpandMark::None)
+    );
+    assert_eq!(synced.mark_policies().get("link"), MarkPolicy::default());
+
+    // concurrent updates converge on the same policy
+    doc.set_mark_policy("bold", MarkPolicy::new(ExpandMark::Both));
+    synced.set_mark_policy("bold", MarkPolicy::new(ExpandMark::Before));
+    let mut merged1 = doc.fork();
+    merged1.merge(&mut synced).unwrap();
+    let mut merged2 = synced.fork();
+    merged2.merge(&mut doc).unwrap();
+    assert_eq!(merged1.mark_policies(), merged2.mark_policies());
+    assert_eq!(
+        merged1.mark_policies().get("bold"),
+        MarkPolicy::new(ExpandMark::Before)
+    );
+}
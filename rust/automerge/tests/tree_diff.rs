@@ -0,0 +1,211 @@
+use automerge::transaction::Transactable;
+use automerge::tree_diff::{Hunk, Line, Move, ObjChanges, Summary, ValueChange};
+use automerge::{
+    hydrate, hydrate_list, hydrate_map, AutoCommit, ObjType, Prop, ReadDoc, ScalarValue, ROOT,
+};
+
+#[test]
+fn values_grouped_by_object() {
+    let mut doc = AutoCommit::new();
+    let todos = doc.put_object(ROOT, "todos", ObjType::List).unwrap();
+    for (i, title) in ["shop", "cook", "eat"].iter().enumerate() {
+        let todo = doc.insert_object(&todos, i, ObjType::Map).unwrap();
+        doc.put(&todo, "title", *title).unwrap();
+    }
+    let archive = doc.put_object(ROOT, "archive", ObjType::List).unwrap();
+    let settings = doc.put_object(ROOT, "settings", ObjType::Map).unwrap();
+    doc.put(&settings, "theme", "dark").unwrap();
+    doc.put(ROOT, "visits", ScalarValue::counter(1)).unwrap();
+    let before = doc.get_heads();
+
+    // move "shop" to the archive, rename "cook", add a todo and drop the settings
+    doc.delete(&todos, 0).unwrap();
+    let shop = doc.insert_object(&archive, 0, ObjType::Map).unwrap();
+    doc.put(&shop, "title", "shop").unwrap();
+    let cook = doc.get(&todos, 0).unwrap().unwrap().1;
+    doc.put(&cook, "title", "bake").unwrap();
+    doc.insert(&todos, 2, "wash up").unwrap();
+    doc.delete(ROOT, "settings").unwrap();
+    doc.increment(ROOT, "visits", 2).unwrap();
+    let after = doc.get_heads();
+
+    let diff = doc.tree_diff(&before, &after);
+    assert_eq!(
+        diff.summary,
+        Summary {
+            added: 1,
+            deleted: 1,
+            updated: 2,
+            moved: 1,
+            lines_added: 0,
+            lines_removed: 0,
+        }
+    );
+    assert_eq!(
+        diff.moved,
+        vec![Move {
+            from: vec![Prop::from("todos"), Prop::from(0)],
+            to: vec![Prop::from("archive"), Prop::from(0)],
+            value: hydrate_map! {"title" => "shop"},
+        }]
+    );
+
+    let paths = diff
+        .objects
+        .iter()
+        .map(|o| o.path.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            vec![],
+            vec![Prop::from("todos")],
+            vec![Prop::from("todos"), Prop::from(0)],
+        ]
+    );
+    assert_eq!(
+        diff.objects[0].changes,
+        ObjChanges::Values(vec![
+            ValueChange::Deleted {
+                prop: "settings".into(),
+                value: hydrate_map! {"theme" => "dark"},
+            },
+            ValueChange::Updated {
+                prop: "visits".into(),
+                before: ScalarValue::counter(1).into(),
+                after: ScalarValue::counter(3).into(),
+            },
+        ])
+    );
+    assert_eq!(
+        diff.objects[1].changes,
+        ObjChanges::Values(vec![ValueChange::Added {
+            prop: 2.into(),
+            value: "wash up".into(),
+        }])
+    );
+    assert_eq!(diff.objects[2].obj, cook);
+
+    // a replaced list is an update with both versions in full
+    let before = doc.get_heads();
+    doc.put_object(ROOT, "archive", ObjType::List).unwrap();
+    let after = doc.get_heads();
+    let diff = doc.tree_diff(&before, &after);
+    assert_eq!(
+        diff.objects[0].changes,
+        ObjChanges::Values(vec![ValueChange::Updated {
+            prop: "archive".into(),
+            before: hydrate_list![hydrate_map! {"title" => "shop"}],
+            after: hydrate_list![],
+        }])
+    );
+    assert!(doc.tree_diff(&after, &after).is_empty());
+}
+
+#[test]
+fn nested_changes_in_either_direction() {
+    let mut doc = AutoCommit::new();
+    let outer = doc.put_object(ROOT, "outer", ObjType::Map).unwrap();
+    let list = doc.put_object(&outer, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "first").unwrap();
+    let inner = doc.insert_object(&list, 1, ObjType::Map).unwrap();
+    doc.put(&inner, "key", "before").unwrap();
+    let untouched = doc.put_object(ROOT, "untouched", ObjType::Map).unwrap();
+    doc.put(&untouched, "key", 1).unwrap();
+    let before = doc.get_heads();
+    doc.put(&inner, "key", "after").unwrap();
+    doc.insert(&list, 0, "zeroth").unwrap();
+    let after = doc.get_heads();
+
+    let paths = |from: &[_], to: &[_]| {
+        doc.tree_diff(from, to)
+            .objects
+            .iter()
+            .map(|o| (o.obj.clone(), o.path.clone()))
+            .collect::<Vec<_>>()
+    };
+    let list_path = vec![Prop::from("outer"), Prop::from("list")];
+    let inner_path = |index: usize| {
+        let mut path = list_path.clone();
+        path.push(Prop::from(index));
+        path
+    };
+    assert_eq!(
+        paths(&before, &after),
+        vec![
+            (list.clone(), list_path.clone()),
+            (inner.clone(), inner_path(2))
+        ]
+    );
+    // paths are as at the later heads
+    assert_eq!(
+        paths(&after, &before),
+        vec![(list.clone(), list_path.clone()), (inner, inner_path(1))]
+    );
+}
+
+#[test]
+fn text_hunks() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    let lines = (0..20).map(|i| format!("line {}\n", i)).collect::<String>();
+    doc.splice_text(&text, 0, 0, &lines).unwrap();
+    let before = doc.get_heads();
+    doc.update_text(
+        &text,
+        lines
+            .replace("line 2\n", "")
+            .replace("line 5\n", "line five\n")
+            .replace("line 15\n", "line 15\nline 15.5\n"),
+    )
+    .unwrap();
+    let after = doc.get_heads();
+
+    let diff = doc.tree_diff(&before, &after);
+    assert_eq!(diff.summary.lines_added, 2);
+    assert_eq!(diff.summary.lines_removed, 2);
+    let hunks = match &diff.objects[0].changes {
+        ObjChanges::Text(hunks) => hunks,
+        other => panic!("unexpected changes {:?}", other),
+    };
+    let context = |i: usize| Line::Context(format!("line {}", i));
+    // the first two changes are close enough to share a hunk
+    assert_eq!(
+        hunks,
+        &vec![
+            Hunk {
+                before_start: 0,
+                before_len: 9,
+                after_start: 0,
+                after_len: 8,
+                lines: vec![
+                    context(0),
+                    context(1),
+                    Line::Removed("line 2".to_string()),
+                    context(3),
+                    context(4),
+                    Line::Removed("line 5".to_string()),
+                    Line::Added("line five".to_string()),
+                    context(6),
+                    context(7),
+                    context(8),
+                ],
+            },
+            Hunk {
+                before_start: 13,
+                before_len: 6,
+                after_start: 12,
+                after_len: 7,
+                lines: vec![
+                    context(13),
+                    context(14),
+                    context(15),
+                    Line::Added("line 15.5".to_string()),
+                    context(16),
+                    context(17),
+                    context(18),
+                ],
+            },
+        ]
+    );
+}
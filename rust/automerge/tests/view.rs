@@ -0,0 +1,104 @@
+use automerge::transaction::Transactable;
+use automerge::{ActorId, AutoCommit, AutomergeError, ChangeHash, ObjType, ReadDoc, ROOT};
+
+#[test]
+fn stepping_through_concurrent_history() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+    for word in ["one ", "two "] {
+        doc1.splice_text(&text, 0, 0, word).unwrap();
+        doc1.commit();
+        doc2.splice_text(&text, 0, 0, word).unwrap();
+        doc2.commit();
+    }
+    doc1.merge(&mut doc2).unwrap();
+    doc1.put(ROOT, "done", true).unwrap();
+    doc1.commit();
+
+    let heads = doc1.get_heads();
+    // every point a slider stops at reads the same as the equivalent `*_at()` call
+    let mut view = doc1.view_at(&[]);
+    let mut points = vec![view.heads().to_vec()];
+    while view.step_forward().is_some() {
+        assert_eq!(
+            view.text(&text).unwrap(),
+            doc1.text_at(&text, view.heads()).unwrap()
+        );
+        assert_eq!(
+            view.keys(ROOT).count(),
+            doc1.keys_at(ROOT, view.heads()).count()
+        );
+        points.push(view.heads().to_vec());
+    }
+    assert_eq!(points.len(), 7);
+    assert_eq!(view.heads(), heads);
+    assert_eq!(
+        view.get(ROOT, "done").unwrap().unwrap().0.to_bool(),
+        Some(true)
+    );
+
+    while view.step_back().is_some() {
+        points.pop();
+        assert_eq!(view.heads(), points.last().unwrap().as_slice());
+    }
+    assert!(view.heads().is_empty());
+    assert_eq!(view.length(ROOT), 0);
+}
+
+#[test]
+fn adding_and_removing_changes() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    doc1.put(ROOT, "base", 1).unwrap();
+    let base = doc1.commit().unwrap();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+    doc1.put(ROOT, "x", 1).unwrap();
+    let ours = doc1.commit().unwrap();
+    doc2.put(ROOT, "y", 1).unwrap();
+    let theirs = doc2.commit().unwrap();
+    doc1.merge(&mut doc2).unwrap();
+
+    let mut view = doc1.view_at(&[ours]);
+    assert!(matches!(
+        view.add_change(ours),
+        Err(AutomergeError::InvalidViewStep(_))
+    ));
+    assert!(matches!(
+        view.remove_change(base),
+        Err(AutomergeError::InvalidViewStep(_))
+    ));
+    assert!(matches!(
+        view.add_change(ChangeHash([7; 32])),
+        Err(AutomergeError::MissingHash(_))
+    ));
+
+    view.add_change(theirs).unwrap();
+    let mut heads = vec![ours, theirs];
+    heads.sort();
+    assert_eq!(view.heads(), heads.as_slice());
+    assert_eq!(view.keys(ROOT).collect::<Vec<_>>(), vec!["base", "x", "y"]);
+
+    view.remove_change(ours).unwrap();
+    assert_eq!(view.heads(), &[theirs]);
+    assert_eq!(view.keys(ROOT).collect::<Vec<_>>(), vec!["base", "y"]);
+    // reads with their own heads ignore the view's
+    assert_eq!(view.keys_at(ROOT, &[ours]).count(), 2);
+    assert!(view.get_at(ROOT, "x", &[ours]).unwrap().is_some());
+
+    view.remove_change(theirs).unwrap();
+    assert_eq!(view.heads(), &[base]);
+
+    // stepping carries on from changes added or removed out of order
+    let mut view = doc1.view_at(&[theirs]);
+    assert_eq!(view.step_forward(), Some(ours));
+    assert_eq!(view.heads(), heads.as_slice());
+    assert_eq!(view.step_forward(), None);
+    assert_eq!(view.step_back(), Some(theirs));
+    assert_eq!(view.heads(), &[ours]);
+    view.remove_change(ours).unwrap();
+    assert_eq!(view.step_back(), Some(base));
+    assert!(view.heads().is_empty());
+    assert_eq!(view.step_back(), None);
+    assert_eq!(view.step_forward(), Some(base));
+}